@@ -0,0 +1,64 @@
+//! Gathers the story formats built into tweec with the `bundled-formats`
+//! feature, writing each format.js to `OUT_DIR` for `formats.rs` to include
+//!
+//! A format.js in `formats/`, in a directory named for its key, is used as
+//! given. Otherwise the format is downloaded from the Twine repository. See
+//! formats/README.md
+
+fn main() {
+    #[cfg(feature = "bundled-formats")]
+    bundled::write();
+}
+
+#[cfg(feature = "bundled-formats")]
+mod bundled {
+    use std::path::{Path, PathBuf};
+
+    /// The formats to bundle, by key, with the URL each is downloaded from if
+    /// it is not in `formats/`
+    const BUNDLED: &[(&str, &str)] = &[
+        (
+            "harlowe-3",
+            "https://raw.githubusercontent.com/klembot/twinejs/2.10.0/public/story-formats/harlowe-3.3.9/format.js",
+        ),
+        (
+            "sugarcube-2",
+            "https://raw.githubusercontent.com/klembot/twinejs/2.10.0/public/story-formats/sugarcube-2.37.3/format.js",
+        ),
+    ];
+
+    /// Writes each bundled format's format.js to `OUT_DIR/<key>/format.js`
+    pub fn write() {
+        let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+        println!("cargo:rerun-if-changed=formats");
+        for (key, url) in BUNDLED {
+            let local = Path::new("formats").join(key).join("format.js");
+            let source = match std::fs::read_to_string(&local) {
+                Ok(source) => source,
+                Err(_) => download(url).unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to download story format {} from {}: {}. Put its format.js at \
+                         {} to build without downloading it",
+                        key,
+                        url,
+                        e,
+                        local.display()
+                    )
+                }),
+            };
+            assert!(
+                source.contains("window.storyFormat("),
+                "The format.js for {} is not a story format",
+                key
+            );
+            let dir = out_dir.join(key);
+            std::fs::create_dir_all(&dir).expect("Failed to create bundled format directory");
+            std::fs::write(dir.join("format.js"), source).expect("Failed to write bundled format");
+        }
+    }
+
+    /// Downloads the format.js at `url`
+    fn download(url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(ureq::get(url).call()?.into_string()?)
+    }
+}
@@ -0,0 +1,489 @@
+//! Finds the local media a story refers to, and either inlines images into
+//! the compiled HTML as data URIs or copies media next to it
+//!
+//! Media are found in HTML `src` attributes (`<img src="map.png">`,
+//! `<audio src="rain.ogg">`), image markup (`[img[map.png]]`, as used by
+//! SugarCube and Harlowe), and CSS `url(...)`s, in passages and stylesheets.
+//! References with a scheme (such as `https:` or `data:`) or that look like
+//! story variables are not local files. Paths are looked for relative to the
+//! directories of the story's Twee files, and then to the directory of the
+//! output file, where a browser would look for them
+
+use crate::inputs;
+use crate::Config;
+use base64::Engine;
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use tweep::Story;
+
+/// Extensions of media files, along with their MIME types
+const MEDIA_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("bmp", "image/bmp"),
+    ("ico", "image/x-icon"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("m4a", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("flac", "audio/flac"),
+    ("mp4", "video/mp4"),
+    ("m4v", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+];
+
+/// A reference to a local media file in a passage or stylesheet
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    /// The byte range of the path in the text
+    pub range: Range<usize>,
+
+    /// The path, as written
+    pub path: String,
+}
+
+impl Reference {
+    /// Gets the path without any query or fragment
+    fn file(&self) -> &str {
+        self.path.split(['?', '#']).next().unwrap_or_default()
+    }
+
+    /// Gets the query and fragment of the path, if any
+    fn suffix(&self) -> &str {
+        &self.path[self.file().len()..]
+    }
+
+    /// Whether the reference is to an image
+    fn is_image(&self) -> bool {
+        media_type(&self.path).is_some_and(|mime| mime.starts_with("image/"))
+    }
+}
+
+/// Gets the MIME type of a media file from its extension
+fn media_type(path: &str) -> Option<&'static str> {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let extension = Path::new(path)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    MEDIA_TYPES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime)| *mime)
+}
+
+/// Whether a path in a reference is a local file, rather than a URL or
+/// something the story format fills in
+fn is_local(path: &str) -> bool {
+    !path.is_empty()
+        && !path.starts_with("//")
+        && !path.starts_with(['$', '_', '#'])
+        && !path.contains("<<")
+        && !path.contains("{{")
+        && !path
+            .split_once(':')
+            .is_some_and(|(scheme, _)| scheme.len() > 1 && !scheme.contains(['/', '\\']))
+}
+
+/// Trims whitespace and matching quotes from the given range of `text`
+fn unquote(text: &str, range: Range<usize>) -> Range<usize> {
+    let value = &text[range.clone()];
+    let start = range.start + (value.len() - value.trim_start().len());
+    let end = range.end - (value.len() - value.trim_end().len());
+    let value = &text[start..end];
+    if value.len() >= 2
+        && (value.starts_with('"') && value.ends_with('"')
+            || value.starts_with('\'') && value.ends_with('\''))
+    {
+        start + 1..end - 1
+    } else {
+        start..end
+    }
+}
+
+/// Finds the local media referred to in a passage or stylesheet
+pub fn references(text: &str) -> Vec<Reference> {
+    let mut ranges = Vec::new();
+
+    // <img src="...">, <audio src="...">, and similar attributes
+    for (start, _) in text.match_indices("src=") {
+        let value = start + "src=".len();
+        let end = match text[value..].chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                text[value + 1..].find(quote).map(|end| value + 1 + end + 1)
+            }
+            _ => text[value..]
+                .find(|c: char| c.is_whitespace() || c == '>')
+                .map(|end| value + end)
+                .or(Some(text.len())),
+        };
+        if let Some(end) = end {
+            ranges.push(unquote(text, value..end));
+        }
+    }
+
+    // [img[path]], [img[title|path]], and [img[path][link]]
+    for (start, _) in text.match_indices("[img[") {
+        let value = start + "[img[".len();
+        if let Some(end) = text[value..].find(']').map(|end| value + end) {
+            let value = text[value..end]
+                .rfind('|')
+                .map(|bar| value + bar + 1)
+                .unwrap_or(value);
+            ranges.push(unquote(text, value..end));
+        }
+    }
+
+    // CSS url(...)
+    for (start, _) in text.match_indices("url(") {
+        let value = start + "url(".len();
+        if let Some(end) = text[value..].find(')').map(|end| value + end) {
+            ranges.push(unquote(text, value..end));
+        }
+    }
+
+    ranges.sort_by_key(|range| range.start);
+    ranges
+        .into_iter()
+        .map(|range| Reference {
+            path: text[range.clone()].to_string(),
+            range,
+        })
+        .filter(|reference| is_local(&reference.path) && media_type(&reference.path).is_some())
+        .collect()
+}
+
+/// Gets the directory the output file is written to under the given config
+pub fn output_dir(config: &Config) -> PathBuf {
+    let output = match config.output_file.as_deref() {
+        Some("-") | None => {
+            return config
+                .output_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("."))
+        }
+        Some(output) => match &config.output_dir {
+            Some(dir) => dir.join(output),
+            None => PathBuf::from(output),
+        },
+    };
+    match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Gets the directories media are looked for in under the given config: the
+/// directories of the story's Twee files, then the output directory
+pub fn search_dirs(config: &Config) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let twee_dirs = inputs::twee_files(&config.inputs, &config.extensions)
+        .into_iter()
+        .map(|file| match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        });
+    for dir in twee_dirs.chain(std::iter::once(output_dir(config))) {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// Finds the file a reference refers to in the first of `dirs` that has it
+pub fn resolve(reference: &Reference, dirs: &[PathBuf]) -> Option<PathBuf> {
+    dirs.iter()
+        .map(|dir| dir.join(reference.file()))
+        .find(|path| path.is_file())
+}
+
+/// Replaces the references to local media in `text` that exist with what
+/// `replace` gives for them, if anything
+fn rewrite<F>(text: &str, dirs: &[PathBuf], mut replace: F) -> Result<String>
+where
+    F: FnMut(&Reference, PathBuf) -> Result<Option<String>>,
+{
+    let mut rewritten = String::with_capacity(text.len());
+    let mut last = 0;
+    for reference in references(text) {
+        // Missing media are reported by the MissingAsset lint
+        let replacement = match resolve(&reference, dirs) {
+            Some(path) => replace(&reference, path)?,
+            None => None,
+        };
+        if let Some(replacement) = replacement {
+            rewritten.push_str(&text[last..reference.range.start]);
+            rewritten.push_str(&replacement);
+            last = reference.range.end;
+        }
+    }
+    rewritten.push_str(&text[last..]);
+    Ok(rewritten)
+}
+
+/// Rewrites the references in every passage and stylesheet of the story
+fn rewrite_story<F>(story: &mut Story, dirs: &[PathBuf], mut replace: F) -> Result<()>
+where
+    F: FnMut(&Reference, PathBuf) -> Result<Option<String>>,
+{
+    for passage in story.passages.values_mut() {
+        passage.content.content = rewrite(&passage.content.content, dirs, &mut replace)?;
+    }
+    for stylesheet in story.stylesheets.iter_mut() {
+        *stylesheet = rewrite(stylesheet, dirs, &mut replace)?;
+    }
+    Ok(())
+}
+
+/// Data URIs of embedded images, keyed by the SHA-256 hash of the image and
+/// its MIME type
+///
+/// `tweec build --watch` keeps the cache between rebuilds, so only images
+/// that changed are encoded again
+#[derive(Debug, Default)]
+pub struct EmbedCache {
+    uris: HashMap<(Vec<u8>, &'static str), String>,
+}
+
+/// Adds every local image referred to in `text` that exists to `found`, along
+/// with its MIME type
+fn images(text: &str, dirs: &[PathBuf], found: &mut HashMap<PathBuf, &'static str>) {
+    for reference in references(text) {
+        if !reference.is_image() {
+            continue;
+        }
+        if let Some(path) = resolve(&reference, dirs) {
+            let mime = media_type(&reference.path).expect("references are media");
+            found.entry(path).or_insert(mime);
+        }
+    }
+}
+
+/// Reads the images at `paths`, encoding those that aren't in `cache` yet,
+/// split across as many threads as there are CPUs
+///
+/// Gives the cache key of each image, along with its data URI if it was
+/// encoded
+fn encode_images(
+    paths: &[(PathBuf, &'static str)],
+    cache: &EmbedCache,
+) -> Result<Vec<(Vec<u8>, Option<String>)>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = paths.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(path, mime)| {
+                            let bytes = std::fs::read(path)
+                                .wrap_err_with(|| format!("Failed to read image {:?}", path))?;
+                            let hash = ring::digest::digest(&ring::digest::SHA256, &bytes)
+                                .as_ref()
+                                .to_vec();
+                            if cache.uris.contains_key(&(hash.clone(), *mime)) {
+                                return Ok((hash, None));
+                            }
+                            let uri = format!(
+                                "data:{};base64,{}",
+                                mime,
+                                base64::engine::general_purpose::STANDARD.encode(bytes)
+                            );
+                            Ok((hash, Some(uri)))
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        let mut encoded = Vec::with_capacity(paths.len());
+        for handle in handles {
+            encoded.extend(handle.join().expect("image encoding panicked")?);
+        }
+        Ok(encoded)
+    })
+}
+
+/// Inlines the local images referred to in the story's passages and
+/// stylesheets as data URIs, so that the compiled story is a single file
+///
+/// Each image is read once, however many times it is referred to, and images
+/// are encoded in parallel. Images whose contents are already in `cache` are
+/// not encoded again, and images no longer referred to are dropped from it
+pub fn embed_images(story: &mut Story, dirs: &[PathBuf], cache: &mut EmbedCache) -> Result<()> {
+    let mut found = HashMap::new();
+    for passage in story.passages.values() {
+        images(&passage.content.content, dirs, &mut found);
+    }
+    for stylesheet in &story.stylesheets {
+        images(stylesheet, dirs, &mut found);
+    }
+    let paths: Vec<(PathBuf, &'static str)> = found.into_iter().collect();
+
+    let encoded = encode_images(&paths, cache)?;
+    let mut uris: HashMap<PathBuf, String> = HashMap::new();
+    let mut used = HashMap::new();
+    for ((path, mime), (hash, uri)) in paths.into_iter().zip(encoded) {
+        let key = (hash, mime);
+        let uri = match uri {
+            Some(uri) => uri,
+            None => cache.uris[&key].clone(),
+        };
+        used.insert(key, uri.clone());
+        uris.insert(path, uri);
+    }
+    cache.uris = used;
+
+    rewrite_story(story, dirs, |reference, path| {
+        Ok(if reference.is_image() {
+            uris.get(&path).cloned()
+        } else {
+            None
+        })
+    })
+}
+
+/// Gets where a reference is copied to within the assets directory: its path
+/// as written if that stays inside the directory, or else just its file name
+fn asset_path(reference: &Reference) -> PathBuf {
+    let path = Path::new(reference.file());
+    let inside = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if inside {
+        path.components()
+            .filter(|component| *component != Component::CurDir)
+            .collect()
+    } else {
+        path.file_name().map(PathBuf::from).unwrap_or_default()
+    }
+}
+
+/// Copies the local media referred to in the story's passages and
+/// stylesheets into `assets_dir`, which is relative to `output_dir`, and
+/// points the references at the copies
+///
+/// Returns the number of files copied. Files already in place are not copied
+/// again
+pub fn copy_assets(
+    story: &mut Story,
+    dirs: &[PathBuf],
+    output_dir: &Path,
+    assets_dir: &Path,
+) -> Result<usize> {
+    let mut copied: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut count = 0;
+    rewrite_story(story, dirs, |reference, path| {
+        let relative = assets_dir.join(asset_path(reference));
+        let destination = output_dir.join(&relative);
+        match copied.get(&destination) {
+            Some(source) if *source != path => {
+                return Err(eyre!(
+                    "Both {:?} and {:?} would be copied to {:?}",
+                    source,
+                    path,
+                    destination
+                ));
+            }
+            Some(_) => (),
+            None => {
+                let same = match (path.canonicalize(), destination.canonicalize()) {
+                    (Ok(source), Ok(destination)) => source == destination,
+                    _ => false,
+                };
+                if !same {
+                    if let Some(parent) = destination.parent() {
+                        std::fs::create_dir_all(parent).wrap_err_with(|| {
+                            format!("Failed to create assets directory {:?}", parent)
+                        })?;
+                    }
+                    std::fs::copy(&path, &destination).wrap_err_with(|| {
+                        format!("Failed to copy {:?} to {:?}", path, destination)
+                    })?;
+                    count += 1;
+                }
+                copied.insert(destination, path);
+            }
+        }
+        let relative: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        Ok(Some(format!(
+            "{}{}",
+            relative.join("/"),
+            reference.suffix()
+        )))
+    })?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_local_media() {
+        let text = "<img src=\"map.png\" alt=\"Map\"> [img[Cat|img/cat.JPG][Next]]\n\
+                    [img[$portrait]] <img src=https://example.com/a.png>\n\
+                    body { background: url( 'bg.webp?v=2' ); } <script src=\"x.js\">\n\
+                    <audio src='../sound/rain.ogg' loop>";
+        let references = references(text);
+        let paths: Vec<&str> = references.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["map.png", "img/cat.JPG", "bg.webp?v=2", "../sound/rain.ogg"]
+        );
+        assert_eq!(&text[references[0].range.clone()], "map.png");
+        assert!(references[2].is_image());
+        assert!(!references[3].is_image());
+
+        assert_eq!(asset_path(&references[1]), Path::new("img/cat.JPG"));
+        assert_eq!(asset_path(&references[2]), Path::new("bg.webp"));
+        assert_eq!(asset_path(&references[3]), Path::new("rain.ogg"));
+    }
+
+    #[test]
+    fn embeds_images_with_cache() {
+        let dir = std::env::temp_dir().join(format!("tweec-embed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.png"), "a").unwrap();
+        std::fs::write(dir.join("b.gif"), "a").unwrap();
+        let input = ":: Start\n<img src=\"a.png\"> [img[b.gif]] <img src=\"missing.png\">\n";
+        let dirs = vec![dir.clone()];
+        let mut cache = EmbedCache::default();
+        let embed = |cache: &mut EmbedCache| {
+            let mut story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+            embed_images(&mut story, &dirs, cache).unwrap();
+            story.passages["Start"].content.content.clone()
+        };
+
+        assert_eq!(
+            embed(&mut cache),
+            "<img src=\"data:image/png;base64,YQ==\"> [img[data:image/gif;base64,YQ==]] \
+             <img src=\"missing.png\">\n"
+        );
+        // The same contents are cached once for each MIME type
+        assert_eq!(cache.uris.len(), 2);
+
+        std::fs::write(dir.join("a.png"), "b").unwrap();
+        let embedded = embed(&mut cache);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(embedded.starts_with("<img src=\"data:image/png;base64,Yg==\">"));
+        assert_eq!(cache.uris.len(), 2);
+    }
+}
@@ -0,0 +1,169 @@
+//! Records a story's current warnings so that later runs only report new ones
+//!
+//! `tweec lint --write-baseline baseline.json` writes every warning found to
+//! the baseline, and `--baseline baseline.json` leaves those warnings out of
+//! later runs. Warnings are matched by rule, file, and message rather than by
+//! position, so that they stay matched as lines move. Errors from the parser
+//! are never left out
+
+use crate::issue::Issue;
+use color_eyre::Result;
+use eyre::eyre;
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What a warning is matched by: its rule, file, and message
+type Key = (String, Option<String>, String);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    rule: String,
+    file: Option<String>,
+    message: String,
+    count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BaselineFile {
+    version: u32,
+    issues: Vec<Entry>,
+}
+
+const VERSION: u32 = 1;
+
+/// The number of times each warning was found when the baseline was written
+#[derive(Debug, Default, PartialEq)]
+pub struct Baseline {
+    counts: BTreeMap<Key, usize>,
+}
+
+fn key(issue: &Issue) -> Option<Key> {
+    match issue {
+        Issue::Error(_) => None,
+        Issue::Warning { .. } | Issue::Lint { .. } => Some((
+            issue.get_name().to_string(),
+            issue
+                .get_context()
+                .as_ref()
+                .and_then(|context| context.get_file_name().clone()),
+            issue.get_message(),
+        )),
+    }
+}
+
+impl Baseline {
+    /// Creates a baseline of the warnings among the given issues
+    pub fn new(issues: &[Issue]) -> Self {
+        let mut counts = BTreeMap::new();
+        for key in issues.iter().filter_map(key) {
+            *counts.entry(key).or_insert(0) += 1;
+        }
+        Baseline { counts }
+    }
+
+    /// Reads a baseline file
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .wrap_err_with(|| format!("Failed to open baseline file {:?}", path))?;
+        let file: BaselineFile = serde_json::from_reader(std::io::BufReader::new(file))
+            .wrap_err_with(|| format!("Failed to parse baseline file {:?}", path))?;
+        if file.version != VERSION {
+            return Err(eyre!(
+                "Unsupported baseline file version {} in {:?}",
+                file.version,
+                path
+            ));
+        }
+        let counts = file
+            .issues
+            .into_iter()
+            .map(|entry| ((entry.rule, entry.file, entry.message), entry.count))
+            .collect();
+        Ok(Baseline { counts })
+    }
+
+    /// Writes the baseline to a file
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = BaselineFile {
+            version: VERSION,
+            issues: self
+                .counts
+                .iter()
+                .map(|((rule, file, message), count)| Entry {
+                    rule: rule.clone(),
+                    file: file.clone(),
+                    message: message.clone(),
+                    count: *count,
+                })
+                .collect(),
+        };
+        let mut contents = serde_json::to_string_pretty(&file)?;
+        contents.push('\n');
+        crate::utils::write_atomic(path, contents.as_bytes())
+            .wrap_err_with(|| format!("Failed to write baseline file {:?}", path))
+    }
+
+    /// Gets the number of warnings in the baseline
+    pub fn len(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// Whether the baseline has no warnings
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Leaves out the issues in the baseline, returning the rest
+    ///
+    /// If a warning is found more times than the baseline records, the extra
+    /// ones are kept
+    pub fn filter(&self, issues: Vec<Issue>) -> Vec<Issue> {
+        let mut remaining = self.counts.clone();
+        issues
+            .into_iter()
+            .filter(
+                |issue| match key(issue).and_then(|key| remaining.get_mut(&key)) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        false
+                    }
+                    _ => true,
+                },
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issue::LintWarning;
+
+    fn lint(name: &'static str, message: &str) -> Issue {
+        Issue::Lint {
+            lint: LintWarning::new(name, message, None),
+            denied: true,
+        }
+    }
+
+    #[test]
+    fn filters_baselined_warnings() {
+        let baseline = Baseline::new(&[lint("DeadEnd", "End"), lint("DeadEnd", "End")]);
+        assert_eq!(baseline.len(), 2);
+
+        let issues = vec![
+            lint("DeadEnd", "End"),
+            lint("DeadEnd", "Other"),
+            lint("DeadEnd", "End"),
+            lint("DeadEnd", "End"),
+        ];
+        let remaining: Vec<String> = baseline
+            .filter(issues)
+            .iter()
+            .map(Issue::get_message)
+            .collect();
+        assert_eq!(remaining, vec!["Other", "End"]);
+    }
+}
@@ -1,7 +1,7 @@
 use ::tweec::tweec;
 
-use color_eyre::Result;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     tweec::run()
 }
@@ -0,0 +1,292 @@
+//! Lints and compiles stories without printing, writing files, or exiting, so
+//! that tweec can be used as a library
+//!
+//! [`check`] lints a story, collecting its issues instead of reporting them,
+//! and [`compile`] turns a story into HTML with a parsed story format:
+//!
+//! ```no_run
+//! use tweec::compile::{self, OutputOptions};
+//! use tweec::StoryFormat;
+//! use tweep::Story;
+//!
+//! # fn main() -> color_eyre::Result<()> {
+//! let story = Story::from_path("story.twee").take().0.unwrap();
+//! let format = StoryFormat::parse(&"format.js".into())?;
+//! let options = OutputOptions {
+//!     title: Some("My Story".to_string()),
+//!     ..OutputOptions::default()
+//! };
+//! let html = compile::compile(&story, &format, &options)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The `tweec` binary reports the issues and writes the output with the same
+//! functions
+//!
+//! [`check`]: fn.check.html
+//! [`compile`]: fn.compile.html
+
+use crate::layout;
+use crate::linter;
+use crate::linter::links::LinkSyntax;
+use crate::minify;
+use crate::order;
+use crate::story_html;
+use crate::story_json::{self, StoryJson};
+use crate::Config;
+use crate::Issue;
+use crate::Layout;
+use crate::OutputStyle;
+use crate::SortPassages;
+use crate::StoryFormat;
+use crate::StoryResult;
+use color_eyre::Result;
+use eyre::eyre;
+use tweep::{Output, Story};
+
+/// Options for compiling a story, as given by a [`Config`]
+///
+/// [`Config`]: ../struct.Config.html
+#[derive(Clone, Debug)]
+pub struct OutputOptions {
+    /// How the compiled HTML is laid out
+    pub style: OutputStyle,
+
+    /// The order passages are numbered and written in
+    pub sort_passages: SortPassages,
+
+    /// Where passages are placed on the Twine editor's story map
+    pub layout: Layout,
+
+    /// The link syntaxes followed when laying out passages
+    pub links: Vec<LinkSyntax>,
+
+    /// The title to use instead of the story's own
+    pub title: Option<String>,
+
+    /// The name of the passage to start at instead of the story's own
+    pub start: Option<String>,
+
+    /// The zoom level to use instead of the story's own
+    pub zoom: Option<f32>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            style: OutputStyle::Normal,
+            sort_passages: SortPassages::File,
+            layout: Layout::Keep,
+            links: LinkSyntax::DEFAULT.to_vec(),
+            title: None,
+            start: None,
+            zoom: None,
+        }
+    }
+}
+
+impl From<&Config> for OutputOptions {
+    fn from(config: &Config) -> Self {
+        OutputOptions {
+            style: config.output_style,
+            sort_passages: config.sort_passages,
+            layout: config.layout,
+            links: config.links.clone(),
+            title: config.title.clone(),
+            start: config.start.clone(),
+            zoom: config.zoom,
+        }
+    }
+}
+
+/// The result of linting a story
+pub struct Checked {
+    /// The story, or the errors that kept it from parsing
+    pub story: StoryResult,
+
+    /// The errors and warnings found, leaving out those the config allows
+    pub issues: Vec<Issue>,
+}
+
+impl Checked {
+    /// Whether the story failed to parse or has any issues reported as errors
+    pub fn is_err(&self) -> bool {
+        self.story.is_err() || self.issues.iter().any(Issue::is_error)
+    }
+
+    /// Gets the story if it can be compiled: it parsed, and none of its issues
+    /// are errors
+    pub fn into_story(self) -> Option<Story> {
+        match self.is_err() {
+            true => None,
+            false => self.story.ok(),
+        }
+    }
+}
+
+/// Lints the parsed story with tweec's built-in lints, at the levels the
+/// config gives them
+///
+/// Nothing is reported, and the config's baseline is not applied
+pub fn check(story_output: Output<StoryResult>, config: &Config) -> Checked {
+    let (story, issues, _) = linter::collect(story_output, config);
+    Checked { story, issues }
+}
+
+/// Replaces the story's metadata and lays it out as the options ask
+fn apply(story: &mut Story, options: &OutputOptions) -> Result<()> {
+    if let Some(title) = &options.title {
+        story.title = Some(title.clone());
+    }
+    if let Some(start) = &options.start {
+        if !story.passages.contains_key(start) {
+            return Err(eyre!(
+                "Can't start at passage {}, as it doesn't exist",
+                start
+            ));
+        }
+    }
+    if options.start.is_some() || options.zoom.is_some() {
+        let data = story
+            .data
+            .as_mut()
+            .ok_or_else(|| eyre!("The story has no StoryData"))?;
+        if let Some(start) = &options.start {
+            data.start = Some(start.clone());
+        }
+        if let Some(zoom) = options.zoom {
+            data.zoom = Some(zoom);
+        }
+    }
+    if options.layout == Layout::Auto {
+        layout::apply(story, &options.links);
+    }
+    Ok(())
+}
+
+/// Renumbers the story's passages, replaces its metadata, and lays it out as
+/// the options ask
+///
+/// It is an error to override the start passage or zoom level of a story
+/// without `StoryData`, or to start at a passage that doesn't exist
+pub fn prepare(story: &mut Story, options: &OutputOptions) -> Result<()> {
+    order::renumber(story, options.sort_passages);
+    apply(story, options)
+}
+
+/// Copies a story, numbering its passages in the given order
+///
+/// `tweep` stories can't be cloned, so the story is written as Twee and parsed
+/// again. The copy has no code map, so the order is found from the original
+fn copy(story: &Story, order: SortPassages) -> Result<Story> {
+    let source = story_json::to_twee(&StoryJson::new(story));
+    let (copy, _) = Story::from_string(source).take();
+    let mut copy = copy.map_err(|_| eyre!("Failed to copy the story"))?;
+    for (i, name) in order::names(story, order).into_iter().enumerate() {
+        if let Some(passage) = copy.passages.get_mut(&name) {
+            passage.content.pid = i + 1;
+        }
+    }
+    Ok(copy)
+}
+
+/// Compiles the story into HTML using the given story format, prepared as the
+/// options ask. See [`prepare`] and [`render`]
+///
+/// The story itself is left as it is
+///
+/// [`prepare`]: fn.prepare.html
+/// [`render`]: fn.render.html
+pub fn compile(
+    story: &Story,
+    story_format: &StoryFormat,
+    options: &OutputOptions,
+) -> Result<String> {
+    let mut story = copy(story, options.sort_passages)?;
+    apply(&mut story, options)?;
+    render(&story, story_format, options.style)
+}
+
+/// Compiles the story into HTML using the given story format, as it is
+///
+/// The story must have `StoryData` and a start passage, as a successful lint
+/// followed by [`ifid::ensure`] guarantees. Partials are expanded, and are not
+/// included in the output themselves. The story data is rendered first, then
+/// placed in the format's template, each laid out as `style` asks. The title
+/// is escaped, as it is in the story data
+///
+/// [`ifid::ensure`]: ../ifid/fn.ensure.html
+pub fn render(story: &Story, story_format: &StoryFormat, style: OutputStyle) -> Result<String> {
+    let story_title = story.title.as_deref().unwrap_or("Untitled Story");
+    let story_data =
+        story_html::story_data(story, &story_format.name, &story_format.version, style)?;
+    let template = match style {
+        OutputStyle::Minified => minify::html(&story_format.source),
+        OutputStyle::Normal | OutputStyle::Pretty => story_format.source.clone(),
+    };
+    let output = template
+        .replace("{{STORY_NAME}}", &story_html::escape(story_title))
+        .replace("{{STORY_DATA}}", &story_data);
+    Ok(format!("{}\n", output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format() -> StoryFormat {
+        StoryFormat::from_source(
+            r#"{"name": "Test", "version": "1.0.0", "source": "<title>{{STORY_NAME}}</title>{{STORY_DATA}}"}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn merges_special_passages_into_user_blocks() {
+        let input = ":: StoryTitle\nTest\n\n:: StoryData\n{\"ifid\": \
+                     \"D674C58C-DEFA-4F70-B7A2-27742230C0FC\", \"start\": \"Start\"}\n\n\
+                     :: Start\nHello\n\n:: Setup [script]\nvar a = 1;\n\n\
+                     :: More [script widget]\nvar b = 2;\n\n\
+                     :: Theme [stylesheet]\nbody { color: red; }\n"
+            .to_string();
+        let story = Story::from_string(input).take().0.ok().unwrap();
+        let output = render(&story, &format(), OutputStyle::Normal).unwrap();
+
+        assert_eq!(output.matches("<tw-passagedata").count(), 1);
+        assert!(output.contains("role=\"stylesheet\">body { color: red; }</style>"));
+        assert!(output.contains("var a = 1;") && output.contains("var b = 2;"));
+        assert!(!output.contains("name=\"Setup\""));
+    }
+
+    #[test]
+    fn compiles_with_options() {
+        let input = ":: StoryData\n{\"ifid\": \"D674C58C-DEFA-4F70-B7A2-27742230C0FC\"}\n\n\
+                     :: Start\n[[End]]\n\n:: End\nDone.\n"
+            .to_string();
+        let story = Story::from_string(input).take().0.ok().unwrap();
+        let options = OutputOptions {
+            title: Some("Renamed".to_string()),
+            start: Some("End".to_string()),
+            ..OutputOptions::default()
+        };
+        let output = compile(&story, &format(), &options).unwrap();
+        assert!(output.starts_with("<title>Renamed</title>"));
+        assert!(output.contains("startnode=\"1\""));
+        assert!(output.contains("<tw-passagedata pid=\"1\" name=\"End\""));
+        assert!(story.title.is_none());
+
+        let options = OutputOptions {
+            start: Some("Nowhere".to_string()),
+            ..OutputOptions::default()
+        };
+        assert!(compile(&story, &format(), &options).is_err());
+
+        let story = Story::from_string(":: Start\nHello\n".to_string())
+            .take()
+            .0
+            .ok()
+            .unwrap();
+        assert!(compile(&story, &format(), &OutputOptions::default()).is_err());
+    }
+}
@@ -1,132 +1,1102 @@
-use clap::{crate_authors, crate_description, crate_name, crate_version};
-use clap::{App, Arg};
+use crate::formats;
+use crate::glob;
+use crate::inputs;
+use crate::issue::{self, LintLevel};
+use crate::linter::links::LinkSyntax;
+use crate::logging;
+use crate::playtest;
+use crate::project::ProjectConfig;
+use crate::smoke::SmokeTest;
+use crate::stdin;
+use crate::story_format;
+use crate::toml;
+use crate::utils;
+use crate::StoryFormat;
+use clap::{crate_description, crate_name, crate_version};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use color_eyre::Result;
 use eyre::eyre;
 use eyre::WrapErr;
 use json_comments::StripComments;
+use log::{debug, info, LevelFilter};
 use serde::Deserialize;
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::Write;
 
 use termcolor::ColorChoice;
+use tweep::Story;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Represents a unified configuration for a run of tweec.
 ///
 /// Compiled from the configuration file and command-line arguments given
+#[derive(Clone)]
 pub struct Config {
-    /// True if this is a lint-only run
-    pub linting: bool,
+    /// What tweec should do
+    pub command: Command,
 
     /// Input file(s)/director(y/ies)
     pub inputs: Vec<String>,
 
+    /// Extensions of the Twee files read from input directories, without the
+    /// leading `.`
+    pub extensions: Vec<String>,
+
     /// The path to the format file to use
     pub format_file: PathBuf,
 
-    /// Output file, if necessary/given
+    /// The SHA-256 hash the format file must have, if the project pins it
+    pub format_sha256: Option<String>,
+
+    /// The URL the format file is downloaded from, if the format is given by
+    /// URL. It is downloaded to `format_file` when the format is loaded, if it
+    /// isn't cached there already
+    pub format_url: Option<String>,
+
+    /// If true, download the format at `format_url` even if it is cached
+    pub refresh_formats: bool,
+
+    /// Output file, if necessary/given. It is relative to the output
+    /// directory if one is given
     pub output_file: Option<String>,
 
+    /// Directory the compiled story and the files written next to it go in,
+    /// if given
+    pub output_dir: Option<PathBuf>,
+
     /// True if the output should be sent to `opener`
     pub should_open: bool,
 
-    /// List of allowed (ignored) warning names
-    pub allowed: Vec<String>,
+    /// The program and arguments to open the output with: the program given
+    /// by `--open-with`, or else the config file's `opener`. The system's
+    /// default program is used if neither is given. See [`open`]
+    ///
+    /// [`open`]: ../open/index.html
+    pub opener: Option<Vec<String>>,
 
-    /// List of denied (treated as errors) warning names
-    pub denied: Vec<String>,
+    /// Levels of warnings by name, from least to most specific: the default
+    /// format config, the story format's config, the project config, then
+    /// the command line. See [`issue::lint_level`]
+    ///
+    /// [`issue::lint_level`]: ../issue/fn.lint_level.html
+    pub levels: Vec<(String, LintLevel)>,
 
     /// Whether or not to use color output
     pub use_color: ColorChoice,
 
-    /// If true, use compact output format
-    pub compact: bool,
+    /// How to output warnings and errors
+    pub message_format: MessageFormat,
+
+    /// If true, fold repeats of the same warning into a summary line
+    pub fold: bool,
+
+    /// If true, write generated IFIDs back into the story source
+    pub write_ifid: bool,
+
+    /// If true, rerun whenever the inputs change
+    pub watch: bool,
+
+    /// If true, build with a proofing format instead of the story's format
+    pub proof: bool,
+
+    /// If true, remove repeated tags and metadata keys from passage headers
+    /// before linting
+    pub fix: bool,
+
+    /// If true, parse the compiled HTML and warn about structural problems
+    pub validate_html: bool,
+
+    /// If true, inline local images into the output as data URIs
+    pub embed_images: bool,
+
+    /// Directory to copy local media into, relative to the output file, if
+    /// given
+    pub assets_dir: Option<PathBuf>,
+
+    /// If true, write a source map relating the output's passages back to
+    /// the Twee source
+    pub source_map: bool,
+
+    /// If true, run the whole build but write nothing, only checking that the
+    /// output file could be written
+    pub check: bool,
+
+    /// If true, overwrite an existing output file even if tweec didn't write
+    /// it
+    pub force: bool,
+
+    /// How to package the compiled story for upload to itch.io instead of
+    /// writing it, if `--package` is given. The package is written next to
+    /// where the story would have been
+    pub package: Option<PackageConfig>,
+
+    /// How to make the compiled story installable as a web app, if it should
+    /// be: from the project config, or the defaults if `--pwa` is given
+    pub pwa: Option<PwaConfig>,
+
+    /// Whether to minify or indent the compiled HTML
+    pub output_style: OutputStyle,
+
+    /// The order of the passages in the compiled HTML
+    pub sort_passages: SortPassages,
+
+    /// Values of custom placeholders in the story format's source, by name
+    /// without the surrounding braces, from the project config and then the
+    /// command line
+    pub placeholders: BTreeMap<String, String>,
+
+    /// Where to place passages in the compiled story
+    pub layout: Layout,
+
+    /// Title to build the story with in place of its `StoryTitle`, if given
+    pub title: Option<String>,
+
+    /// Passage to start the story at in place of the one in its `StoryData`,
+    /// if given
+    pub start: Option<String>,
+
+    /// Zoom level to build the story with in place of the one in its
+    /// `StoryData`, if given
+    pub zoom: Option<f32>,
+
+    /// HTML shell to use in place of the story format's source, if given. See
+    /// [`template`]
+    ///
+    /// [`template`]: ../template/index.html
+    pub template: Option<PathBuf>,
+
+    /// Baseline file of known warnings to leave out of the report, if given
+    pub baseline: Option<PathBuf>,
+
+    /// File to write the current warnings to as a baseline, if given
+    pub write_baseline: Option<PathBuf>,
+
+    /// Most warnings allowed before linting fails, if limited
+    pub max_warnings: Option<usize>,
+
+    /// HTML file to write a report of the warnings and errors to, if given.
+    /// See [`html_report`]
+    ///
+    /// [`html_report`]: ../html_report/index.html
+    pub html_report: Option<PathBuf>,
+
+    /// The name of the story format in use (e.g., sugarcube-2), if known
+    pub format: Option<String>,
+
+    /// Maximum number of links allowed in a passage, if limited
+    pub max_links: Option<usize>,
+
+    /// Maximum number of links allowed in passages with a given tag,
+    /// overriding `max_links`
+    pub tag_max_links: HashMap<String, usize>,
+
+    /// Chapter tags, in story order
+    pub chapters: Vec<String>,
+
+    /// Tags that allow a passage to link back to earlier chapters
+    pub recap_tags: Vec<String>,
+
+    /// The link syntaxes recognized when building the link graph and checking
+    /// for dead links
+    pub links: Vec<LinkSyntax>,
+
+    /// Tags of passages that are used without being linked to, such as
+    /// widgets
+    pub unlinked_tags: Vec<String>,
+
+    /// Names of passages that are used without being linked to, such as a
+    /// story format's special passages
+    pub unlinked_passages: Vec<String>,
+
+    /// Tags that mark a passage as an ending, which may have no links out
+    pub ending_tags: Vec<String>,
+
+    /// The story format's smoke test, run on each compiled story
+    pub smoke_test: Option<SmokeTest>,
+
+    /// Names of macros defined outside the story, such as by a library, which
+    /// the macro lints should accept
+    pub macros: Vec<String>,
+
+    /// Word lists the spelling lint checks passage text against
+    pub dictionaries: Vec<PathBuf>,
+
+    /// The story's own word list of names and invented words, if any
+    pub dictionary: Option<PathBuf>,
+
+    /// Warnings about the command line, such as input patterns that match no
+    /// files
+    pub warnings: Vec<String>,
+
+    /// How much to log about what tweec is doing
+    pub log_level: LevelFilter,
+}
+
+/// What tweec should do
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Compile the story to HTML
+    Build,
+
+    /// Lint the story without producing any output
+    Lint,
+
+    /// List the rules that would run when linting and how each is treated
+    Rules,
+
+    /// List every rule tweec knows, with its description and default level
+    ListRules,
+
+    /// Print the extended explanation of the named rule
+    Explain(String),
+
+    /// Extract a passage neighborhood into a standalone story
+    Extract(ExtractConfig),
+
+    /// Compile the story with the translations from a catalog
+    Translate(TranslateConfig),
+
+    /// Convert a compiled Twine 2 HTML story back into Twee
+    Decompile(DecompileConfig),
+
+    /// List, install, or remove story formats
+    Format(FormatCommand),
+
+    /// Run as a language server
+    Lsp,
+
+    /// Run a daemon that answers requests on the given local port
+    Daemon(u16),
+
+    /// Write a report of the story's issues for an issue tracker
+    Report(ReportConfig),
+
+    /// Compile the story and bundle it for upload to itch.io
+    Package(PackageConfig),
+
+    /// Bundle several stories, one per input, into a Twine 2 archive
+    Archive(ArchiveConfig),
+
+    /// Print statistics about the story
+    Stats(StatsConfig),
+
+    /// Write the passage link graph
+    Graph(GraphConfig),
+
+    /// Write the parsed story in a format for other tools
+    Export(ExportConfig),
+
+    /// Walk scripted playthroughs of the story and check where they lead
+    Test(TestConfig),
+
+    /// Apply suggested fixes to the story's source files
+    Fix(FixConfig),
+
+    /// Print the configuration resolved from the config files and command
+    /// line
+    ShowConfig {
+        /// Whether to print JSON instead of a table
+        json: bool,
+    },
+}
+
+impl Command {
+    /// Whether this command compiles the story and so needs a story format
+    pub fn needs_format(&self) -> bool {
+        matches!(
+            self,
+            Command::Build | Command::Package(_) | Command::Translate(_)
+        )
+    }
+
+    /// Whether this command reads the story and so needs inputs
+    pub fn needs_inputs(&self) -> bool {
+        !matches!(
+            self,
+            Command::Rules
+                | Command::ListRules
+                | Command::Explain(_)
+                | Command::Decompile(_)
+                | Command::Format(_)
+                | Command::Lsp
+                | Command::ShowConfig { .. }
+        )
+    }
+}
+
+/// The format used to output warnings and errors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageFormat {
+    /// Rich output with source snippets
+    Human,
+
+    /// One line per warning or error
+    Compact,
+
+    /// A JSON object listing the warnings and errors. See
+    /// [`reporter::JsonReporter`]
+    ///
+    /// [`reporter::JsonReporter`]: ../reporter/struct.JsonReporter.html
+    Json,
+
+    /// A SARIF 2.1 log, for code scanning tools
+    Sarif,
+
+    /// JUnit XML, for CI systems. See [`junit`]
+    ///
+    /// [`junit`]: ../junit/index.html
+    Junit,
+}
+
+/// How the compiled HTML is laid out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputStyle {
+    /// As the story format's template and the story's source give it
+    Normal,
+
+    /// With needless whitespace and comments removed from the template and the
+    /// story's stylesheet and script
+    Minified,
+
+    /// With each element of the story data on its own indented line
+    Pretty,
+}
+
+/// The order passages are numbered and written in the compiled story
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortPassages {
+    /// In the order they appear in the source, files in the order read
+    File,
+
+    /// By name
+    Name,
+}
+
+/// Where passages are placed on the Twine editor's story map
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// As given by their `position` metadata
+    Keep,
+
+    /// By following their links, as described in [`layout`]
+    ///
+    /// [`layout`]: ../layout/index.html
+    Auto,
+}
+
+/// The format of an issue report
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    /// A Markdown document with a section for each ticket
+    Markdown,
+
+    /// A JSON array of tickets with a title, body, and labels
+    Json,
+}
+
+/// Options for writing an issue report
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportConfig {
+    /// The format to write the report in
+    pub format: ReportFormat,
+
+    /// The file to write the report to, or standard output if not given
+    pub output: Option<PathBuf>,
+
+    /// If true, only report errors, such as denied warnings
+    pub errors_only: bool,
+}
+
+/// The format of an exported link graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphFormat {
+    /// A GraphViz DOT digraph
+    Dot,
+
+    /// A Mermaid flowchart, for embedding in Markdown
+    Mermaid,
+
+    /// A JSON object with passages and links, for custom tools
+    Json,
+}
+
+/// Options for exporting the passage link graph
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphConfig {
+    /// The format to write the graph in
+    pub format: GraphFormat,
+
+    /// The file to write the graph to, or standard output if not given
+    pub output: Option<PathBuf>,
+}
+
+/// The format of an exported story
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// A JSON object with the story's data and passages
+    Json,
+}
+
+/// Options for exporting the parsed story
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportConfig {
+    /// The format to write the story in
+    pub format: ExportFormat,
+
+    /// The file to write the story to, or standard output if not given
+    pub output: Option<PathBuf>,
+}
+
+/// Options for running playthrough tests
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestConfig {
+    /// The test scripts to run, and directories of them
+    pub scripts: Vec<PathBuf>,
+}
+
+/// Options for applying suggested fixes
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixConfig {
+    /// If true, also apply fixes that may be wrong, such as changing a dead
+    /// link to the passage with the most similar name
+    pub aggressive: bool,
+
+    /// If true, print a diff of each file instead of changing it
+    pub dry_run: bool,
+}
+
+/// The format of printed story statistics
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatsFormat {
+    /// Aligned tables
+    Table,
+
+    /// JSON, for tracking the numbers over time
+    Json,
+}
+
+/// Options for printing statistics about a story
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsConfig {
+    /// If true, list when and by whom each passage was last changed, from git
+    pub authors: bool,
+
+    /// The format to print the statistics in
+    pub format: StatsFormat,
+}
+
+/// Options for making the compiled story installable as a web app. See
+/// [`pwa`]
+///
+/// [`pwa`]: ../pwa/index.html
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct PwaConfig {
+    /// The app's name, or the story's title if not given
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// An image to show as the app's icon, copied next to the compiled story
+    #[serde(default)]
+    pub icon: Option<PathBuf>,
+
+    /// The color of the app's title bar and splash screen, as a CSS color
+    #[serde(default)]
+    pub theme_color: Option<String>,
+}
+
+/// Options for packaging a compiled story for itch.io
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackageConfig {
+    /// If true, write a zip file instead of a directory
+    pub zip: bool,
+
+    /// The zip file or directory to write, or `<Story Title>.zip` or
+    /// `<Story Title>` if not given
+    pub output: Option<PathBuf>,
+
+    /// Files and directories to copy into the package alongside the story
+    pub assets: Vec<PathBuf>,
+
+    /// A cover image to include as `cover.<ext>`
+    pub cover: Option<PathBuf>,
+
+    /// If true, include a `metadata.json` describing the story
+    pub metadata: bool,
+
+    /// If true, include an `.itch.toml` manifest telling the itch app to
+    /// play `index.html`
+    pub itch_toml: bool,
+
+    /// The directory media were copied into by `--assets-dir`, if any, with
+    /// the `/`-separated path it is packaged at, so the story's references
+    /// to the copies still work
+    pub copied_assets: Option<(PathBuf, String)>,
+}
+
+/// Options for decompiling a Twine 2 HTML story, or a Twine 1 story
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecompileConfig {
+    /// The HTML or `.tws` file to decompile
+    pub input: PathBuf,
+
+    /// The file to write the Twee source to, or standard output if not given
+    pub output: Option<PathBuf>,
+
+    /// If true, leave out metadata only used by the Twine editor
+    pub minimal_metadata: bool,
+
+    /// If true, read every story in a Twine 2 archive, writing each to its
+    /// own directory in the output directory
+    pub split: bool,
+
+    /// Where to place the decompiled passages
+    pub layout: Layout,
+}
+
+/// Options for bundling stories into a Twine 2 archive
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveConfig {
+    /// The file to write the archive to, or standard output if not given
+    pub output: Option<PathBuf>,
+}
+
+/// A story format management command
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatCommand {
+    /// List the formats found in the configured format paths
+    List,
+
+    /// Install a format from a URL, path, or known format name, to the given
+    /// directory or the first configured format path
+    Install {
+        /// A known format name with an optional version, or the URL or path
+        /// of a format.js
+        source: String,
+
+        /// The directory to install to
+        dir: Option<PathBuf>,
+    },
+
+    /// Remove an installed format, given by key or name
+    Remove(String),
+
+    /// Check a format.js against the Twine 2 spec, reporting every problem
+    Check(PathBuf),
+}
+
+/// Options for extracting a passage and its linked neighborhood into a
+/// standalone story
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtractConfig {
+    /// Name of the passage to extract
+    pub passage: String,
+
+    /// Number of links to follow out from the passage
+    pub depth: usize,
+
+    /// Directory to write the extracted story to
+    pub output_dir: PathBuf,
+}
+
+/// Options for compiling a translation of the story
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranslateConfig {
+    /// The language to build, as named by its catalog (e.g., fr)
+    pub lang: String,
+
+    /// The gettext catalog holding the translations
+    pub catalog: PathBuf,
 }
 
 impl Config {
-    /// Loads the [`ConfigFile`], parses the [`CliConfig`], and produces a
-    /// unified `Config`
+    /// Loads the [`ConfigFile`], parses the [`CliConfig`], finds the
+    /// [`ProjectConfig`], and produces a unified `Config`
     ///
     /// [`CliConfig`]: struct.CliConfig.html
     /// [`ConfigFile`]: struct.ConfigFile.html
+    /// [`ProjectConfig`]: ../project/struct.ProjectConfig.html
     pub fn build() -> Result<Self> {
         let config_file = ConfigFile::load()?;
-        let cli_config = CliConfig::from_args();
-        Ok(Config::layer(config_file, cli_config))
+        let mut cli_config = CliConfig::from_args(&config_file.build_args)?;
+        if stdin::is_read(&cli_config.inputs)
+            && (cli_config.watch || cli_config.fix || cli_config.write_ifid)
+        {
+            return Err(eyre!(
+                "--watch, --fix, and --write-ifid can't be used with a story read from standard \
+                 input (-)"
+            ));
+        }
+        if let Command::Fix(FixConfig { dry_run: false, .. }) = cli_config.command {
+            if stdin::is_read(&cli_config.inputs) {
+                return Err(eyre!(
+                    "tweec fix can't change a story read from standard input (-); use --dry-run \
+                     to see the fixes"
+                ));
+            }
+        }
+        let project_config = match cli_config.command {
+            Command::Decompile(_) | Command::Format(_) => None,
+            _ => ProjectConfig::find(&cli_config.inputs)?,
+        };
+        stdin::replace_input(&mut cli_config.inputs)?;
+        let mut builder = Config::builder()
+            .config_file(config_file)
+            .cli_config(cli_config);
+        if let Some(project_config) = project_config {
+            builder = builder.project_config(project_config);
+        }
+        builder.build()
+    }
+
+    /// Starts building a `Config` without reading the config file or the
+    /// command line. See [`ConfigBuilder`]
+    ///
+    /// [`ConfigBuilder`]: struct.ConfigBuilder.html
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
     }
 
-    /// Creates a unified `Config` file from the given [`ConfigFile`] and
-    /// [`CliConfig`]
+    /// Creates a unified `Config` file from the given [`ConfigFile`],
+    /// [`ProjectConfig`], and [`CliConfig`], in increasing order of
+    /// precedence
+    ///
+    /// The project's inputs are used if none are given on the command line,
+    /// and glob patterns among the inputs are expanded
+    ///
+    /// If no format is given on the command line, the format is detected from
+    /// the `format` and `format-version` fields of the story's `StoryData`
+    /// and resolved against the formats found by [`ConfigFile::load`]. It is an
+    /// error if the detected format is not installed, unless the command does
+    /// not need a story format
     ///
     /// [`CliConfig`]: struct.CliConfig.html
     /// [`ConfigFile`]: struct.ConfigFile.html
-    pub fn layer(config_file: ConfigFile, cli_config: CliConfig) -> Self {
-        let format_file = cli_config
-            .format
-            .as_ref()
-            .map(|f| {
-                config_file
-                    .formats
-                    .get(f)
-                    .cloned()
-                    .unwrap_or_else(|| f.into())
-            })
-            .unwrap_or_else(|| "format.js".into());
+    /// [`ConfigFile::load`]: struct.ConfigFile.html#method.load
+    /// [`ProjectConfig`]: ../project/struct.ProjectConfig.html
+    pub fn layer(
+        config_file: ConfigFile,
+        project_config: Option<ProjectConfig>,
+        mut cli_config: CliConfig,
+    ) -> Result<Self> {
+        let project_config = project_config.unwrap_or_default();
+        if cli_config.inputs.is_empty() {
+            cli_config.inputs = project_config.inputs;
+        }
+        if cli_config.inputs.is_empty() && cli_config.command.needs_inputs() {
+            return Err(eyre!(
+                "No inputs given. Give the story's files or directories, or list them as inputs \
+                 in a {} file",
+                crate::project::FILE_NAME
+            ));
+        }
+        let (inputs, warnings) = glob::expand_inputs(&cli_config.inputs);
+        debug!("Using inputs {:?}", inputs);
+        cli_config.inputs = inputs;
+        // The project's pinned hash only applies to the project's format
+        let format_sha256 = match cli_config.format {
+            Some(_) => None,
+            None => project_config.format_sha256,
+        };
+        cli_config.format = cli_config.format.or(project_config.format);
+        if matches!(
+            cli_config.command,
+            Command::Build | Command::Daemon(_) | Command::ShowConfig { .. }
+        ) {
+            cli_config.output_file = cli_config.output_file.or(project_config.output_file);
+            cli_config.output_dir = cli_config.output_dir.or(project_config.output_dir);
+        }
 
-        let mut allowed = cli_config.allowed;
-        let mut default_allowed = config_file
-            .format_configs
-            .get("default")
-            .map(|f| f.allow.clone())
-            .unwrap_or_default();
-        allowed.append(&mut default_allowed);
-        let mut format_allowed = cli_config
-            .format
+        let extensions: Vec<String> = if !cli_config.extensions.is_empty() {
+            cli_config.extensions.clone()
+        } else {
+            project_config
+                .extensions
+                .or_else(|| config_file.extensions.clone())
+                .unwrap_or_else(inputs::default_extensions)
+        }
+        .iter()
+        .map(|ext| ext.trim_start_matches('.').to_string())
+        .collect();
+
+        let key = match &cli_config.format {
+            Some(format) if formats::is_url(format) => {
+                Some(formats::cache_path(format)?.to_string_lossy().into_owned())
+            }
+            Some(format) => Some(
+                StoryFormat::resolve_request(&config_file.formats, format)?
+                    .unwrap_or(format)
+                    .to_string(),
+            ),
+            None => match Config::detect_format(&config_file, &cli_config.inputs, &extensions) {
+                Ok(format) => format,
+                Err(_) if !cli_config.command.needs_format() => None,
+                Err(e) => return Err(e),
+            },
+        };
+
+        let proofing = cli_config.proof && cli_config.command.needs_format();
+        let format_sha256 = format_sha256.filter(|_| !proofing);
+        let format_url = if proofing {
+            config_file.proofing_format.clone()
+        } else {
+            cli_config.format.clone()
+        }
+        .filter(|format| formats::is_url(format));
+        let format_file = if proofing {
+            Config::proofing_format_file(&config_file)?
+        } else {
+            key.as_ref()
+                .map(|f| {
+                    config_file
+                        .formats
+                        .get(f)
+                        .cloned()
+                        .unwrap_or_else(|| f.into())
+                })
+                .unwrap_or_else(|| "format.js".into())
+        };
+        info!("Using story format file {:?}", format_file);
+
+        // Formats installed per version (e.g., sugarcube-2.36.1) share the
+        // config for their family (e.g., sugarcube-2)
+        let format = key.map(|key| {
+            if config_file.format_configs.contains_key(&key) {
+                return key;
+            }
+            match config_file.formats.get(&key).map(StoryFormat::parse) {
+                Some(Ok(story_format)) => story_format.family(),
+                _ => key,
+            }
+        });
+
+        let default_config = config_file.format_configs.get("default");
+        let format_config = format
             .as_ref()
-            .map(|f| {
-                config_file
-                    .format_configs
-                    .get(f)
-                    .map(|f| f.allow.clone())
-                    .unwrap_or_default()
-            })
+            .and_then(|f| config_file.format_configs.get(f));
+
+        let max_links = format_config
+            .and_then(|f| f.max_links)
+            .or_else(|| default_config.and_then(|f| f.max_links));
+        let mut tag_max_links = default_config
+            .map(|f| f.tag_max_links.clone())
             .unwrap_or_default();
-        allowed.append(&mut format_allowed);
+        if let Some(f) = format_config {
+            tag_max_links.extend(f.tag_max_links.clone());
+        }
 
-        let mut denied = cli_config.denied;
-        let mut default_denied = config_file
-            .format_configs
-            .get("default")
-            .map(|f| f.deny.clone())
+        let chapters = format_config
+            .and_then(|f| f.chapters.clone())
+            .or_else(|| default_config.and_then(|f| f.chapters.clone()))
             .unwrap_or_default();
-        denied.append(&mut default_denied);
-        let mut format_denied = cli_config
-            .format
-            .as_ref()
-            .map(|f| {
-                config_file
-                    .format_configs
-                    .get(f)
-                    .map(|f| f.deny.clone())
-                    .unwrap_or_default()
-            })
+        let recap_tags = format_config
+            .and_then(|f| f.recap_tags.clone())
+            .or_else(|| default_config.and_then(|f| f.recap_tags.clone()))
+            .unwrap_or_else(|| vec!["recap".to_string()]);
+        let unlinked_tags = format_config
+            .and_then(|f| f.unlinked_tags.clone())
+            .or_else(|| default_config.and_then(|f| f.unlinked_tags.clone()))
+            .unwrap_or_else(|| {
+                vec![
+                    "widget".to_string(),
+                    "script".to_string(),
+                    "stylesheet".to_string(),
+                ]
+            });
+        let unlinked_passages = format_config
+            .and_then(|f| f.unlinked_passages.clone())
+            .or_else(|| default_config.and_then(|f| f.unlinked_passages.clone()))
+            .unwrap_or_default();
+        let ending_tags = format_config
+            .and_then(|f| f.ending_tags.clone())
+            .or_else(|| default_config.and_then(|f| f.ending_tags.clone()))
+            .unwrap_or_else(|| vec!["ending".to_string()]);
+        let smoke_test = format_config
+            .and_then(|f| f.smoke_test.clone())
+            .or_else(|| default_config.and_then(|f| f.smoke_test.clone()));
+        let macros = format_config
+            .and_then(|f| f.macros.clone())
+            .or_else(|| default_config.and_then(|f| f.macros.clone()))
+            .unwrap_or_default();
+        let dictionaries = format_config
+            .and_then(|f| f.dictionaries.as_ref())
+            .or_else(|| default_config.and_then(|f| f.dictionaries.as_ref()))
+            .map(|paths| paths.iter().map(|p| expand_path(p)).collect())
+            .transpose()?
             .unwrap_or_default();
-        denied.append(&mut format_denied);
+        let dictionary = format_config
+            .and_then(|f| f.dictionary.as_deref())
+            .or_else(|| default_config.and_then(|f| f.dictionary.as_deref()))
+            .map(expand_path)
+            .transpose()?;
+        let links = match format_config
+            .and_then(|f| f.links.as_ref())
+            .or_else(|| default_config.and_then(|f| f.links.as_ref()))
+        {
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    LinkSyntax::from_name(name).ok_or_else(|| {
+                        let known: Vec<&str> = LinkSyntax::ALL.iter().map(|s| s.name()).collect();
+                        eyre!(
+                            "Unknown link syntax {} in config file (known syntaxes: {})",
+                            name,
+                            known.join(", ")
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => LinkSyntax::DEFAULT.to_vec(),
+        };
+
+        let mut levels = default_config.map(FormatConfig::levels).unwrap_or_default();
+        levels.extend(format_config.map(FormatConfig::levels).unwrap_or_default());
+        levels.extend(project_config.levels);
+        let mut placeholders = project_config.placeholders;
+        placeholders.extend(cli_config.placeholders);
+        levels.extend(cli_config.levels);
 
-        Config {
-            linting: cli_config.linting,
+        Ok(Config {
+            command: cli_config.command,
             inputs: cli_config.inputs,
+            extensions,
+            format_sha256,
+            format_url,
+            refresh_formats: cli_config.refresh_formats,
             format_file,
             output_file: cli_config.output_file,
-            should_open: cli_config.should_open,
-            allowed,
-            denied,
+            output_dir: cli_config.output_dir,
+            should_open: cli_config.should_open || cli_config.open_with.is_some(),
+            opener: cli_config
+                .open_with
+                .map(|program| vec![program])
+                .or(config_file.opener),
+            levels,
             use_color: cli_config.use_color,
-            compact: cli_config.compact,
+            message_format: cli_config.message_format,
+            fold: cli_config.fold,
+            write_ifid: cli_config.write_ifid,
+            watch: cli_config.watch,
+            proof: cli_config.proof,
+            fix: cli_config.fix,
+            validate_html: cli_config.validate_html,
+            embed_images: cli_config.embed_images,
+            assets_dir: cli_config.assets_dir,
+            source_map: cli_config.source_map,
+            check: cli_config.check,
+            force: cli_config.force,
+            package: cli_config.package,
+            // Unlike the other options, --pwa carries no settings of its own,
+            // so it only turns the web app on, and the project's [pwa] settings
+            // are kept when both are given
+            pwa: project_config
+                .pwa
+                .or(cli_config.pwa.then(PwaConfig::default)),
+            output_style: cli_config.output_style,
+            sort_passages: cli_config.sort_passages,
+            placeholders,
+            layout: cli_config.layout,
+            title: cli_config.title.or(project_config.title),
+            start: cli_config.start.or(project_config.start),
+            zoom: cli_config.zoom.or(project_config.zoom),
+            template: cli_config.template,
+            baseline: cli_config.baseline.or(project_config.baseline),
+            write_baseline: cli_config.write_baseline,
+            max_warnings: cli_config.max_warnings.or(project_config.max_warnings),
+            html_report: cli_config.html_report,
+            format,
+            max_links,
+            tag_max_links,
+            chapters,
+            recap_tags,
+            links,
+            unlinked_tags,
+            unlinked_passages,
+            ending_tags,
+            smoke_test,
+            macros,
+            dictionaries,
+            dictionary,
+            warnings,
+            log_level: cli_config.log_level,
+        })
+    }
+
+    /// Gets the resolved settings, named as in the config file where they can
+    /// be set there, in the order `tweec config show` prints them
+    pub fn settings(&self) -> Vec<(&'static str, Value)> {
+        let message_format = match self.message_format {
+            MessageFormat::Human => "human",
+            MessageFormat::Compact => "compact",
+            MessageFormat::Json => "json",
+            MessageFormat::Sarif => "sarif",
+            MessageFormat::Junit => "junit",
+        };
+        let output_style = match self.output_style {
+            OutputStyle::Normal => "normal",
+            OutputStyle::Minified => "minified",
+            OutputStyle::Pretty => "pretty",
+        };
+        let layout = match self.layout {
+            Layout::Keep => "keep",
+            Layout::Auto => "auto",
+        };
+        let sort_passages = match self.sort_passages {
+            SortPassages::File => "file",
+            SortPassages::Name => "name",
+        };
+        let links: Vec<&str> = self.links.iter().map(|syntax| syntax.name()).collect();
+        let levels: Vec<String> = self
+            .levels
+            .iter()
+            .map(|(name, level)| format!("{} {}", level, name))
+            .collect();
+        let smoke_test = self.smoke_test.as_ref().map(|smoke_test| {
+            json!({
+                "command": smoke_test.command,
+                "expect": smoke_test.expect,
+                "timeout": smoke_test.timeout,
+            })
+        });
+        vec![
+            ("inputs", json!(self.inputs)),
+            ("extensions", json!(self.extensions)),
+            ("format", json!(self.format)),
+            ("format_file", json!(self.format_file)),
+            ("format_sha256", json!(self.format_sha256)),
+            ("format_url", json!(self.format_url)),
+            ("output", json!(self.output_file)),
+            ("output_dir", json!(self.output_dir)),
+            ("levels", json!(levels)),
+            ("message_format", json!(message_format)),
+            ("fold", json!(self.fold)),
+            ("open", json!(self.should_open)),
+            ("opener", json!(self.opener)),
+            ("watch", json!(self.watch)),
+            ("proof", json!(self.proof)),
+            ("fix", json!(self.fix)),
+            ("validate_html", json!(self.validate_html)),
+            ("embed_images", json!(self.embed_images)),
+            ("assets_dir", json!(self.assets_dir)),
+            ("source_map", json!(self.source_map)),
+            ("check", json!(self.check)),
+            ("force", json!(self.force)),
+            ("package", json!(self.package.as_ref().map(|_| "zip"))),
+            (
+                "pwa",
+                json!(self.pwa.as_ref().map(|pwa| json!({
+                    "title": pwa.title,
+                    "icon": pwa.icon,
+                    "theme_color": pwa.theme_color,
+                }))),
+            ),
+            ("output_style", json!(output_style)),
+            ("sort_passages", json!(sort_passages)),
+            ("placeholders", json!(self.placeholders)),
+            ("layout", json!(layout)),
+            ("title", json!(self.title)),
+            ("start", json!(self.start)),
+            ("zoom", json!(self.zoom)),
+            ("template", json!(self.template)),
+            ("baseline", json!(self.baseline)),
+            ("max_warnings", json!(self.max_warnings)),
+            ("html_report", json!(self.html_report)),
+            ("write_ifid", json!(self.write_ifid)),
+            ("max_links", json!(self.max_links)),
+            ("tag_max_links", json!(self.tag_max_links)),
+            ("chapters", json!(self.chapters)),
+            ("recap_tags", json!(self.recap_tags)),
+            ("links", json!(links)),
+            ("unlinked_tags", json!(self.unlinked_tags)),
+            ("unlinked_passages", json!(self.unlinked_passages)),
+            ("ending_tags", json!(self.ending_tags)),
+            ("smoke_test", json!(smoke_test)),
+            ("macros", json!(self.macros)),
+            ("dictionaries", json!(self.dictionaries)),
+            ("dictionary", json!(self.dictionary)),
+        ]
+    }
+
+    /// Whether the compiled story is written to standard output, which is
+    /// asked for with `--output -`. Warnings and errors then go to standard
+    /// error instead
+    pub fn writes_to_stdout(&self) -> bool {
+        self.output_file.as_deref() == Some("-")
+    }
+
+    /// Gets the path to write the compiled story to: the output file, or else
+    /// `<Story Title>.html` with the characters that aren't allowed in file
+    /// names replaced, in the output directory if one is given
+    ///
+    /// Proofing builds default to a separate `<Story Title> (proof).html`, so
+    /// they don't overwrite the playable story, and translations to
+    /// `<Story Title>.<lang>.html`
+    pub fn output_path(&self, story: &Story) -> PathBuf {
+        let file_name = match &self.output_file {
+            Some(output_file) => PathBuf::from(output_file),
+            None => {
+                let story_title = story.title.as_deref().unwrap_or("Untitled Story");
+                let mut suffix = if self.proof { " (proof)" } else { "" }.to_string();
+                if let Command::Translate(translate) = &self.command {
+                    suffix.push_str(&format!(".{}", translate.lang));
+                }
+                let file_name = format!("{}{}.html", story_title, suffix);
+                PathBuf::from(utils::sanitize_file_name(&file_name))
+            }
+        };
+        match &self.output_dir {
+            Some(output_dir) => output_dir.join(file_name),
+            None => file_name,
         }
     }
+
+    /// Gets the path of the proofing format to use for `--proof`: the one
+    /// named by `proofing_format` in the config file, or else the first
+    /// installed proofing format. A proofing format given by URL is at its
+    /// cache path, and is downloaded when it is loaded. See
+    /// [`formats::fetch_cached`]
+    ///
+    /// [`formats::fetch_cached`]: ../formats/fn.fetch_cached.html
+    fn proofing_format_file(config_file: &ConfigFile) -> Result<PathBuf> {
+        let key = match &config_file.proofing_format {
+            Some(request) if formats::is_url(request) => return formats::cache_path(request),
+            Some(request) => match StoryFormat::resolve_request(&config_file.formats, request)? {
+                Some(key) => key,
+                None => return Ok(request.into()),
+            },
+            None => StoryFormat::find_proofing(&config_file.formats).ok_or_else(|| {
+                eyre!(
+                    "No proofing format is installed. Install one (e.g., tweec format install \
+                     paperthin-1) or set proofing_format in the config file"
+                )
+            })?,
+        };
+        Ok(config_file.formats[key].clone())
+    }
+
+    /// Detects the story format from the `StoryData` passage in the given
+    /// inputs, returning the key of the matching installed format
+    ///
+    /// Returns `None` if there is no `StoryData` or it does not name a format
+    fn detect_format(
+        config_file: &ConfigFile,
+        inputs: &[String],
+        extensions: &[String],
+    ) -> Result<Option<String>> {
+        let story_data = match utils::read_story_data(inputs, extensions) {
+            Some(story_data) => story_data,
+            None => return Ok(None),
+        };
+        let name = match story_data.get("format").and_then(|f| f.as_str()) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let version = story_data.get("format-version").and_then(|v| v.as_str());
+        StoryFormat::resolve(&config_file.formats, name, version)
+            .map(|key| Some(key.to_string()))
+            .wrap_err_with(|| "Failed to detect story format from StoryData")
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -134,42 +1104,439 @@ pub struct FormatConfig {
     #[serde(default)]
     pub allow: Vec<String>,
     #[serde(default)]
+    pub warn: Vec<String>,
+    #[serde(default)]
     pub deny: Vec<String>,
+    #[serde(default)]
+    pub forbid: Vec<String>,
+    #[serde(default)]
+    pub max_links: Option<usize>,
+    #[serde(default)]
+    pub tag_max_links: HashMap<String, usize>,
+    #[serde(default)]
+    pub chapters: Option<Vec<String>>,
+    #[serde(default)]
+    pub recap_tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub links: Option<Vec<String>>,
+    #[serde(default)]
+    pub unlinked_tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub unlinked_passages: Option<Vec<String>>,
+    #[serde(default)]
+    pub ending_tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub smoke_test: Option<SmokeTest>,
+    #[serde(default)]
+    pub macros: Option<Vec<String>>,
+    #[serde(default)]
+    pub dictionaries: Option<Vec<String>>,
+    #[serde(default)]
+    pub dictionary: Option<String>,
+}
+
+impl FormatConfig {
+    /// Gets the levels given by the allow, warn, deny, and forbid lists
+    fn levels(&self) -> Vec<(String, LintLevel)> {
+        issue::lint_levels(&self.allow, &self.warn, &self.deny, &self.forbid)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BuildConfig {
+    #[serde(default)]
+    pub args: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ConfigFileInternal {
     pub format_paths: Vec<String>,
     pub format_configs: HashMap<String, FormatConfig>,
+    #[serde(default)]
+    pub proofing_format: Option<String>,
+    #[serde(default)]
+    pub extensions: Option<Vec<String>>,
+    #[serde(default)]
+    pub build: BuildConfig,
+    #[serde(default)]
+    pub opener: Option<Vec<String>>,
+}
+
+/// Builds a [`Config`] in code, for using tweec as a library
+///
+/// Nothing is read from the config file or the command line: settings that
+/// aren't given are as if tweec had no config file and no options. A
+/// [`ConfigFile`], [`ProjectConfig`], or [`CliConfig`] can be given to start
+/// from, and are layered as in [`Config::layer`]:
+///
+/// ```no_run
+/// use tweec::Config;
+///
+/// # fn main() -> color_eyre::Result<()> {
+/// let config = Config::builder()
+///     .inputs(["src"])
+///     .format_path("formats/harlowe-3/format.js")
+///     .deny(["DeadLink"])
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`CliConfig`]: struct.CliConfig.html
+/// [`Config`]: struct.Config.html
+/// [`Config::layer`]: struct.Config.html#method.layer
+/// [`ConfigFile`]: struct.ConfigFile.html
+/// [`ProjectConfig`]: ../project/struct.ProjectConfig.html
+#[derive(Default)]
+pub struct ConfigBuilder {
+    config_file: ConfigFile,
+    project_config: Option<ProjectConfig>,
+    cli_config: CliConfig,
+}
+
+impl ConfigBuilder {
+    /// Uses the given config file's formats and settings
+    pub fn config_file(mut self, config_file: ConfigFile) -> Self {
+        self.config_file = config_file;
+        self
+    }
+
+    /// Uses the given project config, below the other options given
+    pub fn project_config(mut self, project_config: ProjectConfig) -> Self {
+        self.project_config = Some(project_config);
+        self
+    }
+
+    /// Uses the given command line options, replacing any options given
+    /// before
+    pub fn cli_config(mut self, cli_config: CliConfig) -> Self {
+        self.cli_config = cli_config;
+        self
+    }
+
+    /// Sets what tweec should do (default: build)
+    pub fn command(mut self, command: Command) -> Self {
+        self.cli_config.command = command;
+        self
+    }
+
+    /// Sets the input files and directories, which may be glob patterns
+    pub fn inputs<I, S>(mut self, inputs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cli_config.inputs = inputs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the extensions of the Twee files read from input directories
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cli_config.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the story format by name, as `--format` does. It must be installed
+    /// in the config file's format paths
+    pub fn format(mut self, format: impl Into<String>) -> Self {
+        self.cli_config.format = Some(format.into());
+        self
+    }
+
+    /// Sets the story format by the path to its `format.js`
+    pub fn format_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.cli_config.format = Some(path.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Sets the output file, or `-` for standard output
+    pub fn output(mut self, output: impl Into<String>) -> Self {
+        self.cli_config.output_file = Some(output.into());
+        self
+    }
+
+    /// Sets the directory to write the output to
+    pub fn output_dir(mut self, output_dir: impl AsRef<Path>) -> Self {
+        self.cli_config.output_dir = Some(output_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets how to output warnings and errors
+    pub fn message_format(mut self, message_format: MessageFormat) -> Self {
+        self.cli_config.message_format = message_format;
+        self
+    }
+
+    /// Sets the most warnings allowed before linting fails
+    pub fn max_warnings(mut self, max_warnings: usize) -> Self {
+        self.cli_config.max_warnings = Some(max_warnings);
+        self
+    }
+
+    /// Sets whether to minify or indent the compiled HTML
+    pub fn output_style(mut self, output_style: OutputStyle) -> Self {
+        self.cli_config.output_style = output_style;
+        self
+    }
+
+    /// Sets whether to use color output (default: never)
+    pub fn color(mut self, use_color: ColorChoice) -> Self {
+        self.cli_config.use_color = use_color;
+        self
+    }
+
+    fn levels<I, S>(mut self, names: I, level: LintLevel) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cli_config
+            .levels
+            .extend(names.into_iter().map(|name| (name.into(), level)));
+        self
+    }
+
+    /// Ignores the given warnings, as `--allow` does
+    pub fn allow<I, S>(self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.levels(names, LintLevel::Allow)
+    }
+
+    /// Reports the given warnings as warnings, as `--warn` does
+    pub fn warn<I, S>(self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.levels(names, LintLevel::Warn)
+    }
+
+    /// Reports the given warnings as errors, as `--deny` does
+    pub fn deny<I, S>(self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.levels(names, LintLevel::Deny)
+    }
+
+    /// Reports the given warnings as errors that can't be given another
+    /// level, as `--forbid` does
+    pub fn forbid<I, S>(self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.levels(names, LintLevel::Forbid)
+    }
+
+    /// Layers the settings into a `Config`, checking that they can be used
+    /// together
+    pub fn build(self) -> Result<Config> {
+        let config = Config::layer(self.config_file, self.project_config, self.cli_config)?;
+        if config.should_open && config.writes_to_stdout() {
+            return Err(eyre!(
+                "Can't open the output in a browser when writing it to standard output"
+            ));
+        }
+        if config.assets_dir.is_some() && config.writes_to_stdout() {
+            return Err(eyre!(
+                "Can't copy media next to the output when writing it to standard output"
+            ));
+        }
+        if config.output_dir.is_some() && config.writes_to_stdout() {
+            return Err(eyre!(
+                "Can't write to an output directory when writing the output to standard output"
+            ));
+        }
+        if config.package.is_some() && config.writes_to_stdout() {
+            return Err(eyre!(
+                "Can't package the output when writing it to standard output"
+            ));
+        }
+        if config.pwa.is_some() && config.writes_to_stdout() {
+            return Err(eyre!(
+                "Can't make the output a web app when writing it to standard output"
+            ));
+        }
+        if config.source_map && config.writes_to_stdout() {
+            return Err(eyre!(
+                "Can't write a source map when writing the output to standard output"
+            ));
+        }
+        if config.check && config.watch {
+            return Err(eyre!("--check can't be used when watching the inputs"));
+        }
+        Ok(config)
+    }
 }
 
 /// Stores format paths and settings parsed from the tweec config file
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ConfigFile {
     /// Maps formats to paths based on the name of the containing directories
     pub formats: HashMap<String, std::path::PathBuf>,
 
-    /// Maps a format (or default) to a config to use for that format
-    pub format_configs: HashMap<String, FormatConfig>,
-}
+    /// Directories searched for story formats, with variables replaced
+    pub format_paths: Vec<PathBuf>,
+
+    /// Maps a format (or default) to a config to use for that format
+    pub format_configs: HashMap<String, FormatConfig>,
+
+    /// The proofing format to use with `--proof`, if configured
+    pub proofing_format: Option<String>,
+
+    /// Extensions of the Twee files read from input directories, if
+    /// configured
+    pub extensions: Option<Vec<String>>,
+
+    /// Command line arguments to use before any given by the user
+    pub build_args: Vec<String>,
+
+    /// The program and arguments to open the output with, if configured
+    pub opener: Option<Vec<String>>,
+}
+
+impl ConfigFile {
+    /// Loads the config file
+    ///
+    /// Reads `config.toml` from the config directory if it exists, and
+    /// otherwise `config.json`, which is commented JSON. If neither exists, a
+    /// default `config.json` is created. Also searches for all Twine 2 formats
+    /// in the paths specified by the config file, then in the Twine 2 desktop
+    /// app's format directories, adding any bundled formats that weren't
+    /// found. See [`formats::twine_format_paths`] and [`formats::add_bundled`]
+    ///
+    /// [`formats::add_bundled`]: ../formats/fn.add_bundled.html
+    /// [`formats::twine_format_paths`]: ../formats/fn.twine_format_paths.html
+    pub fn load() -> Result<Self> {
+        let config_dir = dirs_next::config_dir()
+            .ok_or_else(|| eyre!("Error getting config directory"))?
+            .join("tweec");
+        let toml_path = config_dir.join("config.toml");
+        let config_path = if toml_path.exists() {
+            toml_path
+        } else {
+            config_dir.join("config.json")
+        };
+        let cf = if config_path.extension().is_some_and(|ext| ext == "toml") {
+            ConfigFile::read_toml(&config_path)?
+        } else {
+            ConfigFile::read_json(&config_path)?
+        };
+        info!("Read config file {:?}", config_path);
+
+        let format_paths = cf
+            .format_paths
+            .iter()
+            .map(|p| expand_path(p))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Accumulator below needs its type to be specified, but it's long so
+        // alias it here. Formats downloaded through the Twine 2 desktop app
+        // are found after those in the configured paths
+        type Res = color_eyre::Result<HashMap<String, PathBuf>>;
+        let mut formats = format_paths
+            .iter()
+            .chain(&formats::twine_format_paths())
+            .try_fold(HashMap::new(), |mut acc, path_buf| -> Res {
+                let path = path_buf.to_string_lossy();
+                if !path_buf.exists() {
+                    // Continue without error if the path simply doesn't exist
+                    debug!("Skipping format path {}, which doesn't exist", path);
+                    return Ok(acc);
+                }
+
+                if !path_buf.is_dir() {
+                    // Continue without error if the path isn't a directory
+                    debug!("Skipping format path {}, which isn't a directory", path);
+                    return Ok(acc);
+                }
+                debug!("Searching format path {}", path);
+
+                let formats_dir = std::fs::read_dir(path_buf)
+                    .wrap_err_with(|| format!("Error while reading directory {}", path))?;
+                for entry in formats_dir {
+                    if entry.is_err() {
+                        continue;
+                    }
+
+                    let format_path = entry.ok().unwrap().path();
+                    if !format_path.is_dir() {
+                        continue;
+                    }
+
+                    let dir = std::fs::read_dir(format_path.clone());
+                    let dir = match dir {
+                        Ok(dir) => dir,
+                        Err(_) => continue,
+                    };
+
+                    for entry in dir {
+                        let entry = match entry {
+                            Ok(entry) => entry,
+                            Err(_) => continue,
+                        };
+                        if entry.file_name() == "format.js" {
+                            let dir_name = format_path.file_name().ok_or_else(|| {
+                                eyre!("Error getting directory name for path {}", path)
+                            })?;
+                            let dir_name = dir_name.to_string_lossy().into_owned();
+                            debug!("Found story format {} at {:?}", dir_name, entry.path());
+                            acc.entry(dir_name).or_insert_with(|| entry.path());
+                        }
+                    }
+                }
+
+                Ok(acc)
+            })?;
+        formats::add_bundled(&mut formats);
+
+        Ok(ConfigFile {
+            formats,
+            format_paths,
+            format_configs: cf.format_configs,
+            proofing_format: cf.proofing_format,
+            extensions: cf.extensions,
+            build_args: cf.build.args,
+            opener: cf.opener,
+        })
+    }
 
-impl ConfigFile {
-    /// Loads the config file
-    ///
-    /// If the config file does not exist, it will try to create a default one
-    /// in the config directory. Also searches for all Twine 2 formats in the
-    /// paths specified by the config file
-    pub fn load() -> Result<Self> {
-        let config_path = dirs_next::config_dir()
-            .ok_or_else(|| eyre!("Error getting config directory"))?
-            .join("tweec/config.json");
+    /// Reads a TOML config file
+    fn read_toml(config_path: &Path) -> Result<ConfigFileInternal> {
+        let contents = std::fs::read_to_string(config_path)
+            .wrap_err_with(|| format!("Error reading config file: {:?}", config_path))?;
+        toml::parse(&contents)
+            .and_then(|table| Ok(serde_json::from_value(Value::Object(table))?))
+            .wrap_err_with(|| format!("Error parsing config file: {:?}", config_path))
+    }
 
-        let config_contents = if !config_path.exists() {
-            let prefix = config_path.parent().unwrap();
+    /// Reads a commented JSON config file, creating the default one if it
+    /// doesn't exist
+    fn read_json(config_path: &Path) -> Result<ConfigFileInternal> {
+        let prefix = config_path.parent().unwrap();
+        if !config_path.exists() {
             std::fs::create_dir_all(prefix)
                 .wrap_err_with(|| format!("Error creating config directory: {:?}", prefix))?;
+        }
+
+        // Hold the lock while checking for and creating the default config, so
+        // that tweec processes started at the same time don't race to create it
+        let lock = utils::FileLock::acquire(config_path)
+            .wrap_err_with(|| format!("Error locking config file: {:?}", config_path))?;
+        let config_contents = if !config_path.exists() {
             let default_config = r#"// This file defines the configuration for tweec
 // It is mostly standard JSON, but supports //, /**/, and # style comments.
+// The same settings can be written as TOML in config.toml in this directory,
+// which is read instead of this file if it exists.
 //
 // For path related configuration, tweec defines several special variables that
 // can be used to specify locations:
@@ -180,7 +1547,9 @@ impl ConfigFile {
 //
 // Arbitrary environment variables are not currently supported
 {
-  // Directories to search for story formats in
+  // Directories to search for story formats in. The formats that came with
+  // the Twine 2 desktop app are found after these, where it is installed by
+  // default
   "format_paths": [
     "$TWEEC_DATA_DIR/storyformats",
     "$TWEEC_DATA_DIR/.storyformats",
@@ -197,19 +1566,101 @@ impl ConfigFile {
     // will be appended to the values given in default, but items not defined
     // will use the default config's value.
     "default": {
-      // Warnings to ignore ("allow")
+      // Warnings to ignore ("allow"), report ("warn"), or treat as errors
+      // ("deny"). Forbidden warnings ("forbid") are errors that more specific
+      // config, such as a story format's or the command line, can't allow.
+      // "all" names every warning
       "allow": [],
-      // Warnings to treat as errors ("deny")
-      "deny": []
+      "warn": [],
+      "deny": [],
+      "forbid": [],
+      // Maximum number of links in a passage before TooManyLinks is reported.
+      // Remove to disable the check
+      "max_links": 6,
+      // Per-tag overrides of max_links. If a passage has several of these tags,
+      // the highest limit is used
+      "tag_max_links": {
+        "hub": 12,
+        "menu": 12
+      },
+      // Chapter tags in story order. Links from a passage in one chapter back
+      // to an earlier chapter are reported as BackwardsChapterLink
+      "chapters": [],
+      // Passages with these tags may link back to earlier chapters
+      "recap_tags": [ "recap" ],
+      // Link syntaxes that count as links when building the link graph and
+      // checking for dead links: "simple" ([[A]]), "pipe" ([[a|A]]),
+      // "right-arrow" ([[a->A]]), "left-arrow" ([[A<-a]]), "link-macro"
+      // (SugarCube's <<link "a" "A">>), and "link-goto" (Harlowe's
+      // (link-goto: "a", "A"))
+      "links": [ "simple", "pipe", "right-arrow", "left-arrow" ],
+      // Passages with these tags, and passages with these names, are used
+      // without being linked to, so they are not reported as
+      // UnreachablePassage. Passages they link to count as reachable
+      "unlinked_tags": [ "widget", "script", "stylesheet" ],
+      "unlinked_passages": [],
+      // Passages with these tags are endings, so they are not reported as
+      // DeadEnd for having no links out
+      "ending_tags": [ "ending" ],
+      // Macros defined outside the story, such as by a library loaded by the
+      // story format, so they are not reported as UnknownMacro
+      "macros": [],
+      // Word lists to check the spelling of passage text against, one word
+      // per line, such as [ "/usr/share/dict/words" ]. Hunspell .dic files
+      // also work, but their affix rules are not used. Misspelled words are
+      // reported as Spelling, which is only checked when a list is given
+      "dictionaries": []
+      // The story's own word list, for names and invented words, which is
+      // checked along with dictionaries if it exists
+      // "dictionary": "$PWD/dictionary.txt"
+      // A command run on each compiled story, such as a headless browser, to
+      // check that the story format starts. {output} is replaced with the
+      // path of the compiled story. The build fails if the command fails or
+      // its output is missing any of expect, where {start} is replaced with
+      // the start passage's name. Usually set per format, as below
+      // "smoke_test": { "command": [], "expect": [], "timeout": 30 }
     },
     "sugarcube-2": {
       // SugarCube handles whitespace in links, so allow them when using it
-      "allow": [ "WhitespaceInLink" ]
+      "allow": [ "WhitespaceInLink" ],
+      "links": [ "simple", "pipe", "right-arrow", "left-arrow", "link-macro" ],
+      // SugarCube's special passages
+      "unlinked_passages": [
+        "StoryInit", "StoryCaption", "StoryMenu", "StoryBanner", "StorySubtitle",
+        "StoryAuthor", "StoryInterface", "StoryShare", "PassageReady",
+        "PassageDone", "PassageHeader", "PassageFooter"
+      ]
+      // Checks that the start passage renders, using headless Chromium
+      // "smoke_test": {
+      //   "command": [ "chromium", "--headless", "--virtual-time-budget=5000",
+      //                "--dump-dom", "file://{output}" ],
+      //   "expect": [ "data-passage=\"{start}\"" ]
+      // }
+    },
+    "harlowe-3": {
+      "links": [ "simple", "pipe", "right-arrow", "left-arrow", "link-goto" ]
     }
+  },
+  // Proofing format to build with when --proof is given (e.g., paperthin-1),
+  // or the URL of its format.js, which is downloaded once and cached. If not
+  // set, the first installed proofing format is used
+  // "proofing_format": "paperthin-1",
+  // Extensions of the Twee files read from input directories, which
+  // --extension overrides. Other files in those directories are left out
+  // "extensions": [ "tw", "twee" ],
+  // Program and arguments to open the output with for --open, in place of
+  // the system's default, which --open-with overrides. {output} is replaced
+  // with the path of the compiled story, or the path is added at the end
+  // "opener": [ "firefox", "--private-window", "{output}" ],
+  "build": {
+    // Arguments to add before those given on the command line, e.g.
+    // [ "--compact", "--deny", "DeadLink" ]. Arguments given on the command
+    // line take precedence
+    "args": []
   }
 }"#;
-            let mut config_file = File::create(config_path)?;
-            config_file.write_all(default_config.as_bytes())?;
+            utils::write_atomic(config_path, default_config.as_bytes())
+                .wrap_err_with(|| format!("Error creating config file: {:?}", config_path))?;
 
             default_config.to_string()
         } else {
@@ -219,236 +1670,1016 @@ impl ConfigFile {
             config_file.read_to_string(&mut contents)?;
             contents
         };
+        drop(lock);
+
         // Strip the comments from the input (use `as_bytes()` to get a `Read`).
         let stripped = StripComments::new(config_contents.as_bytes());
         // Parse the string of data into serde_json::Value.
-        let cf: ConfigFileInternal = serde_json::from_reader(stripped)?;
-
-        // Accumulator below needs its type to be specified, but it's long so
-        // alias it here
-        type Res = color_eyre::Result<HashMap<String, PathBuf>>;
-        let formats = cf
-            .format_paths
-            .iter()
-            .fold(Ok(HashMap::new()), |acc: Res, p| {
-                // If something has already failed, continue failing
-                let mut acc = acc?;
-                let mut path = p.clone();
-
-                // Loop over any variables to replace them
-                while let Some(start) = path.find('$') {
-                    let end = match path[start..].find('/') {
-                        Some(pos) => pos,
-                        None => path.len(),
-                    };
-
-                    // Including the $
-                    let var = &path[start..end];
-
-                    // Excluding the $
-                    let var_name = &var[1..];
-                    let replace = match var_name {
-                        "HOME" => dirs_next::home_dir().ok_or_else(|| eyre!("Failed to get HOME")),
-                        "PWD" => std::env::current_dir().wrap_err_with(|| "Failed to get PWD"),
-                        "TWEEC_BIN_DIR" => match std::env::current_exe() {
-                            Ok(ok) => ok
-                                .parent()
-                                .map(|p| p.to_path_buf())
-                                .ok_or_else(|| eyre!("Failed to get tweec executable's parent")),
-                            Err(err) => Err(err).wrap_err_with(|| "Failed to get TWEEC_BIN_DIR"),
-                        },
-                        "TWEEC_DATA_DIR" => dirs_next::data_dir()
-                            .ok_or_else(|| eyre!("Failed to get TWEEC_DATA_DIR")),
-                        _ => Err(eyre!(
-                            "Arbitrary environment variables are not currently supported"
-                        )),
-                    }
-                    .map(|p| p.into_os_string().to_string_lossy().into_owned())
-                    .wrap_err_with(|| format!("Error while parsing {}", p))?;
-                    path = path.replace(var, &replace);
-                }
-
-                let path_buf: PathBuf = path.clone().into();
-                if !path_buf.exists() {
-                    // Continue without error if the path simply doesn't exist
-                    // TODO: consider warning user
-                    return Ok(acc);
-                }
-
-                if !path_buf.is_dir() {
-                    // Continue without error if the path isn't a directory
-                    // TODO: consider warning user
-                    return Ok(acc);
-                }
-
-                let formats_dir = std::fs::read_dir(path_buf)
-                    .wrap_err_with(|| format!("Error while reading directory {}", path))?;
-                for entry in formats_dir {
-                    if entry.is_err() {
-                        continue;
-                    }
-
-                    let format_path = entry.ok().unwrap().path();
-                    if !format_path.is_dir() {
-                        continue;
-                    }
+        Ok(serde_json::from_reader(stripped)?)
+    }
+}
 
-                    let dir = std::fs::read_dir(format_path.clone());
-                    let dir = match dir {
-                        Ok(dir) => dir,
-                        Err(_) => continue,
-                    };
+/// Replaces the special variables (`$HOME`, `$PWD`, `$TWEEC_BIN_DIR`, and
+/// `$TWEEC_DATA_DIR`) in a path from the config file
+fn expand_path(p: &str) -> Result<PathBuf> {
+    let mut path = p.to_string();
 
-                    for entry in dir {
-                        let entry = match entry {
-                            Ok(entry) => entry,
-                            Err(_) => continue,
-                        };
-                        if entry.file_name() == "format.js" {
-                            let dir_name = format_path.file_name().ok_or_else(|| {
-                                eyre!("Error getting directory name for path {}", path)
-                            })?;
-                            let dir_name = dir_name.to_string_lossy().into_owned();
-                            acc.entry(dir_name).or_insert_with(|| entry.path());
-                        }
-                    }
-                }
+    // Loop over any variables to replace them
+    while let Some(start) = path.find('$') {
+        let end = match path[start..].find('/') {
+            Some(pos) => pos,
+            None => path.len(),
+        };
 
-                Ok(acc)
-            })?;
+        // Including the $
+        let var = &path[start..end];
 
-        Ok(ConfigFile {
-            formats,
-            format_configs: cf.format_configs,
-        })
+        // Excluding the $
+        let var_name = &var[1..];
+        let replace = match var_name {
+            "HOME" => dirs_next::home_dir().ok_or_else(|| eyre!("Failed to get HOME")),
+            "PWD" => std::env::current_dir().wrap_err_with(|| "Failed to get PWD"),
+            "TWEEC_BIN_DIR" => match std::env::current_exe() {
+                Ok(ok) => ok
+                    .parent()
+                    .map(|p| p.to_path_buf())
+                    .ok_or_else(|| eyre!("Failed to get tweec executable's parent")),
+                Err(err) => Err(err).wrap_err_with(|| "Failed to get TWEEC_BIN_DIR"),
+            },
+            "TWEEC_DATA_DIR" => {
+                dirs_next::data_dir().ok_or_else(|| eyre!("Failed to get TWEEC_DATA_DIR"))
+            }
+            _ => Err(eyre!(
+                "Arbitrary environment variables are not currently supported"
+            )),
+        }
+        .map(|p| p.into_os_string().to_string_lossy().into_owned())
+        .wrap_err_with(|| format!("Error while parsing {}", p))?;
+        path = path.replace(var, &replace);
     }
+
+    Ok(path.into())
 }
 
 /// The command line options supplied by the user
 pub struct CliConfig {
-    /// If true, lint then exit
-    pub linting: bool,
+    /// What tweec should do
+    pub command: Command,
 
     /// Input files or directories to lint/compile
     pub inputs: Vec<String>,
 
+    /// Extensions of the Twee files to read from input directories, if given
+    pub extensions: Vec<String>,
+
     /// The story format name or path
     pub format: Option<String>,
 
     /// The html file name to output. Defaults to <story name>.html
     pub output_file: Option<String>,
 
+    /// The directory to write the output to, if given
+    pub output_dir: Option<PathBuf>,
+
     /// If true, send the output file to `opener` for the user
     pub should_open: bool,
 
-    /// List of allowed (ignored) warnings, by name
-    pub allowed: Vec<String>,
+    /// The program to open the output file with, if given
+    pub open_with: Option<String>,
 
-    /// List of denied (treated as errors) warnings, by name
-    pub denied: Vec<String>,
+    /// Levels of warnings by name, in the order they were given
+    pub levels: Vec<(String, LintLevel)>,
 
     /// Controls color output
     pub use_color: ColorChoice,
 
-    /// If true, use compact warning and error output
-    pub compact: bool,
+    /// How to output warnings and errors
+    pub message_format: MessageFormat,
+
+    /// If true, fold repeats of the same warning into a summary line
+    pub fold: bool,
+
+    /// If true, write generated IFIDs back into the story source
+    pub write_ifid: bool,
+
+    /// If true, rerun whenever the inputs change
+    pub watch: bool,
+
+    /// If true, build with a proofing format
+    pub proof: bool,
+
+    /// If true, fix repeated tags and metadata keys before linting
+    pub fix: bool,
+
+    /// If true, validate the compiled HTML
+    pub validate_html: bool,
+
+    /// If true, inline local images into the output
+    pub embed_images: bool,
+
+    /// Directory to copy local media into, if given
+    pub assets_dir: Option<PathBuf>,
+
+    /// If true, write a source map next to the output
+    pub source_map: bool,
+
+    /// If true, build without writing anything
+    pub check: bool,
+
+    /// If true, overwrite output files that tweec didn't write
+    pub force: bool,
+
+    /// How to package the compiled story, if it should be
+    pub package: Option<PackageConfig>,
+
+    /// If true, make the compiled story installable as a web app
+    pub pwa: bool,
+
+    /// Whether to minify or indent the output
+    pub output_style: OutputStyle,
+
+    /// The order of the passages in the output
+    pub sort_passages: SortPassages,
+
+    /// Values of custom placeholders, by name
+    pub placeholders: Vec<(String, String)>,
+
+    /// Where to place passages in the output
+    pub layout: Layout,
+
+    /// Title to build the story with, if given
+    pub title: Option<String>,
+
+    /// Passage to start the story at, if given
+    pub start: Option<String>,
+
+    /// Zoom level to build the story with, if given
+    pub zoom: Option<f32>,
+
+    /// HTML shell to use in place of the format's source, if given
+    pub template: Option<PathBuf>,
+
+    /// Baseline file of known warnings, if given
+    pub baseline: Option<PathBuf>,
+
+    /// File to write a baseline of the current warnings to, if given
+    pub write_baseline: Option<PathBuf>,
+
+    /// Most warnings allowed before linting fails, if given
+    pub max_warnings: Option<usize>,
+
+    /// HTML file to write a report of the warnings and errors to, if given
+    pub html_report: Option<PathBuf>,
+
+    /// If true, download story formats given by URL even if they are cached
+    pub refresh_formats: bool,
+
+    /// How much to log, from `-v` and `-q`
+    pub log_level: LevelFilter,
+}
+
+impl Default for CliConfig {
+    /// The options when none are given
+    fn default() -> Self {
+        CliConfig {
+            command: Command::Build,
+            inputs: Vec::new(),
+            extensions: Vec::new(),
+            format: None,
+            output_file: None,
+            output_dir: None,
+            should_open: false,
+            open_with: None,
+            levels: Vec::new(),
+            use_color: ColorChoice::Never,
+            message_format: MessageFormat::Human,
+            fold: true,
+            write_ifid: false,
+            watch: false,
+            proof: false,
+            fix: false,
+            validate_html: false,
+            embed_images: false,
+            assets_dir: None,
+            source_map: false,
+            check: false,
+            force: false,
+            package: None,
+            pwa: false,
+            output_style: OutputStyle::Normal,
+            sort_passages: SortPassages::File,
+            placeholders: Vec::new(),
+            layout: Layout::Keep,
+            title: None,
+            start: None,
+            zoom: None,
+            template: None,
+            baseline: None,
+            write_baseline: None,
+            max_warnings: None,
+            html_report: None,
+            refresh_formats: false,
+            log_level: logging::DEFAULT_LEVEL,
+        }
+    }
+}
+
+/// Maximum depth of response files including other response files
+const MAX_RESPONSE_FILE_DEPTH: usize = 8;
+
+/// Expands any `@file` arguments into the arguments contained in that file
+///
+/// Response files contain whitespace separated arguments, which may be quoted
+/// with `"` or `'`. Lines starting with `#` are comments. Response files may
+/// refer to other response files, and `@@` escapes a literal leading `@`
+fn expand_response_files(args: Vec<String>, depth: usize) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for arg in args {
+        if arg.starts_with("@@") {
+            expanded.push(arg[1..].to_string());
+            continue;
+        }
+        let path = match arg.strip_prefix('@') {
+            Some(path) if !path.is_empty() => path,
+            _ => {
+                expanded.push(arg);
+                continue;
+            }
+        };
+        if depth >= MAX_RESPONSE_FILE_DEPTH {
+            return Err(eyre!(
+                "Response files nested too deeply while reading {}",
+                path
+            ));
+        }
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Error reading response file {}", path))?;
+        let args = split_response_file(&contents)
+            .wrap_err_with(|| format!("Error parsing response file {}", path))?;
+        expanded.append(&mut expand_response_files(args, depth + 1)?);
+    }
+    Ok(expanded)
+}
+
+/// Splits the contents of a response file into arguments
+fn split_response_file(contents: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for line in contents.lines() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut arg: Option<String> = None;
+        let mut quote = None;
+        for c in line.chars() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+                (None, '"') | (None, '\'') => {
+                    quote = Some(c);
+                    arg.get_or_insert_with(String::new);
+                }
+                (None, c) if c.is_whitespace() => args.extend(arg.take()),
+                (None, c) => arg.get_or_insert_with(String::new).push(c),
+            }
+        }
+        if quote.is_some() {
+            return Err(eyre!("Unterminated quote in: {}", line));
+        }
+        args.extend(arg);
+    }
+    Ok(args)
 }
 
 impl CliConfig {
-    /// Parses the command line arguments
-    pub fn from_args() -> Self {
+    /// Parses the command line arguments, preceded by the given arguments
+    /// from the config file
+    ///
+    /// Arguments of the form `@file` are replaced with the arguments read from
+    /// that file
+    pub fn from_args(build_args: &[String]) -> Result<Self> {
+        let mut env_args = std::env::args();
+        let name = env_args.next();
+        let args = build_args.iter().cloned().chain(env_args).collect();
+        let args = expand_response_files(args, 0)?;
+        CliConfig::from_iter(name.into_iter().chain(args))
+    }
+
+    /// Parses the given arguments, the first of which is the program name
+    ///
+    /// Running without a subcommand is the same as running `build`, so the
+    /// build options are accepted both at the top level and under `build`.
+    /// Options given at the top level also apply to any subcommand that
+    /// accepts them
+    ///
+    /// Invalid arguments, and requests for help or the version, are returned
+    /// as a `clap::Error` holding the message to show
+    pub fn from_iter<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let input = || {
+            Arg::with_name("INPUT")
+                .help(
+                    "Sets the input file(s), directory(s), or glob pattern(s) such as \
+                     \"src/**/*.twee\" to use, or - for standard input (default: the inputs \
+                     in tweec.toml)",
+                )
+                .multiple(true)
+        };
+
         #[allow(deprecated)]
         let m = App::new(crate_name!())
             .about(crate_description!())
-            .author(crate_authors!("\n"))
+            .author(env!("CARGO_PKG_AUTHORS"))
             .version(crate_version!())
+            .setting(AppSettings::SubcommandsNegateReqs)
+            .setting(AppSettings::AllArgsOverrideSelf)
             .arg(
-                Arg::with_name("allow")
-                    .help("Specifies warnings to ignore. Overrides deny.")
-                    .short("a")
-                    .long("allow")
-                    .takes_value(true)
-                    .multiple(true),
+                Arg::with_name("verbose")
+                    .help(
+                        "Logs what tweec is doing, such as the config files read and the files \
+                         parsed. Give twice for more detail",
+                    )
+                    .short("v")
+                    .long("verbose")
+                    .multiple(true)
+                    .global(true),
             )
             .arg(
-                Arg::with_name("color")
-                    .help("Turns on colored output")
-                    .long("color")
-                    .takes_value(true),
+                Arg::with_name("quiet")
+                    .help("Only logs errors")
+                    .short("q")
+                    .long("quiet")
+                    .conflicts_with("verbose")
+                    .global(true),
             )
-            .arg(
-                Arg::with_name("compact")
-                    .help("Turns on compact error and warning output")
-                    .long("compact"),
+            .subcommand(
+                SubCommand::with_name("build")
+                    .about("Compiles the story to HTML (the default)")
+                    .args(&build_args())
+                    .arg(input().index(1)),
             )
-            .arg(
-                Arg::with_name("deny")
-                    .help("Specifies warnings to treat as errors")
-                    .short("D")
-                    .long("deny")
-                    .takes_value(true)
-                    .multiple(true),
+            .subcommand(
+                SubCommand::with_name("lint")
+                    .about("Runs the linter without producing any output")
+                    .args(&lint_args())
+                    .arg(watch_arg())
+                    .arg(
+                        Arg::with_name("rules")
+                            .help(
+                                "Lists the rules that would run and how each would be treated, \
+                                 without linting",
+                            )
+                            .long("rules")
+                            .conflicts_with("watch"),
+                    )
+                    .arg(
+                        Arg::with_name("list-rules")
+                            .help(
+                                "Lists every warning and error tweec knows, with its default \
+                                 level and what it checks for, without reading the story",
+                            )
+                            .long("list-rules")
+                            .conflicts_with_all(&["watch", "rules"]),
+                    )
+                    .arg(
+                        Arg::with_name("fix")
+                            .help(
+                                "Removes repeated tags and metadata keys from passage headers \
+                                 before linting",
+                            )
+                            .long("fix")
+                            .conflicts_with_all(&["watch", "rules", "list-rules"]),
+                    )
+                    .arg(
+                        Arg::with_name("write-baseline")
+                            .help(
+                                "Writes the current warnings to a baseline file, so that \
+                                 later runs with --baseline only report new ones",
+                            )
+                            .long("write-baseline")
+                            .value_name("FILE")
+                            .takes_value(true)
+                            .conflicts_with_all(&["watch", "rules", "list-rules", "baseline"]),
+                    )
+                    .arg(
+                        Arg::with_name("report")
+                            .help(
+                                "Also writes the warnings and errors to a standalone HTML \
+                                 report, grouped by file with source snippets",
+                            )
+                            .long("report")
+                            .value_name("FILE")
+                            .takes_value(true)
+                            .conflicts_with_all(&["rules", "list-rules"]),
+                    )
+                    .arg(input().index(1)),
             )
-            .arg(
-                Arg::with_name("format")
-                    .help("Sets the story format by name (e.g., sugarcube-2) or file location")
-                    .short("f")
-                    .long("format")
-                    .takes_value(true),
+            .subcommand(
+                SubCommand::with_name("watch")
+                    .about("Compiles the story, then recompiles it whenever the inputs change")
+                    .args(&build_args())
+                    .arg(input().index(1)),
             )
-            .arg(
-                Arg::with_name("lint")
-                    .help("Runs the linter without producing any output")
-                    .short("L")
-                    .long("lint"),
+            .subcommand(
+                SubCommand::with_name("decompile")
+                    .about(
+                        "Converts a compiled Twine 2 HTML story, or a Twine 1 HTML story or \
+                         .tws archive, back into Twee",
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .help("Sets the output file (default: standard output)")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("minimal-metadata")
+                            .help(
+                                "Leaves out metadata only used by the Twine editor, such as \
+                                 zoom, tag colors, and passage positions",
+                            )
+                            .long("minimal-metadata"),
+                    )
+                    .arg(
+                        Arg::with_name("split")
+                            .help(
+                                "Reads every story in a Twine 2 archive, writing each to \
+                                 <Story Title>/story.twee in the output directory (default: the \
+                                 current directory)",
+                            )
+                            .long("split"),
+                    )
+                    .arg(layout_arg().conflicts_with("minimal-metadata"))
+                    .arg(
+                        Arg::with_name("INPUT")
+                            .help("Sets the HTML or .tws file to decompile")
+                            .required(true)
+                            .index(1),
+                    ),
             )
-            .arg(
-                Arg::with_name("open")
-                    .help("Opens the html output in a web browser")
-                    .long("open")
-                    .conflicts_with("lint"),
+            .subcommand(
+                SubCommand::with_name("archive")
+                    .about(
+                        "Bundles several stories into a Twine 2 archive for importing into the \
+                         Twine editor",
+                    )
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("output")
+                            .help("Sets the output file (default: standard output)")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("INPUT")
+                            .help("Sets the stories to bundle, each a Twee file or directory")
+                            .required(true)
+                            .multiple(true)
+                            .index(1),
+                    ),
             )
-            .arg(
-                Arg::with_name("output")
-                    .help("Sets the output file (default: <Story Title>.html")
-                    .short("o")
-                    .long("output")
-                    .takes_value(true)
-                    .conflicts_with("lint"),
+            .subcommand(
+                SubCommand::with_name("report")
+                    .about(
+                        "Writes the story's issues, grouped by file and rule, for import into \
+                         an issue tracker",
+                    )
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("report-format")
+                            .help("Sets the report format (default: markdown)")
+                            .long("report-format")
+                            .possible_values(&["markdown", "json"])
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("denied-only")
+                            .help("Only reports errors, such as denied warnings")
+                            .long("denied-only"),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .help("Sets the output file (default: standard output)")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(input().index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("package")
+                    .about(
+                        "Compiles the story and bundles it with its assets as index.html, ready \
+                         to upload to itch.io",
+                    )
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("zip")
+                            .help("Writes a zip file instead of a directory")
+                            .long("zip"),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .help(
+                                "Sets the output zip file or directory (default: <Story \
+                                 Title>.zip or <Story Title>)",
+                            )
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("assets")
+                            .help(
+                                "Copies a file or directory into the package, keeping its name \
+                                 so relative paths in the story still work",
+                            )
+                            .long("assets")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .multiple(true),
+                    )
+                    .arg(
+                        Arg::with_name("cover")
+                            .help("Includes a PNG, JPEG, or GIF cover image as cover.<ext>")
+                            .long("cover")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("metadata")
+                            .help(
+                                "Includes a metadata.json with the story's title, IFID, and \
+                                 story format",
+                            )
+                            .long("metadata"),
+                    )
+                    .arg(itch_toml_arg())
+                    .arg(
+                        Arg::with_name("proof")
+                            .help("Packages the story built with the configured proofing format")
+                            .long("proof"),
+                    )
+                    .arg(input().index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("config")
+                    .about("Inspects tweec's configuration")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("show")
+                            .about(
+                                "Prints the configuration resolved from the config file, the \
+                                 project's tweec.toml, and the other options given",
+                            )
+                            .args(&build_args())
+                            .arg(
+                                Arg::with_name("json")
+                                    .help("Prints the configuration as JSON")
+                                    .long("json"),
+                            )
+                            .arg(input().index(1)),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("stats")
+                    .about("Prints statistics about the story")
+                    .arg(
+                        Arg::with_name("authors")
+                            .help(
+                                "Lists when and by whom each passage was last changed, oldest \
+                                 first, using git blame",
+                            )
+                            .long("authors"),
+                    )
+                    .arg(
+                        Arg::with_name("stats-format")
+                            .help("Sets the output format (default: table)")
+                            .long("format")
+                            .possible_values(&["table", "json"])
+                            .takes_value(true),
+                    )
+                    .arg(input().index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("graph")
+                    .about("Writes the passage link graph")
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("graph-format")
+                            .help("Sets the graph format (default: dot)")
+                            .long("graph-format")
+                            .possible_values(&["dot", "mermaid", "json"])
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .help("Sets the output file (default: standard output)")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(input().index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("i18n")
+                    .about("Builds translations of the story")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("build")
+                            .about(
+                                "Compiles the story with the translations from a gettext \
+                                 catalog, writing <Story Title>.<LANG>.html",
+                            )
+                            .args(&build_args())
+                            .arg(
+                                Arg::with_name("lang")
+                                    .help("Sets the language to build (e.g., fr)")
+                                    .long("lang")
+                                    .value_name("LANG")
+                                    .takes_value(true)
+                                    .required(true),
+                            )
+                            .arg(
+                                Arg::with_name("catalog")
+                                    .help("Sets the catalog to read (default: i18n/<LANG>.po)")
+                                    .long("catalog")
+                                    .takes_value(true),
+                            )
+                            .arg(input().index(1)),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("export")
+                    .about("Writes the parsed story as JSON for other tools")
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("export-format")
+                            .help("Sets the export format (default: json)")
+                            .long("export-format")
+                            .possible_values(&["json"])
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .help("Sets the output file (default: standard output)")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(input().index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("test")
+                    .about("Walks scripted playthroughs of the story and checks where they lead")
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("tests")
+                            .help(
+                                "Sets the test scripts to run, or directories of them (default: \
+                                 tests)",
+                            )
+                            .short("t")
+                            .long("tests")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1),
+                    )
+                    .arg(input().index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("fix")
+                    .about(
+                        "Applies suggested fixes to the story's source files, such as trimming \
+                         whitespace around link targets",
+                    )
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("aggressive")
+                            .help(
+                                "Also applies fixes that may be wrong, such as changing a dead \
+                                 link to the passage with the most similar name",
+                            )
+                            .long("aggressive"),
+                    )
+                    .arg(
+                        Arg::with_name("dry-run")
+                            .help("Prints a diff of each file instead of changing it")
+                            .long("dry-run"),
+                    )
+                    .arg(input().index(1)),
             )
+            .subcommand(
+                SubCommand::with_name("daemon")
+                    .about(
+                        "Keeps the story loaded and answers lint, compile, and graph requests \
+                         over a local socket",
+                    )
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("port")
+                            .help("Sets the port to listen on (default: any free port)")
+                            .short("p")
+                            .long("port")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .help("Sets the output file, or - for standard output (default: <Story Title>.html)")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(output_dir_arg())
+                    .arg(force_arg())
+                    .arg(input().index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("formats")
+                    .about("Lists the story formats found in the configured format paths")
+                    .setting(AppSettings::Hidden),
+            )
+            .subcommand(
+                SubCommand::with_name("format")
+                    .about("Lists, installs, removes, or checks story formats")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("list")
+                            .about("Lists the story formats found in the configured format paths"),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("install")
+                            .about(
+                                "Downloads a story format and installs it to a configured \
+                                 format path",
+                            )
+                            .arg(
+                                Arg::with_name("dir")
+                                    .help(
+                                        "Sets the directory to install to (default: the first \
+                                         configured format path)",
+                                    )
+                                    .short("d")
+                                    .long("dir")
+                                    .takes_value(true),
+                            )
+                            .arg(
+                                Arg::with_name("FORMAT")
+                                    .help(
+                                        "The format to install: a known format with an optional \
+                                         version (e.g., sugarcube-2@2.36.1), or the URL or path \
+                                         of a format.js",
+                                    )
+                                    .required(true)
+                                    .index(1),
+                            ),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("remove")
+                            .about("Removes an installed story format")
+                            .arg(
+                                Arg::with_name("FORMAT")
+                                    .help(
+                                        "The format to remove, by directory name (e.g., \
+                                         sugarcube-2) or name with an optional version",
+                                    )
+                                    .required(true)
+                                    .index(1),
+                            ),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("check")
+                            .about(
+                                "Checks that a format.js is a valid story format, reporting \
+                                 every problem found",
+                            )
+                            .arg(
+                                Arg::with_name("FORMAT")
+                                    .help("The path of the format.js to check")
+                                    .required(true)
+                                    .index(1),
+                            ),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("extract")
+                    .about("Copies a passage and its linked neighborhood into a standalone story")
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("depth")
+                            .help("Number of links to follow out from the passage (default: 1)")
+                            .short("d")
+                            .long("depth")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .help("Sets the output directory (default: excerpt)")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("PASSAGE")
+                            .help("Name of the passage to extract")
+                            .required(true)
+                            .index(1),
+                    )
+                    .arg(input().index(2)),
+            )
+            .subcommand(
+                SubCommand::with_name("explain")
+                    .about("Explains a warning or error, with an example of how to fix it")
+                    .arg(
+                        Arg::with_name("RULE")
+                            .help("The name of the warning or error, such as DeadLink")
+                            .required(true)
+                            .index(1),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("lsp")
+                    .about("Runs a language server that reports warnings and errors to an editor")
+                    .args(&lint_args())
+                    .arg(
+                        Arg::with_name("INPUT")
+                            .help(
+                                "Sets the input file(s) or directory(s) to use (default: the \
+                                 workspace root)",
+                            )
+                            .multiple(true)
+                            .index(1),
+                    ),
+            )
+            .args(&build_args())
             .arg(
-                Arg::with_name("INPUT")
-                    .help("Sets the input file(s) or directory(s) to use")
-                    .required(true)
-                    .multiple(true)
-                    .index(1),
+                Arg::with_name("lint")
+                    .help("Runs the linter without producing any output")
+                    .short("L")
+                    .long("lint")
+                    .conflicts_with_all(&["open", "open-with", "output", "write-ifid"]),
             )
-            .get_matches();
+            .arg(input().index(1))
+            .get_matches_from_safe(args)?;
 
-        let linting = m.is_present("lint");
-        let inputs: Vec<String> = m
-            .values_of("INPUT")
-            .unwrap()
-            .map(|s| s.to_string())
-            .collect();
-        let format = m.value_of("format").map(|s| s.to_string());
-        let output_file = m.value_of("output").map(|s| s.to_string());
-        let should_open = m.is_present("open");
-        let allowed = m
-            .values_of("allow")
-            .unwrap_or_default()
-            .map(|s| s.to_string())
-            .collect();
-        let denied = m
-            .values_of("deny")
-            .unwrap_or_default()
-            .map(|s| s.to_string())
-            .collect();
-        let use_color = match m.value_of("color").unwrap_or("auto") {
+        let (subcommand, sub) = m.subcommand();
+        // `config show` takes its options one level further down
+        let sub = match (subcommand, sub) {
+            ("config", Some(config)) => config.subcommand().1,
+            ("i18n", Some(i18n)) => i18n.subcommand().1,
+            _ => sub,
+        };
+        let matches = Matches { top: &m, sub };
+
+        let command = match subcommand {
+            "lint" if matches.is_present("rules") => Command::Rules,
+            "lint" if matches.is_present("list-rules") => Command::ListRules,
+            "lint" => Command::Lint,
+            "formats" => Command::Format(FormatCommand::List),
+            "format" => {
+                let (action, format) = sub.unwrap().subcommand();
+                let name = || format.unwrap().value_of("FORMAT").unwrap().to_string();
+                Command::Format(match action {
+                    "install" => FormatCommand::Install {
+                        source: name(),
+                        dir: format.unwrap().value_of("dir").map(PathBuf::from),
+                    },
+                    "remove" => FormatCommand::Remove(name()),
+                    "check" => FormatCommand::Check(PathBuf::from(name())),
+                    _ => FormatCommand::List,
+                })
+            }
+            "daemon" => Command::Daemon(
+                matches
+                    .value_of("port")
+                    .and_then(|p| p.parse().ok())
+                    .unwrap_or(0),
+            ),
+            "explain" => Command::Explain(sub.unwrap().value_of("RULE").unwrap().to_string()),
+            "lsp" => Command::Lsp,
+            "report" => Command::Report(ReportConfig {
+                format: match matches.value_of("report-format") {
+                    Some("json") => ReportFormat::Json,
+                    _ => ReportFormat::Markdown,
+                },
+                output: matches.value_of("output").map(PathBuf::from),
+                errors_only: matches.is_present("denied-only"),
+            }),
+            "package" => Command::Package(PackageConfig {
+                zip: matches.is_present("zip"),
+                output: matches.value_of("output").map(PathBuf::from),
+                assets: matches
+                    .values_of("assets")
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect(),
+                cover: matches.value_of("cover").map(PathBuf::from),
+                metadata: matches.is_present("metadata"),
+                itch_toml: matches.is_present("itch-toml"),
+                copied_assets: None,
+            }),
+            "graph" => Command::Graph(GraphConfig {
+                format: match matches.value_of("graph-format") {
+                    Some("mermaid") => GraphFormat::Mermaid,
+                    Some("json") => GraphFormat::Json,
+                    _ => GraphFormat::Dot,
+                },
+                output: matches.value_of("output").map(PathBuf::from),
+            }),
+            "export" => Command::Export(ExportConfig {
+                format: ExportFormat::Json,
+                output: matches.value_of("output").map(PathBuf::from),
+            }),
+            "test" => {
+                let mut scripts: Vec<PathBuf> = matches
+                    .values_of("tests")
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect();
+                if scripts.is_empty() {
+                    scripts.push(playtest::DEFAULT_DIR.into());
+                }
+                Command::Test(TestConfig { scripts })
+            }
+            "fix" => Command::Fix(FixConfig {
+                aggressive: matches.is_present("aggressive"),
+                dry_run: matches.is_present("dry-run"),
+            }),
+            "config" => Command::ShowConfig {
+                json: matches.is_present("json"),
+            },
+            "stats" => Command::Stats(StatsConfig {
+                authors: matches.is_present("authors"),
+                format: match matches.value_of("stats-format") {
+                    Some("json") => StatsFormat::Json,
+                    _ => StatsFormat::Table,
+                },
+            }),
+            "decompile" => Command::Decompile(DecompileConfig {
+                input: matches.value_of("INPUT").unwrap().into(),
+                output: matches.value_of("output").map(PathBuf::from),
+                minimal_metadata: matches.is_present("minimal-metadata"),
+                split: matches.is_present("split"),
+                layout: layout(&matches),
+            }),
+            "archive" => Command::Archive(ArchiveConfig {
+                output: matches.value_of("output").map(PathBuf::from),
+            }),
+            "i18n" => {
+                let lang = matches.value_of("lang").unwrap().to_string();
+                Command::Translate(TranslateConfig {
+                    catalog: matches
+                        .value_of("catalog")
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| Path::new("i18n").join(format!("{}.po", lang))),
+                    lang,
+                })
+            }
+            "extract" => Command::Extract(ExtractConfig {
+                passage: matches.value_of("PASSAGE").unwrap().to_string(),
+                depth: matches
+                    .value_of("depth")
+                    .and_then(|d| d.parse().ok())
+                    .unwrap_or(1),
+                output_dir: matches.value_of("output").unwrap_or("excerpt").into(),
+            }),
+            _ if m.is_present("lint") => Command::Lint,
+            _ => Command::Build,
+        };
+
+        let inputs = match command {
+            Command::Decompile(_) | Command::Format(_) => Vec::new(),
+            _ => matches.values_of("INPUT"),
+        };
+        let extensions = matches.values_of("extension");
+        let format = matches.value_of("format").map(|s| s.to_string());
+        let output_file = match command {
+            Command::Build
+            | Command::Translate(_)
+            | Command::Daemon(_)
+            | Command::ShowConfig { .. } => matches.value_of("output").map(|s| s.to_string()),
+            _ => None,
+        };
+        let output_dir = match command {
+            Command::Build
+            | Command::Translate(_)
+            | Command::Daemon(_)
+            | Command::ShowConfig { .. } => matches.value_of("output-dir").map(PathBuf::from),
+            _ => None,
+        };
+        let should_open = matches.is_present("open");
+        let open_with = matches.value_of("open-with").map(String::from);
+        let levels = matches.lint_levels();
+        // Warnings and errors go to standard error when the story is written
+        // to standard output
+        let stream = match output_file.as_deref() {
+            Some("-") => atty::Stream::Stderr,
+            _ => atty::Stream::Stdout,
+        };
+        let use_color = match matches.value_of("color").unwrap_or("auto") {
             "always" => ColorChoice::Always,
             "ansi" => ColorChoice::AlwaysAnsi,
             "auto" => {
-                if atty::is(atty::Stream::Stdout) {
+                if atty::is(stream) {
                     ColorChoice::Auto
                 } else {
                     ColorChoice::Never
@@ -456,18 +2687,538 @@ impl CliConfig {
             }
             _ => ColorChoice::Never,
         };
-        let compact = m.is_present("compact");
+        let message_format = match matches.value_of("message-format") {
+            Some("sarif") => MessageFormat::Sarif,
+            Some("junit") => MessageFormat::Junit,
+            Some("json") => MessageFormat::Json,
+            Some("compact") => MessageFormat::Compact,
+            _ if matches.is_present("compact") => MessageFormat::Compact,
+            _ => MessageFormat::Human,
+        };
+        let fold = !matches.is_present("no-fold");
+        let write_ifid = matches.is_present("write-ifid");
+        let watch = subcommand == "watch" || matches.is_present("watch");
+        let proof = matches.is_present("proof");
+        let fix = matches.is_present("fix");
+        let validate_html = matches.is_present("validate-html");
+        let source_map = matches.is_present("source-map");
+        let force = matches.is_present("force");
+        let pwa = matches.is_present("pwa");
+        let package = match (&command, matches.value_of("package")) {
+            (Command::Build, Some(_)) => Some(PackageConfig {
+                zip: true,
+                output: None,
+                assets: Vec::new(),
+                cover: None,
+                metadata: false,
+                itch_toml: matches.is_present("itch-toml"),
+                copied_assets: None,
+            }),
+            _ => None,
+        };
+        let check = matches.is_present("check");
+        let output_style = if matches.is_present("minify") {
+            OutputStyle::Minified
+        } else if matches.is_present("pretty") {
+            OutputStyle::Pretty
+        } else {
+            OutputStyle::Normal
+        };
+        let sort_passages = match matches.value_of("sort-passages") {
+            Some("name") => SortPassages::Name,
+            _ => SortPassages::File,
+        };
+        let placeholders = matches
+            .values_of("define")
+            .iter()
+            .filter_map(|define| define.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let embed_images = matches.is_present("embed-images");
+        let assets_dir = matches.value_of("assets-dir").map(PathBuf::from);
+        let layout = layout(&matches);
+        let title = matches.value_of("title").map(String::from);
+        let start = matches.value_of("start").map(String::from);
+        let zoom = matches.value_of("zoom").and_then(|zoom| zoom.parse().ok());
+        let template = matches.value_of("template").map(PathBuf::from);
+        let baseline = matches.value_of("baseline").map(PathBuf::from);
+        let write_baseline = matches.value_of("write-baseline").map(PathBuf::from);
+        let max_warnings = matches
+            .value_of("max-warnings")
+            .and_then(|max| max.parse().ok());
+        let html_report = matches.value_of("report").map(PathBuf::from);
+        let refresh_formats = matches.is_present("refresh-formats");
+        let log_level = logging::level(
+            matches.occurrences_of("verbose"),
+            matches.is_present("quiet"),
+        );
 
-        CliConfig {
-            linting,
+        Ok(CliConfig {
+            command,
             inputs,
+            extensions,
             format,
             output_file,
+            output_dir,
             should_open,
-            allowed,
-            denied,
+            open_with,
+            levels,
             use_color,
-            compact,
+            message_format,
+            fold,
+            write_ifid,
+            watch,
+            proof,
+            fix,
+            validate_html,
+            embed_images,
+            assets_dir,
+            source_map,
+            check,
+            force,
+            package,
+            pwa,
+            output_style,
+            sort_passages,
+            placeholders,
+            layout,
+            title,
+            start,
+            zoom,
+            template,
+            baseline,
+            write_baseline,
+            max_warnings,
+            html_report,
+            refresh_formats,
+            log_level,
+        })
+    }
+}
+
+/// Looks up arguments in the matches for a subcommand, falling back to the
+/// top level matches
+struct Matches<'a> {
+    top: &'a ArgMatches<'a>,
+    sub: Option<&'a ArgMatches<'a>>,
+}
+
+impl<'a> Matches<'a> {
+    fn is_present(&self, name: &str) -> bool {
+        self.sub.map(|sub| sub.is_present(name)).unwrap_or(false) || self.top.is_present(name)
+    }
+
+    fn occurrences_of(&self, name: &str) -> u64 {
+        // Global arguments are counted at every level they propagate to
+        self.sub
+            .map(|sub| sub.occurrences_of(name))
+            .unwrap_or(0)
+            .max(self.top.occurrences_of(name))
+    }
+
+    fn value_of(&self, name: &str) -> Option<&'a str> {
+        self.sub
+            .and_then(|sub| sub.value_of(name))
+            .or_else(|| self.top.value_of(name))
+    }
+
+    fn values_of(&self, name: &str) -> Vec<String> {
+        let sub = self.sub.and_then(|sub| sub.values_of(name));
+        self.top
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .chain(sub.into_iter().flatten())
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    /// Gets the levels given with `--allow`, `--warn`, `--deny`, and
+    /// `--forbid`, in the order they were given
+    fn lint_levels(&self) -> Vec<(String, LintLevel)> {
+        let flags = [
+            ("allow", LintLevel::Allow),
+            ("warn", LintLevel::Warn),
+            ("deny", LintLevel::Deny),
+            ("forbid", LintLevel::Forbid),
+        ];
+        // Top-level arguments come before the subcommand's
+        let mut levels = Vec::new();
+        for matches in std::iter::once(self.top).chain(self.sub) {
+            let mut indexed = Vec::new();
+            for (flag, level) in &flags {
+                if let (Some(indices), Some(values)) =
+                    (matches.indices_of(flag), matches.values_of(flag))
+                {
+                    indexed.extend(
+                        indices
+                            .zip(values)
+                            .map(|(index, name)| (index, name.to_string(), *level)),
+                    );
+                }
+            }
+            indexed.sort_by_key(|(index, _, _)| *index);
+            levels.extend(indexed.into_iter().map(|(_, name, level)| (name, level)));
         }
+        levels
+    }
+}
+
+fn watch_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("watch")
+        .help("Watches the inputs and reruns whenever they change")
+        .short("w")
+        .long("watch")
+}
+
+/// Arguments that control linting, shared by every subcommand that lints
+fn lint_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("allow")
+            .help("Specifies warnings to ignore")
+            .short("a")
+            .long("allow")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("baseline")
+            .help("Leaves the warnings recorded in a baseline file out of the report")
+            .long("baseline")
+            .value_name("FILE")
+            .takes_value(true),
+        Arg::with_name("color")
+            .help("Turns on colored output")
+            .long("color")
+            .takes_value(true),
+        Arg::with_name("compact")
+            .help("Turns on compact error and warning output")
+            .long("compact"),
+        Arg::with_name("message-format")
+            .help("Sets the output format for errors and warnings")
+            .long("message-format")
+            .takes_value(true)
+            .possible_values(&["human", "compact", "json", "sarif", "junit"])
+            .conflicts_with("compact"),
+        Arg::with_name("deny")
+            .help("Specifies warnings to treat as errors")
+            .short("D")
+            .long("deny")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("forbid")
+            .help("Specifies warnings to treat as errors that can't be allowed later")
+            .short("F")
+            .long("forbid")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("extension")
+            .help(
+                "Sets the extensions of the Twee files read from input directories \
+                 (default: tw, twee)",
+            )
+            .long("extension")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("format")
+            .help(
+                "Sets the story format by name (e.g., sugarcube-2), file location, or the URL \
+                 of its format.js",
+            )
+            .short("f")
+            .long("format")
+            .takes_value(true),
+        Arg::with_name("max-warnings")
+            .help(
+                "Fails if more warnings than this are reported, even if none are treated as \
+                 errors",
+            )
+            .long("max-warnings")
+            .value_name("COUNT")
+            .takes_value(true)
+            .validator(|max| match max.parse::<usize>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("Expected a whole number".to_string()),
+            }),
+        Arg::with_name("refresh-formats")
+            .help("Downloads story formats given by URL again instead of using cached copies")
+            .long("refresh-formats"),
+        Arg::with_name("no-fold")
+            .help("Shows every warning instead of folding repeats of the same warning")
+            .long("no-fold"),
+        Arg::with_name("warn")
+            .help("Specifies warnings to report as warnings")
+            .short("W")
+            .long("warn")
+            .takes_value(true)
+            .multiple(true),
+    ]
+}
+
+/// The argument that sets where passages are placed on the story map
+fn layout_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("layout")
+        .help(
+            "Sets where passages are placed on the Twine editor's story map: as their positions \
+             give, or laid out by following their links (default: keep)",
+        )
+        .long("layout")
+        .possible_values(&["keep", "auto"])
+        .takes_value(true)
+}
+
+/// Gets where passages are placed on the story map from the matches
+fn layout(matches: &Matches) -> Layout {
+    match matches.value_of("layout") {
+        Some("auto") => Layout::Auto,
+        _ => Layout::Keep,
+    }
+}
+
+/// The argument setting the directory the compiled story is written to
+fn output_dir_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("output-dir")
+        .help(
+            "Writes the output and the files next to it into this directory, creating it if \
+             needed. --output is then relative to it",
+        )
+        .short("d")
+        .long("output-dir")
+        .value_name("DIR")
+        .takes_value(true)
+}
+
+/// The argument allowing the output to replace a file tweec didn't write
+fn force_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("force")
+        .help("Overwrites the output file even if it wasn't written by tweec")
+        .long("force")
+}
+
+/// The argument adding an `.itch.toml` manifest to a package
+fn itch_toml_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("itch-toml")
+        .help("Includes an .itch.toml manifest telling the itch app to play index.html")
+        .long("itch-toml")
+}
+
+/// Arguments that control compiling, shared by `build` and `watch`
+fn build_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = lint_args();
+    args.extend(vec![
+        Arg::with_name("open")
+            .help("Opens the html output in a web browser")
+            .long("open"),
+        Arg::with_name("open-with")
+            .help("Opens the html output with the given program, such as firefox")
+            .long("open-with")
+            .value_name("PROGRAM")
+            .takes_value(true),
+        Arg::with_name("output")
+            .help("Sets the output file, or - for standard output (default: <Story Title>.html)")
+            .short("o")
+            .long("output")
+            .takes_value(true),
+        output_dir_arg(),
+        watch_arg(),
+        Arg::with_name("write-ifid")
+            .help("Writes a generated IFID back into the story's StoryData passage")
+            .long("write-ifid"),
+        Arg::with_name("proof")
+            .help(
+                "Builds with the configured proofing format instead, writing <Story Title> \
+                 (proof).html",
+            )
+            .long("proof"),
+        Arg::with_name("validate-html")
+            .help(
+                "Parses the compiled HTML and warns about passages that a browser would read \
+                 differently",
+            )
+            .long("validate-html"),
+        Arg::with_name("embed-images")
+            .help(
+                "Inlines local images referred to by passages and stylesheets as data URIs, so \
+                 the output is a single file",
+            )
+            .long("embed-images"),
+        Arg::with_name("assets-dir")
+            .help(
+                "Copies local media referred to by passages and stylesheets into this directory \
+                 next to the output, and points the story at the copies",
+            )
+            .long("assets-dir")
+            .value_name("DIR")
+            .takes_value(true),
+        Arg::with_name("source-map")
+            .help(
+                "Writes <output>.map, relating each passage in the output to the file and lines \
+                 it came from",
+            )
+            .long("source-map"),
+        Arg::with_name("check")
+            .help(
+                "Runs the whole build without writing anything, checking that the format parses \
+                 and the output file could be written",
+            )
+            .long("check")
+            .conflicts_with_all(&["open", "open-with", "write-ifid", "assets-dir"]),
+        force_arg(),
+        Arg::with_name("package")
+            .help(
+                "Bundles the compiled story as index.html with any copied media into <output>.zip, \
+                 ready to upload to itch.io, instead of writing it",
+            )
+            .long("package")
+            .value_name("FORMAT")
+            .possible_values(&["zip"])
+            .takes_value(true),
+        itch_toml_arg().requires("package"),
+        Arg::with_name("pwa")
+            .help(
+                "Writes a web app manifest and a service worker next to the output, so the story \
+                 can be installed and played offline",
+            )
+            .long("pwa"),
+        Arg::with_name("minify")
+            .help(
+                "Removes needless whitespace and comments from the output and the story's \
+                 stylesheet and script",
+            )
+            .long("minify")
+            .conflicts_with("pretty"),
+        Arg::with_name("pretty")
+            .help("Puts each passage in the output on its own indented line, for debugging")
+            .long("pretty"),
+        Arg::with_name("sort-passages")
+            .help(
+                "Sets the order passages are numbered and written in: as they appear in the \
+                 source files, or by name (default: file)",
+            )
+            .long("sort-passages")
+            .possible_values(&["file", "name"])
+            .takes_value(true),
+        layout_arg(),
+        Arg::with_name("title")
+            .help("Builds the story with this title in place of the one in StoryTitle")
+            .long("title")
+            .takes_value(true),
+        Arg::with_name("start")
+            .help("Starts the story at this passage in place of the one in StoryData")
+            .long("start")
+            .value_name("PASSAGE")
+            .takes_value(true),
+        Arg::with_name("zoom")
+            .help("Builds the story with this zoom level in place of the one in StoryData")
+            .long("zoom")
+            .takes_value(true)
+            .validator(|zoom| match zoom.parse::<f32>() {
+                Ok(zoom) if zoom.is_finite() && zoom > 0. => Ok(()),
+                _ => Err("Expected a positive number".to_string()),
+            }),
+        Arg::with_name("template")
+            .help(
+                "Uses an HTML shell in place of the story format's source, filling in \
+                 {{STORY_NAME}}, {{STORY_DATA}}, {{FORMAT_HEAD}}, and {{FORMAT_BODY}}",
+            )
+            .long("template")
+            .value_name("FILE")
+            .takes_value(true),
+        Arg::with_name("define")
+            .help(
+                "Fills in the placeholder {{NAME}} in the story format's source with VALUE, \
+                 overriding the project config",
+            )
+            .long("define")
+            .value_name("NAME=VALUE")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .validator(|define| match define.split_once('=') {
+                Some((name, _)) => story_format::check_placeholder_name(name),
+                None => Err("Expected NAME=VALUE".to_string()),
+            }),
+    ]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_response_files() {
+        let contents = "# Build options\n--deny DeadLink\n-o \"My Story.html\" 'src dir'\n";
+        assert_eq!(
+            split_response_file(contents).ok().unwrap(),
+            vec!["--deny", "DeadLink", "-o", "My Story.html", "src dir"]
+        );
+        assert!(split_response_file("-o \"unterminated").is_err());
+    }
+
+    #[test]
+    fn builds_config_without_files() {
+        let config = Config::builder()
+            .inputs(["src"])
+            .format_path("formats/test/format.js")
+            .deny(["DeadLink"])
+            .allow(vec!["DeadEnd".to_string()])
+            .build()
+            .unwrap();
+        assert_eq!(config.command, Command::Build);
+        assert_eq!(config.inputs, vec!["src"]);
+        assert_eq!(config.format_file, PathBuf::from("formats/test/format.js"));
+        assert_eq!(config.format_url, None);
+        assert_eq!(config.extensions, inputs::default_extensions());
+        assert_eq!(issue::lint_level("DeadLink", &config), LintLevel::Deny);
+        assert_eq!(issue::lint_level("DeadEnd", &config), LintLevel::Allow);
+
+        assert!(Config::builder().build().is_err());
+
+        // A format given by URL is only downloaded when it is loaded
+        let url = "https://example.invalid/format.js";
+        let config = Config::builder()
+            .inputs(["src"])
+            .format(url)
+            .build()
+            .unwrap();
+        assert_eq!(config.format_file, formats::cache_path(url).unwrap());
+        assert_eq!(config.format_url.as_deref(), Some(url));
+        assert!(Config::builder()
+            .cli_config(CliConfig {
+                inputs: vec!["src".to_string()],
+                output_file: Some("-".to_string()),
+                source_map: true,
+                ..CliConfig::default()
+            })
+            .build()
+            .is_err());
+    }
+    #[test]
+    fn puts_output_in_output_dir() {
+        let story = Story::from_string(":: StoryTitle\nMy Story\n".to_string())
+            .take()
+            .0
+            .unwrap();
+        let builder = || {
+            Config::builder()
+                .inputs(["src"])
+                .format_path("formats/test/format.js")
+        };
+        let config = builder().build().unwrap();
+        assert_eq!(config.output_path(&story), PathBuf::from("My Story.html"));
+        let config = builder().output_dir("dist").build().unwrap();
+        assert_eq!(
+            config.output_path(&story),
+            PathBuf::from("dist/My Story.html")
+        );
+        let config = builder()
+            .output_dir("dist")
+            .output("web/index.html")
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.output_path(&story),
+            PathBuf::from("dist/web/index.html")
+        );
+        assert!(builder().output_dir("dist").output("-").build().is_err());
     }
 }
@@ -0,0 +1,468 @@
+//! A daemon that keeps the parsed story in memory and answers requests from
+//! editor plugins over a local socket
+//!
+//! The daemon listens on a TCP port on the loopback interface and speaks
+//! JSON-RPC 2.0, with one message per line. Each client is served on its own
+//! thread, and requests are answered one at a time. It understands the
+//! following methods:
+//!
+//! * `authenticate`: takes the `token` printed when the daemon starts, and
+//!   must be called before any other method, so that other users of the
+//!   machine can't use the daemon
+//! * `lint`: returns the story's issues
+//! * `compile`: compiles the story, writing it to the configured output file,
+//!   or to the `output` param if given, which must be a relative path within
+//!   the output file's directory, and returns the path written
+//! * `graph`: returns the story's passages and the links between them
+//! * `shutdown`: stops the daemon
+//!
+//! The story is only parsed again when the inputs change, and the story format
+//! only when its file changes
+
+use crate::compile::{self, OutputOptions};
+use crate::ifid;
+use crate::inputs;
+use crate::issue::{self, Issue};
+use crate::linter::graph::LinkGraph;
+use crate::linter::Linter;
+use crate::reporter;
+use crate::tweec;
+use crate::utils;
+use crate::watch::Watcher;
+use crate::Config;
+use crate::StoryFiles;
+use crate::StoryFormat;
+use crate::StoryResult;
+use color_eyre::Result;
+use eyre::WrapErr;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tweep::Warning;
+
+/// JSON-RPC error code for a request that could not be parsed
+const PARSE_ERROR: i64 = -32700;
+
+/// JSON-RPC error code for an unknown method
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Error code for a request that failed, such as a compile of a story with
+/// errors
+const REQUEST_FAILED: i64 = -32000;
+
+/// Error code for a request made before the client authenticated
+const UNAUTHORIZED: i64 = -32001;
+
+/// The daemon's cached state
+struct Daemon {
+    config: Config,
+    watcher: Watcher,
+    story_result: StoryResult,
+    warnings: Vec<Warning>,
+    format: Option<(Option<SystemTime>, StoryFormat, Vec<String>)>,
+}
+
+/// Runs the daemon on the given port until it receives a `shutdown` request
+///
+/// If the port is 0, any free port is used. The address being listened on and
+/// the token clients authenticate with are printed once the daemon is ready
+pub fn run(config: &Config, port: u16) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .wrap_err_with(|| format!("Failed to listen on port {}", port))?;
+    let token = new_token()?;
+    println!("Listening on {}", listener.local_addr()?);
+    println!("Token {}", token);
+    std::io::stdout().flush()?;
+    listen(listener, Daemon::new(config), token)
+}
+
+/// Makes a random token for clients to authenticate with
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut bytes)
+        .map_err(|_| eyre::eyre!("Failed to generate a daemon token"))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// A message from a client's thread to the thread that owns the daemon
+enum Message {
+    /// A request for a method, and where to send its result
+    Request(String, Value, Sender<RpcResult>),
+    Shutdown,
+}
+
+/// Serves each client that connects on its own thread until one of them
+/// sends a `shutdown` request
+///
+/// The parsed story can't be shared between threads, so the daemon stays on
+/// this thread, and clients' threads send it their requests
+fn listen(listener: TcpListener, mut daemon: Daemon, token: String) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let token: Arc<str> = token.into();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let (sender, token) = (sender.clone(), token.clone());
+            // A broken connection only ends that client's session
+            std::thread::spawn(move || serve(&sender, &token, stream));
+        }
+    });
+
+    for message in receiver {
+        match message {
+            Message::Request(method, params, reply) => {
+                let _ = reply.send(daemon.handle(&method, &params));
+            }
+            Message::Shutdown => break,
+        }
+    }
+    Ok(())
+}
+
+/// Answers requests from a single client until it disconnects or asks the
+/// daemon to shut down
+fn serve(daemon: &Sender<Message>, token: &str, stream: TcpStream) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut authenticated = false;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (response, shutdown) = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                let method = request.get("method").and_then(Value::as_str);
+                let params = request.get("params").cloned().unwrap_or(Value::Null);
+                let result = match method {
+                    Some("authenticate") => {
+                        authenticated = params.get("token").and_then(Value::as_str) == Some(token);
+                        if authenticated {
+                            Ok(Value::Null)
+                        } else {
+                            Err((UNAUTHORIZED, "Wrong token".to_string(), None))
+                        }
+                    }
+                    Some(_) if !authenticated => Err((
+                        UNAUTHORIZED,
+                        "Call authenticate with the daemon's token first".to_string(),
+                        None,
+                    )),
+                    Some("shutdown") => Ok(Value::Null),
+                    Some(method) => {
+                        let (reply, result) = mpsc::channel();
+                        daemon.send(Message::Request(method.to_string(), params, reply))?;
+                        result.recv()?
+                    }
+                    None => Err((METHOD_NOT_FOUND, "Missing method".to_string(), None)),
+                };
+                let shutdown = method == Some("shutdown") && result.is_ok();
+                (response(id, result), shutdown)
+            }
+            Err(e) => (
+                response(Value::Null, Err((PARSE_ERROR, e.to_string(), None))),
+                false,
+            ),
+        };
+        writeln!(writer, "{}", response)?;
+        writer.flush()?;
+        if shutdown {
+            daemon.send(Message::Shutdown)?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Gets where to write the compiled story for a `compile` request: the
+/// configured output file, or `output` within that file's directory
+fn output_file(default: PathBuf, output: Option<&str>) -> std::result::Result<PathBuf, String> {
+    let output = match output {
+        Some(output) => Path::new(output),
+        None => return Ok(default),
+    };
+    let within = output.components().next().is_some()
+        && output
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !within {
+        return Err(format!(
+            "Output {:?} must be a relative path within the output directory",
+            output
+        ));
+    }
+    Ok(match default.parent() {
+        Some(dir) => dir.join(output),
+        None => output.to_path_buf(),
+    })
+}
+
+impl Daemon {
+    fn new(config: &Config) -> Self {
+        let (story_result, warnings) =
+            inputs::read_story(&config.inputs, &config.extensions).take();
+        Daemon {
+            config: config.clone(),
+            watcher: Watcher::new(&config.inputs, &config.extensions),
+            story_result,
+            warnings,
+            format: None,
+        }
+    }
+
+    fn handle(&mut self, method: &str, params: &Value) -> RpcResult {
+        self.refresh();
+        match method {
+            "lint" => {
+                let (issues, _) = self.issues();
+                Ok(json!({ "issues": self.issues_json(&issues) }))
+            }
+            "compile" => self.compile(params),
+            "graph" => Ok(self.graph()),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method), None)),
+        }
+    }
+
+    /// Parses the story again if any of the inputs have changed
+    fn refresh(&mut self) {
+        if !self.watcher.poll().is_empty() {
+            let (story_result, warnings) =
+                inputs::read_story(&self.config.inputs, &self.config.extensions).take();
+            self.story_result = story_result;
+            self.warnings = warnings;
+        }
+    }
+
+    /// Lints the cached story, returning its issues and whether any of them
+    /// are errors
+    fn issues(&self) -> (Vec<Issue>, bool) {
+        let lints = match &self.story_result {
+            Ok(story) => Linter::new().check(story, &self.config),
+            Err(_) => Vec::new(),
+        };
+        issue::filter_and_sort_issues(
+            &self.story_result,
+            self.warnings.clone(),
+            lints,
+            &self.config,
+        )
+    }
+
+    fn issues_json(&self, issues: &[Issue]) -> Vec<Value> {
+        let story_files = StoryFiles::new(&self.story_result);
+        let paths = utils::file_paths(&self.config.inputs, &self.config.extensions);
+        issues
+            .iter()
+            .map(|issue| reporter::issue_json(issue, &story_files, &paths))
+            .collect()
+    }
+
+    /// Parses the story format again if its file has changed since it was
+    /// last parsed
+    fn refresh_format(&mut self) -> Result<()> {
+        let modified = std::fs::metadata(&self.config.format_file)
+            .and_then(|m| m.modified())
+            .ok();
+        let stale = match &self.format {
+            Some((cached, _, _)) => modified.is_none() || *cached != modified,
+            None => true,
+        };
+        if stale {
+            self.format = None;
+            let (story_format, warnings) = tweec::load_format(&self.config)?;
+            self.format = Some((modified, story_format, warnings));
+        }
+        Ok(())
+    }
+
+    fn compile(&mut self, params: &Value) -> RpcResult {
+        let (issues, is_err) = self.issues();
+        if is_err {
+            return Err((
+                REQUEST_FAILED,
+                "Failed due to previous errors".to_string(),
+                Some(json!({ "issues": self.issues_json(&issues) })),
+            ));
+        }
+        if let Err(e) = self.refresh_format() {
+            return Err((REQUEST_FAILED, format!("{:#}", e), None));
+        }
+        let (_, story_format, format_warnings) = self.format.as_ref().unwrap();
+
+        let mut warnings = format_warnings.clone();
+        let config = &self.config;
+        let story = self.story_result.as_mut().ok().unwrap();
+        if let Some(ifid) = ifid::ensure(story) {
+            warnings.push(format!(
+                "No IFID found; generated {} for this session",
+                ifid
+            ));
+        }
+
+        let file_name = output_file(
+            config.output_path(story),
+            params.get("output").and_then(Value::as_str),
+        )
+        .map_err(|message| (REQUEST_FAILED, message, None))?;
+        let output = compile::compile(story, story_format, &OutputOptions::from(config))
+            .map_err(|e| (REQUEST_FAILED, format!("{:#}", e), None))?;
+        tweec::write_output(&file_name, &output, config.force)
+            .map_err(|e| (REQUEST_FAILED, format!("{:#}", e), None))?;
+
+        Ok(json!({
+            "output": file_name,
+            "issues": self.issues_json(&issues),
+            "warnings": warnings,
+        }))
+    }
+
+    fn graph(&self) -> Value {
+        let story = match &self.story_result {
+            Ok(story) => story,
+            Err(_) => return json!({ "passages": [], "links": [] }),
+        };
+        let graph = LinkGraph::new(story, &self.config.links);
+        let passages: Vec<Value> = graph
+            .passages()
+            .into_iter()
+            .map(|name| json!({ "name": name, "tags": graph.tags(name) }))
+            .collect();
+        let links: Vec<Value> = graph
+            .passages()
+            .into_iter()
+            .flat_map(|name| {
+                graph
+                    .outgoing(name)
+                    .iter()
+                    .map(move |edge| json!({ "from": name, "to": edge.target }))
+            })
+            .collect();
+        json!({ "passages": passages, "links": links })
+    }
+}
+
+/// The result of a request: a value, or an error code, message, and data
+type RpcResult = std::result::Result<Value, (i64, String, Option<Value>)>;
+
+fn response(id: Value, result: RpcResult) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message, data)) => {
+            let mut error = json!({ "code": code, "message": message });
+            if let Some(data) = data {
+                error["data"] = data;
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": error })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shuts the daemon down if a client's assertion fails, so that the test
+    /// fails instead of waiting forever
+    struct ShutdownOnPanic(std::net::SocketAddr);
+
+    impl Drop for ShutdownOnPanic {
+        fn drop(&mut self) {
+            if std::thread::panicking() {
+                if let Ok(mut stream) = TcpStream::connect(self.0) {
+                    let _ = writeln!(
+                        stream,
+                        "{}\n{}",
+                        json!({ "method": "authenticate", "params": { "token": "secret" } }),
+                        json!({ "method": "shutdown" })
+                    );
+                    let _ = BufReader::new(stream).lines().nth(1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn serves_clients_concurrently() {
+        let dir = std::env::temp_dir().join(format!("tweec-daemon-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("story.tw"), ":: Start [a\nHello\n").unwrap();
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.inputs = vec![dir.to_string_lossy().into_owned()];
+        let daemon = Daemon::new(&config);
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        // The daemon can't move to another thread, so the clients do
+        let clients = std::thread::spawn(move || {
+            let _guard = ShutdownOnPanic(address);
+            let connect = || {
+                let stream = TcpStream::connect(address).unwrap();
+                (stream.try_clone().unwrap(), BufReader::new(stream).lines())
+            };
+            let call = |client: &mut (TcpStream, std::io::Lines<BufReader<TcpStream>>),
+                        method: &str,
+                        params: Value| {
+                let request =
+                    json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+                writeln!(client.0, "{}", request).unwrap();
+                serde_json::from_str::<Value>(&client.1.next().unwrap().unwrap()).unwrap()
+            };
+            let authenticate = json!({ "token": "secret" });
+
+            let mut first = connect();
+            assert_eq!(
+                call(&mut first, "lint", Value::Null)["error"]["code"],
+                UNAUTHORIZED
+            );
+            assert_eq!(
+                call(&mut first, "authenticate", json!({ "token": "guess" }))["error"]["code"],
+                UNAUTHORIZED
+            );
+            assert_eq!(
+                call(&mut first, "authenticate", authenticate.clone())["result"],
+                Value::Null
+            );
+
+            // A second client is served while the first is still connected
+            let mut second = connect();
+            call(&mut second, "authenticate", authenticate);
+            let lint = call(&mut second, "lint", Value::Null);
+            assert_eq!(lint["result"]["issues"][0]["code"], "UnclosedTagBlock");
+            let compile = call(&mut second, "compile", Value::Null);
+            assert_eq!(compile["error"]["code"], REQUEST_FAILED);
+            assert_eq!(compile["error"]["message"], "Failed due to previous errors");
+            assert_eq!(
+                call(&mut first, "frobnicate", Value::Null)["error"]["code"],
+                METHOD_NOT_FOUND
+            );
+
+            assert_eq!(
+                call(&mut first, "shutdown", Value::Null)["result"],
+                Value::Null
+            );
+        });
+        listen(listener, daemon, "secret".to_string()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        clients.join().unwrap();
+    }
+
+    #[test]
+    fn keeps_output_in_output_directory() {
+        let default = PathBuf::from("out/Story.html");
+        assert_eq!(output_file(default.clone(), None), Ok(default.clone()));
+        assert_eq!(
+            output_file(default.clone(), Some("draft/Story.html")),
+            Ok(PathBuf::from("out/draft/Story.html"))
+        );
+        assert!(output_file(default.clone(), Some("../Story.html")).is_err());
+        assert!(output_file(default.clone(), Some("/tmp/Story.html")).is_err());
+        assert!(output_file(default, Some("")).is_err());
+    }
+}
@@ -0,0 +1,564 @@
+//! Converts compiled Twine 2 HTML stories back into Twee v3 source
+//!
+//! Stories published by Twine 1, or saved by it as `.tws` archives, can be
+//! decompiled too. See [`twine1`]
+//!
+//! [`twine1`]: twine1/index.html
+
+use crate::layout;
+use crate::linter::graph::LinkGraph;
+use crate::linter::links::LinkSyntax;
+use crate::twee;
+use crate::utils;
+use crate::{DecompileConfig, Layout};
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+pub mod pickle;
+pub mod twine1;
+
+/// A passage read from a `tw-passagedata` element
+#[derive(Debug)]
+pub struct DecompiledPassage {
+    /// The passage's pid
+    pub pid: Option<usize>,
+
+    /// The name of the passage
+    pub name: String,
+
+    /// The passage's tags
+    pub tags: Vec<String>,
+
+    /// The passage's metadata, such as its position in the Twine editor
+    pub metadata: Map<String, Value>,
+
+    /// The content of the passage
+    pub content: String,
+}
+
+/// A story read from a `tw-storydata` element
+#[derive(Debug)]
+pub struct DecompiledStory {
+    /// The story's title
+    pub title: String,
+
+    /// The contents of the `StoryData` passage
+    pub data: Map<String, Value>,
+
+    /// The user script of the story
+    pub script: String,
+
+    /// The user stylesheet of the story
+    pub stylesheet: String,
+
+    /// The story's passages, in the order they appear
+    pub passages: Vec<DecompiledPassage>,
+}
+
+/// An HTML start tag
+struct Tag {
+    /// Byte offset of the start of the tag
+    start: usize,
+
+    /// Byte offset just past the end of the tag
+    end: usize,
+
+    /// The tag's attributes, with lowercase names and unescaped values
+    attributes: HashMap<String, String>,
+}
+
+impl Tag {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+}
+
+/// Replaces the HTML character references in the given text
+pub fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        unescaped.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let semi = match rest.find(';') {
+            Some(semi) if semi <= 10 => semi,
+            _ => {
+                unescaped.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..semi];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse()))
+                .and_then(|code| code.ok())
+                .and_then(std::char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Finds the next start tag with the given name at or after `from`
+///
+/// `lower` must be `html` converted to ASCII lowercase, so byte offsets match
+fn find_tag(html: &str, lower: &str, from: usize, name: &str) -> Option<Tag> {
+    let open = format!("<{}", name);
+    let mut search = from;
+    loop {
+        let start = search + lower.get(search..)?.find(&open)?;
+        let after = start + open.len();
+        match lower[after..].chars().next() {
+            Some(c) if c.is_whitespace() || c == '>' || c == '/' => {
+                let (attributes, end) = parse_attributes(html, after)?;
+                return Some(Tag {
+                    start,
+                    end,
+                    attributes,
+                });
+            }
+            _ => search = after,
+        }
+    }
+}
+
+/// Parses the attributes of a start tag beginning at `pos`, returning them
+/// along with the offset just past the end of the tag
+fn parse_attributes(html: &str, mut pos: usize) -> Option<(HashMap<String, String>, usize)> {
+    let mut attributes = HashMap::new();
+    let bytes = html.as_bytes();
+    loop {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'/') {
+            pos += 1;
+        }
+        match bytes.get(pos)? {
+            b'>' => return Some((attributes, pos + 1)),
+            _ => {
+                let name_start = pos;
+                while pos < bytes.len()
+                    && !bytes[pos].is_ascii_whitespace()
+                    && !matches!(bytes[pos], b'=' | b'>' | b'/')
+                {
+                    pos += 1;
+                }
+                let name = html[name_start..pos].to_ascii_lowercase();
+                while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                    pos += 1;
+                }
+                let mut value = String::new();
+                if bytes.get(pos) == Some(&b'=') {
+                    pos += 1;
+                    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                        pos += 1;
+                    }
+                    let (value_start, value_end) = match bytes.get(pos)? {
+                        quote @ b'"' | quote @ b'\'' => {
+                            let end = pos + 1 + html[pos + 1..].find(*quote as char)?;
+                            let range = (pos + 1, end);
+                            pos = end + 1;
+                            range
+                        }
+                        _ => {
+                            let start = pos;
+                            while pos < bytes.len()
+                                && !bytes[pos].is_ascii_whitespace()
+                                && bytes[pos] != b'>'
+                            {
+                                pos += 1;
+                            }
+                            (start, pos)
+                        }
+                    };
+                    value = unescape(&html[value_start..value_end]);
+                }
+                attributes.insert(name, value);
+            }
+        }
+    }
+}
+
+/// Gets the raw contents of the element started by `tag`, up to its end tag
+fn element_contents<'a>(html: &'a str, lower: &str, tag: &Tag, name: &str) -> &'a str {
+    let close = format!("</{}", name);
+    let end = lower[tag.end..]
+        .find(&close)
+        .map(|end| tag.end + end)
+        .unwrap_or_else(|| html.len());
+    &html[tag.end..end]
+}
+
+/// Reads the first story in the given Twine 2 HTML
+pub fn parse(html: &str) -> Result<DecompiledStory> {
+    let lower = html.to_ascii_lowercase();
+    parse_at(html, &lower, 0)
+        .map(|(story, _)| story)
+        .ok_or_else(|| eyre!("No Twine 2 story data (tw-storydata) found"))
+}
+
+/// Reads every story in the given Twine 2 HTML, such as an archive of the
+/// Twine editor's library
+pub fn parse_all(html: &str) -> Result<Vec<DecompiledStory>> {
+    let lower = html.to_ascii_lowercase();
+    let mut stories = Vec::new();
+    let mut pos = 0;
+    while let Some((story, end)) = parse_at(html, &lower, pos) {
+        stories.push(story);
+        pos = end;
+    }
+    if stories.is_empty() {
+        return Err(eyre!("No Twine 2 story data (tw-storydata) found"));
+    }
+    Ok(stories)
+}
+
+/// Reads the next story at or after `from`, returning it along with the
+/// offset of its end
+fn parse_at(html: &str, lower: &str, from: usize) -> Option<(DecompiledStory, usize)> {
+    let story_tag = find_tag(html, lower, from, "tw-storydata")?;
+    let story_end = lower[story_tag.end..]
+        .find("</tw-storydata")
+        .map(|end| story_tag.end + end)
+        .unwrap_or_else(|| html.len());
+    let (html, lower) = (&html[..story_end], &lower[..story_end]);
+
+    let mut script = String::new();
+    let mut stylesheet = String::new();
+    let mut tag_colors = Map::new();
+    let mut passages = Vec::new();
+    let mut pos = story_tag.end;
+    while let Some(tag) = ["tw-passagedata", "script", "style", "tw-tag"]
+        .iter()
+        .filter_map(|name| find_tag(html, lower, pos, name).map(|tag| (*name, tag)))
+        .min_by_key(|(_, tag)| tag.start)
+    {
+        let (name, tag) = tag;
+        let contents = element_contents(html, lower, &tag, name);
+        pos = tag.end + contents.len();
+        match name {
+            "script" => script.push_str(contents),
+            "style" => stylesheet.push_str(contents),
+            "tw-tag" => {
+                if let (Some(tag_name), Some(color)) = (tag.get("name"), tag.get("color")) {
+                    tag_colors.insert(tag_name.to_string(), color.into());
+                }
+            }
+            _ => {
+                let mut metadata = Map::new();
+                for key in &["position", "size"] {
+                    if let Some(value) = tag.get(key).filter(|value| !value.is_empty()) {
+                        metadata.insert(key.to_string(), value.into());
+                    }
+                }
+                passages.push(DecompiledPassage {
+                    pid: tag.get("pid").and_then(|pid| pid.parse().ok()),
+                    name: tag.get("name").unwrap_or_default().to_string(),
+                    tags: tag
+                        .get("tags")
+                        .unwrap_or_default()
+                        .split_whitespace()
+                        .map(String::from)
+                        .collect(),
+                    metadata,
+                    content: unescape(contents),
+                });
+            }
+        }
+    }
+
+    let mut data = Map::new();
+    for key in &["ifid", "format", "format-version"] {
+        if let Some(value) = story_tag.get(key).filter(|value| !value.is_empty()) {
+            data.insert(key.to_string(), value.into());
+        }
+    }
+    let start = story_tag
+        .get("startnode")
+        .and_then(|pid| pid.parse::<usize>().ok())
+        .and_then(|pid| passages.iter().find(|p| p.pid == Some(pid)));
+    if let Some(start) = start {
+        data.insert("start".to_string(), start.name.clone().into());
+    }
+    if !tag_colors.is_empty() {
+        data.insert("tag-colors".to_string(), Value::Object(tag_colors));
+    }
+    if let Some(zoom) = story_tag.get("zoom").and_then(|z| z.parse::<f64>().ok()) {
+        data.insert("zoom".to_string(), zoom.into());
+    }
+
+    let story = DecompiledStory {
+        title: story_tag
+            .get("name")
+            .unwrap_or("Untitled Story")
+            .to_string(),
+        data,
+        script: script.trim().to_string(),
+        stylesheet: stylesheet.trim().to_string(),
+        passages,
+    };
+    Some((story, story_end))
+}
+
+/// `StoryData` keys that only affect how the Twine editor displays the story
+pub const EDITOR_STORY_DATA: &[&str] = &["zoom", "tag-colors"];
+
+/// Passage metadata keys that only affect how the Twine editor displays the
+/// passage
+pub const EDITOR_PASSAGE_METADATA: &[&str] = &["position", "size"];
+
+/// Removes the editor-only `StoryData` keys and passage metadata from a
+/// decompiled story, returning a description of each thing removed
+pub fn strip_editor_metadata(story: &mut DecompiledStory) -> Vec<String> {
+    let mut dropped = Vec::new();
+    for key in EDITOR_STORY_DATA {
+        if story.data.remove(*key).is_some() {
+            dropped.push(format!("{} from StoryData", key));
+        }
+    }
+    for key in EDITOR_PASSAGE_METADATA {
+        let count = story
+            .passages
+            .iter_mut()
+            .map(|passage| passage.metadata.remove(*key))
+            .filter(Option::is_some)
+            .count();
+        if count > 0 {
+            let plural = if count == 1 { "" } else { "s" };
+            dropped.push(format!("{} from {} passage{}", key, count, plural));
+        }
+    }
+    dropped
+}
+
+/// Creates the Twee source for a decompiled story
+///
+/// The story's user script and stylesheet become passages tagged `script` and
+/// `stylesheet`, which tweec merges back into the story when compiling
+pub fn to_twee(story: &DecompiledStory) -> Result<String> {
+    let mut output = format!(":: StoryTitle\n{}\n\n", story.title);
+    output.push_str(&format!(
+        ":: StoryData\n{}\n\n",
+        serde_json::to_string_pretty(&story.data)?
+    ));
+
+    if !story.script.is_empty() {
+        output.push_str(&twee::passage(
+            "Story JavaScript",
+            &["script".to_string()],
+            &Map::new(),
+            &story.script,
+        ));
+    }
+    if !story.stylesheet.is_empty() {
+        output.push_str(&twee::passage(
+            "Story Stylesheet",
+            &["stylesheet".to_string()],
+            &Map::new(),
+            &story.stylesheet,
+        ));
+    }
+
+    for passage in &story.passages {
+        output.push_str(&twee::passage(
+            &passage.name,
+            &passage.tags,
+            &passage.metadata,
+            &twee::escape_content(&passage.content),
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Removes editor-only metadata from the story if the config asks for it,
+/// reporting what was dropped on standard error
+fn minimize_metadata(config: &DecompileConfig, story: &mut DecompiledStory) {
+    if !config.minimal_metadata {
+        return;
+    }
+    let dropped = strip_editor_metadata(story);
+    if dropped.is_empty() {
+        eprintln!("No editor-only metadata found");
+    } else {
+        eprintln!("Dropped editor-only metadata: {}", dropped.join(", "));
+    }
+}
+
+/// Replaces the position of every passage by following its links, if the
+/// config asks for it
+///
+/// Links are read in the default syntaxes, and passages that can't be reached
+/// from the start are laid out in the order they appear
+fn layout_passages(config: &DecompileConfig, story: &mut DecompiledStory) -> Result<()> {
+    if config.layout != Layout::Auto {
+        return Ok(());
+    }
+    let (parsed, _) = tweep::Story::from_string(to_twee(story)?).take();
+    let mut parsed = parsed.map_err(|_| eyre!("Failed to parse the decompiled story"))?;
+    for (i, passage) in story.passages.iter().enumerate() {
+        if let Some(parsed) = parsed.passages.get_mut(&passage.name) {
+            parsed.content.pid = i + 1;
+        }
+    }
+    let positions = layout::positions(&LinkGraph::new(&parsed, LinkSyntax::DEFAULT));
+    for passage in &mut story.passages {
+        if let Some(position) = positions.get(&passage.name) {
+            passage
+                .metadata
+                .insert("position".to_string(), Value::String(position.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Decompiles every story in a Twine 2 archive, writing each to
+/// `<Story Title>/story.twee` in the output directory
+fn split_archive(config: &DecompileConfig, html: &str) -> Result<()> {
+    let stories =
+        parse_all(html).wrap_err_with(|| format!("Failed to decompile {:?}", config.input))?;
+    let output_dir = config.output.clone().unwrap_or_else(|| PathBuf::from("."));
+    let mut used = HashSet::new();
+    for mut story in stories {
+        let base = utils::sanitize_file_name(&story.title);
+        let name = (1..)
+            .map(|n| match n {
+                1 => base.clone(),
+                n => format!("{} ({})", base, n),
+            })
+            .find(|name| used.insert(name.clone()))
+            .unwrap();
+        let dir = output_dir.join(name);
+        std::fs::create_dir_all(&dir)
+            .wrap_err_with(|| format!("Failed to create output directory {:?}", dir))?;
+        let file_name = dir.join("story.twee");
+        eprintln!("Writing {} to {:?}", story.title, file_name);
+        layout_passages(config, &mut story)?;
+        minimize_metadata(config, &mut story);
+        utils::write_atomic(&file_name, to_twee(&story)?.as_bytes())
+            .wrap_err_with(|| format!("Failed to write output file {:?}", file_name))?;
+    }
+    Ok(())
+}
+
+/// Decompiles the HTML or `.tws` file given by the config, writing the Twee
+/// source to its output file, or to standard output if not given
+///
+/// Twine 1 stories are converted as described in [`twine1`], with what was
+/// changed reported on standard error. With `split`, every story in a Twine 2
+/// archive is decompiled to its own directory instead
+///
+/// With `minimal_metadata`, editor-only metadata is left out of the source and
+/// what was dropped is reported on standard error. With `layout` set to
+/// [`Layout::Auto`], passages are placed by their links, as in [`layout`]
+///
+/// [`Layout::Auto`]: ../config/enum.Layout.html
+/// [`layout`]: ../layout/index.html
+pub fn decompile_file(config: &DecompileConfig) -> Result<()> {
+    let input = &config.input;
+    let bytes =
+        std::fs::read(input).wrap_err_with(|| format!("Failed to read input file {:?}", input))?;
+    let html = String::from_utf8_lossy(&bytes);
+    if config.split {
+        return split_archive(config, &html);
+    }
+    let (mut story, notes) = if twine1::is_archive(&bytes) {
+        twine1::parse_archive(&bytes)
+    } else if twine1::is_html(&html) && !html.contains("<tw-storydata") {
+        twine1::parse_html(&html)
+    } else {
+        parse(&html).map(|story| (story, Vec::new()))
+    }
+    .wrap_err_with(|| format!("Failed to decompile {:?}", input))?;
+    for note in &notes {
+        eprintln!("{}", note);
+    }
+    layout_passages(config, &mut story)?;
+    minimize_metadata(config, &mut story);
+    let source = to_twee(&story)?;
+    match &config.output {
+        Some(output) => {
+            utils::write_atomic(output, source.as_bytes())
+                .wrap_err_with(|| format!("Failed to write output file {:?}", output))?;
+        }
+        None => print!("{}", source),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompiles_story_data() {
+        let html = r#"<html><body>
+<tw-storydata name="A &amp; B" startnode="2" ifid="ABC" format="Harlowe" format-version="3.1.0" zoom="1.5">
+<style role="stylesheet" id="twine-user-stylesheet" type="text/twine-css">body { color: red; }</style>
+<script role="script" id="twine-user-script" type="text/twine-javascript">var x = 1 < 2;</script>
+<tw-tag name="hub" color="green"></tw-tag>
+<tw-passagedata pid="1" name="Intro" tags="" position="100,100" size="100,100">Hi &lt;b&gt; [[Start]]</tw-passagedata>
+<tw-passagedata pid="2" name="Start" tags="hub two" position="200,100">:: not a header
+[[Intro]]</tw-passagedata>
+</tw-storydata></body></html>"#;
+        let story = parse(html).ok().unwrap();
+        assert_eq!(story.title, "A & B");
+        assert_eq!(story.data["start"], "Start");
+        assert_eq!(story.data["tag-colors"]["hub"], "green");
+        assert_eq!(story.script, "var x = 1 < 2;");
+        assert_eq!(story.passages.len(), 2);
+        assert_eq!(story.passages[0].content, "Hi <b> [[Start]]");
+        assert_eq!(story.passages[1].tags, vec!["hub", "two"]);
+
+        let source = to_twee(&story).ok().unwrap();
+        assert!(
+            source.contains(":: Start [hub two] {\"position\":\"200,100\"}\n\\:: not a header\n")
+        );
+        assert!(source.contains(":: Story JavaScript [script]\nvar x = 1 < 2;\n"));
+
+        let mut story = story;
+        let dropped = strip_editor_metadata(&mut story);
+        assert_eq!(
+            dropped,
+            vec![
+                "zoom from StoryData",
+                "tag-colors from StoryData",
+                "position from 2 passages",
+                "size from 1 passage"
+            ]
+        );
+        assert!(story.passages.iter().all(|p| p.metadata.is_empty()));
+    }
+
+    #[test]
+    fn reads_every_story_in_an_archive() {
+        let html = r#"<tw-storydata name="One" startnode="1" ifid="A"><tw-passagedata pid="1" name="Start">1</tw-passagedata></tw-storydata>
+<tw-storydata name="Two: Return?" startnode="1" ifid="B"><tw-passagedata pid="1" name="Begin">2</tw-passagedata></tw-storydata>"#;
+        let stories = parse_all(html).ok().unwrap();
+        assert_eq!(stories.len(), 2);
+        assert_eq!(stories[1].data["start"], "Begin");
+        assert_eq!(stories[1].passages[0].content, "2");
+        assert_eq!(utils::sanitize_file_name(&stories[1].title), "Two_ Return_");
+    }
+}
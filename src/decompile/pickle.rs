@@ -0,0 +1,381 @@
+//! Reads Python pickles, as used by Twine 1's `.tws` story archives, into
+//! JSON values
+//!
+//! Only what Python 2 writes with protocols 0 to 2 is supported. Class
+//! instances become objects holding their state, and anything built by
+//! calling a function, such as a `time.struct_time`, becomes `null`. Objects
+//! are copied rather than shared, so changes made to an object after it is
+//! first referenced again are not seen through that reference
+
+use color_eyre::Result;
+use eyre::eyre;
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+/// An entry on the pickle machine's stack
+enum Item {
+    Value(Value),
+    Mark,
+}
+
+/// Reads the bytes of a pickle, keeping track of the position
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| eyre!("Pickle ended unexpectedly"))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn line(&mut self) -> Result<String> {
+        let len = self.bytes[self.pos..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| eyre!("Pickle ended unexpectedly"))?;
+        let line = String::from_utf8_lossy(self.take(len)?).into_owned();
+        self.pos += 1;
+        Ok(line)
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value> {
+        Ok(Value::String(
+            String::from_utf8_lossy(self.take(len)?).into_owned(),
+        ))
+    }
+}
+
+/// Decodes the escapes of a quoted string written by protocol 0
+fn unquote(line: &str) -> String {
+    let inner = line
+        .get(1..line.len().saturating_sub(1))
+        .unwrap_or_default();
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    out.push(c);
+                }
+            }
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Gets the string to use as an object key for a value
+fn key(value: Value) -> String {
+    match value {
+        Value::String(key) => key,
+        other => other.to_string(),
+    }
+}
+
+fn int(n: i64) -> Value {
+    Value::Number(n.into())
+}
+
+fn float(f: f64) -> Value {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+struct Machine {
+    stack: Vec<Item>,
+    memo: HashMap<usize, Value>,
+}
+
+impl Machine {
+    fn push(&mut self, value: Value) {
+        self.stack.push(Item::Value(value));
+    }
+
+    fn pop(&mut self) -> Result<Value> {
+        match self.stack.pop() {
+            Some(Item::Value(value)) => Ok(value),
+            _ => Err(eyre!("Pickle stack is empty")),
+        }
+    }
+
+    fn top(&mut self) -> Result<&mut Value> {
+        match self.stack.last_mut() {
+            Some(Item::Value(value)) => Ok(value),
+            _ => Err(eyre!("Pickle stack is empty")),
+        }
+    }
+
+    /// Pops the values above the topmost mark, and the mark
+    fn pop_mark(&mut self) -> Result<Vec<Value>> {
+        let mark = self
+            .stack
+            .iter()
+            .rposition(|item| matches!(item, Item::Mark))
+            .ok_or_else(|| eyre!("Pickle has no mark"))?;
+        let values = self
+            .stack
+            .drain(mark..)
+            .skip(1)
+            .filter_map(|item| match item {
+                Item::Value(value) => Some(value),
+                Item::Mark => None,
+            })
+            .collect();
+        Ok(values)
+    }
+
+    fn put(&mut self, id: usize) -> Result<()> {
+        let value = self.top()?.clone();
+        self.memo.insert(id, value);
+        Ok(())
+    }
+
+    fn get(&mut self, id: usize) -> Result<()> {
+        let value = self
+            .memo
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| eyre!("Pickle refers to unknown object {}", id))?;
+        self.push(value);
+        Ok(())
+    }
+
+    fn set_items(&mut self, items: Vec<Value>) -> Result<()> {
+        let target = self.top()?;
+        if let Value::Object(map) = target {
+            let mut items = items.into_iter();
+            while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                map.insert(key(k), v);
+            }
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, items: Vec<Value>) -> Result<()> {
+        if let Value::Array(list) = self.top()? {
+            list.extend(items);
+        }
+        Ok(())
+    }
+
+    /// Sets the state of the instance on top of the stack
+    fn build(&mut self) -> Result<()> {
+        let state = match self.pop()? {
+            // State given as a dict and a dict of slots
+            Value::Array(mut parts) if parts.len() == 2 => {
+                let slots = parts.pop().unwrap_or_default();
+                let mut state = parts.pop().unwrap_or_default();
+                if let (Value::Object(state), Value::Object(slots)) = (&mut state, slots) {
+                    state.extend(slots);
+                }
+                state
+            }
+            state => state,
+        };
+        let target = self.top()?;
+        match (target, state) {
+            (Value::Object(map), Value::Object(state)) => map.extend(state),
+            (target, state) => *target = state,
+        }
+        Ok(())
+    }
+}
+
+/// Reads a pickle into a JSON value
+pub fn load(bytes: &[u8]) -> Result<Value> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut machine = Machine {
+        stack: Vec::new(),
+        memo: HashMap::new(),
+    };
+    loop {
+        let opcode = reader.byte()?;
+        match opcode {
+            0x80 => {
+                reader.byte()?;
+            }
+            b'.' => return machine.pop(),
+            b'(' => machine.stack.push(Item::Mark),
+            b'}' => machine.push(Value::Object(Map::new())),
+            b']' | b')' => machine.push(Value::Array(Vec::new())),
+            b'N' => machine.push(Value::Null),
+            0x88 => machine.push(Value::Bool(true)),
+            0x89 => machine.push(Value::Bool(false)),
+            b'J' => machine.push(int(reader.u32()? as i32 as i64)),
+            b'K' => machine.push(int(reader.byte()? as i64)),
+            b'M' => machine.push(int(reader.u16()? as i64)),
+            b'I' => {
+                let line = reader.line()?;
+                machine.push(match line.as_str() {
+                    "00" => Value::Bool(false),
+                    "01" => Value::Bool(true),
+                    n => int(n.parse()?),
+                });
+            }
+            b'L' => {
+                let line = reader.line()?;
+                machine.push(int(line.trim_end_matches('L').parse()?));
+            }
+            0x8a => {
+                let len = reader.byte()? as usize;
+                let digits = reader.take(len)?;
+                let mut n: i64 = 0;
+                for (i, b) in digits.iter().enumerate().take(8) {
+                    n |= (*b as i64) << (8 * i);
+                }
+                if len > 0 && len < 8 && digits[len - 1] & 0x80 != 0 {
+                    n -= 1 << (8 * len);
+                }
+                machine.push(int(n));
+            }
+            b'G' => machine.push(float(f64::from_be_bytes(reader.take(8)?.try_into()?))),
+            b'F' => machine.push(float(reader.line()?.parse()?)),
+            b'X' | b'T' | b'B' => {
+                let len = reader.u32()? as usize;
+                let value = reader.string(len)?;
+                machine.push(value);
+            }
+            b'U' | b'C' => {
+                let len = reader.byte()? as usize;
+                let value = reader.string(len)?;
+                machine.push(value);
+            }
+            b'S' => machine.push(Value::String(unquote(&reader.line()?))),
+            b'V' => machine.push(Value::String(reader.line()?)),
+            b'q' => machine.put(reader.byte()? as usize)?,
+            b'r' => machine.put(reader.u32()? as usize)?,
+            b'p' => machine.put(reader.line()?.parse()?)?,
+            b'h' => machine.get(reader.byte()? as usize)?,
+            b'j' => machine.get(reader.u32()? as usize)?,
+            b'g' => machine.get(reader.line()?.parse()?)?,
+            b's' => {
+                let value = machine.pop()?;
+                let key = machine.pop()?;
+                machine.set_items(vec![key, value])?;
+            }
+            b'u' => {
+                let items = machine.pop_mark()?;
+                machine.set_items(items)?;
+            }
+            b'a' => {
+                let value = machine.pop()?;
+                machine.append(vec![value])?;
+            }
+            b'e' => {
+                let items = machine.pop_mark()?;
+                machine.append(items)?;
+            }
+            b't' => {
+                let items = machine.pop_mark()?;
+                machine.push(Value::Array(items));
+            }
+            0x85..=0x87 => {
+                let len = (opcode - 0x84) as usize;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(machine.pop()?);
+                }
+                items.reverse();
+                machine.push(Value::Array(items));
+            }
+            b'c' => {
+                let module = reader.line()?;
+                let name = reader.line()?;
+                machine.push(Value::String(format!("{}.{}", module, name)));
+            }
+            0x81 => {
+                machine.pop()?;
+                machine.pop()?;
+                machine.push(Value::Object(Map::new()));
+            }
+            b'R' => {
+                machine.pop()?;
+                machine.pop()?;
+                machine.push(Value::Null);
+            }
+            b'o' => {
+                machine.pop_mark()?;
+                machine.push(Value::Object(Map::new()));
+            }
+            b'i' => {
+                reader.line()?;
+                reader.line()?;
+                machine.pop_mark()?;
+                machine.push(Value::Object(Map::new()));
+            }
+            b'b' => machine.build()?,
+            b'0' => {
+                machine.stack.pop();
+            }
+            b'2' => {
+                let value = machine.top()?.clone();
+                machine.push(value);
+            }
+            _ => {
+                return Err(eyre!(
+                    "Unsupported pickle opcode 0x{:02x} at byte {}",
+                    opcode,
+                    reader.pos - 1
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn loads_instances() {
+        // pickle.dumps({'widgets': [tiddler], 'scale': 1.5, 'key': 'title'}, 2) for
+        // a tiddlywiki.Tiddler with title 'Start', tags ['a'], and text 'Hi'
+        let bytes: &[u8] =
+            b"\x80\x02}q\x00(X\x07\x00\x00\x00widgetsq\x01]q\x02ctiddlywiki\nTiddler\n\
+            q\x03)\x81q\x04}q\x05(X\x05\x00\x00\x00titleq\x06X\x05\x00\x00\x00Startq\x07X\x04\
+            \x00\x00\x00tagsq\x08]q\tX\x01\x00\x00\x00aq\naX\x04\x00\x00\x00textq\x0bX\x02\x00\
+            \x00\x00Hiq\x0cubaX\x05\x00\x00\x00scaleq\rG?\xf8\x00\x00\x00\x00\x00\x00X\x03\x00\
+            \x00\x00keyq\x0eh\x06u.";
+        assert_eq!(
+            load(bytes).unwrap(),
+            json!({
+                "widgets": [{ "title": "Start", "tags": ["a"], "text": "Hi" }],
+                "scale": 1.5,
+                "key": "title",
+            })
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Converts Twine 1 stories, published as HTML or saved as `.tws` archives,
+//! into decompiled stories
+//!
+//! Twine 1 keeps the title and settings of a story in the `StoryTitle` and
+//! `StorySettings` passages, and has no `StoryData`. These passages are
+//! turned into the title and a generated `StoryData`, using the IFID from
+//! `StorySettings` if it has one and `Start` as the start passage, as Twine 1
+//! always did. Script and stylesheet passages are kept as passages, since
+//! their tags mean the same to tweec
+
+use super::pickle;
+use super::{element_contents, find_tag, unescape, DecompiledPassage, DecompiledStory};
+use crate::ifid;
+use crate::legacy;
+use color_eyre::Result;
+use eyre::eyre;
+use serde_json::{Map, Value};
+
+/// The ids Twine 1 gives the element holding the passages of a published
+/// story
+const STORE_AREA_IDS: &[&str] = &["storearea", "store-area"];
+
+/// A passage read from a Twine 1 story
+#[derive(Debug)]
+struct Tiddler {
+    title: String,
+    tags: Vec<String>,
+    text: String,
+    position: Option<String>,
+}
+
+/// Decodes the escapes Twine 1 uses to keep passage text on one line
+fn decode_text(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => decoded.push('\n'),
+            Some('t') => decoded.push('\t'),
+            Some('s') => decoded.push('\\'),
+            Some(c) => {
+                decoded.push('\\');
+                decoded.push(c);
+            }
+            None => decoded.push('\\'),
+        }
+    }
+    decoded
+}
+
+/// Whether the given HTML is a story published by Twine 1
+pub fn is_html(html: &str) -> bool {
+    let lower = html.to_ascii_lowercase();
+    STORE_AREA_IDS
+        .iter()
+        .any(|id| lower.contains(&format!("id=\"{}\"", id)))
+}
+
+/// Whether the given bytes look like a Twine 1 `.tws` archive, which is a
+/// Python pickle
+pub fn is_archive(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&0x80) || bytes.starts_with(b"(dp")
+}
+
+/// Builds the decompiled story from a Twine 1 story's passages, returning it
+/// along with a description of what was converted
+fn story(tiddlers: Vec<Tiddler>) -> (DecompiledStory, Vec<String>) {
+    let mut notes = Vec::new();
+    let mut title = None;
+    let mut settings = Vec::new();
+    let mut passages = Vec::new();
+    for tiddler in tiddlers {
+        match tiddler.title.as_str() {
+            "StoryTitle" => title = Some(tiddler.text.trim().to_string()),
+            legacy::SETTINGS => settings = legacy::settings(&tiddler.text),
+            legacy::INCLUDES => notes.push(format!(
+                "Dropped StoryIncludes; decompile these stories separately: {}",
+                tiddler
+                    .text
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+            _ => {
+                let mut metadata = Map::new();
+                if let Some(position) = tiddler.position {
+                    metadata.insert("position".to_string(), position.into());
+                }
+                passages.push(DecompiledPassage {
+                    pid: None,
+                    name: tiddler.title,
+                    tags: tiddler.tags,
+                    metadata,
+                    content: tiddler.text,
+                });
+            }
+        }
+    }
+
+    let mut data = Map::new();
+    match settings.iter().find(|(key, _)| key == "ifid") {
+        Some((_, ifid)) => {
+            data.insert("ifid".to_string(), ifid.to_uppercase().into());
+        }
+        None => {
+            let ifid = ifid::generate();
+            notes.push(format!("Generated IFID {}", ifid));
+            data.insert("ifid".to_string(), ifid.into());
+        }
+    }
+    let ignored: Vec<&str> = settings
+        .iter()
+        .map(|(key, _)| key.as_str())
+        .filter(|key| *key != "ifid")
+        .collect();
+    if !ignored.is_empty() {
+        notes.push(format!(
+            "Dropped settings from StorySettings: {}",
+            ignored.join(", ")
+        ));
+    }
+    if passages.iter().any(|passage| passage.name == "Start") {
+        data.insert("start".to_string(), "Start".into());
+    }
+
+    let story = DecompiledStory {
+        title: title.unwrap_or_else(|| "Untitled Story".to_string()),
+        data,
+        script: String::new(),
+        stylesheet: String::new(),
+        passages,
+    };
+    (story, notes)
+}
+
+/// Reads a story published by Twine 1, whose passages are `div` elements with
+/// a `tiddler` attribute inside its store area
+pub fn parse_html(html: &str) -> Result<(DecompiledStory, Vec<String>)> {
+    let lower = html.to_ascii_lowercase();
+    let mut pos = 0;
+    let store = loop {
+        let tag = find_tag(html, &lower, pos, "div")
+            .ok_or_else(|| eyre!("No Twine 1 story data (storeArea) found"))?;
+        pos = tag.end;
+        let id = tag.get("id").unwrap_or_default().to_ascii_lowercase();
+        if STORE_AREA_IDS.contains(&id.as_str()) {
+            break tag;
+        }
+    };
+
+    let mut tiddlers = Vec::new();
+    let mut pos = store.end;
+    while let Some(tag) = find_tag(html, &lower, pos, "div") {
+        let contents = element_contents(html, &lower, &tag, "div");
+        pos = tag.end + contents.len();
+        let title = match tag.get("tiddler") {
+            Some(title) => title.to_string(),
+            None => continue,
+        };
+        tiddlers.push(Tiddler {
+            title,
+            tags: tag
+                .get("tags")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+            text: decode_text(&unescape(contents)),
+            position: tag
+                .get("twine-position")
+                .filter(|position| !position.is_empty())
+                .map(String::from),
+        });
+    }
+    if tiddlers.is_empty() {
+        return Err(eyre!("No Twine 1 passages found in the story data"));
+    }
+    Ok(story(tiddlers))
+}
+
+/// Reads a story saved by Twine 1 as a `.tws` archive
+///
+/// The archive pickles the editor's state, in which each passage is a widget
+/// on the story map holding the passage and its position
+pub fn parse_archive(bytes: &[u8]) -> Result<(DecompiledStory, Vec<String>)> {
+    let state = pickle::load(bytes)?;
+    let widgets = state["storyPanel"]["widgets"]
+        .as_array()
+        .ok_or_else(|| eyre!("No passages (storyPanel.widgets) found in the archive"))?;
+    let tiddlers = widgets
+        .iter()
+        .filter_map(|widget| {
+            let passage = &widget["passage"];
+            let position = match widget["pos"].as_array().map(Vec::as_slice) {
+                Some([x, y]) => Some(format!("{},{}", x.as_f64()?.round(), y.as_f64()?.round())),
+                _ => None,
+            };
+            Some(Tiddler {
+                title: passage["title"].as_str()?.to_string(),
+                tags: passage["tags"]
+                    .as_array()
+                    .map(|tags| {
+                        tags.iter()
+                            .filter_map(Value::as_str)
+                            .map(String::from)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                text: passage["text"].as_str().unwrap_or_default().to_string(),
+                position,
+            })
+        })
+        .collect();
+    Ok(story(tiddlers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompiles_published_html() {
+        let html = r#"<html><body><div id="storeArea" data-size="4" hidden>
+<div tiddler="StoryTitle" tags="" modifier="twee" twine-position="10,10">Old &amp; Gold</div>
+<div tiddler="StorySettings" tags="" twine-position="10,140">undo:on\nifid:0ac8a9c6-1a1a-4b2b-9c9c-0123456789ab</div>
+<div tiddler="Start" tags="intro" twine-position="200,10">Hi &lt;b&gt;\n\tpath\sname [[Next]]</div>
+<div tiddler="Style" tags="stylesheet" twine-position="">body { color: red; }</div>
+</div></body></html>"#;
+        assert!(is_html(html));
+        let (story, notes) = parse_html(html).unwrap();
+        assert_eq!(story.title, "Old & Gold");
+        assert_eq!(story.data["ifid"], "0AC8A9C6-1A1A-4B2B-9C9C-0123456789AB");
+        assert_eq!(story.data["start"], "Start");
+        assert_eq!(notes, vec!["Dropped settings from StorySettings: undo"]);
+        assert_eq!(story.passages.len(), 2);
+        assert_eq!(story.passages[0].content, "Hi <b>\n\tpath\\name [[Next]]");
+        assert_eq!(story.passages[0].metadata["position"], "200,10");
+        assert_eq!(story.passages[1].tags, vec!["stylesheet"]);
+        assert!(story.passages[1].metadata.is_empty());
+    }
+}
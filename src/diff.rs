@@ -0,0 +1,176 @@
+//! Produces unified diffs of text, as shown by `tweec fix --dry-run`
+
+/// Lines of unchanged text shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Largest number of line pairs compared when finding the lines two texts have
+/// in common. Past this, the changed region is shown as entirely replaced
+const MAX_COMPARISONS: usize = 4_000_000;
+
+/// A line of a diff
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Finds the lines added and removed going from `old` to `new`, keeping the
+/// longest run of lines the two have in common
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+    if a.len() * b.len() > MAX_COMPARISONS {
+        lines.extend(a.iter().map(|line| Line::Removed(line)));
+        lines.extend(b.iter().map(|line| Line::Added(line)));
+    } else {
+        // common[i][j] is the length of the longest common subsequence of
+        // a[i..] and b[j..]
+        let mut common = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                common[i][j] = if a[i] == b[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                lines.push(Line::Same(a[i]));
+                i += 1;
+                j += 1;
+            } else if common[i + 1][j] >= common[i][j + 1] {
+                lines.push(Line::Removed(a[i]));
+                i += 1;
+            } else {
+                lines.push(Line::Added(b[j]));
+                j += 1;
+            }
+        }
+        lines.extend(a[i..].iter().map(|line| Line::Removed(line)));
+        lines.extend(b[j..].iter().map(|line| Line::Added(line)));
+    }
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+    lines
+}
+
+/// Gets the start of a hunk's range for its header: the 1-based first line,
+/// or the line before it if the range is empty
+fn hunk_start(start: usize, count: usize) -> usize {
+    if count == 0 {
+        start
+    } else {
+        start + 1
+    }
+}
+
+/// Gets a unified diff from `old` to `new`, with the given names in its
+/// header, or an empty string if they are the same
+pub fn unified(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+    let changes: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group the changes into hunks, merging those whose context overlaps
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changes {
+        let start = i.saturating_sub(CONTEXT_LINES);
+        let end = (i + 1 + CONTEXT_LINES).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let (mut old_line, mut new_line, mut index) = (0, 0, 0);
+    for (start, end) in hunks {
+        for line in &lines[index..start] {
+            match line {
+                Line::Same(_) => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                Line::Removed(_) => old_line += 1,
+                Line::Added(_) => new_line += 1,
+            }
+        }
+        let hunk = &lines[start..end];
+        let old_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk_start(old_line, old_count),
+            old_count,
+            hunk_start(new_line, new_count),
+            new_count
+        ));
+        for line in hunk {
+            let (prefix, text) = match line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            out.push(prefix);
+            out.push_str(text);
+            if !text.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        old_line += old_count;
+        new_line += new_count;
+        index = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_hunks_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\ntwo\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\nthirteen";
+        assert_eq!(
+            unified("a", "b", old, new),
+            "--- a\n+++ b\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+thirteen\n\\ No newline at end of file\n"
+        );
+        assert_eq!(unified("a", "b", old, old), "");
+    }
+}
@@ -0,0 +1,79 @@
+//! The exit codes tweec returns, so that scripts can tell failures apart
+//!
+//! | Code | Meaning                                                          |
+//! |------|------------------------------------------------------------------|
+//! | 0    | Success                                                          |
+//! | 1    | The story has errors, such as parse errors or denied warnings    |
+//! | 2    | The command line or a config file is invalid                     |
+//! | 3    | Reading or writing a file failed                                 |
+//! | 4    | Anything else failed, such as the story format or a smoke test   |
+
+use std::fmt;
+
+/// The command succeeded
+pub const SUCCESS: u8 = 0;
+
+/// The story has errors, which have already been reported
+pub const STORY_ERRORS: u8 = 1;
+
+/// The command line or a config file is invalid
+pub const USAGE: u8 = 2;
+
+/// Reading or writing a file failed
+pub const IO: u8 = 3;
+
+/// Anything else failed
+pub const FAILURE: u8 = 4;
+
+/// An error in the story itself, after which tweec exits with
+/// [`STORY_ERRORS`]
+///
+/// [`STORY_ERRORS`]: constant.STORY_ERRORS.html
+#[derive(Debug)]
+pub struct StoryError(pub &'static str);
+
+impl fmt::Display for StoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for StoryError {}
+
+/// Gets the exit code for an error, which is `default` unless the error is
+/// caused by the story or by reading or writing a file
+pub fn code(report: &color_eyre::Report, default: u8) -> u8 {
+    if report.chain().any(|e| e.is::<StoryError>()) {
+        STORY_ERRORS
+    } else if report.chain().any(|e| e.is::<std::io::Error>()) {
+        IO
+    } else {
+        default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eyre::{eyre, WrapErr};
+
+    #[test]
+    fn maps_errors_to_codes() {
+        // Config errors exit with the default tweec::run gives them
+        let usage = crate::Config::builder().build().err().unwrap();
+        assert_eq!(code(&usage, USAGE), 2);
+
+        let io = std::fs::read("/nonexistent/story.twee")
+            .wrap_err("Failed to read the story")
+            .unwrap_err();
+        assert_eq!(code(&io, FAILURE), 3);
+        assert_eq!(code(&io, USAGE), 3);
+
+        let story = Err::<(), _>(StoryError("Failed to parse the story"))
+            .wrap_err("Failed to build")
+            .unwrap_err();
+        assert_eq!(code(&story, FAILURE), 1);
+
+        assert_eq!(code(&eyre!("Smoke test failed"), FAILURE), 4);
+    }
+}
@@ -0,0 +1,240 @@
+//! Exports the passage link graph in GraphViz DOT, Mermaid, or JSON format
+//!
+//! Each passage is a node labeled with its name and tags, and each passage it
+//! links to is an edge. In the drawn formats, the start passage is filled and
+//! passages with a tag given a color in `StoryData` are outlined in that
+//! color, as in the Twine editor. The JSON format is meant for custom tools,
+//! and gives every link separately along with where it is in the source.
+
+use crate::linter::graph::LinkGraph;
+use crate::utils;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fmt::Write;
+use tweep::FullContext;
+
+/// Escapes a string for use inside a quoted DOT ID
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Quotes a string as a DOT ID
+fn quote(text: &str) -> String {
+    format!("\"{}\"", escape(text))
+}
+
+/// Gets the first color given in `StoryData` to one of the tags
+fn tag_color<'a>(graph: &LinkGraph<'a>, tags: &[String]) -> Option<&'a String> {
+    let tag_colors = graph.story().data.as_ref()?.tag_colors.as_ref()?;
+    tags.iter().find_map(|tag| tag_colors.get(tag))
+}
+
+/// Passages linked to from the given passage, without repeats
+fn targets<'a>(graph: &LinkGraph<'a>, name: &str) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    graph
+        .outgoing(name)
+        .iter()
+        .map(|edge| edge.target)
+        .filter(|target| seen.insert(*target))
+        .collect()
+}
+
+/// Writes the graph in DOT format
+pub fn to_dot(graph: &LinkGraph) -> String {
+    let story = graph.story();
+    let title = story.title.as_deref().unwrap_or("Untitled Story");
+    let start = story.get_start_passage_name();
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph {} {{", quote(title)).unwrap();
+    writeln!(dot, "  node [shape=box];").unwrap();
+    for name in graph.passages() {
+        let tags = graph.tags(name);
+        // Tags go on a second line of the label
+        let mut label = escape(name);
+        if !tags.is_empty() {
+            label.push_str(&format!("\\n[{}]", escape(&tags.join(" "))));
+        }
+        let mut attributes = vec![
+            format!("label=\"{}\"", label),
+            format!("tags={}", quote(&tags.join(" "))),
+        ];
+        if let Some(color) = tag_color(graph, tags) {
+            attributes.push(format!("color={}", quote(color)));
+        }
+        if start == Some(name) {
+            attributes.push("style=filled".to_string());
+            attributes.push("fillcolor=\"palegreen\"".to_string());
+            attributes.push("peripheries=2".to_string());
+        }
+        writeln!(dot, "  {} [{}];", quote(name), attributes.join(", ")).unwrap();
+    }
+
+    // Several links to the same passage are drawn as one edge
+    for name in graph.passages() {
+        for target in targets(graph, name) {
+            writeln!(dot, "  {} -> {};", quote(name), quote(target)).unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Escapes a string for use inside a quoted Mermaid label
+fn escape_mermaid(text: &str) -> String {
+    text.replace('&', "#amp;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+/// Writes the graph as a Mermaid flowchart
+///
+/// Passage names can contain anything, so nodes are given IDs by their order
+/// and the names are used as labels
+pub fn to_mermaid(graph: &LinkGraph) -> String {
+    let start = graph.story().get_start_passage_name();
+    let passages = graph.passages();
+    let id = |name: &str| passages.iter().position(|p| *p == name).unwrap();
+
+    let mut mermaid = String::from("flowchart TD\n");
+    for (i, name) in passages.iter().enumerate() {
+        let tags = graph.tags(name);
+        let mut label = escape_mermaid(name);
+        if !tags.is_empty() {
+            label.push_str(&format!("<br/>[{}]", escape_mermaid(&tags.join(" "))));
+        }
+        writeln!(mermaid, "  p{}[\"{}\"]", i, label).unwrap();
+    }
+    for name in &passages {
+        for target in targets(graph, name) {
+            writeln!(mermaid, "  p{} --> p{}", id(name), id(target)).unwrap();
+        }
+    }
+    for (i, name) in passages.iter().enumerate() {
+        let mut styles = Vec::new();
+        if start == Some(name) {
+            styles.push("fill:palegreen".to_string());
+        }
+        if let Some(color) = tag_color(graph, graph.tags(name)) {
+            styles.push(format!("stroke:{}", color));
+        }
+        if !styles.is_empty() {
+            writeln!(mermaid, "  style p{} {}", i, styles.join(",")).unwrap();
+        }
+    }
+    mermaid
+}
+
+/// The location of a link in the source as JSON, with 1-based lines and
+/// columns
+fn span(context: &FullContext) -> Value {
+    let start = context.get_start_position();
+    let end = context.get_end_position();
+    json!({
+        "file": context.get_file_name(),
+        "start": { "line": start.line, "column": start.column },
+        "end": { "line": end.line, "column": end.column },
+    })
+}
+
+/// Writes the graph as JSON, with every passage and every link
+pub fn to_json(graph: &LinkGraph) -> String {
+    let story = graph.story();
+    let passages: Vec<Value> = graph
+        .passages()
+        .into_iter()
+        .map(|name| {
+            json!({
+                "name": name,
+                "pid": story.passages.get(name).map(utils::get_pid),
+                "tags": graph.tags(name),
+                "start": story.get_start_passage_name() == Some(name),
+            })
+        })
+        .collect();
+    let links: Vec<Value> = graph
+        .passages()
+        .into_iter()
+        .flat_map(|name| {
+            graph.outgoing(name).iter().map(move |edge| {
+                json!({
+                    "from": name,
+                    "to": edge.target,
+                    "span": span(&edge.link.context),
+                })
+            })
+        })
+        .collect();
+    let value = json!({
+        "title": story.title,
+        "passages": passages,
+        "links": links,
+    });
+    format!("{}\n", serde_json::to_string_pretty(&value).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::links::LinkSyntax;
+    use tweep::Story;
+
+    const INPUT: &str = r#":: StoryTitle
+A "Quoted" Story
+
+:: StoryData
+{"ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC", "start": "Start", "tag-colors": {"hub": "green"}}
+
+:: Start [intro hub]
+[[End]] [[Go->End]]
+
+:: End
+Done
+"#;
+
+    fn story() -> Story {
+        Story::from_string(INPUT.to_string()).take().0.ok().unwrap()
+    }
+
+    #[test]
+    fn writes_dot() {
+        let story = story();
+        let graph = LinkGraph::new(&story, LinkSyntax::DEFAULT);
+        assert_eq!(
+            to_dot(&graph),
+            r#"digraph "A \"Quoted\" Story" {
+  node [shape=box];
+  "End" [label="End", tags=""];
+  "Start" [label="Start\n[intro hub]", tags="intro hub", color="green", style=filled, fillcolor="palegreen", peripheries=2];
+  "Start" -> "End";
+}
+"#
+        );
+    }
+
+    #[test]
+    fn writes_mermaid_and_json() {
+        let story = story();
+        let graph = LinkGraph::new(&story, LinkSyntax::DEFAULT);
+        assert_eq!(
+            to_mermaid(&graph),
+            r#"flowchart TD
+  p0["End"]
+  p1["Start<br/>[intro hub]"]
+  p1 --> p0
+  style p1 fill:palegreen,stroke:green
+"#
+        );
+
+        let json: Value = serde_json::from_str(&to_json(&graph)).unwrap();
+        assert_eq!(json["passages"][1]["name"], "Start");
+        assert_eq!(json["passages"][1]["tags"], json!(["intro", "hub"]));
+        assert_eq!(json["passages"][1]["start"], true);
+        let links = json["links"].as_array().unwrap();
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[1]["to"], "End");
+        assert_eq!(links[1]["span"]["start"], json!({ "line": 8, "column": 9 }));
+    }
+}
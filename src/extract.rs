@@ -0,0 +1,174 @@
+//! Extracts a passage and its linked neighborhood into a standalone story
+
+use crate::twee;
+use serde_json::Map;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use tweep::Story;
+
+use color_eyre::Result;
+use eyre::eyre;
+
+/// Finds the names of all passages within `depth` links of the passage with
+/// the given name, including the passage itself
+///
+/// Links to passages that do not exist are ignored. The returned set is sorted
+/// by name so that the extracted story is stable between runs
+pub fn neighborhood(story: &Story, passage: &str, depth: usize) -> Result<BTreeSet<String>> {
+    if !story.passages.contains_key(passage) {
+        return Err(eyre!("No passage named \"{}\" found in story", passage));
+    }
+
+    let mut found = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    found.insert(passage.to_string());
+    queue.push_back((passage, 0));
+    while let Some((name, distance)) = queue.pop_front() {
+        if distance >= depth {
+            continue;
+        }
+        for link in story.passages[name].content.get_links() {
+            let target = link.target.trim();
+            if let Some((target, _)) = story.passages.get_key_value(target) {
+                if found.insert(target.clone()) {
+                    queue.push_back((target.as_str(), distance + 1));
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Creates the Twee source for a standalone story containing the named passage
+/// and every passage within `depth` links of it
+///
+/// The extracted story gets a synthesized `StoryTitle` and `StoryData` with the
+/// named passage as its start passage. Passages that are linked to from the
+/// excerpt but fall outside of it are replaced with stubs so the excerpt does
+/// not contain any dead links. The story's scripts and stylesheets are always
+/// included
+pub fn extract(story: &Story, passage: &str, depth: usize) -> Result<String> {
+    let names = neighborhood(story, passage, depth)?;
+    let mut output = String::new();
+
+    let title = story.title.as_deref().unwrap_or("Untitled Story");
+    output.push_str(&format!(":: StoryTitle\n{} (excerpt)\n\n", title));
+
+    let mut data = serde_json::Map::new();
+    if let Some(story_data) = &story.data {
+        if let serde_json::Value::Object(map) = serde_json::to_value(story_data)? {
+            data = map;
+        }
+    }
+    data.retain(|_, v| !v.is_null());
+    data.insert("start".to_string(), passage.into());
+    output.push_str(&format!(
+        ":: StoryData\n{}\n\n",
+        serde_json::to_string_pretty(&data)?
+    ));
+
+    let script_tags = vec!["script".to_string()];
+    for (i, script) in story.scripts.iter().enumerate() {
+        let name = format!("Story JavaScript {}", i + 1);
+        output.push_str(&twee::passage(&name, &script_tags, &Map::new(), script));
+    }
+
+    let stylesheet_tags = vec!["stylesheet".to_string()];
+    for (i, stylesheet) in story.stylesheets.iter().enumerate() {
+        let name = format!("Story Stylesheet {}", i + 1);
+        output.push_str(&twee::passage(
+            &name,
+            &stylesheet_tags,
+            &Map::new(),
+            stylesheet,
+        ));
+    }
+
+    let mut stubs = HashSet::new();
+    for name in &names {
+        let twine = &story.passages[name];
+        output.push_str(&twee::passage(
+            name,
+            &twine.header.tags,
+            &twine.header.metadata,
+            &twine.content.content,
+        ));
+        for link in twine.content.get_links() {
+            let target = link.target.trim();
+            if story.passages.contains_key(target) && !names.contains(target) {
+                stubs.insert(target);
+            }
+        }
+    }
+
+    let mut stubs: Vec<&str> = stubs.into_iter().collect();
+    stubs.sort_unstable();
+    for stub in stubs {
+        output.push_str(&twee::passage(
+            stub,
+            &[],
+            &Map::new(),
+            "This passage was not included in the excerpt.",
+        ));
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn story() -> Story {
+        let input = r#":: StoryTitle
+Test
+
+:: StoryData
+{ "ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC" }
+
+:: Start
+[[A]] [[Missing]]
+
+:: A
+[[B]]
+
+:: B
+[[C]]
+
+:: C
+The end
+"#;
+        Story::from_string(input.to_string()).take().0.ok().unwrap()
+    }
+
+    #[test]
+    fn depth_limits_neighborhood() {
+        let story = story();
+        let names: Vec<String> = neighborhood(&story, "Start", 0)
+            .ok()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(names, vec!["Start"]);
+        let names: Vec<String> = neighborhood(&story, "Start", 2)
+            .ok()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(names, vec!["A", "B", "Start"]);
+        assert!(neighborhood(&story, "Nope", 1).is_err());
+    }
+
+    #[test]
+    fn excerpt_is_standalone() {
+        let story = story();
+        let source = extract(&story, "A", 0).ok().unwrap();
+        let (res, warnings) = Story::from_string(source).take();
+        let excerpt = res.ok().unwrap();
+        assert_eq!(excerpt.title.as_deref(), Some("Test (excerpt)"));
+        assert_eq!(excerpt.get_start_passage_name(), Some("A"));
+        assert!(excerpt.passages.contains_key("B"));
+        assert!(!excerpt.passages.contains_key("C"));
+        assert!(warnings.iter().all(|w| w.get_name() != "DeadLink"));
+    }
+}
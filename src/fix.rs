@@ -0,0 +1,196 @@
+//! Applies the fixes suggested for the story's issues to its source files
+//!
+//! `tweec fix` applies every fix that is certainly right, such as trimming
+//! whitespace around a link's target, and removes repeated tags and metadata
+//! keys from passage headers. With `--aggressive`, fixes that may be wrong are
+//! applied too, such as changing a dead link to the passage with the most
+//! similar name. With `--dry-run`, a unified diff of each file is printed
+//! instead of changing it. See [`Applicability`]
+//!
+//! [`Applicability`]: ../issue/enum.Applicability.html
+
+use crate::diff;
+use crate::inputs;
+use crate::issue::Applicability;
+use crate::linter;
+use crate::linter::headers;
+use crate::utils;
+use crate::Config;
+use crate::FixConfig;
+use crate::StoryFiles;
+use color_eyre::Result;
+use eyre::WrapErr;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
+use std::path::PathBuf;
+use termcolor::StandardStream;
+
+/// A replacement of some of a file's text
+#[derive(Clone, Debug, PartialEq)]
+struct Edit {
+    /// The bytes to replace
+    range: Range<usize>,
+
+    /// The text expected in the range, so that the edit isn't applied to a
+    /// file that changed after it was parsed
+    expected: String,
+
+    replacement: String,
+    title: String,
+}
+
+/// Applies the edits to `source` that fit it and don't overlap an earlier
+/// edit, returning the edited text and the edits applied, in order
+fn apply<'a>(source: &str, edits: &'a [Edit]) -> (String, Vec<&'a Edit>) {
+    let mut edits: Vec<&Edit> = edits.iter().collect();
+    edits.sort_by_key(|edit| (edit.range.start, edit.range.end));
+    let mut fixed = String::with_capacity(source.len());
+    let mut applied = Vec::new();
+    let mut end = 0;
+    for edit in edits {
+        if edit.range.start < end || source.get(edit.range.clone()) != Some(&edit.expected) {
+            continue;
+        }
+        fixed.push_str(&source[end..edit.range.start]);
+        fixed.push_str(&edit.replacement);
+        end = edit.range.end;
+        applied.push(edit);
+    }
+    fixed.push_str(&source[end..]);
+    (fixed, applied)
+}
+
+/// Gets the 1-based line and column of a byte offset in `source`
+fn line_and_column(source: &str, byte: usize) -> (usize, usize) {
+    let before = &source[..byte];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, byte - line_start + 1)
+}
+
+/// Lints the story and applies the fixes suggested for its issues, printing
+/// each fix to `stdout`, along with a diff of each file for a dry run
+pub fn fix(config: &Config, fix: &FixConfig, stdout: &mut StandardStream) -> Result<()> {
+    let (story_result, issues, _) = linter::collect(
+        inputs::read_story(&config.inputs, &config.extensions),
+        config,
+    );
+    let story_files = StoryFiles::new(&story_result);
+    let paths = utils::file_paths(&config.inputs, &config.extensions);
+
+    let mut edits: HashMap<PathBuf, Vec<Edit>> = HashMap::new();
+    for suggestion in issues.iter().flat_map(|issue| issue.fixes(&story_files)) {
+        if suggestion.applicability == Applicability::MaybeIncorrect && !fix.aggressive {
+            continue;
+        }
+        let path = match suggestion
+            .context
+            .get_file_name()
+            .as_ref()
+            .and_then(|name| paths.get(name))
+        {
+            Some(path) => path.clone(),
+            None => continue,
+        };
+        edits.entry(path).or_default().push(Edit {
+            range: suggestion.context.get_byte_range(),
+            expected: suggestion.context.get_contents().to_string(),
+            replacement: suggestion.replacement,
+            title: suggestion.title,
+        });
+    }
+
+    let (mut fixes, mut files) = (0, 0);
+    for path in inputs::twee_files(&config.inputs, &config.extensions) {
+        let source = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read input file {:?}", path))?;
+        let (fixed, applied) = apply(&source, edits.get(&path).map_or(&[], Vec::as_slice));
+        let (fixed, headers) = headers::fix_source(&fixed);
+        if applied.is_empty() && headers == 0 {
+            continue;
+        }
+
+        let display = path.to_string_lossy();
+        for edit in &applied {
+            let (line, column) = line_and_column(&source, edit.range.start);
+            writeln!(stdout, "{}:{}:{}: {}", display, line, column, edit.title)?;
+        }
+        if headers > 0 {
+            writeln!(
+                stdout,
+                "{}: Removed repeated tags or metadata keys from {} passage header{}",
+                display,
+                headers,
+                if headers == 1 { "" } else { "s" }
+            )?;
+        }
+        if fix.dry_run {
+            let name = path.to_string_lossy().replace('\\', "/");
+            write!(
+                stdout,
+                "{}",
+                diff::unified(
+                    &format!("a/{}", name),
+                    &format!("b/{}", name),
+                    &source,
+                    &fixed
+                )
+            )?;
+        } else {
+            utils::write_atomic(&path, fixed.as_bytes())
+                .wrap_err_with(|| format!("Failed to write input file {:?}", path))?;
+        }
+        fixes += applied.len() + headers;
+        files += 1;
+    }
+
+    if fixes == 0 {
+        writeln!(stdout, "Nothing to fix")?;
+    } else {
+        let verb = if fix.dry_run {
+            "Would apply"
+        } else {
+            "Applied"
+        };
+        writeln!(
+            stdout,
+            "{} {} fix{} in {} file{}",
+            verb,
+            fixes,
+            if fixes == 1 { "" } else { "es" },
+            files,
+            if files == 1 { "" } else { "s" }
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(range: Range<usize>, expected: &str, replacement: &str) -> Edit {
+        Edit {
+            range,
+            expected: expected.to_string(),
+            replacement: replacement.to_string(),
+            title: String::new(),
+        }
+    }
+
+    #[test]
+    fn applies_edits_that_fit() {
+        let source = "Go [[ Cave]] or [[Beech]]";
+        let edits = vec![
+            edit(16..25, "[[Beech]]", "[[Beach]]"),
+            edit(3..12, "[[ Cave]]", "[[Cave]]"),
+            // Overlaps the edit before it
+            edit(5..8, " Ca", "Ca"),
+            // No longer matches the source
+            edit(0..2, "So", "Then"),
+        ];
+        let (fixed, applied) = apply(source, &edits);
+        assert_eq!(fixed, "Go [[Cave]] or [[Beach]]");
+        assert_eq!(applied, vec![&edits[1], &edits[0]]);
+    }
+}
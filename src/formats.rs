@@ -0,0 +1,610 @@
+//! Lists, installs, and removes story formats in the configured format paths,
+//! and checks formats being written
+//!
+//! Formats that came with the Twine 2 desktop app are found too, so a story
+//! can be built without installing anything where Twine is installed. See
+//! [`twine_format_paths`]. With the `bundled-formats` feature, the latest Harlowe and SugarCube
+//! formats are built into tweec. See [`add_bundled`]. A format given by URL is
+//! downloaded once and cached. See [`fetch_cached`]
+//!
+//! [`add_bundled`]: fn.add_bundled.html
+//! [`fetch_cached`]: fn.fetch_cached.html
+//! [`twine_format_paths`]: fn.twine_format_paths.html
+
+use crate::story_format;
+use crate::utils;
+use crate::ConfigFile;
+use crate::StoryFormat;
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A story format that can be installed by name, as bundled with a release of
+/// Twine 2
+struct KnownFormat {
+    /// The name used to install the format, which is also the directory it
+    /// is installed to (e.g., sugarcube-2)
+    key: &'static str,
+
+    /// The name of the format's directory in the Twine repository
+    stem: &'static str,
+
+    /// The version of the format
+    version: &'static str,
+
+    /// The Twine release that bundles this version of the format
+    twine: &'static str,
+}
+
+impl KnownFormat {
+    fn url(&self) -> String {
+        format!(
+            "https://raw.githubusercontent.com/klembot/twinejs/{}/public/story-formats/{}-{}/format.js",
+            self.twine, self.stem, self.version
+        )
+    }
+}
+
+const fn known(
+    key: &'static str,
+    stem: &'static str,
+    version: &'static str,
+    twine: &'static str,
+) -> KnownFormat {
+    KnownFormat {
+        key,
+        stem,
+        version,
+        twine,
+    }
+}
+
+/// Formats that can be installed by name, oldest version first
+const KNOWN_FORMATS: &[KnownFormat] = &[
+    known("chapbook-1", "chapbook", "1.2.3", "2.7.1"),
+    known("chapbook-2", "chapbook", "2.2.0", "2.10.0"),
+    known("harlowe-3", "harlowe", "3.3.7", "2.7.1"),
+    known("harlowe-3", "harlowe", "3.3.9", "2.10.0"),
+    known("paperthin-1", "paperthin", "1.0.0", "2.10.0"),
+    known("snowman-2", "snowman", "2.0.2", "2.10.0"),
+    known("sugarcube-2", "sugarcube", "2.36.1", "2.7.1"),
+    known("sugarcube-2", "sugarcube", "2.37.3", "2.10.0"),
+];
+
+/// Story formats built into tweec, by key, with the source of their format.js
+#[cfg(feature = "bundled-formats")]
+const BUNDLED_FORMATS: &[(&str, &str)] = &[
+    (
+        "harlowe-3",
+        include_str!(concat!(env!("OUT_DIR"), "/harlowe-3/format.js")),
+    ),
+    (
+        "sugarcube-2",
+        include_str!(concat!(env!("OUT_DIR"), "/sugarcube-2/format.js")),
+    ),
+];
+
+/// Story formats built into tweec, by key, with the source of their format.js
+#[cfg(not(feature = "bundled-formats"))]
+const BUNDLED_FORMATS: &[(&str, &str)] = &[];
+
+/// Gets the directories the Twine 2 desktop app keeps its story formats in,
+/// where it is installed by default on this OS
+///
+/// Twine lays formats out as tweec does, one directory per format holding its
+/// format.js, but names each directory after the format and its full version
+/// (e.g., `sugarcube-2.37.3`). Those names are the formats' keys, and formats
+/// are also found by the name and version in their format.js, so they can be
+/// used as `--format sugarcube-2` or `--format SugarCube@2.37.3`
+pub fn twine_format_paths() -> Vec<PathBuf> {
+    let app_dirs: Vec<PathBuf> = if cfg!(target_os = "windows") {
+        let env_dir = |name| std::env::var_os(name).map(PathBuf::from);
+        // Twine is installed for one user under Programs, or for everyone
+        vec![
+            env_dir("LOCALAPPDATA").map(|dir| dir.join("Programs").join("Twine")),
+            env_dir("ProgramFiles").map(|dir| dir.join("Twine")),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("resources"))
+        .collect()
+    } else if cfg!(target_os = "macos") {
+        let app = Path::new("Applications")
+            .join("Twine.app")
+            .join("Contents")
+            .join("Resources");
+        dirs_next::home_dir()
+            .into_iter()
+            .chain(Some(PathBuf::from("/")))
+            .map(|root| root.join(&app))
+            .collect()
+    } else {
+        vec![PathBuf::from("/opt/Twine/resources")]
+    };
+    app_dirs
+        .into_iter()
+        .map(|dir| dir.join("app").join("story-formats"))
+        .collect()
+}
+
+/// Gets the directory tweec caches story formats in, under its data directory
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs_next::data_dir().map(|dir| dir.join("tweec").join("cache"))
+}
+
+/// Adds the formats built into tweec to `formats`, the formats found in the
+/// format paths, by key
+///
+/// A bundled format is left out if a format of the same name and major
+/// version was found, such as `sugarcube-2` or `SugarCube-2.36.1` for the
+/// bundled `sugarcube-2`, so installed formats always take precedence. Each bundled format used is
+/// written to the cache directory, so it can be read like any other
+pub fn add_bundled(formats: &mut HashMap<String, PathBuf>) {
+    if let Some(dir) = cache_dir() {
+        add_formats(formats, BUNDLED_FORMATS, &dir.join("bundled"));
+    }
+}
+
+/// Adds the given formats that were not found to `formats`, writing them to
+/// `dir` if they aren't there already
+fn add_formats(formats: &mut HashMap<String, PathBuf>, bundled: &[(&str, &str)], dir: &Path) {
+    for (key, source) in bundled {
+        // Installed formats of other major versions (e.g., harlowe-2) don't
+        // replace the bundled one
+        let same_version = |found: &String| {
+            let found = found.to_lowercase();
+            found == *key || found.starts_with(&format!("{}.", key))
+        };
+        if formats.keys().any(same_version) {
+            continue;
+        }
+
+        let format_path = dir.join(key).join("format.js");
+        let written = std::fs::read_to_string(&format_path).is_ok_and(|old| old == *source);
+        if !written {
+            let result = std::fs::create_dir_all(dir.join(key))
+                .and_then(|_| utils::write_atomic(&format_path, source.as_bytes()));
+            if let Err(e) = result {
+                warn!(
+                    "Failed to write bundled story format {} to {:?}: {}",
+                    key, format_path, e
+                );
+                continue;
+            }
+        }
+        debug!("Using bundled story format {} at {:?}", key, format_path);
+        formats.insert(key.to_string(), format_path);
+    }
+}
+
+/// Splits a format given as `name@version` into its name and version
+fn split_version(format: &str) -> (&str, Option<&str>) {
+    match format.rfind('@') {
+        Some(at) => (&format[..at], Some(&format[at + 1..])),
+        None => (format, None),
+    }
+}
+
+/// Gets the URL to download a known format from, choosing the latest version
+/// if none is given
+fn known_url(name: &str, version: Option<&str>) -> Result<String> {
+    let versions: Vec<&KnownFormat> = KNOWN_FORMATS
+        .iter()
+        .filter(|format| format.key.eq_ignore_ascii_case(name))
+        .collect();
+    if versions.is_empty() {
+        let mut keys: Vec<&str> = KNOWN_FORMATS.iter().map(|format| format.key).collect();
+        keys.dedup();
+        return Err(eyre!(
+            "Unknown story format {} (known formats: {}). Give a URL or path to its format.js \
+             to install it",
+            name,
+            keys.join(", ")
+        ));
+    }
+
+    let format = match version {
+        Some(version) => versions
+            .iter()
+            .find(|format| format.version == version)
+            .ok_or_else(|| {
+                let known: Vec<&str> = versions.iter().map(|format| format.version).collect();
+                eyre!(
+                    "Unknown version {} of {} (known versions: {}). Give a URL or path to its \
+                     format.js to install it",
+                    version,
+                    name,
+                    known.join(", ")
+                )
+            })?,
+        None => versions.last().unwrap(),
+    };
+    Ok(format.url())
+}
+
+/// Whether a format is given by the URL of its format.js
+pub fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Reads the contents of a format.js file from a URL or local path
+///
+/// A format is only downloaded over plain HTTP if it is pinned to a SHA-256
+/// hash, which the download must then have, since anyone along the way could
+/// have changed it
+fn fetch(source: &str, sha256: Option<&str>) -> Result<String> {
+    if source.starts_with("http://") && sha256.is_none() {
+        return Err(eyre!(
+            "Refusing to download story format {} over plain HTTP. Use an https URL, or pin the \
+             format's sha256 in {}",
+            source,
+            crate::project::FILE_NAME
+        ));
+    }
+    let contents = if is_url(source) {
+        ureq::get(source)
+            .call()
+            .wrap_err_with(|| format!("Failed to download {}", source))?
+            .into_string()
+            .wrap_err_with(|| format!("Failed to download {}", source))?
+    } else {
+        std::fs::read_to_string(source).wrap_err_with(|| format!("Failed to read {}", source))?
+    };
+    if let Some(expected) = sha256 {
+        let actual = story_format::sha256(contents.as_bytes());
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(eyre!(
+                "Story format {} has SHA-256 hash {}, but the project pins {}",
+                source,
+                actual,
+                expected
+            ));
+        }
+    }
+    Ok(contents)
+}
+
+/// Gets the name of the directory the format at a URL is cached in: the URL
+/// with characters that aren't safe in file names replaced, shortened, and
+/// followed by a hash of the whole URL so that URLs that look the same once
+/// replaced are kept apart
+fn cache_key(url: &str) -> String {
+    let readable: String = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .take(64)
+        .collect();
+
+    // 64-bit FNV-1a, which stays the same across builds
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{}-{:016x}", readable, hash)
+}
+
+/// Gets the path the format.js at `url` is cached at, whether or not it has
+/// been downloaded yet. See [`fetch_cached`]
+///
+/// [`fetch_cached`]: fn.fetch_cached.html
+pub fn cache_path(url: &str) -> Result<PathBuf> {
+    Ok(cache_dir()
+        .ok_or_else(|| eyre!("Failed to find the data directory to cache story formats in"))?
+        .join("remote")
+        .join(cache_key(url))
+        .join("format.js"))
+}
+
+/// Gets the path of a cached copy of the format.js at `url`, downloading it
+/// first if it isn't cached or `refresh` is true
+///
+/// A download is checked to be a valid story format, with the SHA-256 hash
+/// `sha256` if one is given, before it is cached, so a failed, partial, or
+/// tampered download never replaces a good copy. Plain HTTP URLs need a hash
+pub fn fetch_cached(url: &str, refresh: bool, sha256: Option<&str>) -> Result<PathBuf> {
+    let format_path = cache_path(url)?;
+    if !refresh && format_path.is_file() {
+        debug!("Using cached story format {} at {:?}", url, format_path);
+        return Ok(format_path);
+    }
+
+    info!("Downloading story format {}", url);
+    let contents = fetch(url, sha256)?;
+    StoryFormat::from_source(&contents)
+        .wrap_err_with(|| format!("{} is not a valid story format", url))?;
+    let dir = format_path.parent().unwrap();
+    std::fs::create_dir_all(dir)
+        .wrap_err_with(|| format!("Failed to create directory {:?}", dir))?;
+    let _lock = utils::FileLock::acquire(&format_path)
+        .wrap_err_with(|| format!("Failed to lock {:?}", format_path))?;
+    utils::write_atomic(&format_path, contents.as_bytes())
+        .wrap_err_with(|| format!("Failed to write {:?}", format_path))?;
+    Ok(format_path)
+}
+
+/// Installs a story format, returning the path of its format.js
+///
+/// `source` may be the URL or path of a format.js file, or the name of a known
+/// format with an optional version (e.g., `sugarcube-2@2.36.1`). The format is
+/// installed to `dir`, or the first configured format path if not given. An
+/// installed format with the same name and major version is replaced
+pub fn install(config_file: &ConfigFile, source: &str, dir: Option<&Path>) -> Result<PathBuf> {
+    // Formats installed by name are checked to be the format asked for
+    let (url, expected) = if is_url(source) || Path::new(source).exists() {
+        (source.to_string(), None)
+    } else {
+        let (name, version) = split_version(source);
+        (known_url(name, version)?, Some(name))
+    };
+
+    let contents = fetch(&url, None)?;
+    let format = StoryFormat::from_source(&contents)
+        .wrap_err_with(|| format!("{} is not a valid story format", url))?;
+    if let Some(name) = expected {
+        if !format.family().eq_ignore_ascii_case(name) {
+            return Err(eyre!(
+                "Downloaded {} {} instead of {}",
+                format.name,
+                format.version,
+                source
+            ));
+        }
+    }
+
+    let dir = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => config_file
+            .format_paths
+            .first()
+            .cloned()
+            .ok_or_else(|| eyre!("No format paths are configured to install to"))?,
+    };
+    let format_dir = dir.join(format.family());
+    std::fs::create_dir_all(&format_dir)
+        .wrap_err_with(|| format!("Failed to create directory {:?}", format_dir))?;
+
+    let format_path = format_dir.join("format.js");
+    let _lock = utils::FileLock::acquire(&format_path)
+        .wrap_err_with(|| format!("Failed to lock {:?}", format_path))?;
+    let replaced = StoryFormat::parse(&format_path).ok();
+    utils::write_atomic(&format_path, contents.as_bytes())
+        .wrap_err_with(|| format!("Failed to write {:?}", format_path))?;
+
+    match replaced {
+        Some(old) => println!(
+            "Replaced {} {} with {} {} in {:?}",
+            old.name, old.version, format.name, format.version, format_dir
+        ),
+        None => println!(
+            "Installed {} {} to {:?}",
+            format.name, format.version, format_dir
+        ),
+    }
+    Ok(format_path)
+}
+
+/// Prints every format found in the configured format paths, with the name,
+/// version, and author read from its format.js
+///
+/// Formats that cannot be parsed are listed with the reason instead
+pub fn list(config_file: &ConfigFile) -> Result<()> {
+    let mut keys: Vec<&String> = config_file.formats.keys().collect();
+    keys.sort();
+
+    let rows: Vec<Vec<String>> = keys
+        .into_iter()
+        .map(|key| {
+            let path = &config_file.formats[key];
+            let path_text = path.to_string_lossy().into_owned();
+            match StoryFormat::parse(path) {
+                Ok(format) => vec![
+                    key.clone(),
+                    format.name,
+                    format.version,
+                    format.author.unwrap_or_default(),
+                    path_text,
+                ],
+                Err(e) => vec![
+                    key.clone(),
+                    format!("(invalid: {})", e),
+                    String::new(),
+                    String::new(),
+                    path_text,
+                ],
+            }
+        })
+        .collect();
+    if rows.is_empty() {
+        println!(
+            "No story formats found. Searched: {}",
+            config_file
+                .format_paths
+                .iter()
+                .chain(&twine_format_paths())
+                .map(|path| path.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        return Ok(());
+    }
+
+    print!(
+        "{}",
+        utils::format_table(&["KEY", "NAME", "VERSION", "AUTHOR", "PATH"], &rows)
+    );
+    Ok(())
+}
+
+/// Removes an installed story format
+///
+/// `format` is the format's key (the name of its directory), or its name with
+/// an optional version (e.g., `SugarCube@2.36.1`). Only formats in one of the
+/// configured format paths are removed, never those found in Twine's own
+/// directories or built into tweec
+pub fn remove(config_file: &ConfigFile, format: &str) -> Result<()> {
+    let key = StoryFormat::resolve_request(&config_file.formats, format)?
+        .ok_or_else(|| eyre!("Story format {} is not installed", format))?;
+    let format_path = &config_file.formats[key];
+    let format_dir = format_path
+        .parent()
+        .ok_or_else(|| eyre!("Failed to find the directory of {:?}", format_path))?;
+    let canonical = format_dir
+        .canonicalize()
+        .wrap_err_with(|| format!("Failed to find {:?}", format_dir))?;
+    let installed = config_file.format_paths.iter().any(|dir| {
+        dir.canonicalize()
+            .is_ok_and(|dir| canonical.starts_with(&dir) && canonical != dir)
+    });
+    if !installed {
+        return Err(eyre!(
+            "Story format {} at {:?} is not in any of the format paths, so it was not \
+             installed by tweec and is not removed",
+            key,
+            format_dir
+        ));
+    }
+    std::fs::remove_dir_all(format_dir)
+        .wrap_err_with(|| format!("Failed to remove {:?}", format_dir))?;
+    println!("Removed {} from {:?}", key, format_dir);
+    Ok(())
+}
+
+/// Checks a format.js against the Twine 2 spec, printing every problem found
+///
+/// Fails if there are any problems, so that a script can check a format as it
+/// is built
+pub fn check(path: &Path) -> Result<()> {
+    let contents =
+        std::fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {:?}", path))?;
+    let problems = StoryFormat::check_source(&contents);
+    if problems.is_empty() {
+        let format = StoryFormat::from_source(&contents)?;
+        println!(
+            "{}: {} {} is a valid story format",
+            path.to_string_lossy(),
+            format.name,
+            format.version
+        );
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}: {}", path.to_string_lossy(), problem);
+    }
+    Err(eyre!(
+        "Found {} problem{} in {:?}",
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" },
+        path
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installs_and_removes_formats() {
+        let dir = std::env::temp_dir().join(format!("tweec-formats-{}", std::process::id()));
+        let source = dir.join("source.js");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &source,
+            r#"window.storyFormat({"name":"My Format","version":"2.1.0","source":"{{STORY_DATA}}"});"#,
+        )
+        .unwrap();
+
+        let mut config_file = ConfigFile {
+            formats: HashMap::new(),
+            format_paths: vec![dir.join("formats")],
+            format_configs: HashMap::new(),
+            proofing_format: None,
+            extensions: None,
+            build_args: Vec::new(),
+            opener: None,
+        };
+        let path = install(&config_file, source.to_str().unwrap(), None).unwrap();
+        assert_eq!(path, dir.join("formats/my-format-2/format.js"));
+        assert_eq!(StoryFormat::parse(&path).unwrap().version, "2.1.0");
+
+        config_file
+            .formats
+            .insert("my-format-2".to_string(), path.clone());
+        remove(&config_file, "My Format@2.1.0").unwrap();
+        assert!(!dir.join("formats/my-format-2").exists());
+
+        // Formats outside the format paths, such as Twine's own, are kept
+        let twine = dir.join("twine/harlowe-3.3.9/format.js");
+        std::fs::create_dir_all(twine.parent().unwrap()).unwrap();
+        std::fs::copy(&source, &twine).unwrap();
+        config_file
+            .formats
+            .insert("harlowe-3.3.9".to_string(), twine.clone());
+        assert!(remove(&config_file, "harlowe-3.3.9").is_err());
+        assert!(twine.exists());
+
+        assert!(known_url("sugarcube-2", Some("0.1.0")).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn adds_bundled_formats_not_installed() {
+        let dir = std::env::temp_dir().join(format!("tweec-bundled-{}", std::process::id()));
+        let bundled = &[("harlowe-3", "harlowe"), ("sugarcube-2", "sugarcube")];
+        let mut formats = HashMap::new();
+        formats.insert(
+            "SugarCube-2.36.1".to_string(),
+            PathBuf::from("installed/format.js"),
+        );
+        // Other major versions of Harlowe don't hide the bundled Harlowe 3
+        formats.insert("harlowe-2".to_string(), PathBuf::from("h2/format.js"));
+        formats.insert("Harlowe-1.2.4".to_string(), PathBuf::from("h1/format.js"));
+        add_formats(&mut formats, bundled, &dir);
+
+        let harlowe = dir.join("harlowe-3/format.js");
+        assert_eq!(formats.get("harlowe-3"), Some(&harlowe));
+        assert_eq!(std::fs::read_to_string(&harlowe).unwrap(), "harlowe");
+        assert!(!formats.contains_key("sugarcube-2"));
+
+        let mut formats = HashMap::new();
+        formats.insert("harlowe-3".to_string(), PathBuf::from("h3/format.js"));
+        formats.insert("sugarcube-2".to_string(), PathBuf::from("sc2/format.js"));
+        add_formats(&mut formats, bundled, &dir);
+        assert_eq!(formats["harlowe-3"], PathBuf::from("h3/format.js"));
+        assert_eq!(formats["sugarcube-2"], PathBuf::from("sc2/format.js"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn caches_urls_by_key() {
+        let key = cache_key("https://example.com/formats/my format/format.js");
+        assert!(key.starts_with("example.com_formats_my_format_format.js-"));
+        assert_ne!(
+            key,
+            cache_key("https://example.com/formats/my_format/format.js")
+        );
+        assert!(cache_key(&format!("https://{}", "a".repeat(500))).len() < 100);
+    }
+
+    #[test]
+    fn checks_downloads() {
+        let error = fetch("http://example.invalid/format.js", None).unwrap_err();
+        assert!(error.to_string().contains("plain HTTP"));
+
+        let path = std::env::temp_dir().join(format!("tweec-fetch-{}.js", std::process::id()));
+        std::fs::write(&path, "window.storyFormat({})").unwrap();
+        let source = path.to_string_lossy().into_owned();
+        let sha256 = story_format::sha256(b"window.storyFormat({})");
+        let pinned = fetch(&source, Some(&sha256));
+        let mismatched = fetch(&source, Some(&"0".repeat(64)));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(pinned.unwrap(), "window.storyFormat({})");
+        assert!(mismatched.is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Expands glob patterns given as inputs, such as `src/**/*.twee`
+//!
+//! Not every shell expands patterns before running tweec (Windows shells
+//! don't), so patterns are expanded here. `*` matches any part of a name, `?`
+//! matches one character, `[abc]` and `[a-z]` match one of a set, `[!abc]`
+//! matches one character not in the set, and `**` matches any number of
+//! directories. Names starting with `.` are only matched by a pattern that
+//! starts with `.` too
+//!
+//! Patterns only match files. Each directory given as an input is already
+//! searched for Twee files, so matching directories as well would read the
+//! same files twice
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Whether the input is a pattern to expand, rather than a path
+pub fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '[']) && !Path::new(input).exists()
+}
+
+/// Whether a file name matches one component of a pattern
+pub fn matches(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    matches_including_hidden(pattern, name)
+}
+
+/// Whether a file name matches one component of a pattern, without treating
+/// names starting with `.` specially
+pub fn matches_including_hidden(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_chars(&pattern, &name)
+}
+
+fn matches_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| matches_chars(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && matches_chars(&pattern[1..], &name[1..]),
+        Some('[') => match (class(&pattern[1..]), name.first()) {
+            (Some((set, len)), Some(c)) => {
+                set.contains(*c) && matches_chars(&pattern[1 + len..], &name[1..])
+            }
+            // A [ with no ] is matched literally
+            (None, Some('[')) => matches_chars(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some(c) => name.first() == Some(c) && matches_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+/// A set of characters in a pattern, such as `[a-z_]`
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    fn contains(&self, c: char) -> bool {
+        self.ranges
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&c))
+            != self.negated
+    }
+}
+
+/// Parses the set of characters after a `[`, returning it along with how many
+/// characters of the pattern it takes up, including the closing `]`
+fn class(pattern: &[char]) -> Option<(Class, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let mut i = usize::from(negated);
+    let mut ranges = Vec::new();
+    // A ] right after the [ is part of the set
+    let mut first = true;
+    loop {
+        let c = *pattern.get(i)?;
+        if c == ']' && !first {
+            return Some((Class { negated, ranges }, i + 1));
+        }
+        first = false;
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                ranges.push((c, end));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Finds the files matching the remaining components of a pattern under
+/// `base`
+fn expand(base: &Path, components: &[&str], found: &mut Vec<PathBuf>) {
+    let (component, rest) = match components.split_first() {
+        Some(split) => split,
+        None => {
+            if base.is_file() {
+                found.push(base.to_path_buf());
+            }
+            return;
+        }
+    };
+    if !component.contains(['*', '?', '[']) {
+        expand(&base.join(component), rest, found);
+        return;
+    }
+
+    let dir = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base
+    };
+    let mut entries: Vec<(String, PathBuf)> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.path(),
+                )
+            })
+            .collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    if *component == "**" {
+        // Any number of directories, starting with none
+        expand(base, rest, found);
+    }
+    for (name, path) in entries {
+        let path = if base.as_os_str().is_empty() {
+            PathBuf::from(&name)
+        } else {
+            path
+        };
+        if *component == "**" {
+            if path.is_dir() && !name.starts_with('.') {
+                expand(&path, components, found);
+            }
+        } else if matches(component, &name) {
+            expand(&path, rest, found);
+        }
+    }
+}
+
+/// Expands the patterns among the inputs, keeping the other inputs as given,
+/// and drops any input given more than once. Returns the inputs along with a
+/// warning for each pattern that matches nothing
+pub fn expand_inputs(inputs: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut expanded = Vec::new();
+    let mut warnings = Vec::new();
+    for input in inputs {
+        if !is_pattern(input) {
+            expanded.push(PathBuf::from(input));
+            continue;
+        }
+        let separators: &[char] = if cfg!(windows) { &['/', '\\'] } else { &['/'] };
+        let (base, pattern) = match input.strip_prefix(separators) {
+            Some(pattern) => (PathBuf::from(&input[..1]), pattern),
+            None => (PathBuf::new(), input.as_str()),
+        };
+        let components: Vec<&str> = pattern
+            .split(separators)
+            .filter(|component| !component.is_empty() && *component != ".")
+            .collect();
+        let mut found = Vec::new();
+        expand(&base, &components, &mut found);
+        if found.is_empty() {
+            warnings.push(format!("Input pattern {} matches no files", input));
+        }
+        expanded.append(&mut found);
+    }
+
+    let mut seen = HashSet::new();
+    let inputs = expanded
+        .into_iter()
+        .filter(|path| seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    (inputs, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_names() {
+        assert!(matches("*.twee", "story.twee"));
+        assert!(!matches("*.twee", "story.tw"));
+        assert!(matches("ch?p[0-9].tw*", "chap1.twee"));
+        assert!(!matches("[!a-c]*", "b.twee"));
+        assert!(matches("[]x]", "]"));
+        assert!(!matches("*", ".hidden"));
+        assert!(matches(".*", ".hidden"));
+    }
+
+    #[test]
+    fn expands_patterns() {
+        let dir = std::env::temp_dir().join(format!("tweec-glob-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src/chapters/.drafts")).unwrap();
+        for file in [
+            "src/start.twee",
+            "src/notes.txt",
+            "src/chapters/one.twee",
+            "src/chapters/.drafts/two.twee",
+        ] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        let root = dir.to_string_lossy();
+        let start = format!("{}/src/start.twee", root);
+        let (inputs, warnings) = expand_inputs(&[
+            format!("{}/src/**/*.twee", root),
+            start.clone(),
+            format!("{}/missing/*.twee", root),
+        ]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            inputs,
+            vec![start, format!("{}/src/chapters/one.twee", root)]
+        );
+        assert_eq!(
+            warnings,
+            vec![format!(
+                "Input pattern {}/missing/*.twee matches no files",
+                root
+            )]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Writes the issues found by the linter as a standalone HTML page, for
+//! sharing with writers who don't run tweec themselves
+//!
+//! `tweec lint --report report.html` writes the page alongside the usual
+//! output. Issues are grouped by file, each with the lines of source around
+//! it and the part it refers to highlighted. Checkboxes at the top show or
+//! hide errors and warnings. The page needs nothing but itself: its styles
+//! and script are inline
+
+use crate::issue::Issue;
+use crate::reporter::{Reporter, Summary};
+use crate::rules;
+use crate::story_html::escape;
+use crate::StoryFiles;
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use codespan_reporting::files::Files;
+use color_eyre::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Lines of source shown before and after the lines an issue refers to
+const CONTEXT_LINES: usize = 1;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:60em;color:#222}\
+h2{border-bottom:1px solid #ccc;padding-bottom:.2em}\
+.issue{margin:1em 0;padding:.5em 1em;border-left:4px solid}\
+.error{border-color:#c33}.warning{border-color:#d90}\
+.severity{font-weight:bold;text-transform:uppercase;font-size:.8em}\
+.error .severity{color:#c33}.warning .severity{color:#b70}\
+.rule{color:#666;font-family:monospace}\
+pre{background:#f6f6f6;padding:.5em;overflow-x:auto}\
+.line{color:#999;user-select:none}mark{background:#fd8}\
+.hidden{display:none}";
+
+const SCRIPT: &str = "document.querySelectorAll('input[data-severity]').forEach(function(box){\
+box.addEventListener('change',function(){\
+document.querySelectorAll('.issue.'+box.dataset.severity).forEach(function(issue){\
+issue.classList.toggle('hidden',!box.checked);});});});";
+
+/// Lines of source around an issue
+struct Snippet {
+    /// The 1-based number of the first line
+    first_line: usize,
+
+    /// Each line as HTML, with the part the issue refers to marked
+    lines: Vec<String>,
+}
+
+/// An issue as shown in the report
+struct Entry {
+    severity: &'static str,
+    rule: String,
+    message: String,
+    notes: Vec<String>,
+
+    /// The 1-based line and column of the issue, if it has a location
+    position: Option<(usize, usize)>,
+    snippet: Option<Snippet>,
+}
+
+/// Gets the lines of the given file around a byte range, as HTML, with the
+/// range marked
+fn snippet(story_files: &StoryFiles, id: usize, start: usize, end: usize) -> Option<Snippet> {
+    let source = story_files.source(id)?;
+    let start_line = story_files.line_index(id, start)?;
+    let end_line = story_files.line_index(id, end).unwrap_or(start_line);
+    let first_line = start_line.saturating_sub(CONTEXT_LINES);
+    let mut lines = Vec::new();
+    for index in first_line..=end_line + CONTEXT_LINES {
+        // The end of a file ending in a newline is not a line of its own
+        let range = match story_files.line_range(id, index) {
+            Some(range) if range.start < source.len() => range,
+            _ => break,
+        };
+        let text = source.get(range.clone())?;
+        let text = text.trim_end_matches(['\n', '\r']);
+        let line_end = range.start + text.len();
+        let (mark_start, mark_end) = (
+            start.clamp(range.start, line_end) - range.start,
+            end.clamp(range.start, line_end) - range.start,
+        );
+        let line = match (text.get(..mark_start), text.get(mark_start..mark_end)) {
+            (Some(before), Some(marked)) if !marked.is_empty() => format!(
+                "{}<mark>{}</mark>{}",
+                escape(before),
+                escape(marked),
+                escape(&text[mark_end..])
+            ),
+            _ => escape(text),
+        };
+        lines.push(line);
+    }
+    Some(Snippet {
+        first_line: first_line + 1,
+        lines,
+    })
+}
+
+/// Collects every issue and writes them as an HTML page when finished
+pub struct HtmlReporter<W> {
+    out: W,
+    paths: HashMap<String, PathBuf>,
+    files: BTreeMap<String, Vec<Entry>>,
+}
+
+impl<W: Write> HtmlReporter<W> {
+    /// Creates a reporter that writes to `out`, using `paths` to find the
+    /// path of each file by name
+    pub fn new(out: W, paths: HashMap<String, PathBuf>) -> Self {
+        HtmlReporter {
+            out,
+            paths,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Writes the page, with the given number of errors and warnings
+    fn write_page(&mut self, summary: &Summary) -> Result<()> {
+        let out = &mut self.out;
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html>")?;
+        writeln!(out, "<head>")?;
+        writeln!(out, "<meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>Lint report</title>")?;
+        writeln!(out, "<style>{}</style>", STYLE)?;
+        writeln!(out, "</head>")?;
+        writeln!(out, "<body>")?;
+        writeln!(out, "<h1>Lint report</h1>")?;
+        writeln!(
+            out,
+            "<p>{} error{}, {} warning{}</p>",
+            summary.errors,
+            if summary.errors == 1 { "" } else { "s" },
+            summary.warnings,
+            if summary.warnings == 1 { "" } else { "s" }
+        )?;
+        writeln!(
+            out,
+            "<p><label><input type=\"checkbox\" data-severity=\"error\" checked> Errors</label> \
+             <label><input type=\"checkbox\" data-severity=\"warning\" checked> Warnings</label></p>"
+        )?;
+        if self.files.is_empty() {
+            writeln!(out, "<p>No outstanding issues.</p>")?;
+        }
+        for (file, entries) in &self.files {
+            writeln!(out, "<h2>{}</h2>", escape(file))?;
+            for entry in entries {
+                writeln!(out, "<div class=\"issue {}\">", entry.severity)?;
+                write!(
+                    out,
+                    "<p><span class=\"severity\">{}</span> <span class=\"rule\">{}</span>",
+                    entry.severity,
+                    escape(&entry.rule)
+                )?;
+                if let Some((line, column)) = entry.position {
+                    write!(out, " at line {}, column {}", line, column)?;
+                }
+                writeln!(out, "</p>")?;
+                writeln!(out, "<p>{}</p>", escape(&entry.message))?;
+                if let Some(snippet) = &entry.snippet {
+                    write!(out, "<pre>")?;
+                    let width = (snippet.first_line + snippet.lines.len()).to_string().len();
+                    for (i, line) in snippet.lines.iter().enumerate() {
+                        writeln!(
+                            out,
+                            "<span class=\"line\">{:>width$} | </span>{}",
+                            snippet.first_line + i,
+                            line,
+                            width = width
+                        )?;
+                    }
+                    writeln!(out, "</pre>")?;
+                }
+                if !entry.notes.is_empty() {
+                    writeln!(out, "<ul>")?;
+                    for note in &entry.notes {
+                        writeln!(out, "<li>{}</li>", escape(note))?;
+                    }
+                    writeln!(out, "</ul>")?;
+                }
+                writeln!(out, "</div>")?;
+            }
+        }
+        writeln!(out, "<script>{}</script>", SCRIPT)?;
+        writeln!(out, "</body>")?;
+        writeln!(out, "</html>")?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Reporter for HtmlReporter<W> {
+    fn report(&mut self, issue: &Issue, story_files: &StoryFiles) -> Result<()> {
+        let diagnostic = issue.report(story_files);
+        let rule = diagnostic.code.clone().unwrap_or_default();
+        let mut notes = diagnostic.notes.clone();
+        if rules::find(&rule).is_some() {
+            notes.push(format!("Run `tweec explain {}` for more information", rule));
+        }
+        let primary = diagnostic
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary);
+        let (file, position, snippet) = match primary {
+            Some(label) => {
+                let name = story_files.name(label.file_id).unwrap_or_default();
+                let file = self
+                    .paths
+                    .get(name)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| name.to_string());
+                let (line, column) = story_files.position(label.file_id, label.range.start);
+                let snippet = snippet(
+                    story_files,
+                    label.file_id,
+                    label.range.start,
+                    label.range.end,
+                );
+                (file, Some((line + 1, column + 1)), snippet)
+            }
+            None => ("Story".to_string(), None, None),
+        };
+        self.files.entry(file).or_default().push(Entry {
+            severity: match diagnostic.severity {
+                Severity::Bug | Severity::Error => "error",
+                _ => "warning",
+            },
+            rule,
+            message: diagnostic.message,
+            notes,
+            position,
+            snippet,
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<()> {
+        self.write_page(summary)?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn folds(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tweep::Story;
+
+    #[test]
+    fn writes_issues_with_snippets() {
+        let input = ":: StoryTitle\nReport\n\n:: Start\nGo [[Nowhere & <back>]]\n";
+        let path = std::env::temp_dir().join(format!("tweec-html-{}.twee", std::process::id()));
+        std::fs::write(&path, input).unwrap();
+        let (story_result, warnings) = Story::from_path(&path).take();
+        std::fs::remove_file(&path).unwrap();
+        let story_files = StoryFiles::new(&story_result);
+        let issues: Vec<Issue> = warnings
+            .into_iter()
+            .filter(|warning| warning.kind.get_name() == "DeadLink")
+            .map(|warning| Issue::Warning {
+                warning,
+                denied: true,
+            })
+            .collect();
+        let mut out = Vec::new();
+        let mut reporter = HtmlReporter::new(&mut out, HashMap::new());
+        for issue in &issues {
+            reporter.report(issue, &story_files).unwrap();
+        }
+        reporter.finish(&Summary::new(&issues, 0)).unwrap();
+
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<p>1 error, 0 warnings</p>"));
+        assert!(html.contains("<div class=\"issue error\">"));
+        assert!(html.contains("Go <mark>[[Nowhere &amp; &lt;back&gt;]]</mark>"));
+        assert!(html.contains("4 | </span>:: Start"));
+        assert!(html.contains("tweec explain DeadLink"));
+    }
+}
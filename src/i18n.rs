@@ -0,0 +1,287 @@
+//! Builds translated versions of a story from gettext catalogs
+//!
+//! `tweec i18n build --lang fr` reads the catalog `i18n/fr.po`, or the one
+//! given with `--catalog`, replaces each segment of the story that it
+//! translates, and writes `<Story Title>.fr.html`.
+//!
+//! A segment is a paragraph of a passage's text: a run of lines between blank
+//! lines, without the whitespace around it. The story's title is a segment
+//! too. Each segment is an entry in the catalog whose `msgctxt` is the name of
+//! the passage, or `StoryTitle` for the title, and whose `msgid` is the text
+//! of the segment. Script and stylesheet passages are not translated.
+//!
+//! Segments that have no entry, or an entry with an empty `msgstr`, are left
+//! as written and warned about as `MissingTranslation`. Entries marked
+//! `#, fuzzy` are left out too, and warned about as `FuzzyTranslation`
+
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use tweep::Story;
+
+/// The `msgctxt` of the story title's entry
+pub const TITLE_CONTEXT: &str = "StoryTitle";
+
+/// A translation in a catalog
+#[derive(Debug, Default)]
+struct Entry {
+    context: String,
+    id: String,
+    translation: String,
+    fuzzy: bool,
+}
+
+/// The catalog field a quoted string continues
+#[derive(Clone, Copy)]
+enum Field {
+    Context,
+    Id,
+    Translation,
+    Other,
+}
+
+/// How a segment is translated by a catalog
+#[derive(Debug, PartialEq)]
+pub enum Lookup<'a> {
+    /// The segment has a translation ready to use
+    Translated(&'a str),
+
+    /// The segment's translation is marked fuzzy, so it needs review
+    Fuzzy,
+
+    /// The segment has no translation
+    Missing,
+}
+
+/// The translations of a story into one language
+#[derive(Debug, Default)]
+pub struct Catalog {
+    entries: HashMap<(String, String), Entry>,
+}
+
+/// Decodes a quoted string from a catalog
+fn unquote(text: &str, row: usize) -> Result<String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .filter(|_| text.len() >= 2)
+        .ok_or_else(|| eyre!("Expected a quoted string on line {}", row + 1))?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    Ok(out)
+}
+
+impl Catalog {
+    /// Parses the source of a gettext `.po` catalog
+    ///
+    /// Only what tweec needs is read: the context, id, translation, and
+    /// whether the entry is fuzzy. Plural forms use their first translation
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut catalog = Catalog::default();
+        let mut entry = Entry::default();
+        let mut field = Field::Other;
+        let mut translated = false;
+        for (row, line) in source.lines().enumerate() {
+            let line = line.trim();
+            // Comments and blank lines come between entries. Obsolete
+            // entries, marked `#~`, are comments too
+            if line.is_empty() || line.starts_with('#') {
+                if translated {
+                    catalog.insert(std::mem::take(&mut entry));
+                    translated = false;
+                    field = Field::Other;
+                }
+                if let Some(flags) = line.strip_prefix("#,") {
+                    entry.fuzzy |= flags.split(',').any(|flag| flag.trim() == "fuzzy");
+                }
+                continue;
+            }
+            if line.starts_with('"') {
+                let text = unquote(line, row)?;
+                match field {
+                    Field::Context => entry.context.push_str(&text),
+                    Field::Id => entry.id.push_str(&text),
+                    Field::Translation => entry.translation.push_str(&text),
+                    Field::Other => (),
+                }
+                continue;
+            }
+            let (keyword, rest) = match line.split_once(char::is_whitespace) {
+                Some((keyword, rest)) => (keyword, rest.trim()),
+                None => (line, ""),
+            };
+            if translated && matches!(keyword, "msgctxt" | "msgid") {
+                catalog.insert(std::mem::take(&mut entry));
+                translated = false;
+            }
+            field = match keyword {
+                "msgctxt" => Field::Context,
+                "msgid" => Field::Id,
+                "msgstr" | "msgstr[0]" => Field::Translation,
+                "msgid_plural" => Field::Other,
+                keyword if keyword.starts_with("msgstr[") => Field::Other,
+                _ => return Err(eyre!("Unknown keyword {:?} on line {}", keyword, row + 1)),
+            };
+            let text = unquote(rest, row)?;
+            match field {
+                Field::Context => entry.context = text,
+                Field::Id => entry.id = text,
+                Field::Translation => {
+                    entry.translation = text;
+                    translated = true;
+                }
+                Field::Other => (),
+            }
+        }
+        if translated {
+            catalog.insert(entry);
+        }
+        Ok(catalog)
+    }
+
+    /// Reads a gettext `.po` catalog
+    pub fn read(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read catalog {:?}", path))?;
+        Catalog::parse(&source).wrap_err_with(|| format!("Failed to parse catalog {:?}", path))
+    }
+
+    fn insert(&mut self, entry: Entry) {
+        // The entry with an empty id is the catalog's header
+        if entry.id.is_empty() {
+            return;
+        }
+        self.entries
+            .insert((entry.context.clone(), entry.id.clone()), entry);
+    }
+
+    /// Looks up the translation of a segment of the passage named `context`
+    pub fn lookup(&self, context: &str, text: &str) -> Lookup<'_> {
+        match self.entries.get(&(context.to_string(), text.to_string())) {
+            Some(entry) if entry.fuzzy => Lookup::Fuzzy,
+            Some(entry) if !entry.translation.is_empty() => Lookup::Translated(&entry.translation),
+            _ => Lookup::Missing,
+        }
+    }
+}
+
+/// Finds the byte ranges of the segments of a passage's text
+pub fn segments(content: &str) -> Vec<Range<usize>> {
+    let mut segments = Vec::new();
+    let mut start = None;
+    let mut end = 0;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            if let Some(start) = start.take() {
+                segments.push(start..end);
+            }
+        } else {
+            let leading = line.len() - line.trim_start().len();
+            start.get_or_insert(offset + leading);
+            end = offset + leading + trimmed.len();
+        }
+        offset += line.len();
+    }
+    if let Some(start) = start {
+        segments.push(start..end);
+    }
+    segments
+}
+
+/// Whether a passage is left untranslated
+pub fn is_skipped(tags: &[String]) -> bool {
+    tags.iter()
+        .any(|tag| tag == "script" || tag == "stylesheet")
+}
+
+/// Replaces each segment of the story that the catalog translates
+pub fn translate(story: &mut Story, catalog: &Catalog) {
+    if let Some(title) = &mut story.title {
+        if let Lookup::Translated(translation) = catalog.lookup(TITLE_CONTEXT, title.trim()) {
+            *title = translation.to_string();
+        }
+    }
+    for (name, passage) in story.passages.iter_mut() {
+        if is_skipped(&passage.header.tags) {
+            continue;
+        }
+        let content = &mut passage.content.content;
+        for range in segments(content).into_iter().rev() {
+            if let Lookup::Translated(translation) = catalog.lookup(name, &content[range.clone()]) {
+                content.replace_range(range, translation);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_segments() {
+        let catalog = Catalog::parse(
+            r#"# French translation
+msgid ""
+msgstr ""
+"Language: fr\n"
+
+msgctxt "StoryTitle"
+msgid "The Cave"
+msgstr "La grotte"
+
+msgctxt "Start"
+msgid "You stand at the mouth\n"
+"of a cave."
+msgstr "Vous êtes à l'entrée\n"
+"d'une grotte."
+
+#, fuzzy
+msgctxt "Start"
+msgid "[[Go in->Inside]]"
+msgstr "[[Entrer->Inside]]"
+
+msgctxt "Start"
+msgid "Turn back."
+msgstr ""
+
+#~ msgctxt "Start"
+#~ msgid "Old text."
+#~ msgstr "Vieux texte."
+"#,
+        )
+        .unwrap();
+        assert_eq!(catalog.lookup("Start", "[[Go in->Inside]]"), Lookup::Fuzzy);
+        assert_eq!(catalog.lookup("Start", "Turn back."), Lookup::Missing);
+        assert_eq!(catalog.lookup("Start", "Old text."), Lookup::Missing);
+
+        let input = ":: StoryTitle\nThe Cave\n\n:: Start\nYou stand at the mouth\nof a cave.\n\n\
+                     [[Go in->Inside]]\n\n  Turn back.\n\n:: Inside\nDark.\n"
+            .to_string();
+        let mut story = Story::from_string(input).take().0.ok().unwrap();
+        translate(&mut story, &catalog);
+        assert_eq!(story.title.as_deref(), Some("La grotte"));
+        assert_eq!(
+            story.passages["Start"].content.content,
+            "Vous êtes à l'entrée\nd'une grotte.\n\n[[Go in->Inside]]\n\n  Turn back.\n"
+        );
+        assert_eq!(story.passages["Inside"].content.content, "Dark.\n");
+    }
+}
@@ -0,0 +1,150 @@
+//! Generation of Interactive Fiction IDentifiers (IFIDs) for stories that
+//! lack one
+//!
+//! Per the Twee 3 spec, an IFID is a v4 UUID, conventionally written in upper
+//! case. If a story has no `StoryData` passage, or its `StoryData` has no
+//! `ifid` field, a new IFID can be generated and optionally written back into
+//! the story source so that subsequent builds use the same one.
+
+use crate::utils;
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tweep::{Story, StoryData};
+
+/// Generates a new, upper case, v4 UUID suitable for use as an IFID
+pub fn generate() -> String {
+    uuid::Uuid::new_v4().to_string().to_uppercase()
+}
+
+/// Ensures that the given story has `StoryData` with an IFID, generating one
+/// if necessary
+///
+/// If the story's `StoryData` passage failed to parse only because it lacked
+/// an `ifid` field, the remaining fields are recovered from the source.
+/// Returns the newly generated IFID, or `None` if the story already had one
+pub fn ensure(story: &mut Story) -> Option<String> {
+    if story.data.is_some() {
+        return None;
+    }
+
+    let ifid = generate();
+    let recovered = find_story_data(story)
+        .and_then(|json| serde_json::from_str::<Value>(json).ok())
+        .and_then(|mut value| {
+            value
+                .as_object_mut()?
+                .insert("ifid".to_string(), ifid.clone().into());
+            serde_json::from_value::<StoryData>(value).ok()
+        });
+
+    story.data = Some(recovered.unwrap_or_else(|| StoryData {
+        ifid: ifid.clone(),
+        format: None,
+        format_version: None,
+        start: None,
+        tag_colors: None,
+        zoom: None,
+    }));
+
+    Some(ifid)
+}
+
+/// Finds the contents of the `StoryData` passage in the story's source files
+fn find_story_data(story: &Story) -> Option<&str> {
+    utils::contexts(&story.code_map)
+        .find_map(|context| utils::story_data_block(context.get_contents()))
+}
+
+/// Writes the given IFID back into the story source found in `inputs`
+///
+/// If a `StoryData` passage exists, the `ifid` field is inserted into its JSON
+/// blob. Otherwise, a new `StoryData` passage is appended to the first input
+/// file, or created as `StoryData.twee` if the first input is a directory.
+/// Returns the path of the file that was modified
+pub fn write_back(inputs: &[String], extensions: &[String], ifid: &str) -> Result<PathBuf> {
+    for path in crate::inputs::twee_files(inputs, extensions) {
+        let source = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read input file {:?}", path))?;
+        if let Some(block) = utils::story_data_block(&source) {
+            let offset = block.as_ptr() as usize - source.as_ptr() as usize;
+            let brace = offset
+                + block
+                    .find('{')
+                    .ok_or_else(|| eyre!("StoryData in {:?} is not a JSON object", path))?;
+            let has_fields = !block[brace - offset + 1..].trim_start().starts_with('}');
+            let mut updated = source[..=brace].to_string();
+            updated.push_str(&format!(
+                "\n  \"ifid\": \"{}\"{}",
+                ifid,
+                if has_fields { "," } else { "\n" }
+            ));
+            updated.push_str(&source[brace + 1..]);
+            write(&path, &updated)?;
+            return Ok(path);
+        }
+    }
+
+    let first = inputs
+        .first()
+        .map(Path::new)
+        .ok_or_else(|| eyre!("No inputs to write IFID to"))?;
+    let passage = format!(":: StoryData\n{{\n  \"ifid\": \"{}\"\n}}\n", ifid);
+    if first.is_dir() {
+        let path = first.join("StoryData.twee");
+        write(&path, &passage)?;
+        Ok(path)
+    } else {
+        let mut source = std::fs::read_to_string(first)
+            .wrap_err_with(|| format!("Failed to read input file {:?}", first))?;
+        if !source.ends_with('\n') {
+            source.push('\n');
+        }
+        source.push('\n');
+        source.push_str(&passage);
+        write(first, &source)?;
+        Ok(first.to_path_buf())
+    }
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    std::fs::write(path, contents).wrap_err_with(|| format!("Failed to write IFID to {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_ifids_are_v4() {
+        let ifid = generate();
+        let parsed = uuid::Uuid::parse_str(&ifid).ok().unwrap();
+        assert_eq!(parsed.get_version_num(), 4);
+        assert_eq!(ifid, ifid.to_uppercase());
+    }
+
+    #[test]
+    fn recovers_story_data_fields() {
+        let dir = std::env::temp_dir().join(format!("tweec-ifid-{}", generate()));
+        std::fs::create_dir_all(&dir).ok().unwrap();
+        let path = dir.join("story.twee");
+        let input = ":: StoryData\n{ \"start\": \"Begin\" }\n\n:: Begin\nHello\n";
+        std::fs::write(&path, input).ok().unwrap();
+
+        let mut story = Story::from_path(&path).take().0.ok().unwrap();
+        assert!(story.data.is_none());
+        let ifid = ensure(&mut story).unwrap();
+        let data = story.data.as_ref().unwrap();
+        assert_eq!(data.ifid, ifid);
+        assert_eq!(data.start.as_deref(), Some("Begin"));
+        assert_eq!(ensure(&mut story), None);
+
+        let inputs = vec![dir.to_string_lossy().into_owned()];
+        let extensions = crate::inputs::default_extensions();
+        assert_eq!(write_back(&inputs, &extensions, &ifid).ok().unwrap(), path);
+        let story = Story::from_path(&path).take().0.ok().unwrap();
+        assert_eq!(story.data.unwrap().ifid, ifid);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,310 @@
+//! Collects the Twee files to read from the inputs
+//!
+//! Files given as inputs are always read. Directories are searched
+//! (non-recursively) for files with one of the configured extensions, `.tw`
+//! and `.twee` by default, skipping any excluded by a `.tweecignore` file in
+//! the directory, so that drafts, notes, and backups can sit alongside the
+//! story
+//!
+//! Ignore files use gitignore syntax: each line is a pattern, blank lines and
+//! lines starting with `#` are skipped, a leading `!` includes files again
+//! that an earlier pattern excluded, and a trailing `/` only matches
+//! directories. A pattern containing a `/` other than at the end is matched
+//! against the path relative to the directory, while any other pattern is
+//! matched against names at any depth. Patterns use the same wildcards as
+//! [glob inputs], except that `*` also matches names starting with `.`
+//!
+//! [glob inputs]: ../glob/index.html
+
+use crate::glob;
+use crate::legacy;
+use crate::order;
+use crate::story_json;
+use crate::SortPassages;
+use crate::StoryResult;
+use log::debug;
+use std::path::{Component, Path, PathBuf};
+use tweep::{Output, Story};
+
+/// The name of the file listing which files in a directory to leave out
+pub const IGNORE_FILE: &str = ".tweecignore";
+
+/// The extensions of the Twee files read from directories, unless configured
+/// otherwise
+pub const DEFAULT_EXTENSIONS: [&str; 2] = ["tw", "twee"];
+
+/// Gets the default extensions as owned strings
+pub fn default_extensions() -> Vec<String> {
+    DEFAULT_EXTENSIONS
+        .iter()
+        .map(|ext| ext.to_string())
+        .collect()
+}
+
+/// Whether a file name has one of the given extensions, which are given
+/// without their leading `.` and may contain `.` themselves, such as
+/// `twee.txt`
+pub fn has_extension(name: &str, extensions: &[String]) -> bool {
+    extensions.iter().any(|ext| {
+        name.strip_suffix(ext.as_str())
+            .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+    })
+}
+
+/// A single line of an ignore file
+#[derive(Debug)]
+struct Pattern {
+    negated: bool,
+    dir_only: bool,
+    components: Vec<String>,
+}
+
+impl Pattern {
+    /// Parses a line of an ignore file, returning `None` for blank lines and
+    /// comments
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let mut components: Vec<String> = line
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(String::from)
+            .collect();
+        if components.is_empty() {
+            return None;
+        }
+        if !line.contains('/') {
+            components.insert(0, "**".to_string());
+        }
+        Some(Pattern {
+            negated,
+            dir_only,
+            components,
+        })
+    }
+
+    /// Whether the pattern matches a path, given as its components relative
+    /// to the directory of the ignore file
+    fn matches(&self, path: &[&str], is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && matches_components(&self.components, path)
+    }
+}
+
+fn matches_components(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((component, rest)) if component == "**" => {
+            (0..=path.len()).any(|skip| matches_components(rest, &path[skip..]))
+        }
+        Some((component, rest)) => match path.split_first() {
+            Some((name, path)) => {
+                glob::matches_including_hidden(component, name) && matches_components(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+/// The patterns of an ignore file
+#[derive(Debug, Default)]
+pub struct Ignore {
+    patterns: Vec<Pattern>,
+}
+
+impl Ignore {
+    /// Parses the contents of an ignore file
+    pub fn parse(source: &str) -> Self {
+        Ignore {
+            patterns: source.lines().filter_map(Pattern::parse).collect(),
+        }
+    }
+
+    /// Reads the ignore file in a directory, if there is one
+    pub fn read(dir: &Path) -> Self {
+        std::fs::read_to_string(dir.join(IGNORE_FILE))
+            .map(|source| Ignore::parse(&source))
+            .unwrap_or_default()
+    }
+
+    /// Whether a file is excluded, given its path relative to the directory of
+    /// the ignore file. A file in an excluded directory is always excluded
+    pub fn ignores(&self, path: &Path) -> bool {
+        let components: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let components: Vec<&str> = components.iter().map(String::as_str).collect();
+        (1..=components.len()).any(|len| {
+            let is_dir = len < components.len();
+            self.patterns
+                .iter()
+                .rev()
+                .find(|pattern| pattern.matches(&components[..len], is_dir))
+                .is_some_and(|pattern| !pattern.negated)
+        })
+    }
+}
+
+/// Finds the Twee files in a directory that have one of the given extensions
+/// and are not excluded by its ignore file, in order of their paths
+fn scan_dir(dir: &Path, extensions: &[String]) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let ignore = Ignore::read(dir);
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            has_extension(&name.to_string_lossy(), extensions) && !ignore.ignores(Path::new(&name))
+        })
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Gets the paths of all Twee files given by a list of inputs
+///
+/// Files are included as given, and directories are scanned for the files
+/// with one of the given extensions that they don't ignore. Inputs that don't
+/// exist are left out
+pub fn twee_files<P: AsRef<Path>>(inputs: &[P], extensions: &[String]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        if input.is_file() {
+            files.push(input.to_path_buf());
+        } else {
+            files.append(&mut scan_dir(input, extensions));
+        }
+    }
+    files
+}
+
+/// Gets the paths of the files a story is read from, given a list of inputs
+///
+/// Unlike [`twee_files`], inputs that aren't directories are passed on as
+/// given, so that `tweep` reports any that don't exist. Files listed by
+/// `StoryIncludes` passages are added, after the Twee files, and then any
+/// JSON stories
+///
+/// [`twee_files`]: fn.twee_files.html
+pub fn story_paths<P: AsRef<Path>>(inputs: &[P], extensions: &[String]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        if input.is_dir() {
+            paths.append(&mut scan_dir(input, extensions));
+        } else {
+            paths.push(input.to_path_buf());
+        }
+    }
+    let (json_paths, paths): (Vec<PathBuf>, Vec<PathBuf>) = paths
+        .into_iter()
+        .partition(|path| story_json::is_json(path));
+    let mut paths = legacy::with_includes(paths);
+    paths.extend(json_paths);
+    paths
+}
+
+/// Parses the story from the given files, as found by [`story_paths`]
+///
+/// Legacy Twee is translated as described in [`legacy`], files ending in
+/// `.json` are read as [JSON stories], and passages are numbered in file
+/// order, as described in [`order`]
+///
+/// [`legacy`]: ../legacy/index.html
+/// [JSON stories]: ../story_json/index.html
+/// [`order`]: ../order/index.html
+/// [`story_paths`]: fn.story_paths.html
+pub fn read_paths(paths: &[PathBuf]) -> Output<StoryResult> {
+    let (json_paths, paths): (Vec<PathBuf>, Vec<PathBuf>) = paths
+        .iter()
+        .cloned()
+        .partition(|path| story_json::is_json(path));
+    for path in paths.iter().chain(&json_paths) {
+        debug!("Parsing {:?}", path);
+    }
+    let mut output = if json_paths.is_empty() {
+        legacy::translate(Story::from_paths(&paths))
+    } else {
+        legacy::translate(story_json::read_story(&paths, &json_paths))
+    };
+    if let Ok(story) = output.mut_output() {
+        order::renumber(story, SortPassages::File);
+    }
+    output
+}
+
+/// Parses the story from the Twee files given by a list of inputs, including
+/// the files listed by `StoryIncludes` and any JSON stories. See
+/// [`story_paths`] and [`read_paths`]
+///
+/// [`read_paths`]: fn.read_paths.html
+/// [`story_paths`]: fn.story_paths.html
+pub fn read_story<P: AsRef<Path>>(inputs: &[P], extensions: &[String]) -> Output<StoryResult> {
+    read_paths(&story_paths(inputs, extensions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ignore_files() {
+        let ignore = Ignore::parse(
+            "# Drafts and backups\n*.bak.twee\ndrafts/\n/notes*\n!notes-keep.twee\n\\!bang.twee\n",
+        );
+        assert!(ignore.ignores(Path::new("chapter.bak.twee")));
+        assert!(ignore.ignores(Path::new("sub/.old.bak.twee")));
+        assert!(ignore.ignores(Path::new("drafts/one.twee")));
+        assert!(!ignore.ignores(Path::new("drafts")));
+        assert!(ignore.ignores(Path::new("notes.twee")));
+        assert!(!ignore.ignores(Path::new("sub/notes.twee")));
+        assert!(!ignore.ignores(Path::new("notes-keep.twee")));
+        assert!(ignore.ignores(Path::new("!bang.twee")));
+        assert!(!ignore.ignores(Path::new("story.twee")));
+    }
+
+    #[test]
+    fn finds_twee_files() {
+        let dir = std::env::temp_dir().join(format!("tweec-inputs-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in [
+            "story.twee",
+            "story.twee.swp",
+            "draft.twee",
+            "notes.txt",
+            IGNORE_FILE,
+        ] {
+            let contents = if file == IGNORE_FILE {
+                "draft.twee\n"
+            } else {
+                ""
+            };
+            std::fs::write(dir.join(file), contents).unwrap();
+        }
+
+        let files = twee_files(&[&dir], &default_extensions());
+        let text_files = twee_files(&[&dir], &["txt".to_string()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("story.twee")]);
+        assert_eq!(text_files, vec![dir.join("notes.txt")]);
+    }
+}
@@ -1,11 +1,14 @@
 //! This module provides functionality for dealing with warnings and errors
 
+use crate::linter::links;
+use crate::suppress::Suppressions;
 use crate::Config;
 use crate::StoryFiles;
 use crate::StoryResult;
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 use codespan_reporting::files::Files;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::io::Write;
 use std::ops::Range;
 use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
@@ -13,12 +16,129 @@ use tweep::FullContext;
 use tweep::Warning;
 use tweep::WarningKind;
 
-/// A warning or error generated by `tweep`
+/// A warning generated by one of tweec's own lints
+#[derive(Clone, Debug)]
+pub struct LintWarning {
+    /// The name of the lint that generated this warning, used for allowing or
+    /// denying it
+    pub name: &'static str,
+
+    /// The message describing the warning
+    pub message: String,
+
+    /// The location in the source code this warning refers to
+    pub context: Option<FullContext>,
+
+    /// Additional locations related to this warning, with a message for each
+    pub secondary: Vec<(FullContext, String)>,
+
+    /// Notes to display along with the warning, such as suggested fixes
+    pub notes: Vec<String>,
+
+    /// Edits that resolve the warning, which editors can apply
+    pub fixes: Vec<Fix>,
+}
+
+impl LintWarning {
+    /// Creates a new `LintWarning` with no secondary locations or notes
+    pub fn new<S: Into<String>>(
+        name: &'static str,
+        message: S,
+        context: Option<FullContext>,
+    ) -> Self {
+        LintWarning {
+            name,
+            message: message.into(),
+            context,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            fixes: Vec::new(),
+        }
+    }
+
+    /// Adds a secondary location with the given message to this warning
+    pub fn with_secondary<S: Into<String>>(mut self, context: FullContext, message: S) -> Self {
+        self.secondary.push((context, message.into()));
+        self
+    }
+
+    /// Adds a note to this warning
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Adds a fix to this warning, replacing the text at `context`
+    pub fn with_fix<S: Into<String>, T: Into<String>>(
+        mut self,
+        context: FullContext,
+        replacement: S,
+        title: T,
+        applicability: Applicability,
+    ) -> Self {
+        self.fixes.push(Fix {
+            title: title.into(),
+            context,
+            replacement: replacement.into(),
+            applicability,
+        });
+        self
+    }
+}
+
+impl std::fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(context) = &self.context {
+            let pos = context.get_start_position();
+            write!(
+                f,
+                " at {}:{}:{}",
+                context.get_file_name().as_deref().unwrap_or("<unknown>"),
+                pos.line,
+                pos.column
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// An edit that resolves an issue by replacing some of the source, such as a
+/// suggested fix from its notes
+#[derive(Clone, Debug)]
+pub struct Fix {
+    /// A short description of the edit
+    pub title: String,
+
+    /// The text to replace
+    pub context: FullContext,
+
+    /// The text to replace it with
+    pub replacement: String,
+
+    /// How sure it is that the edit is what the author meant
+    pub applicability: Applicability,
+}
+
+/// How sure it is that a [`Fix`] is what the author meant
+///
+/// [`Fix`]: struct.Fix.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Applicability {
+    /// The fix is certainly right, and can be applied without review
+    MachineApplicable,
+
+    /// The fix is a guess, such as a similarly named passage, that should be
+    /// reviewed
+    MaybeIncorrect,
+}
+
+/// A warning or error generated by `tweep` or by one of tweec's lints
 pub enum Issue {
     /// An error
     Error(tweep::Error),
 
-    /// A warning and whether it should be denied (treated as an error)
+    /// A warning and whether it is denied or forbidden (treated as an error)
     Warning {
         /// The contained warning
         warning: Warning,
@@ -26,26 +146,46 @@ pub enum Issue {
         /// If true, treat as an error
         denied: bool,
     },
+
+    /// A lint warning and whether it is denied or forbidden (treated as an
+    /// error)
+    Lint {
+        /// The contained lint warning
+        lint: LintWarning,
+
+        /// If true, treat as an error
+        denied: bool,
+    },
 }
 
 impl Issue {
-    fn get_name(&self) -> &str {
+    /// Whether this issue is reported as an error
+    pub fn is_error(&self) -> bool {
+        match self {
+            Issue::Error(_) => true,
+            Issue::Warning { denied, .. } | Issue::Lint { denied, .. } => *denied,
+        }
+    }
+
+    pub(crate) fn get_name(&self) -> &str {
         match self {
             Issue::Error(e) => e.get_name(),
             Issue::Warning { warning: w, .. } => w.kind.get_name(),
+            Issue::Lint { lint, .. } => lint.name,
         }
     }
 
-    fn get_message(&self) -> String {
+    pub(crate) fn get_message(&self) -> String {
         match self {
             Issue::Error(e) => format!("{}", e.kind),
             Issue::Warning { warning, .. } => format!("{}", warning.kind),
+            Issue::Lint { lint, .. } => lint.message.clone(),
         }
     }
 
     fn get_referent(&self) -> Option<&FullContext> {
         match self {
-            Issue::Error(_) => None,
+            Issue::Error(_) | Issue::Lint { .. } => None,
             Issue::Warning { warning, .. } => warning.get_referent(),
         }
     }
@@ -54,108 +194,272 @@ impl Issue {
         &self,
         story_files: &StoryFiles,
     ) -> Option<(usize, Range<usize>)> {
-        self.get_referent().and_then(|context| {
-            context
-                .get_file_name()
-                .as_ref()
-                .and_then(|file_name| story_files.code_map.lookup_id(file_name.clone()))
-                .map(|id| (id, context.get_byte_range()))
-        })
+        self.get_referent()
+            .and_then(|context| file_id_and_range(context, story_files))
     }
 
-    fn get_file_id_and_range(&self, story_files: &StoryFiles) -> Option<(usize, Range<usize>)> {
-        let context = match self {
+    pub(crate) fn get_context(&self) -> &Option<FullContext> {
+        match self {
             Issue::Error(e) => &e.context,
             Issue::Warning { warning, .. } => &warning.context,
-        };
-        context.as_ref().and_then(|context| {
-            context
-                .get_file_name()
-                .as_ref()
-                .and_then(|file_name| story_files.code_map.lookup_id(file_name.clone()))
-                .map(|id| (id, context.get_byte_range()))
-        })
+            Issue::Lint { lint, .. } => &lint.context,
+        }
+    }
+
+    fn get_file_id_and_range(&self, story_files: &StoryFiles) -> Option<(usize, Range<usize>)> {
+        self.get_context()
+            .as_ref()
+            .and_then(|context| file_id_and_range(context, story_files))
+    }
+
+    /// Gets the edits that resolve this `Issue`, if any
+    pub fn fixes(&self, story_files: &StoryFiles) -> Vec<Fix> {
+        match self {
+            Issue::Error(_) => Vec::new(),
+            Issue::Warning { warning, .. } => {
+                let context = match &warning.context {
+                    Some(context) => context,
+                    None => return Vec::new(),
+                };
+                let link = context.get_contents();
+                let fix = match &warning.kind {
+                    WarningKind::DeadLink(dead) => {
+                        similar_passage(dead, story_files).and_then(|suggestion| {
+                            let target = link_target_range(link)
+                                .filter(|range| link[range.clone()].trim() == dead)?;
+                            Some(Fix {
+                                title: format!("Link to \"{}\"", suggestion),
+                                context: context.clone(),
+                                replacement: retarget(link, target, &suggestion),
+                                applicability: Applicability::MaybeIncorrect,
+                            })
+                        })
+                    }
+                    WarningKind::WhitespaceInLink => trimmed_link(link).map(|suggested| Fix {
+                        title: format!("Replace with {}", suggested),
+                        context: context.clone(),
+                        replacement: suggested,
+                        applicability: Applicability::MachineApplicable,
+                    }),
+                    _ => None,
+                };
+                fix.into_iter().collect()
+            }
+            Issue::Lint { lint, .. } => lint.fixes.clone(),
+        }
     }
 
     /// Gets a codespan diagnostic from this `Issue`
-    pub fn report(&self, story_files: &StoryFiles) -> Diagnostic<<StoryFiles as Files>::FileId> {
+    pub fn report(
+        &self,
+        story_files: &StoryFiles,
+    ) -> Diagnostic<<StoryFiles<'_> as Files<'_>>::FileId> {
         let diagnostic = match self {
-            Issue::Error(_) | Issue::Warning { denied: true, .. } => Diagnostic::error(),
-            Issue::Warning { denied: false, .. } => Diagnostic::warning(),
+            Issue::Error(_)
+            | Issue::Warning { denied: true, .. }
+            | Issue::Lint { denied: true, .. } => Diagnostic::error(),
+            Issue::Warning { denied: false, .. } | Issue::Lint { denied: false, .. } => {
+                Diagnostic::warning()
+            }
         }
         .with_message(self.get_message())
         .with_code(self.get_name());
 
         let help_message = match self {
             Issue::Warning { warning: w, .. } => match &w.kind {
+                // If it's a dead link, try to offer a similar passage name
                 WarningKind::DeadLink(dead) => {
-                    // If it's a dead link, try to offer a similar passage name
-                    story_files.passage_names.as_ref().and_then(|names| {
-                        did_you_mean(dead, names).pop().map(|suggestion| {
-                            format!("Found passage with similar name: \"{}\"", suggestion)
-                        })
+                    similar_passage(dead, story_files).map(|suggestion| {
+                        format!("Found passage with similar name: \"{}\"", suggestion)
                     })
                 }
                 WarningKind::WhitespaceInLink => w.context.as_ref().and_then(|ctx| {
-                    // Get the full link
                     let link = ctx.get_contents();
-
-                    // Pull out the [[contents]]
-                    let contents = &link[2..link.len() - 2];
-
-                    // Get the target of the link
-                    let target = if contents.contains('|') {
-                        let mut iter = contents.split('|');
-                        let _ = iter.next();
-                        iter.next().unwrap()
-                    } else if contents.contains("<-") {
-                        contents.split("<-").next().unwrap()
-                    } else if contents.contains("->") {
-                        let mut iter = contents.split("->");
-                        let _ = iter.next();
-                        iter.next().unwrap()
-                    } else {
-                        contents
-                    };
-
-                    // Trim the target and create a valid link
-                    let trimmed = target.trim();
-                    let suggested = link.replace(target, trimmed);
-                    Some(format!("Try replacing {} with {}", link, suggested))
+                    trimmed_link(link)
+                        .map(|suggested| format!("Try replacing {} with {}", link, suggested))
                 }),
                 _ => None,
             },
             _ => None,
         };
 
-        self.get_file_id_and_range(&story_files)
-            .and_then(|(fid, range)| {
+        self.get_file_id_and_range(story_files)
+            .map(|(fid, range)| {
                 let mut labels = Vec::new();
-                labels.push(Label::primary(fid, range));
-
-                self.get_referent_file_id_and_range(&story_files)
-                    .and_then(|(fid, range)| {
-                        labels.push(
-                            Label::secondary(fid, range)
-                                .with_message("Previously defined here. Duplicate discarded."),
-                        );
-
-                        Some(())
-                    });
-
                 let mut notes = Vec::new();
+                let referent = self.get_referent_file_id_and_range(story_files);
+                match (self, &referent) {
+                    (Issue::Warning { warning: w, .. }, Some(original)) => {
+                        if let WarningKind::DuplicatePassage(name) = &w.kind {
+                            labels.push(
+                                Label::primary(fid, header_range(fid, &range, story_files))
+                                    .with_message("This definition is discarded"),
+                            );
+                            labels.push(
+                                Label::secondary(
+                                    original.0,
+                                    header_range(original.0, &original.1, story_files),
+                                )
+                                .with_message("This definition is kept"),
+                            );
+                            notes.extend(duplicate_notes(
+                                name,
+                                original,
+                                &(fid, range),
+                                story_files,
+                            ));
+                        } else {
+                            labels.push(Label::primary(fid, range));
+                            labels.push(
+                                Label::secondary(original.0, original.1.clone())
+                                    .with_message("Previously defined here. Duplicate discarded."),
+                            );
+                        }
+                    }
+                    _ => labels.push(Label::primary(fid, range)),
+                }
+
                 if let Some(msg) = help_message {
                     notes.push(msg);
                 }
 
-                Some(diagnostic.clone().with_labels(labels).with_notes(notes))
+                if let Issue::Lint { lint, .. } = self {
+                    for (context, message) in &lint.secondary {
+                        if let Some((fid, range)) = file_id_and_range(context, story_files) {
+                            labels.push(Label::secondary(fid, range).with_message(message));
+                        }
+                    }
+                    notes.extend(lint.notes.iter().cloned());
+                }
+
+                diagnostic.clone().with_labels(labels).with_notes(notes)
             })
             .unwrap_or(diagnostic)
     }
 }
 
+/// Narrows the range of a passage to its header line
+fn header_range(fid: usize, range: &Range<usize>, story_files: &StoryFiles) -> Range<usize> {
+    let end = story_files
+        .source(fid)
+        .and_then(|source| source.get(range.clone()))
+        .and_then(|passage| passage.find('\n'))
+        .map_or(range.end, |newline| range.start + newline);
+    range.start..end
+}
+
+/// Gets the notes explaining a duplicate passage: where both definitions are,
+/// and why the one that is kept wins
+fn duplicate_notes(
+    name: &str,
+    original: &(usize, Range<usize>),
+    duplicate: &(usize, Range<usize>),
+    story_files: &StoryFiles,
+) -> Vec<String> {
+    let file = |fid: usize| story_files.name(fid).unwrap_or("<unknown>");
+    let line = |(fid, range): &(usize, Range<usize>)| story_files.position(*fid, range.start).0 + 1;
+    let mut notes = Vec::new();
+    if original.0 == duplicate.0 {
+        notes.push(format!(
+            "Passage \"{}\" is defined twice in {}, on lines {} and {}",
+            name,
+            file(original.0),
+            line(original),
+            line(duplicate)
+        ));
+        notes.push(
+            "The first definition in a file is kept, and later ones are discarded".to_string(),
+        );
+    } else {
+        notes.push(format!(
+            "Passage \"{}\" is defined in both {} (line {}) and {} (line {})",
+            name,
+            file(original.0),
+            line(original),
+            file(duplicate.0),
+            line(duplicate)
+        ));
+        notes.push(format!(
+            "The definition in {} is kept because that file was read first: inputs are read in \
+             the order they are given, and the files in a directory in order of their paths",
+            file(original.0)
+        ));
+    }
+    notes.push(
+        "Rename one of the passages so links can reach both, or remove the duplicate".to_string(),
+    );
+    notes
+}
+
+/// Gets the byte range of the target of a `[[link]]` within it, if it is one
+fn link_target_range(link: &str) -> Option<Range<usize>> {
+    if link.len() < 4 || !link.starts_with("[[") || !link.ends_with("]]") {
+        return None;
+    }
+
+    // Pull out the [[contents]]
+    let contents = &link[2..link.len() - 2];
+
+    // Get the target of the link
+    let (start, end) = if let Some(bar) = contents.find('|') {
+        let start = bar + 1;
+        let end = contents[start..]
+            .find('|')
+            .map_or(contents.len(), |i| start + i);
+        (start, end)
+    } else if let Some(arrow) = contents.find("<-") {
+        (0, arrow)
+    } else if let Some(arrow) = contents.find("->") {
+        let start = arrow + 2;
+        let end = contents[start..]
+            .find("->")
+            .map_or(contents.len(), |i| start + i);
+        (start, end)
+    } else {
+        (0, contents.len())
+    };
+    Some(start + 2..end + 2)
+}
+
+/// Gets a link with its target replaced
+fn retarget(link: &str, target: Range<usize>, replacement: &str) -> String {
+    format!(
+        "{}{}{}",
+        &link[..target.start],
+        replacement,
+        &link[target.end..]
+    )
+}
+
+/// Gets a `[[link]]` with whitespace trimmed from around its target
+fn trimmed_link(link: &str) -> Option<String> {
+    let target = link_target_range(link)?;
+    Some(retarget(link, target.clone(), link[target].trim()))
+}
+
+/// Finds the name of a passage similar to that of a dead link
+fn similar_passage(dead: &str, story_files: &StoryFiles) -> Option<String> {
+    story_files
+        .passage_names
+        .as_ref()
+        .and_then(|names| did_you_mean(dead, names).pop())
+}
+
+fn file_id_and_range(
+    context: &FullContext,
+    story_files: &StoryFiles,
+) -> Option<(usize, Range<usize>)> {
+    context
+        .get_file_name()
+        .as_ref()
+        .and_then(|file_name| story_files.code_map.lookup_id(file_name.clone()))
+        .map(|id| (id, context.get_byte_range()))
+}
+
+/// Gets the possible values that `v` may be a misspelling of, from least to
+/// most similar
 // Shamelessly stolen from clap
-fn did_you_mean<T, I>(v: &str, possible_values: I) -> Vec<String>
+pub(crate) fn did_you_mean<T, I>(v: &str, possible_values: I) -> Vec<String>
 where
     T: AsRef<str>,
     I: IntoIterator<Item = T>,
@@ -169,29 +473,52 @@ where
     candidates.into_iter().map(|(_, pv)| pv).collect()
 }
 
-/// Given the output of parsing a story and a config, create, sort, and filter a
-/// list of `Issue`s
+/// Given the output of parsing a story, any lint warnings, and a config,
+/// create, sort, and filter a list of `Issue`s
 pub fn filter_and_sort_issues(
     story_result: &StoryResult,
     mut warnings: Vec<Warning>,
+    mut lints: Vec<LintWarning>,
     config: &Config,
 ) -> (Vec<Issue>, bool) {
     let mut issues = Vec::new();
     let mut is_err = false;
 
-    let all = "all".to_string();
-    let allow_all = config.allowed.contains(&all);
-    let deny_all = config.denied.contains(&all);
-    for warning in warnings.drain(..) {
-        let name = warning.get_name().to_string();
-        if allow_all || config.allowed.contains(&name) {
-            continue;
+    let suppressions = match story_result {
+        Ok(story) => {
+            links::adjust_dead_links(story, &mut warnings, &config.links);
+            Suppressions::new(story)
         }
-        let denied = deny_all || config.denied.contains(&name);
-        if denied {
+        Err(_) => Suppressions::default(),
+    };
+
+    // Returns None if the named warning is allowed, either by the config or
+    // by a comment where it occurs, otherwise whether or not it is denied or
+    // forbidden
+    let mut check = |name: &str, context: &Option<FullContext>| match lint_level(name, config) {
+        LintLevel::Allow => None,
+        LintLevel::Forbid => {
+            is_err = true;
+            Some(true)
+        }
+        _ if suppressions.allows(name, context) => None,
+        LintLevel::Warn => Some(false),
+        LintLevel::Deny => {
             is_err = true;
+            Some(true)
+        }
+    };
+
+    for warning in warnings.drain(..) {
+        if let Some(denied) = check(warning.get_name(), &warning.context) {
+            issues.push(Issue::Warning { warning, denied });
+        }
+    }
+
+    for lint in lints.drain(..) {
+        if let Some(denied) = check(lint.name, &lint.context) {
+            issues.push(Issue::Lint { lint, denied });
         }
-        issues.push(Issue::Warning { warning, denied });
     }
 
     if let Err(e) = &story_result {
@@ -201,16 +528,8 @@ pub fn filter_and_sort_issues(
         }
     }
 
-    issues.sort_by(|left, right| {
-        let left = match left {
-            Issue::Error(e) => &e.context,
-            Issue::Warning { warning, .. } => &warning.context,
-        };
-        let right = match right {
-            Issue::Error(e) => &e.context,
-            Issue::Warning { warning, .. } => &warning.context,
-        };
-        match (left, right) {
+    issues.sort_by(
+        |left, right| match (left.get_context(), right.get_context()) {
             (None, _) => Ordering::Less,
             (_, None) => Ordering::Greater,
             (Some(lctx), Some(rctx)) => match (lctx.get_file_name(), rctx.get_file_name()) {
@@ -229,20 +548,143 @@ pub fn filter_and_sort_issues(
                     }
                 }
             },
-        }
-    });
+        },
+    );
 
     (issues, is_err)
 }
 
+/// How a warning is treated, from least to most severe
+///
+/// Mirrors rustc's lint levels: a forbidden warning is an error that no later,
+/// more specific configuration can allow, warn about, or merely deny
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintLevel {
+    /// Ignored
+    Allow,
+
+    /// Reported as a warning
+    Warn,
+
+    /// Reported as an error
+    Deny,
+
+    /// Reported as an error, and cannot be given another level
+    Forbid,
+}
+
+impl LintLevel {
+    /// Gets the name of the level, as used in config files
+    pub fn name(self) -> &'static str {
+        match self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny",
+            LintLevel::Forbid => "forbid",
+        }
+    }
+}
+
+impl std::fmt::Display for LintLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Turns the allow, warn, deny, and forbid lists of one config into levels,
+/// in the order they are applied
+///
+/// Entries for "all" come before those naming a warning, so that a named
+/// warning can be given a different level than every other. Within those,
+/// allowing takes precedence over warning, which takes precedence over
+/// denying, matching the older allow and deny lists
+pub fn lint_levels(
+    allow: &[String],
+    warn: &[String],
+    deny: &[String],
+    forbid: &[String],
+) -> Vec<(String, LintLevel)> {
+    let lists = [
+        (forbid, LintLevel::Forbid),
+        (deny, LintLevel::Deny),
+        (warn, LintLevel::Warn),
+        (allow, LintLevel::Allow),
+    ];
+    let mut levels = Vec::new();
+    for all in &[true, false] {
+        for (list, level) in &lists {
+            levels.extend(
+                list.iter()
+                    .filter(|name| (*name == "all") == *all)
+                    .map(|name| (name.clone(), *level)),
+            );
+        }
+    }
+    levels
+}
+
+/// Decides the level of the named warning under the given config
+///
+/// The config's levels are applied in order, starting from
+/// [`LintLevel::Warn`], and "all" matches every warning. Once a warning is
+/// forbidden, later levels no longer apply to it
+///
+/// [`LintLevel::Warn`]: enum.LintLevel.html#variant.Warn
+pub fn lint_level(name: &str, config: &Config) -> LintLevel {
+    config
+        .levels
+        .iter()
+        .filter(|(n, _)| n == name || n == "all")
+        .fold(LintLevel::Warn, |level, (_, next)| match level {
+            LintLevel::Forbid => level,
+            _ => *next,
+        })
+}
+
+/// Number of issues with the same name and message that are shown before the
+/// rest are folded
+pub const FOLD_LIMIT: usize = 3;
+
+/// Decides which of the given issues to show when folding repeats
+///
+/// Returns one entry per issue: `None` if the issue should be hidden, or the
+/// number of issues like it that were hidden, to be mentioned after it. Only
+/// the last shown issue of a folded group has a nonzero count
+pub fn fold_issues(issues: &[Issue]) -> Vec<Option<usize>> {
+    let mut totals: HashMap<(&str, String), usize> = HashMap::new();
+    for issue in issues {
+        *totals
+            .entry((issue.get_name(), issue.get_message()))
+            .or_default() += 1;
+    }
+
+    let mut seen: HashMap<(&str, String), usize> = HashMap::new();
+    issues
+        .iter()
+        .map(|issue| {
+            let key = (issue.get_name(), issue.get_message());
+            let total = totals[&key];
+            let count = seen.entry(key).or_default();
+            *count += 1;
+            match *count {
+                c if c < FOLD_LIMIT => Some(0),
+                c if c == FOLD_LIMIT => Some(total - FOLD_LIMIT),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
 /// Writes the given issue to the given stream
 pub fn print_issue(issue: &Issue, stdout: &mut StandardStream) -> color_eyre::Result<()> {
     let kind = match issue {
-        Issue::Error(_) | Issue::Warning { denied: true, .. } => {
+        Issue::Error(_)
+        | Issue::Warning { denied: true, .. }
+        | Issue::Lint { denied: true, .. } => {
             stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
             "Error"
         }
-        Issue::Warning { denied: false, .. } => {
+        Issue::Warning { denied: false, .. } | Issue::Lint { denied: false, .. } => {
             stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
             "Warning"
         }
@@ -255,7 +697,75 @@ pub fn print_issue(issue: &Issue, stdout: &mut StandardStream) -> color_eyre::Re
         match issue {
             Issue::Error(e) => format!("{}", e),
             Issue::Warning { warning, .. } => format!("{}", warning),
+            Issue::Lint { lint, .. } => format!("{}", lint),
         }
     )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(name: &'static str, message: &str) -> Issue {
+        Issue::Lint {
+            lint: LintWarning::new(name, message, None),
+            denied: false,
+        }
+    }
+
+    #[test]
+    fn folds_repeated_issues() {
+        let mut issues = vec![lint("Other", "Once")];
+        for _ in 0..5 {
+            issues.push(lint("Repeated", "Same message"));
+        }
+        issues.push(lint("Repeated", "Different message"));
+
+        assert_eq!(
+            fold_issues(&issues),
+            vec![Some(0), Some(0), Some(0), Some(2), None, None, Some(0)]
+        );
+    }
+
+    #[test]
+    fn trims_link_targets() {
+        let trimmed = |link| trimmed_link(link).unwrap();
+        assert_eq!(trimmed("[[ Cave ]]"), "[[Cave]]");
+        assert_eq!(trimmed("[[the beach| Beach ]]"), "[[the beach|Beach]]");
+        assert_eq!(trimmed("[[ Beach <-the beach]]"), "[[Beach<-the beach]]");
+        assert_eq!(trimmed("[[the beach-> Beach]]"), "[[the beach->Beach]]");
+        assert_eq!(link_target_range("<<link \"Cave\">>"), None);
+    }
+
+    #[test]
+    fn explains_duplicates_across_files() {
+        let dir = std::env::temp_dir().join(format!("tweec-duplicate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.twee"), dir.join("b.twee"));
+        std::fs::write(&a, ":: Start\n[[Cave]]\n\n:: Cave\nDark\n").unwrap();
+        std::fs::write(&b, ":: Intro\nHi\n\n:: Cave\nDamp\n").unwrap();
+        let (story_result, warnings) = tweep::Story::from_paths(&[&a, &b]).take();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let story_files = StoryFiles::new(&story_result);
+        let warning = warnings
+            .into_iter()
+            .find(|warning| matches!(warning.kind, WarningKind::DuplicatePassage(_)))
+            .unwrap();
+        let diagnostic = Issue::Warning {
+            warning,
+            denied: false,
+        }
+        .report(&story_files);
+
+        assert_eq!(diagnostic.labels.len(), 2);
+        assert_eq!(
+            diagnostic.labels[1].message,
+            "This definition is kept".to_string()
+        );
+        assert!(diagnostic.notes[0].starts_with("Passage \"Cave\" is defined in both "));
+        assert!(diagnostic.notes[0].contains("a.twee (line 4)"));
+        assert!(diagnostic.notes[0].contains("b.twee (line 4)"));
+        assert!(diagnostic.notes[1].contains("a.twee is kept"));
+    }
+}
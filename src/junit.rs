@@ -0,0 +1,148 @@
+//! Serializes issues as JUnit XML, which CI systems such as Jenkins and GitLab
+//! show on their pipeline pages
+//!
+//! Each file with issues is a `testsuite`, and each rule reported in it is a
+//! failed `testcase` named after the rule, whose failure lists every
+//! occurrence. A failure's `type` is `error` if any occurrence is an error,
+//! and `warning` otherwise. Issues that aren't in any file are put in a suite
+//! named `Story`
+
+use crate::issue::Issue;
+use crate::rules;
+use crate::story_html::escape;
+use crate::StoryFiles;
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use codespan_reporting::files::Files;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// The occurrences of one rule in one file
+#[derive(Default)]
+struct TestCase {
+    is_error: bool,
+    occurrences: Vec<String>,
+}
+
+/// A JUnit report that issues are added to one at a time
+#[derive(Default)]
+pub struct Report {
+    suites: BTreeMap<String, BTreeMap<String, TestCase>>,
+}
+
+impl Report {
+    /// Adds an issue to the report. `paths` maps the file names used by
+    /// `story_files` to the paths to report for them
+    pub fn add(
+        &mut self,
+        issue: &Issue,
+        story_files: &StoryFiles,
+        paths: &HashMap<String, PathBuf>,
+    ) {
+        let diagnostic = issue.report(story_files);
+        let rule = diagnostic.code.clone().unwrap_or_default();
+        let primary = diagnostic
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary);
+        let (file, location) = match primary {
+            Some(label) => {
+                let name = story_files.name(label.file_id).unwrap_or_default();
+                let file = paths
+                    .get(name)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| name.to_string());
+                let (line, column) = story_files.position(label.file_id, label.range.start);
+                let location = format!("{}:{}:{}", file, line + 1, column + 1);
+                (file, location)
+            }
+            None => ("Story".to_string(), "Story".to_string()),
+        };
+        let mut occurrence = format!("{}: {}", location, diagnostic.message);
+        for note in &diagnostic.notes {
+            occurrence.push_str("\n  ");
+            occurrence.push_str(note);
+        }
+
+        let case = self
+            .suites
+            .entry(file)
+            .or_default()
+            .entry(rule)
+            .or_default();
+        case.is_error |= matches!(diagnostic.severity, Severity::Bug | Severity::Error);
+        case.occurrences.push(occurrence);
+    }
+
+    /// Gets the report as an XML document
+    pub fn to_xml(&self) -> String {
+        let failures: usize = self.suites.values().map(BTreeMap::len).sum();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"tweec\" tests=\"{0}\" failures=\"{0}\">\n",
+            failures
+        ));
+        for (file, cases) in &self.suites {
+            xml.push_str(&format!(
+                "  <testsuite name=\"{0}\" tests=\"{1}\" failures=\"{1}\">\n",
+                escape(file),
+                cases.len()
+            ));
+            for (rule, case) in cases {
+                let severity = if case.is_error { "error" } else { "warning" };
+                let message = match rules::find(rule) {
+                    Some(found) => format!("{}: {}", rule, found.description),
+                    None => rule.clone(),
+                };
+                xml.push_str(&format!(
+                    "    <testcase name=\"{}\" classname=\"{}\">\n",
+                    escape(rule),
+                    escape(file)
+                ));
+                xml.push_str(&format!(
+                    "      <failure type=\"{}\" message=\"{}\">{}</failure>\n",
+                    severity,
+                    escape(&message),
+                    escape(&case.occurrences.join("\n"))
+                ));
+                xml.push_str("    </testcase>\n");
+            }
+            xml.push_str("  </testsuite>\n");
+        }
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issue::LintWarning;
+    use tweep::Story;
+
+    #[test]
+    fn groups_issues_by_file_and_rule() {
+        let story_result = Story::from_string(":: Start\nHello\n".to_string()).take().0;
+        let story_files = StoryFiles::new(&story_result);
+        let mut report = Report::default();
+        let issues = vec![
+            Issue::Lint {
+                lint: LintWarning::new("DeadEnd", "Passage Start has no links out", None),
+                denied: false,
+            },
+            Issue::Lint {
+                lint: LintWarning::new("DeadEnd", "Passage <End> has no links out", None),
+                denied: true,
+            },
+        ];
+        for issue in &issues {
+            report.add(issue, &story_files, &HashMap::new());
+        }
+
+        let xml = report.to_xml();
+        assert!(xml.contains("<testsuites name=\"tweec\" tests=\"1\" failures=\"1\">"));
+        assert!(xml.contains("<testsuite name=\"Story\" tests=\"1\" failures=\"1\">"));
+        assert!(xml.contains("<testcase name=\"DeadEnd\" classname=\"Story\">"));
+        assert!(xml.contains("<failure type=\"error\""));
+        assert!(xml.contains("Story: Passage &lt;End&gt; has no links out</failure>"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Places passages on the Twine editor's story map by following their links
+//!
+//! With `--layout auto`, each passage's `position` is replaced so that the
+//! story reads from left to right when opened in the Twine 2 editor. Passages
+//! are put in columns by the fewest links needed to reach them from the start
+//! passage, and each column is ordered to sit close to the passages linking
+//! into it. Passages that can't be reached from the start are laid out the
+//! same way from the first of them by pid, and so on, below the others in
+//! each column
+
+use crate::linter::graph::LinkGraph;
+use crate::linter::links::LinkSyntax;
+use crate::utils;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use tweep::Story;
+
+/// Space left above and to the left of the map
+const MARGIN: usize = 100;
+
+/// Distance between the left edges of neighboring columns
+const COLUMN_WIDTH: usize = 200;
+
+/// Distance between the top edges of neighboring passages in a column
+const ROW_HEIGHT: usize = 150;
+
+/// Gets the position of each passage in the graph's story, as written in
+/// passage metadata, such as `300,250`
+pub fn positions(graph: &LinkGraph) -> HashMap<String, String> {
+    let story = graph.story();
+    let mut names: Vec<&str> = story.passages.keys().map(String::as_str).collect();
+    names.sort_by_key(|name| (utils::get_pid(&story.passages[*name]), *name));
+    let roots = story
+        .get_start_passage_name()
+        .filter(|start| story.passages.contains_key(*start))
+        .into_iter()
+        .chain(names.iter().copied());
+
+    // Assign columns breadth first, so each column holds passages in the order
+    // they were found
+    let mut column: HashMap<&str, usize> = HashMap::new();
+    let mut columns: Vec<Vec<&str>> = Vec::new();
+    for root in roots {
+        if column.contains_key(root) {
+            continue;
+        }
+        let mut queue = VecDeque::new();
+        queue.push_back((root, 0));
+        column.insert(root, 0);
+        while let Some((name, depth)) = queue.pop_front() {
+            if columns.len() <= depth {
+                columns.resize_with(depth + 1, Vec::new);
+            }
+            columns[depth].push(name);
+            for edge in graph.outgoing(name) {
+                if !column.contains_key(edge.target) {
+                    column.insert(edge.target, depth + 1);
+                    queue.push_back((edge.target, depth + 1));
+                }
+            }
+        }
+    }
+
+    // Order each column by the average row of the passages linking into it
+    // from earlier columns, keeping the found order for ties
+    let mut row: HashMap<&str, usize> = HashMap::new();
+    for (depth, names) in columns.iter_mut().enumerate() {
+        if depth > 0 {
+            let key = |name: &str| {
+                let rows: Vec<usize> = graph
+                    .incoming(name)
+                    .iter()
+                    .filter(|from| column[**from] < depth)
+                    .map(|from| row[from])
+                    .collect();
+                match rows.len() {
+                    0 => f64::MAX,
+                    n => rows.iter().sum::<usize>() as f64 / n as f64,
+                }
+            };
+            let mut keyed: Vec<(f64, &str)> = names.iter().map(|name| (key(name), *name)).collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            *names = keyed.into_iter().map(|(_, name)| name).collect();
+        }
+        for (i, name) in names.iter().enumerate() {
+            row.insert(name, i);
+        }
+    }
+
+    column
+        .into_iter()
+        .map(|(name, depth)| {
+            let x = MARGIN + depth * COLUMN_WIDTH;
+            let y = MARGIN + row[name] * ROW_HEIGHT;
+            (name.to_string(), format!("{},{}", x, y))
+        })
+        .collect()
+}
+
+/// Replaces the position of every passage in the story, following links
+/// written in the given syntaxes
+pub fn apply(story: &mut Story, syntaxes: &[LinkSyntax]) {
+    let positions = positions(&LinkGraph::new(story, syntaxes));
+    for (name, position) in positions {
+        if let Some(passage) = story.passages.get_mut(&name) {
+            passage
+                .header
+                .metadata
+                .insert("position".to_string(), Value::String(position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order;
+    use crate::SortPassages;
+
+    #[test]
+    fn lays_out_passages_by_links() {
+        let input = ":: StoryData\n{\"ifid\": \"D674C58C-DEFA-4F70-B7A2-27742230C0FC\"}\n\n\
+                     :: Start\n[[Left]] [[Right]]\n\n:: Left\n[[End]]\n\n:: Right\n[[End]]\n\n\
+                     :: End\nDone.\n\n:: Lost\n[[Found]]\n\n:: Found\nHere.\n"
+            .to_string();
+        let mut story = Story::from_string(input).take().0.ok().unwrap();
+        order::renumber(&mut story, SortPassages::Name);
+        apply(&mut story, LinkSyntax::DEFAULT);
+        let position = |name: &str| story.passages[name].header.metadata["position"].clone();
+
+        assert_eq!(position("Start"), "100,100");
+        assert_eq!(position("Left"), "300,100");
+        assert_eq!(position("Right"), "300,250");
+        assert_eq!(position("End"), "500,100");
+        // Unreachable passages are laid out from the first by pid, below the
+        // passages reachable from the start
+        assert_eq!(position("Found"), "100,250");
+        assert_eq!(position("Lost"), "100,400");
+    }
+}
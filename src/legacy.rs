@@ -0,0 +1,214 @@
+//! Reads stories written for Twine 1 era tools, in Twee 1 or Twee2 syntax,
+//! into the Twee 3 model
+//!
+//! Such stories have no `StoryData` passage, and use a few conventions that
+//! Twee 3 dropped:
+//!
+//! * `StorySettings` holds `key:value` lines. Its `ifid` becomes the IFID of
+//!   the story, and it is left out of the story's passages
+//! * `StoryIncludes` lists more Twee files to read, one per line, relative to
+//!   the file listing them. They are read after the inputs, and the passage
+//!   is left out of the story's passages
+//! * Twee2 gives the position of a passage at the end of its header, as in
+//!   `:: Start [intro] <100,200>`, which becomes its `position` metadata
+//!
+//! The [`LegacyTwee`] lint warns about each of these so that the story can be
+//! migrated
+//!
+//! [`LegacyTwee`]: ../linter/legacy/struct.LegacyTwee.html
+
+use crate::utils;
+use crate::StoryResult;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tweep::{Output, Story, StoryData, WarningKind};
+
+/// The passage holding Twine 1 story settings
+pub const SETTINGS: &str = "StorySettings";
+
+/// The passage listing more files to read
+pub const INCLUDES: &str = "StoryIncludes";
+
+/// Finds the Twee2 position at the end of a passage header line, returning
+/// its byte range and the position as `x,y`
+pub fn position(line: &str) -> Option<(Range<usize>, String)> {
+    let trimmed = line.trim_end();
+    let inner = trimmed.strip_suffix('>')?;
+    let start = inner.rfind('<')?;
+    let (x, y) = inner[start + 1..].split_once(',')?;
+    let (x, y) = (x.trim(), y.trim());
+    if x.parse::<f64>().is_err() || y.parse::<f64>().is_err() {
+        return None;
+    }
+    Some((start..trimmed.len(), format!("{},{}", x, y)))
+}
+
+/// Gets the lines of the passage with the given name in some Twee source,
+/// leaving out blank lines
+fn passage_lines<'a>(source: &'a str, name: &str) -> Vec<&'a str> {
+    let mut lines = source.lines();
+    let found = lines.any(|line| {
+        line.strip_prefix("::")
+            .map(|header| header.split('[').next().unwrap_or_default().trim() == name)
+            .unwrap_or(false)
+    });
+    if !found {
+        return Vec::new();
+    }
+    lines
+        .take_while(|line| !line.starts_with("::"))
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Parses the `key:value` lines of a `StorySettings` passage
+pub fn settings(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Adds the files listed by `StoryIncludes` passages to the paths of a
+/// story's Twee files, including those listed by the added files in turn
+///
+/// Files that are already among the paths are not added again. Listed files
+/// that don't exist are still added, so that the parser reports them
+pub fn with_includes(mut paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let key = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut seen: HashSet<PathBuf> = paths.iter().map(|path| key(path)).collect();
+    let mut i = 0;
+    while i < paths.len() {
+        if let Ok(source) = std::fs::read_to_string(&paths[i]) {
+            let dir = paths[i]
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .to_path_buf();
+            for file in passage_lines(&source, INCLUDES) {
+                let path = dir.join(file);
+                if seen.insert(key(&path)) {
+                    paths.push(path);
+                }
+            }
+        }
+        i += 1;
+    }
+    paths
+}
+
+/// Translates the Twee 1 and Twee2 conventions in a parsed story into the
+/// Twee 3 model, returning whether anything changed
+fn translate_story(story: &mut Story) -> bool {
+    let mut changed = false;
+
+    for (name, header) in utils::passage_headers(story) {
+        let (range, position) = match position(header.get_contents()) {
+            Some(position) => position,
+            None => continue,
+        };
+        // Without tags, the parser reads the position as part of the name
+        let written = &header.get_contents()[range];
+        let new_name = name.strip_suffix(written).unwrap_or(&name).trim_end();
+        if new_name != name && story.passages.contains_key(new_name) {
+            continue;
+        }
+        if let Some(mut passage) = story.passages.remove(&name) {
+            passage.header.name = new_name.to_string();
+            passage
+                .header
+                .metadata
+                .insert("position".to_string(), Value::String(position));
+            story.passages.insert(new_name.to_string(), passage);
+            changed = true;
+        }
+    }
+
+    if story.data.is_none() {
+        if let Some(passage) = story.passages.remove(SETTINGS) {
+            let ifid = settings(utils::get_content(&passage))
+                .into_iter()
+                .find(|(key, _)| key == "ifid")
+                .map(|(_, ifid)| ifid.to_uppercase());
+            if let Some(ifid) = ifid {
+                story.data = Some(StoryData {
+                    ifid,
+                    format: None,
+                    format_version: None,
+                    start: None,
+                    tag_colors: None,
+                    zoom: None,
+                });
+            }
+            changed = true;
+        }
+        changed |= story.passages.remove(INCLUDES).is_some();
+    }
+    changed
+}
+
+/// Translates a parsed story's Twee 1 and Twee2 conventions into the Twee 3
+/// model, leaving out the parser's warnings that no longer apply
+pub fn translate(output: Output<StoryResult>) -> Output<StoryResult> {
+    let (result, warnings) = output.take();
+    let mut story = match result {
+        Ok(story) => story,
+        Err(errors) => return Output::new(Err(errors)).with_warnings(warnings),
+    };
+    if !translate_story(&mut story) {
+        return Output::new(Ok(story)).with_warnings(warnings);
+    }
+    let warnings = warnings
+        .into_iter()
+        .filter(|warning| match &warning.kind {
+            WarningKind::MissingStoryData => story.data.is_none(),
+            WarningKind::MissingStartPassage => story.get_start_passage_name().is_none(),
+            WarningKind::DeadLink(target) => !story.passages.contains_key(target),
+            _ => true,
+        })
+        .collect();
+    Output::new(Ok(story)).with_warnings(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_legacy_story() {
+        let dir = std::env::temp_dir().join(format!("tweec-legacy-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("story.tw"),
+            ":: StoryTitle\nOld\n\n:: StorySettings\nundo:on\nifid:0ac8a9c6-1a1a-4b2b-9c9c-\
+             0123456789ab\n\n:: StoryIncludes\nmore.tw\n\n:: Start <100,200>\nHello [[Next]]\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("more.tw"), ":: Next [end] <5, 6>\nBye\n").unwrap();
+
+        let paths = with_includes(vec![dir.join("story.tw"), dir.join("more.tw")]);
+        let (result, warnings) = translate(Story::from_paths(&paths)).take();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(paths.len(), 2);
+        let story = result.ok().unwrap();
+        assert_eq!(
+            story.data.unwrap().ifid,
+            "0AC8A9C6-1A1A-4B2B-9C9C-0123456789AB"
+        );
+        let mut names: Vec<&String> = story.passages.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["Next", "Start"]);
+        assert_eq!(
+            story.passages["Start"].header.metadata["position"],
+            "100,200"
+        );
+        assert_eq!(story.passages["Next"].header.metadata["position"], "5,6");
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+}
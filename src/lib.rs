@@ -4,26 +4,49 @@
 //! features.
 //!
 //! Notes for documentation:
-//!   "all" option for allow/deny
-//!   allow takes precedence over deny
+//!   "all" option for allow/warn/deny/forbid
+//!   more specific config overrides less specific, except for forbid
 //!
-//! - [ ] IFID generation
-//! - [ ] StoryData story format detection
-//! - [ ] Decompilation of Twine2 HTML
+//! - [x] IFID generation
+//! - [x] StoryData story format detection
+//! - [x] Decompilation of Twine2 HTML
 //!
 //! Some nice-to-haves that I may eventually work on:
-//! - [ ] LSP integration
-//! - [ ] Plugin system for linting specific story formats
-//! - [ ] File/directory watcher
+//! - [x] LSP integration
+//! - [x] Plugin system for linting specific story formats
+//! - [x] File/directory watcher
 #![warn(missing_docs)]
 
 /// Alias type for the contained result of parsing a story
 pub type StoryResult = std::result::Result<tweep::Story, tweep::ContextErrorList>;
 
 mod config;
+pub use config::ArchiveConfig;
 pub use config::CliConfig;
+pub use config::Command;
 pub use config::Config;
+pub use config::ConfigBuilder;
 pub use config::ConfigFile;
+pub use config::DecompileConfig;
+pub use config::ExportConfig;
+pub use config::ExportFormat;
+pub use config::ExtractConfig;
+pub use config::FixConfig;
+pub use config::FormatCommand;
+pub use config::GraphConfig;
+pub use config::GraphFormat;
+pub use config::Layout;
+pub use config::MessageFormat;
+pub use config::OutputStyle;
+pub use config::PackageConfig;
+pub use config::PwaConfig;
+pub use config::ReportConfig;
+pub use config::ReportFormat;
+pub use config::SortPassages;
+pub use config::StatsConfig;
+pub use config::StatsFormat;
+pub use config::TestConfig;
+pub use config::TranslateConfig;
 
 pub mod issue;
 pub use issue::Issue;
@@ -36,6 +59,61 @@ pub use story_format::StoryFormat;
 
 pub mod utils;
 
+pub mod twee;
+
+pub mod assets;
+pub mod baseline;
+pub mod compile;
+pub mod exit;
+pub mod extract;
+pub mod fix;
+pub mod formats;
+pub mod glob;
+pub mod html_report;
+pub mod i18n;
+
+pub mod decompile;
+pub mod diff;
+pub mod export;
+
+pub mod ifid;
+pub mod inputs;
+pub mod junit;
+pub mod layout;
+
+pub mod legacy;
+
 pub mod linter;
 
+pub mod logging;
+pub mod lsp;
+pub mod minify;
+pub mod open;
+pub mod order;
+pub mod package;
+pub mod partials;
+pub mod playtest;
+pub mod project;
+pub mod pwa;
+
+pub mod daemon;
+
+pub mod report;
+pub mod reporter;
+pub mod rules;
+
+pub mod sarif;
+pub mod smoke;
+pub mod source_map;
+pub mod stats;
+pub mod stdin;
+pub mod story_html;
+pub mod story_json;
+pub mod suppress;
+pub mod template;
+
+pub mod toml;
 pub mod tweec;
+pub mod validate;
+
+pub mod watch;
@@ -2,51 +2,655 @@
 //!
 //! [`Config`]: struct.Config.html
 
+use crate::baseline::Baseline;
+use crate::exit::StoryError;
+use crate::html_report::HtmlReporter;
 use crate::issue;
+use crate::issue::{Issue, LintLevel, LintWarning};
+use crate::playtest;
+use crate::reporter::{self, Reporter, Summary};
+use crate::rules;
+use crate::utils;
 use crate::Config;
 use crate::StoryFiles;
 use crate::StoryResult;
-use codespan_reporting::term;
-use color_eyre::Result;
-use eyre::eyre;
-use std::io::Write;
-use termcolor::StandardStream;
+use codespan_reporting::diagnostic::Severity;
+use color_eyre::{Report, Result};
+use eyre::WrapErr;
+use graph::LinkGraph;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
 use tweep::Output;
 use tweep::Story;
 
+pub mod assets;
+pub mod chapbook;
+pub mod chapters;
+pub mod collisions;
+pub mod dataflow;
+pub mod dead_ends;
+pub mod graph;
+pub mod harlowe;
+pub mod headers;
+pub mod i18n;
+pub mod legacy;
+pub mod links;
+pub mod macros;
+pub mod max_links;
+pub mod spelling;
+pub mod sugarcube;
+pub mod unreachable;
+
+/// A check over a parsed story that produces lint warnings
+///
+/// Lints are given levels by name, the same as `tweep`'s own warnings.
+/// tweec's built-in lints implement this trait, and other lints can be added
+/// to a [`Linter`] as plugins, either for every story or only for stories
+/// using a particular story format
+///
+/// [`Linter`]: struct.Linter.html
+pub trait Lint {
+    /// Name of the lint, used for allowing or denying it
+    fn name(&self) -> &'static str;
+
+    /// Checks the given story, returning any warnings found
+    fn check(&self, story: &Story, graph: &LinkGraph, config: &Config) -> Vec<LintWarning>;
+
+    /// Whether the lint has anything to check under the given config, such as
+    /// a limit or list of tags it needs. Disabled lints are not run
+    fn enabled(&self, _config: &Config) -> bool {
+        true
+    }
+}
+
+/// Where a rule comes from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleSource {
+    /// Reported by `tweep` while parsing
+    Parser,
+
+    /// One of tweec's lints, or a plugin that runs on every story
+    Lint,
+
+    /// A plugin for the story format in use
+    FormatLint,
+}
+
+/// How a rule would be treated when linting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleLevel {
+    /// Reported as an error
+    Error,
+
+    /// Reported as an error, and cannot be allowed by more specific config
+    Forbidden,
+
+    /// Reported as a warning
+    Warning,
+
+    /// Ignored because it is allowed
+    Allowed,
+
+    /// Not run because the config gives it nothing to check
+    Disabled,
+}
+
+impl std::fmt::Display for RuleLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self {
+            RuleLevel::Error => "error",
+            RuleLevel::Forbidden => "forbidden",
+            RuleLevel::Warning => "warning",
+            RuleLevel::Allowed => "allowed",
+            RuleLevel::Disabled => "disabled",
+        };
+        write!(f, "{}", level)
+    }
+}
+
+/// A rule that applies to a story, along with how it would be treated
+#[derive(Clone, Debug)]
+pub struct RuleStatus {
+    /// The name of the rule
+    pub name: &'static str,
+
+    /// Where the rule comes from
+    pub source: RuleSource,
+
+    /// How the rule would be treated
+    pub level: RuleLevel,
+
+    /// A short description of the rule, if known
+    pub description: &'static str,
+}
+
+/// A set of lints to run over a story
+pub struct Linter {
+    lints: Vec<Box<dyn Lint>>,
+    format_lints: HashMap<String, Vec<Box<dyn Lint>>>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Linter::new()
+    }
+}
+
+impl Linter {
+    /// Creates a linter with all of tweec's built-in lints
+    pub fn new() -> Self {
+        let mut linter = Linter::empty();
+        linter.register(Box::new(max_links::TooManyLinks));
+        linter.register(Box::new(chapters::BackwardsChapterLink));
+        linter.register(Box::new(unreachable::UnreachablePassage));
+        linter.register(Box::new(dead_ends::DeadEnd));
+        linter.register(Box::new(collisions::PassageNameCollision));
+        linter.register(Box::new(headers::DuplicateTag));
+        linter.register(Box::new(headers::TagCaseMismatch));
+        linter.register(Box::new(headers::SingleUseTag));
+        linter.register(Box::new(headers::TagTypo));
+        linter.register(Box::new(headers::DuplicateMetadataKey));
+        linter.register(Box::new(spelling::Spelling));
+        linter.register(Box::new(assets::MissingAsset));
+        linter.register(Box::new(legacy::LegacyTwee));
+        linter.register(Box::new(i18n::MissingTranslation));
+        linter.register(Box::new(i18n::FuzzyTranslation));
+        linter.register_for_format(sugarcube::FORMAT, Box::new(sugarcube::UnclosedMacro));
+        linter.register_for_format(sugarcube::FORMAT, Box::new(sugarcube::UnknownMacro));
+        linter.register_for_format(sugarcube::FORMAT, Box::new(sugarcube::MacroSyntax));
+        linter.register_for_format(
+            sugarcube::FORMAT,
+            Box::new(dataflow::ReadBeforeSet(sugarcube::Variables)),
+        );
+        linter.register_for_format(
+            sugarcube::FORMAT,
+            Box::new(dataflow::UnusedVariable(sugarcube::Variables)),
+        );
+        linter.register_for_format(harlowe::FORMAT, Box::new(harlowe::UnknownMacro));
+        linter.register_for_format(harlowe::FORMAT, Box::new(harlowe::MacroSyntax));
+        linter.register_for_format(harlowe::FORMAT, Box::new(harlowe::HookSyntax));
+        linter.register_for_format(
+            harlowe::FORMAT,
+            Box::new(dataflow::ReadBeforeSet(harlowe::Variables)),
+        );
+        linter.register_for_format(
+            harlowe::FORMAT,
+            Box::new(dataflow::UnusedVariable(harlowe::Variables)),
+        );
+        for format in chapbook::FORMATS {
+            linter.register_for_format(format, Box::new(chapbook::InvalidVariableName));
+            linter.register_for_format(format, Box::new(chapbook::DuplicateVariable));
+            linter.register_for_format(format, Box::new(chapbook::MalformedModifier));
+        }
+        linter
+    }
+
+    /// Creates a linter with no lints
+    pub fn empty() -> Self {
+        Linter {
+            lints: Vec::new(),
+            format_lints: HashMap::new(),
+        }
+    }
+
+    /// Adds a lint that runs on every story
+    pub fn register(&mut self, lint: Box<dyn Lint>) {
+        self.lints.push(lint);
+    }
+
+    /// Adds a lint that only runs on stories using the named story format
+    ///
+    /// The format name is the same one used for format specific settings in
+    /// the config file (e.g., sugarcube-2)
+    pub fn register_for_format(&mut self, format: &str, lint: Box<dyn Lint>) {
+        self.format_lints
+            .entry(format.to_string())
+            .or_default()
+            .push(lint);
+    }
+
+    /// Gets the lints registered for the story format in the config
+    fn format_lints(&self, config: &Config) -> &[Box<dyn Lint>] {
+        config
+            .format
+            .as_ref()
+            .and_then(|format| self.format_lints.get(format))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Runs every applicable lint over the given story
+    ///
+    /// Lints that are allowed or disabled in the config are skipped entirely
+    pub fn check(&self, story: &Story, config: &Config) -> Vec<LintWarning> {
+        let graph = LinkGraph::new(story, &config.links);
+        self.lints
+            .iter()
+            .chain(self.format_lints(config))
+            .filter(|lint| {
+                lint.enabled(config) && issue::lint_level(lint.name(), config) != LintLevel::Allow
+            })
+            .flat_map(|lint| lint.check(story, &graph, config))
+            .collect()
+    }
+
+    /// Lists every rule that applies under the given config, along with how
+    /// it would be treated, without linting anything
+    ///
+    /// Errors from the parser are always reported as errors, regardless of
+    /// the configured lint levels
+    pub fn rules(&self, config: &Config) -> Vec<RuleStatus> {
+        let level = |name: &str| match issue::lint_level(name, config) {
+            LintLevel::Allow => RuleLevel::Allowed,
+            LintLevel::Warn => RuleLevel::Warning,
+            LintLevel::Deny => RuleLevel::Error,
+            LintLevel::Forbid => RuleLevel::Forbidden,
+        };
+        // Rules from lints, including those for other story formats, are not
+        // the parser's, and playthrough failures are only reported by `tweec test`
+        let lint_names: Vec<&str> = self
+            .lints
+            .iter()
+            .chain(self.format_lints.values().flatten())
+            .map(|lint| lint.name())
+            .collect();
+
+        let mut statuses: Vec<RuleStatus> = rules::RULES
+            .iter()
+            .filter(|rule| !lint_names.contains(&rule.name) && rule.name != playtest::NAME)
+            .map(|rule| RuleStatus {
+                name: rule.name,
+                source: RuleSource::Parser,
+                level: match rule.severity {
+                    Severity::Error | Severity::Bug => RuleLevel::Error,
+                    _ => level(rule.name),
+                },
+                description: rule.description,
+            })
+            .collect();
+
+        let lints = self.lints.iter().map(|lint| (lint, RuleSource::Lint));
+        let format_lints = self
+            .format_lints(config)
+            .iter()
+            .map(|lint| (lint, RuleSource::FormatLint));
+        for (lint, source) in lints.chain(format_lints) {
+            statuses.push(RuleStatus {
+                name: lint.name(),
+                source,
+                level: if lint.enabled(config) {
+                    level(lint.name())
+                } else {
+                    RuleLevel::Disabled
+                },
+                description: rules::find(lint.name())
+                    .map(|rule| rule.description)
+                    .unwrap_or_default(),
+            });
+        }
+        statuses
+    }
+
+    /// Gets the name of every lint, whatever the config, along with the story
+    /// formats it is registered for, which are empty if it runs on every story
+    pub fn rule_scopes(&self) -> Vec<(&'static str, Vec<&str>)> {
+        let mut scopes: Vec<(&'static str, Vec<&str>)> = self
+            .lints
+            .iter()
+            .map(|lint| (lint.name(), Vec::new()))
+            .collect();
+        let mut formats: Vec<&String> = self.format_lints.keys().collect();
+        formats.sort();
+        for format in formats {
+            for lint in &self.format_lints[format] {
+                match scopes.iter_mut().find(|(name, _)| *name == lint.name()) {
+                    Some((_, formats)) => formats.push(format),
+                    None => scopes.push((lint.name(), vec![format])),
+                }
+            }
+        }
+        scopes
+    }
+
+    /// Lints the given story based on the given config without outputting
+    /// anything, returning the story result along with the filtered and
+    /// sorted list of `Issue`s and whether any of them are errors
+    pub fn collect(
+        &self,
+        story_output: Output<StoryResult>,
+        config: &Config,
+    ) -> (StoryResult, Vec<Issue>, bool) {
+        let (story_result, warnings) = story_output.take();
+
+        let lints = match &story_result {
+            Ok(story) => self.check(story, config),
+            Err(_) => Vec::new(),
+        };
+
+        let (issues, is_err) =
+            issue::filter_and_sort_issues(&story_result, warnings, lints, config);
+        (story_result, issues, is_err)
+    }
+}
+
+/// Prints the rules that would run under the given config and how each would
+/// be treated. See [`Linter::rules`]
+///
+/// [`Linter::rules`]: struct.Linter.html#method.rules
+pub fn print_rules(config: &Config, stdout: &mut StandardStream) -> Result<()> {
+    stdout.reset()?;
+    match &config.format {
+        Some(format) => writeln!(stdout, "Story format: {}", format)?,
+        None => writeln!(stdout, "Story format: unknown")?,
+    }
+
+    let statuses = Linter::new().rules(config);
+    let width = statuses.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for status in statuses {
+        let color = match status.level {
+            RuleLevel::Error | RuleLevel::Forbidden => Some(Color::Red),
+            RuleLevel::Warning => Some(Color::Yellow),
+            RuleLevel::Allowed | RuleLevel::Disabled => None,
+        };
+        write!(stdout, "{:width$}  ", status.name, width = width)?;
+        stdout.set_color(ColorSpec::new().set_fg(color).set_bold(color.is_some()))?;
+        write!(stdout, "{:9}", status.level.to_string())?;
+        stdout.reset()?;
+        let source = match status.source {
+            RuleSource::Parser => "parser",
+            RuleSource::Lint => "lint",
+            RuleSource::FormatLint => "format lint",
+        };
+        writeln!(stdout, "  {:11}  {}", source, status.description)?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Prints every rule tweec knows, with the level it has when neither allowed
+/// nor denied, where it is checked, and what it checks for
+///
+/// Unlike [`print_rules`], this doesn't depend on the config or story. Lints
+/// for particular story formats list the formats they run for
+///
+/// [`print_rules`]: fn.print_rules.html
+pub fn list_rules(stdout: &mut StandardStream) -> Result<()> {
+    stdout.reset()?;
+    let linter = Linter::new();
+    let scopes = linter.rule_scopes();
+    let scopes: Vec<String> = rules::RULES
+        .iter()
+        .map(
+            |rule| match scopes.iter().find(|(name, _)| *name == rule.name) {
+                Some((_, formats)) if formats.is_empty() => "lint".to_string(),
+                Some((_, formats)) => formats.join(", "),
+                None if rule.name == playtest::NAME => "test".to_string(),
+                None => "parser".to_string(),
+            },
+        )
+        .collect();
+    let width = rules::RULES.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    let scope_width = scopes.iter().map(String::len).max().unwrap_or(0);
+    for (rule, scope) in rules::RULES.iter().zip(scopes) {
+        let (level, color) = match rule.severity {
+            Severity::Error | Severity::Bug => ("error", Color::Red),
+            _ => ("warning", Color::Yellow),
+        };
+        write!(stdout, "{:width$}  ", rule.name, width = width)?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+        write!(stdout, "{:7}", level)?;
+        stdout.reset()?;
+        writeln!(
+            stdout,
+            "  {:scope_width$}  {}",
+            scope,
+            rule.description,
+            scope_width = scope_width
+        )?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Lints the given story with tweec's built-in lints without outputting
+/// anything. See [`Linter::collect`]
+///
+/// [`Linter::collect`]: struct.Linter.html#method.collect
+pub fn collect(
+    story_output: Output<StoryResult>,
+    config: &Config,
+) -> (StoryResult, Vec<Issue>, bool) {
+    Linter::new().collect(story_output, config)
+}
+
+/// Reports the given issues, leaving out repeats if `fold` is true and the
+/// reporter folds them, then finishes the report
+pub fn report(
+    reporter: &mut dyn Reporter,
+    issues: &[Issue],
+    story_files: &StoryFiles,
+    fold: bool,
+) -> Result<()> {
+    let folds = if fold && reporter.folds() {
+        issue::fold_issues(issues)
+    } else {
+        vec![Some(0); issues.len()]
+    };
+    let folded = folds.iter().filter(|fold| fold.is_none()).count();
+    for (issue, fold) in issues.iter().zip(folds) {
+        let hidden = match fold {
+            Some(hidden) => hidden,
+            None => continue,
+        };
+        reporter.report(issue, story_files)?;
+        if hidden > 0 {
+            reporter.fold(hidden)?;
+        }
+    }
+    reporter.finish(&Summary::new(issues, folded))
+}
+
 /// Lints the given story based on the given config and outputs warnings/errors
-/// to the given stream.
+/// to the given stream, through the reporter for the config's message format.
 ///
-/// Warnings are ignored or promoted to errors as specified in the config
+/// Warnings are ignored or promoted to errors as specified in the config, and
+/// warnings in the config's baseline are left out
 pub fn lint(
     story_output: Output<StoryResult>,
     config: &Config,
     stdout: &mut StandardStream,
 ) -> Result<Story> {
-    let (story_result, warnings) = story_output.take();
+    let (story_result, mut issues, mut is_err) = collect(story_output, config);
+
+    let baseline = match (&config.write_baseline, &config.baseline) {
+        (Some(path), _) => {
+            let baseline = Baseline::new(&issues);
+            baseline.write(path)?;
+            writeln!(
+                stdout,
+                "Wrote {} warning{} to baseline {:?}",
+                baseline.len(),
+                if baseline.len() == 1 { "" } else { "s" },
+                path
+            )?;
+            Some(baseline)
+        }
+        (None, Some(path)) => Some(Baseline::read(path)?),
+        (None, None) => None,
+    };
+    if let Some(baseline) = baseline {
+        issues = baseline.filter(issues);
+        is_err = story_result.is_err() || issues.iter().any(Issue::is_error);
+    }
 
     let story_files = StoryFiles::new(&story_result);
+    let paths = utils::file_paths(&config.inputs, &config.extensions);
+    if let Some(path) = &config.html_report {
+        let file = File::create(path)
+            .wrap_err_with(|| format!("Failed to create report file {:?}", path))?;
+        let mut reporter = HtmlReporter::new(BufWriter::new(file), paths.clone());
+        report(&mut reporter, &issues, &story_files, false)?;
+    }
+    let mut reporter = reporter::new(config.message_format, stdout, paths)?;
+    report(&mut *reporter, &issues, &story_files, config.fold)?;
+
+    let warnings = Summary::new(&issues, 0).warnings;
+    if is_err {
+        Err(StoryError("Failed due to previous errors").into())
+    } else if let Some(max) = config.max_warnings.filter(|max| warnings > *max) {
+        Err(Report::new(StoryError("Failed due to too many warnings"))).wrap_err_with(|| {
+            format!(
+                "Found {} warning{}, more than the maximum of {}",
+                warnings,
+                if warnings == 1 { "" } else { "s" },
+                max
+            )
+        })
+    } else {
+        Ok(story_result.ok().unwrap())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::Command;
+    use crate::Layout;
+    use crate::MessageFormat;
+    use crate::OutputStyle;
+    use crate::SortPassages;
+    use termcolor::ColorChoice;
 
-    let (issues, is_err) = issue::filter_and_sort_issues(&story_result, warnings, config);
+    struct EveryPassage;
 
-    if config.compact {
-        for issue in &issues {
-            issue::print_issue(issue, stdout)?;
+    impl Lint for EveryPassage {
+        fn name(&self) -> &'static str {
+            "EveryPassage"
         }
-    } else {
-        let config = term::Config::default();
-        for issue in &issues {
-            let diagnostic = issue.report(&story_files);
-            term::emit(&mut stdout.lock(), &config, &story_files, &diagnostic)?;
+
+        fn check(&self, story: &Story, _: &LinkGraph, _: &Config) -> Vec<LintWarning> {
+            story
+                .passages
+                .keys()
+                .map(|name| LintWarning::new("EveryPassage", name.clone(), None))
+                .collect()
         }
     }
 
-    // Force reset of color
-    stdout.flush()?;
+    pub(crate) fn config(format: Option<&str>, allowed: Vec<String>) -> Config {
+        Config {
+            command: Command::Lint,
+            inputs: Vec::new(),
+            extensions: crate::inputs::default_extensions(),
+            format_file: "format.js".into(),
+            format_sha256: None,
+            format_url: None,
+            refresh_formats: false,
+            output_file: None,
+            output_dir: None,
+            should_open: false,
+            opener: None,
+            levels: allowed
+                .into_iter()
+                .map(|name| (name, LintLevel::Allow))
+                .collect(),
+            use_color: ColorChoice::Never,
+            message_format: MessageFormat::Compact,
+            fold: true,
+            write_ifid: false,
+            watch: false,
+            proof: false,
+            fix: false,
+            validate_html: false,
+            embed_images: false,
+            assets_dir: None,
+            source_map: false,
+            check: false,
+            force: false,
+            package: None,
+            pwa: None,
+            output_style: OutputStyle::Normal,
+            sort_passages: SortPassages::File,
+            placeholders: Default::default(),
+            layout: Layout::Keep,
+            title: None,
+            start: None,
+            zoom: None,
+            template: None,
+            baseline: None,
+            write_baseline: None,
+            max_warnings: None,
+            html_report: None,
+            format: format.map(String::from),
+            max_links: None,
+            tag_max_links: HashMap::new(),
+            chapters: Vec::new(),
+            recap_tags: Vec::new(),
+            links: links::LinkSyntax::DEFAULT.to_vec(),
+            unlinked_tags: Vec::new(),
+            unlinked_passages: Vec::new(),
+            ending_tags: Vec::new(),
+            smoke_test: None,
+            macros: Vec::new(),
+            dictionaries: Vec::new(),
+            dictionary: None,
+            warnings: Vec::new(),
+            log_level: crate::logging::DEFAULT_LEVEL,
+        }
+    }
 
-    if is_err {
-        Err(eyre!("Failed due to previous errors"))
-    } else {
-        Ok(story_result.ok().unwrap())
+    #[test]
+    fn format_plugins_only_run_for_their_format() {
+        let story = Story::from_string(":: Start\nHello\n".to_string())
+            .take()
+            .0
+            .ok()
+            .unwrap();
+        let mut linter = Linter::empty();
+        linter.register_for_format("sugarcube-2", Box::new(EveryPassage));
+
+        assert_eq!(
+            linter
+                .check(&story, &config(Some("sugarcube-2"), vec![]))
+                .len(),
+            1
+        );
+        assert!(linter
+            .check(&story, &config(Some("harlowe-3"), vec![]))
+            .is_empty());
+        assert!(linter.check(&story, &config(None, vec![])).is_empty());
+
+        let allowed = vec!["EveryPassage".to_string()];
+        assert!(linter
+            .check(&story, &config(Some("sugarcube-2"), allowed))
+            .is_empty());
+    }
+
+    #[test]
+    fn previews_rule_levels() {
+        let mut config = config(None, Vec::new());
+        config.levels = issue::lint_levels(
+            &["DeadLink".to_string()],
+            &[],
+            &["all".to_string()],
+            &["WhitespaceInLink".to_string()],
+        );
+        // More specific config can't allow a forbidden warning
+        config
+            .levels
+            .push(("WhitespaceInLink".to_string(), LintLevel::Allow));
+        config.levels.push(("DeadEnd".to_string(), LintLevel::Warn));
+        let rules = Linter::new().rules(&config);
+        let level = |name: &str| rules.iter().find(|r| r.name == name).unwrap().level;
+
+        assert_eq!(level("DeadLink"), RuleLevel::Allowed);
+        assert_eq!(level("UnreachablePassage"), RuleLevel::Error);
+        assert_eq!(level("DeadEnd"), RuleLevel::Warning);
+        assert_eq!(level("WhitespaceInLink"), RuleLevel::Forbidden);
+        assert_eq!(level("TooManyLinks"), RuleLevel::Disabled);
+        assert_eq!(rules.iter().filter(|r| r.name == "TooManyLinks").count(), 1);
+        assert!(rules.iter().all(|r| r.name != "UnknownMacro"));
     }
 }
@@ -0,0 +1,135 @@
+//! Lint for media that a story refers to but that don't exist
+
+use crate::assets;
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::macros;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use tweep::{PassageHeader, Position, Story};
+
+/// Name of the lint, used for allowing or denying it
+pub const NAME: &str = "MissingAsset";
+
+/// Warns about references to local media that don't exist, in passages and
+/// stylesheets
+///
+/// Only runs when images are embedded in the output or media are copied to
+/// an assets directory, since that is when tweec reads them. See [`assets`]
+/// for which references are found and where they are looked for
+///
+/// [`assets`]: ../../assets/index.html
+pub struct MissingAsset;
+
+impl Lint for MissingAsset {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        check(story, config)
+    }
+
+    fn enabled(&self, config: &Config) -> bool {
+        config.embed_images || config.assets_dir.is_some()
+    }
+}
+
+/// Checks the given story for references to missing media. See
+/// [`MissingAsset`]
+///
+/// [`MissingAsset`]: struct.MissingAsset.html
+pub fn check(story: &Story, config: &Config) -> Vec<LintWarning> {
+    let dirs = assets::search_dirs(config);
+    let mut warnings = Vec::new();
+    // Stylesheets are not kept with the story's passages, so every passage is
+    // found in the source instead
+    for context in utils::contexts(&story.code_map) {
+        let lines: Vec<&str> = context.get_contents().split('\n').collect();
+        let headers: Vec<usize> = (0..lines.len())
+            .filter(|row| lines[*row].starts_with("::"))
+            .collect();
+        for (i, row) in headers.iter().enumerate() {
+            let header = context
+                .subcontext(Position::abs(row + 1, 1)..=Position::abs(row + 1, lines[*row].len()));
+            let skip = match PassageHeader::parse(header.clone()).take().0 {
+                Ok(parsed) => {
+                    parsed.name == "StoryData"
+                        || parsed.name == "StoryTitle"
+                        || parsed.tags.iter().any(|tag| tag == "script")
+                }
+                Err(_) => true,
+            };
+            if skip {
+                continue;
+            }
+            let end = headers.get(i + 1).copied().unwrap_or(lines.len());
+            let content = lines[row + 1..end].join("\n");
+            for reference in assets::references(&content) {
+                if assets::resolve(&reference, &dirs).is_some() {
+                    continue;
+                }
+                warnings.push(
+                    LintWarning::new(
+                        NAME,
+                        format!("File {} does not exist", reference.path),
+                        Some(macros::content_context(
+                            &header,
+                            &content,
+                            reference.range.clone(),
+                        )),
+                    )
+                    .with_note(format!("Looked in {:?}", dirs)),
+                );
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_missing_local_media() {
+        let dir = std::env::temp_dir().join(format!("tweec-missing-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("here.png"), "").unwrap();
+        let input = r#":: Start
+<img src="here.png"> <img src="gone.png"> [img[missing/cat.jpg]]
+<img src="https://example.com/a.png"> <img src="data:image/png;base64,AAAA">
+
+:: Style [stylesheet]
+body { background: url("bg.webp"); }
+"#;
+        let path = dir.join("story.tw");
+        std::fs::write(&path, input).unwrap();
+        let story = Story::from_path(&path).take().0.ok().unwrap();
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.inputs = vec![path.to_string_lossy().into_owned()];
+        config.output_dir = Some(dir.clone());
+        config.embed_images = true;
+        let warnings = check(&story, &config);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Remote files and data URIs aren't local media, so aren't reported
+        let messages: Vec<&str> = warnings
+            .iter()
+            .map(|warning| warning.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "File gone.png does not exist",
+                "File missing/cat.jpg does not exist",
+                "File bg.webp does not exist",
+            ]
+        );
+        assert_eq!(
+            warnings[0].context.as_ref().unwrap().get_contents(),
+            "gone.png"
+        );
+    }
+}
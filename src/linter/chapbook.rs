@@ -0,0 +1,362 @@
+//! Lints for the vars sections and modifiers of stories using Chapbook
+//!
+//! A Chapbook passage may start with a vars section, which ends at a line
+//! that is just `--`. Each line of it sets a variable, as `name: value` or,
+//! only when a condition holds, `name (condition): value`. In the rest of the
+//! passage, a line that is just text in square brackets, such as `[if hp > 0]`,
+//! is a modifier, and several can be given on one line separated by `;`.
+
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::macros;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use std::collections::HashSet;
+use std::ops::Range;
+use tweep::Story;
+
+/// The names of the story formats these lints run for
+pub const FORMATS: &[&str] = &["chapbook-1", "chapbook-2"];
+
+/// Name of the lint for variable names Chapbook does not accept
+pub const INVALID_VARIABLE_NAME: &str = "InvalidVariableName";
+
+/// Name of the lint for variables set more than once in a vars section
+pub const DUPLICATE_VARIABLE: &str = "DuplicateVariable";
+
+/// Name of the lint for modifiers Chapbook does not recognize, or that are
+/// missing a condition
+pub const MALFORMED_MODIFIER: &str = "MalformedModifier";
+
+/// Modifiers that take no condition, by their first words
+const MODIFIERS: &[&str] = &[
+    "else",
+    "append",
+    "continue",
+    "cont",
+    "cont'd",
+    "note",
+    "note to self",
+    "fixme",
+    "todo",
+    "fork",
+    "align left",
+    "align center",
+    "align right",
+    "javascript",
+    "css",
+    "after",
+];
+
+/// Modifiers that need a condition after their name
+const CONDITIONAL_MODIFIERS: &[&str] = &["if", "unless"];
+
+/// A line of passage content, with the byte offset it starts at
+fn lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content.split('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len() + 1;
+        Some((start, line))
+    })
+}
+
+/// Whether `name` is a variable name Chapbook accepts: dot-separated parts
+/// made of letters, digits, `_`, and `$`, none starting with a digit
+fn is_valid_name(name: &str) -> bool {
+    name.split('.').all(|part| {
+        !part.is_empty()
+            && !part.starts_with(|c: char| c.is_ascii_digit())
+            && part
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+    })
+}
+
+/// Finds the `:` ending the name and condition of a vars line, skipping any
+/// inside the condition's parentheses
+fn separator(line: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ':' if depth <= 0 => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Checks a passage's vars section, returning each problem with its byte
+/// range in the content
+fn check_vars(content: &str) -> Vec<(&'static str, String, Range<usize>)> {
+    let mut problems = Vec::new();
+    let section: Vec<(usize, &str)> =
+        match lines(content).position(|(_, line)| line.trim_end() == "--") {
+            Some(end) => lines(content).take(end).collect(),
+            None => return problems,
+        };
+
+    // Each name assigned so far, along with its condition
+    let mut assigned: HashSet<(String, String)> = HashSet::new();
+    for (offset, line) in section {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_range = offset..offset + line.trim_end().len();
+        let colon = match separator(line) {
+            Some(colon) => colon,
+            None => {
+                problems.push((
+                    INVALID_VARIABLE_NAME,
+                    "Line in the vars section is missing a : between the name and value"
+                        .to_string(),
+                    line_range,
+                ));
+                continue;
+            }
+        };
+        let target = &line[..colon];
+        let (name, condition) = match target.find('(') {
+            Some(open) => (&target[..open], target[open..].trim()),
+            None => (target, ""),
+        };
+        let name_start = offset + name.len() - name.trim_start().len();
+        let name = name.trim();
+        let name_range = name_start..name_start + name.len().max(1);
+        if !is_valid_name(name) {
+            problems.push((
+                INVALID_VARIABLE_NAME,
+                format!("{:?} is not a valid variable name", name),
+                name_range,
+            ));
+            continue;
+        }
+        let key = (name.to_string(), condition.split_whitespace().collect());
+        if !assigned.insert(key) {
+            let when = if condition.is_empty() {
+                String::new()
+            } else {
+                format!(" with the same condition {}", condition)
+            };
+            problems.push((
+                DUPLICATE_VARIABLE,
+                format!("Variable {} is set more than once{}", name, when),
+                name_range,
+            ));
+        }
+    }
+    problems
+}
+
+/// Checks a single modifier, such as `if hp > 0`, returning a description of
+/// what is wrong with it
+fn check_modifier(modifier: &str) -> Option<String> {
+    let lower = modifier.trim().to_lowercase();
+    if lower.is_empty() {
+        return Some("Modifier is empty".to_string());
+    }
+    for name in CONDITIONAL_MODIFIERS {
+        if lower == *name {
+            return Some(format!("Modifier [{}] is missing its condition", name));
+        }
+        if lower
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+        {
+            return None;
+        }
+    }
+    if lower == "else" {
+        return None;
+    }
+    if lower.starts_with("else ") {
+        return Some("Modifier [else] does not take a condition".to_string());
+    }
+    let known = MODIFIERS.iter().any(|name| {
+        lower
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+    });
+    if known {
+        None
+    } else {
+        Some(format!("Unknown modifier [{}]", modifier.trim()))
+    }
+}
+
+/// Checks the modifiers in a passage after its vars section, returning each
+/// problem with its byte range in the content
+fn check_modifiers(content: &str) -> Vec<(&'static str, String, Range<usize>)> {
+    let body_start = lines(content)
+        .find(|(_, line)| line.trim_end() == "--")
+        .map(|(offset, line)| offset + line.len() + 1)
+        .unwrap_or(0);
+    let mut problems = Vec::new();
+    let mut in_code = false;
+    for (offset, line) in lines(&content[body_start.min(content.len())..]) {
+        let offset = body_start + offset;
+        let trimmed = line.trim();
+        let start = offset + (line.len() - line.trim_start().len());
+        let inner = match trimmed.strip_prefix('[') {
+            // A link on a line of its own is not a modifier
+            Some(_) if trimmed.starts_with("[[") => continue,
+            Some(rest) => match rest.strip_suffix(']') {
+                Some(inner) => inner,
+                // A line starting with [ and never closing it, such as
+                // `[if x > 1`, is a modifier missing its ]
+                None if !rest.contains(']') && !in_code => {
+                    problems.push((
+                        MALFORMED_MODIFIER,
+                        format!("Modifier [{} is missing its closing ]", rest.trim()),
+                        start..start + trimmed.len(),
+                    ));
+                    continue;
+                }
+                None => continue,
+            },
+            None => continue,
+        };
+
+        // The text after [JavaScript] or [CSS] is code, usually ended by
+        // [continue], so bracketed lines in it aren't checked
+        let lower = inner.trim().to_lowercase();
+        if in_code && !lower.starts_with("cont") {
+            continue;
+        }
+        in_code = lower == "javascript" || lower == "css";
+
+        for problem in inner.split(';').filter_map(check_modifier) {
+            problems.push((MALFORMED_MODIFIER, problem, start..start + trimmed.len()));
+        }
+    }
+    problems
+}
+
+/// Checks the vars sections and modifiers of every passage, returning
+/// warnings for all of the Chapbook lints
+fn check(story: &Story) -> Vec<LintWarning> {
+    let headers = utils::passage_headers(story);
+    let mut names: Vec<&String> = story.passages.keys().collect();
+    names.sort();
+    let mut warnings = Vec::new();
+    for name in names {
+        let passage = &story.passages[name];
+        if passage
+            .header
+            .tags
+            .iter()
+            .any(|tag| tag == "script" || tag == "stylesheet")
+        {
+            continue;
+        }
+        let content = utils::get_content(passage);
+        let header = headers.get(name);
+        let mut problems = check_vars(content);
+        problems.extend(check_modifiers(content));
+        for (lint, message, range) in problems {
+            warnings.push(LintWarning::new(
+                lint,
+                format!("{} in passage {}", message, name),
+                header.map(|header| macros::content_context(header, content, range)),
+            ));
+        }
+    }
+    warnings
+}
+
+/// Warns about vars section lines whose variable name Chapbook does not
+/// accept, or that have no name at all
+pub struct InvalidVariableName;
+
+/// Warns about a variable set more than once in the same vars section, under
+/// the same condition
+pub struct DuplicateVariable;
+
+/// Warns about modifiers that are unknown, missing or given a condition they
+/// should not have, or missing their closing `]`
+pub struct MalformedModifier;
+
+macro_rules! chapbook_lint {
+    ($lint:ident, $name:expr) => {
+        impl Lint for $lint {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn check(&self, story: &Story, _: &LinkGraph, _: &Config) -> Vec<LintWarning> {
+                check(story)
+                    .into_iter()
+                    .filter(|warning| warning.name == $name)
+                    .collect()
+            }
+        }
+    };
+}
+
+chapbook_lint!(InvalidVariableName, INVALID_VARIABLE_NAME);
+chapbook_lint!(DuplicateVariable, DUPLICATE_VARIABLE);
+chapbook_lint!(MalformedModifier, MALFORMED_MODIFIER);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_vars_and_modifiers() {
+        let content = "\
+strength: 10
+config.style.page.font: 'Georgia'
+strength (hp > 0): 12
+strength: 11
+2fast: true
+no value here
+--
+[if strength > 10]
+Strong!
+[else]
+[unless]
+[align center; fork]
+[[A link]]
+[JavaScript]
+[not a modifier]
+[continue]
+[bogus]
+[if strength > 1
+[Aside] text after a bracket";
+        let problems: Vec<(&str, String)> = check_vars(content)
+            .into_iter()
+            .chain(check_modifiers(content))
+            .map(|(lint, message, _)| (lint, message))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    DUPLICATE_VARIABLE,
+                    "Variable strength is set more than once".to_string()
+                ),
+                (
+                    INVALID_VARIABLE_NAME,
+                    "\"2fast\" is not a valid variable name".to_string()
+                ),
+                (
+                    INVALID_VARIABLE_NAME,
+                    "Line in the vars section is missing a : between the name and value"
+                        .to_string()
+                ),
+                (
+                    MALFORMED_MODIFIER,
+                    "Modifier [unless] is missing its condition".to_string()
+                ),
+                (MALFORMED_MODIFIER, "Unknown modifier [bogus]".to_string()),
+                (
+                    MALFORMED_MODIFIER,
+                    "Modifier [if strength > 1 is missing its closing ]".to_string()
+                ),
+            ]
+        );
+        assert_eq!(check_vars(content)[0].2, 69..77);
+    }
+}
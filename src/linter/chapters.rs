@@ -0,0 +1,142 @@
+//! Lint for links that jump back to an earlier chapter
+
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use tweep::Story;
+
+/// Name of the lint, used for allowing or denying it
+pub const NAME: &str = "BackwardsChapterLink";
+
+/// Gets the index of the chapter the named passage belongs to, if any
+///
+/// A passage belongs to the earliest chapter it is tagged with
+fn chapter_of(graph: &LinkGraph, name: &str, config: &Config) -> Option<usize> {
+    let tags = graph.tags(name);
+    config
+        .chapters
+        .iter()
+        .position(|chapter| tags.contains(chapter))
+}
+
+/// Warns about any link from a passage in one chapter to a passage in an
+/// earlier chapter
+///
+/// Chapters are given by the ordered list of chapter tags in the config.
+/// Passages without a chapter tag are not checked, and passages tagged with
+/// one of the configured recap tags may link backwards freely
+pub struct BackwardsChapterLink;
+
+impl Lint for BackwardsChapterLink {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn check(&self, _: &Story, graph: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        check(graph, config)
+    }
+
+    fn enabled(&self, config: &Config) -> bool {
+        !config.chapters.is_empty()
+    }
+}
+
+/// Checks the given link graph for links back to earlier chapters. See
+/// [`BackwardsChapterLink`]
+///
+/// [`BackwardsChapterLink`]: struct.BackwardsChapterLink.html
+pub fn check(graph: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    if config.chapters.is_empty() {
+        return warnings;
+    }
+
+    let headers = utils::passage_headers(graph.story());
+    for name in graph.passages() {
+        let from = match chapter_of(graph, name, config) {
+            Some(from) => from,
+            None => continue,
+        };
+        if graph
+            .tags(name)
+            .iter()
+            .any(|tag| config.recap_tags.contains(tag))
+        {
+            continue;
+        }
+
+        for edge in graph.outgoing(name) {
+            let to = match chapter_of(graph, edge.target, config) {
+                Some(to) if to < from => to,
+                _ => continue,
+            };
+            let mut warning = LintWarning::new(
+                NAME,
+                format!(
+                    "Link from chapter {} back to earlier chapter {}",
+                    config.chapters[from], config.chapters[to]
+                ),
+                Some(edge.link.context.clone()),
+            );
+            if let Some(header) = headers.get(edge.target) {
+                warning = warning.with_secondary(
+                    header.clone(),
+                    format!("{} is in chapter {}", edge.target, config.chapters[to]),
+                );
+            }
+            if let Some(recap) = config.recap_tags.first() {
+                warning = warning.with_note(format!(
+                    "Link through a passage tagged {} to revisit earlier chapters",
+                    recap
+                ));
+            }
+            warnings.push(warning);
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::links::LinkSyntax;
+
+    #[test]
+    fn finds_backwards_links() {
+        let input = r#":: Start [one]
+[[Hall]] [[Side]]
+
+:: Hall [two]
+[[Start]] [[Side]] [[Hall]]
+
+:: Recap [two recap]
+[[Start]]
+
+:: Side
+[[Start]] [[Hall]]
+"#;
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let graph = LinkGraph::new(&story, LinkSyntax::DEFAULT);
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.chapters = vec!["one".to_string(), "two".to_string()];
+        config.recap_tags = vec!["recap".to_string()];
+        // Recap may link back, and Side is in no chapter, so neither its links
+        // nor links to it are checked
+        let warnings = check(&graph, &config);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].message,
+            "Link from chapter two back to earlier chapter one"
+        );
+        assert_eq!(
+            warnings[0].context.as_ref().unwrap().get_contents(),
+            "[[Start]]"
+        );
+
+        config.recap_tags.clear();
+        assert_eq!(check(&graph, &config).len(), 2);
+    }
+}
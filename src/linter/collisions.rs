@@ -0,0 +1,209 @@
+//! Lint for passage names that are easily mistaken for one another
+
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use std::collections::BTreeMap;
+use tweep::Story;
+
+/// Name of the lint, used for allowing or denying it
+pub const NAME: &str = "PassageNameCollision";
+
+/// Warns about any passage whose name differs from another passage's only in
+/// case, surrounding whitespace, or characters that look alike, such as a
+/// Cyrillic `а` for a Latin `a`
+///
+/// Some story formats match passage names loosely and others exactly, so a
+/// link written for one of the passages may silently go to the other, or to
+/// neither. Of each group of such names, the one with the most links to it is
+/// taken as the canonical name, and every other name in the group is
+/// reported
+pub struct PassageNameCollision;
+
+impl Lint for PassageNameCollision {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn check(&self, story: &Story, graph: &LinkGraph, _: &Config) -> Vec<LintWarning> {
+        check(story, graph)
+    }
+}
+
+/// How two colliding names differ
+#[derive(Debug, PartialEq)]
+enum Difference {
+    Whitespace,
+    Case,
+    Confusable,
+}
+
+impl Difference {
+    fn between(a: &str, b: &str) -> Self {
+        if a.trim() == b.trim() {
+            Difference::Whitespace
+        } else if a.trim().to_lowercase() == b.trim().to_lowercase() {
+            Difference::Case
+        } else {
+            Difference::Confusable
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Difference::Whitespace => "surrounding whitespace",
+            Difference::Case => "case",
+            Difference::Confusable => "characters that look alike",
+        }
+    }
+}
+
+/// Gets the ASCII character a character is easily mistaken for, if it is
+/// one: look-alike letters from other scripts, full-width forms, and
+/// typographic dashes, quotes, and spaces. Invisible characters map to
+/// `'\0'`, and are removed
+fn confusable(c: char) -> Option<char> {
+    Some(match c {
+        // Cyrillic
+        'а' => 'a',
+        'в' => 'b',
+        'е' | 'ё' => 'e',
+        'һ' => 'h',
+        'і' => 'i',
+        'ј' => 'j',
+        'к' => 'k',
+        'м' => 'm',
+        'н' => 'h',
+        'о' => 'o',
+        'р' => 'p',
+        'с' => 'c',
+        'т' => 't',
+        'у' => 'y',
+        'х' => 'x',
+        'ѕ' => 's',
+        'ԁ' => 'd',
+        'ԛ' => 'q',
+        'ԝ' => 'w',
+        // Greek
+        'α' => 'a',
+        'ε' => 'e',
+        'ι' => 'i',
+        'κ' => 'k',
+        'ν' => 'v',
+        'ο' => 'o',
+        'ρ' => 'p',
+        'τ' => 't',
+        'υ' => 'u',
+        'χ' => 'x',
+        // Dashes, quotes, and spaces
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+        '\u{2018}' | '\u{2019}' | '\u{201b}' | '\u{2032}' => '\'',
+        '\u{201c}' | '\u{201d}' | '\u{201f}' | '\u{2033}' => '"',
+        '\u{a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' | '\u{3000}' => ' ',
+        '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => '\0',
+        // Full-width forms of ASCII
+        '\u{ff01}'..='\u{ff5e}' => std::char::from_u32(c as u32 - 0xff01 + 0x21)?,
+        _ => return None,
+    })
+}
+
+/// Gets the form of a passage name that names easily mistaken for it share:
+/// trimmed, in lowercase, with look-alike characters replaced
+fn skeleton(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| confusable(c).unwrap_or(c))
+        .filter(|&c| c != '\0')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Checks the story for passage names that collide. See
+/// [`PassageNameCollision`]
+///
+/// [`PassageNameCollision`]: struct.PassageNameCollision.html
+pub fn check(story: &Story, graph: &LinkGraph) -> Vec<LintWarning> {
+    let mut groups: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for name in story.passages.keys() {
+        groups.entry(skeleton(name)).or_default().push(name);
+    }
+
+    let headers = utils::passage_headers(story);
+    let mut warnings = Vec::new();
+    for mut names in groups.into_values().filter(|names| names.len() > 1) {
+        // The most linked name is canonical, then a plain one, then the first
+        // in order
+        names.sort_by_key(|name| {
+            let plain = name.is_ascii() && name.trim() == *name;
+            (std::cmp::Reverse(graph.incoming(name).len()), !plain, *name)
+        });
+        let canonical = names[0];
+        let links = graph.incoming(canonical).len();
+        for name in &names[1..] {
+            let difference = Difference::between(name, canonical);
+            let mut warning = LintWarning::new(
+                NAME,
+                format!(
+                    "Passage {:?} differs from passage {:?} only in {}",
+                    name,
+                    canonical,
+                    difference.describe()
+                ),
+                headers.get(*name).cloned(),
+            );
+            if let Some(header) = headers.get(canonical) {
+                warning =
+                    warning.with_secondary(header.clone(), "Easily mistaken for this passage");
+            }
+            warning = warning
+                .with_note(format!(
+                    "Links to one of them may go to the other, or become dead links, in story \
+                     formats that compare passage names differently. {:?} is linked to {} time{}",
+                    canonical,
+                    links,
+                    if links == 1 { "" } else { "s" }
+                ))
+                .with_note(format!(
+                    "Rename it to {:?} and merge the passages if they are meant to be the same, \
+                     or give it a clearly different name",
+                    canonical
+                ));
+            warnings.push(warning);
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::links::LinkSyntax;
+
+    #[test]
+    fn finds_colliding_names() {
+        // The second Cave is spelled with a Cyrillic а
+        let input = ":: Start\n[[Cave]] [[cave]] [[Cave]] [[Beach]]\n\n:: Cave\nA\n\n\
+                     :: cave\nB\n\n:: Cаve\nC\n\n:: Beach\nD\n\n:: Beach\u{200b}\nE\n";
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let graph = LinkGraph::new(&story, LinkSyntax::DEFAULT);
+        let mut messages: Vec<String> = check(&story, &graph)
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect();
+        messages.sort();
+        assert_eq!(
+            messages,
+            vec![
+                "Passage \"Beach\\u{200b}\" differs from passage \"Beach\" only in characters \
+                 that look alike",
+                "Passage \"Cаve\" differs from passage \"Cave\" only in characters that look \
+                 alike",
+                "Passage \"cave\" differs from passage \"Cave\" only in case",
+            ]
+        );
+        assert_eq!(skeleton(" Ｃａｖｅ "), "cave");
+    }
+}
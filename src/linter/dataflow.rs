@@ -0,0 +1,370 @@
+//! Lints that follow story variables across passages
+//!
+//! Each story format writes variables its own way, so a [`VariableSyntax`]
+//! adapter for the format finds where passage content sets and reads them.
+//! Story variables, such as `$gold`, keep their values from passage to
+//! passage, so whether one has been set when it is read depends on how the
+//! reader got there: starting at the start passage, the variables that may
+//! have been set are carried along every link. Passages that can't be reached
+//! that way, such as `StoryInit` or widget passages, are run by the story
+//! format itself, so the variables they set are treated as set from the start.
+//!
+//! Temporary variables, such as `_i`, only last while a passage is shown, so
+//! they are followed within each passage. One set in another passage might be
+//! shared with an included passage, so it is not reported.
+//!
+//! [`VariableSyntax`]: trait.VariableSyntax.html
+
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::macros;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use tweep::Story;
+
+/// Name of the lint for variables read before they are set
+pub const READ_BEFORE_SET: &str = "ReadBeforeSet";
+
+/// Name of the lint for variables that are set but never read
+pub const UNUSED_VARIABLE: &str = "UnusedVariable";
+
+/// How a variable is used
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    /// The variable's value is used
+    Read,
+
+    /// The variable is given a new value
+    Set,
+
+    /// The variable's value is used to give it a new one, such as `$a += 1`
+    Update,
+
+    /// The variable is checked for whether it has been set, which is a read
+    /// that is fine before it is set
+    Test,
+}
+
+/// A use of a variable in passage content
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    /// The name of the variable, including its sigil, such as `$gold`
+    pub name: String,
+
+    /// How the variable is used
+    pub access: Access,
+
+    /// The byte range of the variable in the content
+    pub range: Range<usize>,
+}
+
+impl Variable {
+    /// Creates a use of the named variable
+    pub fn new<S: Into<String>>(name: S, access: Access, range: Range<usize>) -> Self {
+        Variable {
+            name: name.into(),
+            access,
+            range,
+        }
+    }
+
+    /// Whether this is a temporary variable, which starts with `_`
+    pub fn is_temporary(&self) -> bool {
+        self.name.starts_with('_')
+    }
+
+    fn reads(&self) -> bool {
+        self.access != Access::Set
+    }
+
+    fn sets(&self) -> bool {
+        matches!(self.access, Access::Set | Access::Update)
+    }
+}
+
+/// How a story format writes variables
+pub trait VariableSyntax {
+    /// Finds the variables used in passage content, in the order they are used
+    fn variables(&self, content: &str) -> Vec<Variable>;
+
+    /// Gets the names of variables used outside of passage content, such as
+    /// by the story's scripts, which are not reported
+    fn script_variables(&self, _story: &Story) -> HashSet<String> {
+        HashSet::new()
+    }
+}
+
+/// Finds the story variables that may have been set on reaching each passage
+/// that can be reached from `start`, given the variables set in each passage
+/// and those set before the start passage
+fn set_before<'a>(
+    graph: &LinkGraph<'a>,
+    start: &'a str,
+    initial: HashSet<&'a str>,
+    sets: &HashMap<&str, HashSet<&'a str>>,
+) -> HashMap<&'a str, HashSet<&'a str>> {
+    let mut before: HashMap<&str, HashSet<&str>> = HashMap::new();
+    before.insert(start, initial);
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(name) = queue.pop_front() {
+        let mut after = before[name].clone();
+        if let Some(set) = sets.get(name) {
+            after.extend(set);
+        }
+        for edge in graph.outgoing(name) {
+            let target = before.entry(edge.target).or_default();
+            let known = target.len();
+            target.extend(&after);
+            if target.len() > known || visited.insert(edge.target) {
+                queue.push_back(edge.target);
+            }
+        }
+    }
+    before
+}
+
+/// Checks how every passage uses variables, returning warnings for both of
+/// the dataflow lints
+fn check<S: VariableSyntax>(story: &Story, graph: &LinkGraph, syntax: &S) -> Vec<LintWarning> {
+    let headers = utils::passage_headers(story);
+    let mut passages: Vec<(&str, &str, Vec<Variable>)> = story
+        .passages
+        .iter()
+        .filter(|(_, passage)| {
+            !passage
+                .header
+                .tags
+                .iter()
+                .any(|tag| tag == "script" || tag == "stylesheet")
+        })
+        .map(|(name, passage)| {
+            let content = utils::get_content(passage);
+            (name.as_str(), content, syntax.variables(content))
+        })
+        .collect();
+    passages.sort_unstable_by_key(|(name, _, _)| *name);
+
+    let scripted = syntax.script_variables(story);
+    let mut sets: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let mut set_in: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let mut read: HashSet<&str> = HashSet::new();
+    for (name, _, variables) in &passages {
+        for variable in variables {
+            if variable.sets() {
+                set_in.entry(&variable.name).or_default().insert(*name);
+                if !variable.is_temporary() {
+                    sets.entry(*name).or_default().insert(&variable.name);
+                }
+            }
+            if variable.reads() {
+                read.insert(&variable.name);
+            }
+        }
+    }
+
+    // A missing start passage is reported by tweep
+    let start = story
+        .get_start_passage_name()
+        .and_then(|start| story.passages.get_key_value(start))
+        .map(|(start, _)| start.as_str());
+    let reached = start
+        .map(|start| graph.reachable_from(start))
+        .unwrap_or_default();
+    let initial: HashSet<&str> = sets
+        .iter()
+        .filter(|(name, _)| !reached.contains(*name))
+        .flat_map(|(_, set)| set.iter().copied())
+        .collect();
+    let before = match start {
+        Some(start) => set_before(graph, start, initial, &sets),
+        None => HashMap::new(),
+    };
+
+    let mut warnings = Vec::new();
+    let mut unused: HashSet<&str> = HashSet::new();
+    for (name, content, variables) in &passages {
+        let header = headers.get(*name);
+        let context = |range: &Range<usize>| {
+            header.map(|header| macros::content_context(header, content, range.clone()))
+        };
+
+        let mut local: HashSet<&str> = HashSet::new();
+        let mut warned: HashSet<&str> = HashSet::new();
+        for variable in variables {
+            let var = variable.name.as_str();
+            if scripted.contains(var) {
+                continue;
+            }
+            let is_read = matches!(variable.access, Access::Read | Access::Update);
+            if is_read && !local.contains(var) && !warned.contains(var) {
+                let set_elsewhere = set_in
+                    .get(var)
+                    .is_some_and(|passages| passages.iter().any(|passage| passage != name));
+                let warning = if variable.is_temporary() {
+                    (!set_elsewhere).then(|| {
+                        LintWarning::new(
+                            READ_BEFORE_SET,
+                            format!(
+                                "Temporary variable {} is read in passage {} before it is set",
+                                var, name
+                            ),
+                            context(&variable.range),
+                        )
+                    })
+                } else if !set_in.contains_key(var) {
+                    Some(LintWarning::new(
+                        READ_BEFORE_SET,
+                        format!("Variable {} is read in passage {} but never set", var, name),
+                        context(&variable.range),
+                    ))
+                } else {
+                    match (start, before.get(name)) {
+                        (Some(start), Some(before)) if !before.contains(var) => Some(
+                            LintWarning::new(
+                                READ_BEFORE_SET,
+                                format!(
+                                    "Variable {} may be read in passage {} before it is set",
+                                    var, name
+                                ),
+                                context(&variable.range),
+                            )
+                            .with_note(format!(
+                                "No path from {} sets it before reaching {}",
+                                start, name
+                            )),
+                        ),
+                        _ => None,
+                    }
+                };
+                if let Some(warning) = warning {
+                    warnings.push(warning);
+                    warned.insert(var);
+                }
+            }
+
+            if variable.sets() {
+                local.insert(var);
+            }
+            if variable.access == Access::Set && !read.contains(var) && unused.insert(var) {
+                warnings.push(LintWarning::new(
+                    UNUSED_VARIABLE,
+                    format!("Variable {} is set in passage {} but never read", var, name),
+                    context(&variable.range),
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Warns about variables that are read before they are set, either because
+/// they are never set at all or because no path through the story from the
+/// start passage sets them first. Reads that check whether a variable has
+/// been set are not reported
+pub struct ReadBeforeSet<S>(pub S);
+
+/// Warns about variables that are set but never read anywhere in the story
+pub struct UnusedVariable<S>(pub S);
+
+macro_rules! dataflow_lint {
+    ($lint:ident, $name:expr) => {
+        impl<S: VariableSyntax> Lint for $lint<S> {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn check(&self, story: &Story, graph: &LinkGraph, _: &Config) -> Vec<LintWarning> {
+                check(story, graph, &self.0)
+                    .into_iter()
+                    .filter(|warning| warning.name == $name)
+                    .collect()
+            }
+        }
+    };
+}
+
+dataflow_lint!(ReadBeforeSet, READ_BEFORE_SET);
+dataflow_lint!(UnusedVariable, UNUSED_VARIABLE);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::{harlowe, sugarcube};
+
+    fn warnings<S: VariableSyntax>(input: &str, syntax: S) -> Vec<(&'static str, String)> {
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let config = crate::linter::tests::config(None, Vec::new());
+        let graph = LinkGraph::new(&story, &config.links);
+        check(&story, &graph, &syntax)
+            .into_iter()
+            .map(|warning| (warning.name, warning.message))
+            .collect()
+    }
+
+    #[test]
+    fn follows_sugarcube_variables() {
+        let input = r#":: StoryInit
+<<set $gold to 10>><<set $spare to 1>>
+
+:: Start
+You have $gold gold. <<if def $key>>Unlocked.<</if>>
+<<for _i to 0; _i lt 3; _i++>><<print _i>><</for>>
+<<print _typo>> <<textbox "$name" "">>
+[[Shop]] [[Vault][$key to true]]
+
+:: Shop
+<<set $gold -= 5>>$name bought $item. <<run State.variables.hidden = $hidden>>
+[[Vault]]
+
+:: Vault
+<<if $key>>$gold<</if>>
+"#;
+        assert_eq!(
+            warnings(input, sugarcube::Variables),
+            vec![
+                (
+                    READ_BEFORE_SET,
+                    "Variable $item is read in passage Shop but never set".to_string()
+                ),
+                (
+                    READ_BEFORE_SET,
+                    "Temporary variable _typo is read in passage Start before it is set"
+                        .to_string()
+                ),
+                (
+                    UNUSED_VARIABLE,
+                    "Variable $spare is set in passage StoryInit but never read".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_harlowe_variables() {
+        let input = r#":: Start
+(set: $hp to 10, $unused to 2)(put: 3 into $mp)
+(for: each _item, ...(a: 1))[_item] (print: $hp + $mp)
+(set: $hp's 1st to 1)[[Next]]
+
+:: Next
+(print: $later)(set: $later to 1)
+"#;
+        assert_eq!(
+            warnings(input, harlowe::Variables),
+            vec![
+                (
+                    READ_BEFORE_SET,
+                    "Variable $later may be read in passage Next before it is set".to_string()
+                ),
+                (
+                    UNUSED_VARIABLE,
+                    "Variable $unused is set in passage Start but never read".to_string()
+                ),
+            ]
+        );
+    }
+}
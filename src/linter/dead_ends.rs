@@ -0,0 +1,106 @@
+//! Lint for passages that leave the reader with nowhere to go
+
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::unreachable;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use tweep::Story;
+
+/// Name of the lint, used for allowing or denying it
+pub const NAME: &str = "DeadEnd";
+
+/// Warns about any passage with no links out that is not tagged as an ending
+///
+/// Ending tags are given in the config. Passages that are used without being
+/// linked to, as described in [`UnreachablePassage`], are not navigated to and
+/// so are never dead ends. A passage whose only links are dead links is not
+/// reported, since those links are reported as `DeadLink` instead
+///
+/// [`UnreachablePassage`]: ../unreachable/struct.UnreachablePassage.html
+pub struct DeadEnd;
+
+impl Lint for DeadEnd {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn check(&self, story: &Story, graph: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        check(story, graph, config)
+    }
+}
+
+/// Checks the given link graph for dead ends. See [`DeadEnd`]
+///
+/// [`DeadEnd`]: struct.DeadEnd.html
+pub fn check(story: &Story, graph: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let headers = utils::passage_headers(story);
+    for name in graph.passages() {
+        if !graph.outgoing(name).is_empty()
+            || !graph.dead_links(name).is_empty()
+            || unreachable::is_unlinked(graph, name, config)
+            || graph
+                .tags(name)
+                .iter()
+                .any(|tag| config.ending_tags.contains(tag))
+        {
+            continue;
+        }
+
+        let mut warning = LintWarning::new(
+            NAME,
+            format!("Passage {} has no links out and is not an ending", name),
+            headers.get(name).cloned(),
+        );
+        warning = match config.ending_tags.first() {
+            Some(tag) => warning.with_note(format!(
+                "Add a link, or tag it {} if the story is meant to end here",
+                tag
+            )),
+            None => warning.with_note("Add a link, or configure ending_tags to mark endings"),
+        };
+        warnings.push(warning);
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::links::LinkSyntax;
+
+    #[test]
+    fn finds_dead_ends() {
+        let input = r#":: Start
+[[A]] [[B]] [[C]]
+
+:: A
+Oops
+
+:: B [ending]
+The end
+
+:: C
+[[Missing]]
+
+:: Button [widget]
+Widget
+"#;
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let graph = LinkGraph::new(&story, LinkSyntax::DEFAULT);
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.ending_tags = vec!["ending".to_string()];
+        config.unlinked_tags = vec!["widget".to_string()];
+        let dead_ends: Vec<String> = check(&story, &graph, &config)
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect();
+        assert_eq!(
+            dead_ends,
+            vec!["Passage A has no links out and is not an ending"]
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! The passage link graph of a story, used by lints that need to reason about
+//! how passages connect to each other
+
+use crate::linter::links::{self, LinkSyntax};
+use std::collections::{HashMap, HashSet, VecDeque};
+use tweep::Story;
+use tweep::TwineLink;
+
+/// A link from one passage to another
+#[derive(Debug)]
+pub struct Edge<'a> {
+    /// The name of the linked passage
+    pub target: &'a str,
+
+    /// The link that created this edge
+    pub link: TwineLink,
+}
+
+/// A directed graph of the links between the passages of a story
+///
+/// Nodes are passage names. Links to passages that do not exist are not part
+/// of the graph, and link targets are trimmed, matching how `tweep` checks for
+/// dead links. Only links written in one of the syntaxes the graph was built
+/// with are included
+pub struct LinkGraph<'a> {
+    story: &'a Story,
+    outgoing: HashMap<&'a str, Vec<Edge<'a>>>,
+    incoming: HashMap<&'a str, Vec<&'a str>>,
+    dead: HashMap<&'a str, Vec<TwineLink>>,
+}
+
+impl<'a> LinkGraph<'a> {
+    /// Builds the link graph for the given story from links written in the
+    /// given syntaxes
+    pub fn new(story: &'a Story, syntaxes: &[LinkSyntax]) -> Self {
+        let mut outgoing = HashMap::new();
+        let mut incoming: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut dead = HashMap::new();
+        for (name, passage_links) in links::story_links(story, syntaxes) {
+            let mut edges = Vec::new();
+            let mut dead_links = Vec::new();
+            for link in passage_links {
+                if let Some((target, _)) = story.passages.get_key_value(link.target.trim()) {
+                    edges.push(Edge {
+                        target: target.as_str(),
+                        link,
+                    });
+                    incoming.entry(target.as_str()).or_default().push(name);
+                } else {
+                    dead_links.push(link);
+                }
+            }
+            outgoing.insert(name, edges);
+            dead.insert(name, dead_links);
+        }
+
+        LinkGraph {
+            story,
+            outgoing,
+            incoming,
+            dead,
+        }
+    }
+
+    /// Gets the story this graph was built from
+    pub fn story(&self) -> &'a Story {
+        self.story
+    }
+
+    /// Gets the names of all passages in the graph, sorted
+    pub fn passages(&self) -> Vec<&'a str> {
+        let mut names: Vec<&str> = self.outgoing.keys().copied().collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Gets the tags of the named passage
+    pub fn tags(&self, name: &str) -> &'a [String] {
+        self.story
+            .passages
+            .get(name)
+            .map(|p| p.header.tags.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Gets the links out of the named passage, in the order they appear
+    pub fn outgoing(&self, name: &str) -> &[Edge<'a>] {
+        self.outgoing
+            .get(name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Gets the links out of the named passage to passages that don't exist,
+    /// which are not part of the graph
+    pub fn dead_links(&self, name: &str) -> &[TwineLink] {
+        self.dead.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Gets the names of the passages linking to the named passage, with one
+    /// entry per link
+    pub fn incoming(&self, name: &str) -> &[&'a str] {
+        self.incoming
+            .get(name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Gets the set of passages reachable by following links from `start`,
+    /// including `start` itself
+    pub fn reachable_from(&self, start: &str) -> HashSet<&'a str> {
+        self.distances_from(start).into_keys().collect()
+    }
+
+    /// Gets the fewest links needed to reach each passage reachable from
+    /// `start`, which is 0 links away from itself
+    pub fn distances_from(&self, start: &str) -> HashMap<&'a str, usize> {
+        let mut found = HashMap::new();
+        let start = match self.story.passages.get_key_value(start) {
+            Some((name, _)) => name.as_str(),
+            None => return found,
+        };
+        let mut queue = VecDeque::new();
+        found.insert(start, 0);
+        queue.push_back((start, 0));
+        while let Some((name, distance)) = queue.pop_front() {
+            for edge in self.outgoing(name) {
+                if !found.contains_key(edge.target) {
+                    found.insert(edge.target, distance + 1);
+                    queue.push_back((edge.target, distance + 1));
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_edges_and_reachability() {
+        let input = r#":: Start
+[[A]] [[ B ]] [[Missing]]
+
+:: A
+[[Start]]
+
+:: B
+The end
+
+:: Island
+[[A]]
+"#;
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let graph = LinkGraph::new(&story, LinkSyntax::DEFAULT);
+        let targets: Vec<&str> = graph.outgoing("Start").iter().map(|e| e.target).collect();
+        assert_eq!(targets, vec!["A", "B"]);
+        assert_eq!(graph.incoming("A").len(), 2);
+        assert_eq!(graph.dead_links("Start")[0].target, "Missing");
+        let mut reachable: Vec<&str> = graph.reachable_from("Start").into_iter().collect();
+        reachable.sort_unstable();
+        assert_eq!(reachable, vec!["A", "B", "Start"]);
+        assert_eq!(graph.distances_from("Island")["Start"], 2);
+
+        let graph = LinkGraph::new(&story, &[LinkSyntax::Pipe]);
+        assert!(graph.outgoing("Start").is_empty());
+    }
+}
@@ -0,0 +1,762 @@
+//! Lints for the macros and hooks of stories using Harlowe 3
+//!
+//! Passage content is scanned the way Harlowe reads it: a macro call is
+//! `(name: arguments)`, a hook is text in `[` and `]`, and a hook can be named
+//! with a `|name>` tag in front of it. Inside a macro call, strings, nested
+//! calls, parentheses, and hooks given as arguments must all be closed before
+//! the call is. HTML comments and verbatim text in backticks are skipped.
+//!
+//! Macro names are compared the way Harlowe does, ignoring case, `-`, and `_`.
+//! Calls to custom macros stored in variables, such as `($greet: "you")`, are
+//! not checked. When `StoryData` gives a Harlowe version, macros added in
+//! later versions of Harlowe 3 are reported as unknown.
+//!
+//! [`Variables`] tells the dataflow lints how Harlowe passages use variables.
+//!
+//! [`Variables`]: struct.Variables.html
+
+use crate::issue::LintWarning;
+use crate::linter::dataflow::{Access, Variable, VariableSyntax};
+use crate::linter::graph::LinkGraph;
+use crate::linter::macros::{self, MACRO_SYNTAX, UNKNOWN_MACRO};
+use crate::linter::Lint;
+use crate::story_format;
+use crate::utils;
+use crate::Config;
+use std::collections::HashSet;
+use std::ops::Range;
+use tweep::Story;
+
+/// The name of the story format these lints run for
+pub const FORMAT: &str = "harlowe-3";
+
+/// Name of the lint for hooks that are never closed, or hook name tags with no
+/// hook to name
+pub const HOOK_SYNTAX: &str = "HookSyntax";
+
+type Version = (u64, u64, u64);
+
+/// Harlowe's macros, and the version of Harlowe 3 that added them
+const MACROS: &[(Version, &[&str])] = &[
+    (
+        (3, 0, 0),
+        &[
+            "a",
+            "array",
+            "dm",
+            "datamap",
+            "ds",
+            "dataset",
+            "count",
+            "range",
+            "subarray",
+            "substring",
+            "shuffled",
+            "sorted",
+            "rotated",
+            "interlaced",
+            "altered",
+            "find",
+            "all-pass",
+            "some-pass",
+            "none-pass",
+            "folded",
+            "repeated",
+            "datanames",
+            "datavalues",
+            "dataentries",
+            "history",
+            "passage",
+            "passages",
+            "num",
+            "number",
+            "str",
+            "string",
+            "text",
+            "random",
+            "either",
+            "round",
+            "floor",
+            "ceil",
+            "abs",
+            "sign",
+            "sin",
+            "cos",
+            "tan",
+            "min",
+            "max",
+            "pow",
+            "sqrt",
+            "exp",
+            "log",
+            "log10",
+            "log2",
+            "set",
+            "put",
+            "move",
+            "print",
+            "display",
+            "if",
+            "unless",
+            "else-if",
+            "else",
+            "hidden",
+            "show",
+            "replace",
+            "append",
+            "prepend",
+            "click",
+            "click-replace",
+            "click-append",
+            "click-prepend",
+            "mouseover",
+            "mouseover-replace",
+            "mouseover-append",
+            "mouseover-prepend",
+            "mouseout",
+            "mouseout-replace",
+            "mouseout-append",
+            "mouseout-prepend",
+            "link",
+            "link-replace",
+            "link-reveal",
+            "link-repeat",
+            "link-goto",
+            "link-reveal-goto",
+            "link-undo",
+            "link-show",
+            "go-to",
+            "undo",
+            "live",
+            "stop",
+            "event",
+            "for",
+            "loop",
+            "hook",
+            "transition",
+            "t8n",
+            "transition-time",
+            "t8n-time",
+            "text-colour",
+            "text-color",
+            "colour",
+            "color",
+            "background",
+            "css",
+            "font",
+            "align",
+            "text-style",
+            "text-rotate",
+            "hover-style",
+            "save-game",
+            "load-game",
+            "saved-games",
+            "alert",
+            "prompt",
+            "confirm",
+            "open-url",
+            "reload",
+            "goto-url",
+            "page-url",
+            "cycling-link",
+            "seq-link",
+            "dropdown",
+            "current-date",
+            "current-time",
+            "monthday",
+            "weekday",
+            "rgb",
+            "rgba",
+            "hsl",
+            "hsla",
+            "gradient",
+            "lowercase",
+            "uppercase",
+            "lowerfirst",
+            "upperfirst",
+            "words",
+            "joined",
+            "source",
+            "dialog",
+            "dialogue",
+            "enchant",
+        ],
+    ),
+    (
+        (3, 1, 0),
+        &[
+            "transition-delay",
+            "t8n-delay",
+            "transition-skip",
+            "t8n-skip",
+            "link-rerun",
+            "mock-visits",
+        ],
+    ),
+    (
+        (3, 2, 0),
+        &[
+            "unpack",
+            "replace-with",
+            "append-with",
+            "prepend-with",
+            "verbatim",
+            "v6m",
+            "verbatim-print",
+            "v6m-print",
+            "verbatim-source",
+            "v6m-source",
+            "cond",
+            "nth",
+            "rerun",
+            "after",
+            "after-error",
+            "more",
+            "hide",
+            "icon-undo",
+            "icon-redo",
+            "icon-fullscreen",
+            "icon-restart",
+            "icon-counter",
+            "macro",
+            "output",
+            "output-data",
+            "error",
+            "partial",
+            "bind",
+            "2bind",
+            "checkbox",
+            "input",
+            "force-input",
+            "input-box",
+            "force-input-box",
+            "seed",
+            "restart",
+            "lch",
+            "complement",
+            "palette",
+            "mix",
+            "link-fullscreen",
+            "box",
+            "float-box",
+            "char-style",
+            "line-style",
+            "link-style",
+            "text-indent",
+            "text-size",
+            "border",
+            "b4r",
+            "border-colour",
+            "border-color",
+            "b4r-colour",
+            "b4r-color",
+            "border-size",
+            "b4r-size",
+            "corner-radius",
+            "opacity",
+            "collapse",
+            "debug",
+            "str-find",
+            "string-find",
+            "str-nth",
+            "string-nth",
+            "str-repeated",
+            "string-repeated",
+            "str-reversed",
+            "string-reversed",
+            "trimmed",
+            "split",
+            "splitted",
+            "plural",
+            "dm-names",
+            "dm-values",
+            "dm-entries",
+            "data-names",
+            "data-values",
+            "data-entries",
+        ],
+    ),
+    (
+        (3, 3, 0),
+        &[
+            "storylet",
+            "open-storylets",
+            "link-storylet",
+            "exclusivity",
+            "urgency",
+            "metadata",
+            "redirect",
+            "forget-visits",
+            "forget-undos",
+            "scroll",
+            "digit-format",
+            "dm-altered",
+            "permutations",
+            "sum",
+            "trunc",
+        ],
+    ),
+];
+
+/// Normalizes a macro name the way Harlowe compares them
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Gets the version of Harlowe that added the named macro, if it is one of
+/// Harlowe's
+fn added_in(name: &str) -> Option<Version> {
+    let name = normalize(name);
+    MACROS
+        .iter()
+        .find(|(_, names)| names.iter().any(|known| normalize(known) == name))
+        .map(|(version, _)| *version)
+}
+
+/// Gets the Harlowe version given in `StoryData`, if any
+fn story_version(story: &Story) -> Option<Version> {
+    let data = story.data.as_ref()?;
+    if !data.format.as_deref()?.eq_ignore_ascii_case("harlowe") {
+        return None;
+    }
+    story_format::parse_version(data.format_version.as_deref()?)
+}
+
+/// Something opened in passage content that must be closed
+#[derive(Debug)]
+enum Open<'a> {
+    /// A macro call, with its name
+    Macro(&'a str, Range<usize>),
+
+    /// Parentheses inside a macro call
+    Paren(Range<usize>),
+
+    /// A hook
+    Hook(Range<usize>),
+}
+
+/// A problem found in passage content
+#[derive(Debug, PartialEq)]
+struct Problem {
+    name: &'static str,
+    message: String,
+    range: Range<usize>,
+}
+
+impl Problem {
+    fn new<S: Into<String>>(name: &'static str, message: S, range: Range<usize>) -> Self {
+        Problem {
+            name,
+            message: message.into(),
+            range,
+        }
+    }
+
+    fn unclosed(open: &Open) -> Self {
+        match open {
+            Open::Macro(name, range) => Problem::new(
+                MACRO_SYNTAX,
+                format!("({}:) is never closed with )", name),
+                range.clone(),
+            ),
+            Open::Paren(range) => Problem::new(
+                MACRO_SYNTAX,
+                "( in a macro call is never closed with )",
+                range.clone(),
+            ),
+            Open::Hook(range) => {
+                Problem::new(HOOK_SYNTAX, "Hook is never closed with ]", range.clone())
+            }
+        }
+    }
+}
+
+/// Gets the macro name at the start of `text`, which follows a `(`, if it is
+/// a macro call
+fn macro_name(text: &str) -> Option<&str> {
+    let sigil = usize::from(text.starts_with(['$', '_']));
+    let end = sigil
+        + text[sigil..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(text.len() - sigil);
+    if end == sigil || !text[end..].starts_with(':') {
+        return None;
+    }
+    Some(&text[..end])
+}
+
+/// Gets the name in a `|name>` hook tag at the start of `text`
+fn hook_tag(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix('|')?;
+    let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))?;
+    if end == 0 || !rest[end..].starts_with('>') {
+        return None;
+    }
+    Some(&rest[..end])
+}
+
+/// Whether the `'` at `i` is the possessive `'s`, as in `$a's 1st`
+fn is_possessive(content: &str, i: usize) -> bool {
+    let before = content[..i].chars().next_back();
+    let mut after = content[i + 1..].chars();
+    before.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == ')' || c == ']')
+        && after.next() == Some('s')
+        && after.next().is_none_or(|c| !c.is_alphanumeric())
+}
+
+/// Gets the variable name, including its sigil, at the start of `text`
+fn variable_name(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix(['$', '_'])?;
+    if !rest.starts_with(char::is_alphabetic) {
+        return None;
+    }
+    let end = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    Some(&text[..1 + end])
+}
+
+/// Whether `text` starts with the word `word`
+fn starts_with_word(text: &str, word: &str) -> bool {
+    text.strip_prefix(word)
+        .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+/// Whether `text` ends with the word `word`
+fn ends_with_word(text: &str, word: &str) -> bool {
+    text.strip_suffix(word)
+        .is_some_and(|rest| !rest.ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+/// Works out how a variable is used from the macro call it is in, if any, and
+/// the text before and after it
+fn variable_access(call: Option<&str>, name: &str, before: &str, after: &str) -> Access {
+    let before = before.trim_end();
+    let after = after.trim_start();
+    let call = call.map(normalize);
+    let temporary = name.starts_with('_');
+    match call.as_deref() {
+        Some("set") if starts_with_word(after, "to") => Access::Set,
+        Some("put" | "move") if ends_with_word(before, "into") => Access::Set,
+        // A lambda's temporary variable, as in each _item or _a making _b
+        _ if temporary
+            && (["each", "making"]
+                .iter()
+                .any(|word| ends_with_word(before, word))
+                || ["where", "when", "making", "via"]
+                    .iter()
+                    .any(|word| starts_with_word(after, word))) =>
+        {
+            Access::Set
+        }
+        _ => Access::Read,
+    }
+}
+
+/// What is found scanning passage content
+#[derive(Debug)]
+struct Scan<'a> {
+    /// The name and range of each macro call
+    calls: Vec<(&'a str, Range<usize>)>,
+
+    /// The variables used, in order
+    variables: Vec<Variable>,
+
+    problems: Vec<Problem>,
+}
+
+/// Finds the macro calls, variables, and syntax problems in passage content
+fn scan(content: &str) -> Scan<'_> {
+    let mut calls = Vec::new();
+    let mut variables = Vec::new();
+    let mut problems = Vec::new();
+    let mut open: Vec<Open> = Vec::new();
+    let mut i = 0;
+    while i < content.len() {
+        let rest = &content[i..];
+        let in_call = matches!(open.last(), Some(Open::Macro(..) | Open::Paren(_)));
+        let next = rest.chars().next().map(char::len_utf8).unwrap_or(1);
+
+        if rest.starts_with("<!--") {
+            i = rest
+                .find("-->")
+                .map(|end| i + end + 3)
+                .unwrap_or(content.len());
+            continue;
+        }
+        if rest.starts_with('`') {
+            // Verbatim text ends at the next run of as many backticks
+            let ticks = &rest[..rest.find(|c| c != '`').unwrap_or(rest.len())];
+            i = match rest[ticks.len()..].find(ticks) {
+                Some(end) => i + ticks.len() + end + ticks.len(),
+                None => i + ticks.len(),
+            };
+            continue;
+        }
+        if let Some(name) = rest.strip_prefix('(').and_then(macro_name) {
+            let range = i..i + 2 + name.len();
+            if name.starts_with(['$', '_']) {
+                variables.push(Variable::new(name, Access::Read, i + 1..i + 1 + name.len()));
+            }
+            calls.push((name, range.clone()));
+            open.push(Open::Macro(name, range));
+            i += 2 + name.len();
+            continue;
+        }
+        let boundary = content[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !(c.is_alphanumeric() || c == '_' || c == '$'));
+        if let Some(name) = variable_name(rest).filter(|_| boundary) {
+            let call = open.iter().rev().find_map(|open| match open {
+                Open::Macro(name, _) => Some(Some(*name)),
+                Open::Hook(_) => Some(None),
+                Open::Paren(_) => None,
+            });
+            let end = i + name.len();
+            let access = variable_access(call.flatten(), name, &content[..i], &content[end..]);
+            variables.push(Variable::new(name, access, i..end));
+            i = end;
+            continue;
+        }
+
+        match rest.as_bytes()[0] {
+            // The possessive 's, as in $a's name, is not a string
+            b'\'' if in_call && is_possessive(content, i) => (),
+            quote @ (b'"' | b'\'') if in_call => {
+                let mut end = i + 1;
+                let bytes = content.as_bytes();
+                while end < bytes.len() && bytes[end] != quote {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                if end >= bytes.len() {
+                    problems.push(Problem::new(
+                        MACRO_SYNTAX,
+                        "String in a macro call is never closed",
+                        i..i + 1,
+                    ));
+                    return Scan {
+                        calls,
+                        variables,
+                        problems,
+                    };
+                }
+                i = end + 1;
+                continue;
+            }
+            b'(' if in_call => open.push(Open::Paren(i..i + 1)),
+            b')' if in_call => {
+                open.pop();
+            }
+            b'[' if !in_call && rest.starts_with("[[") => {
+                // Links are checked by tweep
+                i = rest.find("]]").map(|end| i + end + 2).unwrap_or(i + 2);
+                continue;
+            }
+            b'[' => open.push(Open::Hook(i..i + 1)),
+            b']' => match open.last() {
+                Some(Open::Hook(_)) => {
+                    open.pop();
+                }
+                Some(unclosed) => {
+                    // The call can't be closed after this, so the rest of the
+                    // passage can't be read reliably
+                    problems.push(Problem::unclosed(unclosed));
+                    return Scan {
+                        calls,
+                        variables,
+                        problems,
+                    };
+                }
+                // Outside of a hook, ] is plain text
+                None => (),
+            },
+            b'|' if !in_call => {
+                if let Some(name) = hook_tag(rest) {
+                    let end = i + name.len() + 2;
+                    if !content[end..].starts_with('[') {
+                        problems.push(Problem::new(
+                            HOOK_SYNTAX,
+                            format!("Hook name tag |{}> is not followed by a hook", name),
+                            i..end,
+                        ));
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        i += next;
+    }
+    problems.extend(open.iter().map(Problem::unclosed));
+    Scan {
+        calls,
+        variables,
+        problems,
+    }
+}
+
+/// Checks the macros and hooks of every passage, returning warnings for all
+/// of the Harlowe lints
+fn check(story: &Story, config: &Config) -> Vec<LintWarning> {
+    let headers = utils::passage_headers(story);
+    let version = story_version(story);
+    let defined: HashSet<String> = config.macros.iter().map(|name| normalize(name)).collect();
+
+    let mut names: Vec<&String> = story.passages.keys().collect();
+    names.sort();
+    let mut warnings = Vec::new();
+    for name in names {
+        let passage = &story.passages[name];
+        if passage
+            .header
+            .tags
+            .iter()
+            .any(|tag| tag == "script" || tag == "stylesheet")
+        {
+            continue;
+        }
+        let content = utils::get_content(passage);
+        let header = headers.get(name);
+        let context = |range: Range<usize>| {
+            header.map(|header| macros::content_context(header, content, range))
+        };
+
+        let Scan {
+            calls, problems, ..
+        } = scan(content);
+        for (call, range) in calls {
+            if call.starts_with(['$', '_']) || defined.contains(&normalize(call)) {
+                continue;
+            }
+            let message = match (added_in(call), version) {
+                (None, _) => format!("Unknown macro ({}:) in passage {}", call, name),
+                (Some(added), Some(version)) if added > version => format!(
+                    "Macro ({}:) in passage {} needs Harlowe {}.{}.{}, but the story uses {}.{}.{}",
+                    call, name, added.0, added.1, added.2, version.0, version.1, version.2
+                ),
+                _ => continue,
+            };
+            warnings.push(
+                LintWarning::new(UNKNOWN_MACRO, message, context(range)).with_note(
+                    "If it is defined outside the story, add it to macros in the config file",
+                ),
+            );
+        }
+        for problem in problems {
+            warnings.push(LintWarning::new(
+                problem.name,
+                format!("{} in passage {}", problem.message, name),
+                context(problem.range),
+            ));
+        }
+    }
+    warnings
+}
+
+/// Warns about macros that are not Harlowe's, or are newer than the version of
+/// Harlowe the story uses
+pub struct UnknownMacro;
+
+/// Warns about macro calls with unbalanced parentheses or unclosed strings
+pub struct MacroSyntax;
+
+/// Warns about hooks that are never closed, and hook name tags with no hook
+pub struct HookSyntax;
+
+macro_rules! harlowe_lint {
+    ($lint:ident, $name:expr) => {
+        impl Lint for $lint {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn check(&self, story: &Story, _: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+                check(story, config)
+                    .into_iter()
+                    .filter(|warning| warning.name == $name)
+                    .collect()
+            }
+        }
+    };
+}
+
+harlowe_lint!(UnknownMacro, UNKNOWN_MACRO);
+harlowe_lint!(MacroSyntax, MACRO_SYNTAX);
+harlowe_lint!(HookSyntax, HOOK_SYNTAX);
+
+/// Finds where Harlowe passages set and read variables
+///
+/// Variables are set by `(set:)`, `(put:)`, and `(move:)`, and temporary
+/// variables by the lambdas that name them, as in `each _item`. Setting part
+/// of a variable, as in `(set: $a's 1st to 2)`, is a read of the variable
+pub struct Variables;
+
+impl VariableSyntax for Variables {
+    fn variables(&self, content: &str) -> Vec<Variable> {
+        scan(content).variables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_macro_and_hook_problems() {
+        let input = r#":: StoryData
+{"ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC", "format": "Harlowe", "format-version": "3.2.3"}
+
+:: Start
+(set: $a to (a: 1, 2))(if: $a's 1st is 1)[Yes (GoTo: "End")] ]
+(print: "(unclosed:") (bogus: 1) (storylet: when $a is 1) ($custom: 1)
+|named>(if: true)[x] `(verbatim:` <!-- (comment: -->
+(external: 1)(set: $b to [1)
+
+:: End
+(if: true)[Never closed
+"#;
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let mut config = crate::linter::tests::config(Some(FORMAT), Vec::new());
+        config.macros = vec!["External".to_string()];
+        let warnings: Vec<(&str, String)> = check(&story, &config)
+            .into_iter()
+            .map(|warning| (warning.name, warning.message))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    HOOK_SYNTAX,
+                    "Hook is never closed with ] in passage End".to_string()
+                ),
+                (
+                    UNKNOWN_MACRO,
+                    "Unknown macro (bogus:) in passage Start".to_string()
+                ),
+                (
+                    UNKNOWN_MACRO,
+                    "Macro (storylet:) in passage Start needs Harlowe 3.3.0, but the story uses \
+                     3.2.3"
+                        .to_string()
+                ),
+                (
+                    HOOK_SYNTAX,
+                    "Hook name tag |named> is not followed by a hook in passage Start".to_string()
+                ),
+                (
+                    MACRO_SYNTAX,
+                    "(set:) is never closed with ) in passage Start".to_string()
+                ),
+                (
+                    HOOK_SYNTAX,
+                    "Hook is never closed with ] in passage Start".to_string()
+                ),
+            ]
+        );
+    }
+}
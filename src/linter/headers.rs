@@ -0,0 +1,638 @@
+//! Strict lints for passage headers: repeated tags, tags that differ only by
+//! case, tags used on a single passage, tags that look like misspellings of
+//! others, and repeated metadata keys
+//!
+//! The parser accepts all of these without complaint, keeping every copy of a
+//! repeated tag and the last value of a repeated metadata key, which can
+//! confuse tools that work with tags. Repeats within a single header can be
+//! removed by [`fix_files`].
+//!
+//! [`fix_files`]: fn.fix_files.html
+
+use crate::issue::{self, LintWarning};
+use crate::linter::graph::LinkGraph;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use color_eyre::Result;
+use eyre::WrapErr;
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+use tweep::{FullContext, Position, Story};
+
+/// Name of the lint for a tag given more than once on a passage
+pub const DUPLICATE_TAG: &str = "DuplicateTag";
+
+/// Name of the lint for tags that differ only by case
+pub const TAG_CASE: &str = "TagCaseMismatch";
+
+/// Name of the lint for a tag used on only one passage
+pub const SINGLE_USE_TAG: &str = "SingleUseTag";
+
+/// Name of the lint for a tag that is a near-match of a more common tag
+pub const TAG_TYPO: &str = "TagTypo";
+
+/// Tags with a special meaning to Twine or a story format, which are never
+/// reported as used only once and are always taken to be correctly spelled
+const SPECIAL_TAGS: &[&str] = &[
+    "bookmark",
+    "debug-footer",
+    "debug-header",
+    "debug-startup",
+    "footer",
+    "header",
+    "init",
+    "nobr",
+    "script",
+    "startup",
+    "stylesheet",
+    "Twine.audio",
+    "Twine.image",
+    "Twine.private",
+    "Twine.video",
+    "Twine.vtt",
+    "widget",
+];
+
+/// Name of the lint for a metadata key given more than once in a passage
+/// header
+pub const DUPLICATE_METADATA_KEY: &str = "DuplicateMetadataKey";
+
+/// The byte ranges of the tag and metadata blocks of a passage header line,
+/// including their brackets
+#[derive(Debug, Default, PartialEq)]
+struct Blocks {
+    tags: Option<Range<usize>>,
+    metadata: Option<Range<usize>>,
+}
+
+/// Finds the tag and metadata blocks of a passage header line, skipping
+/// escaped brackets in the passage name
+fn blocks(line: &str) -> Blocks {
+    let mut blocks = Blocks::default();
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' if blocks.tags.is_none() && blocks.metadata.is_none() => {
+                if let Some(end) = line[i..].find(']') {
+                    blocks.tags = Some(i..i + end + 1);
+                }
+            }
+            '{' if blocks.metadata.is_none() => {
+                if let Some(end) = line.rfind('}').filter(|&end| end > i) {
+                    blocks.metadata = Some(i..end + 1);
+                }
+                break;
+            }
+            _ => (),
+        }
+    }
+    blocks
+}
+
+/// Gets the tags in a tag block with their byte ranges in the header line
+fn tags(line: &str, block: &Range<usize>) -> Vec<(Range<usize>, String)> {
+    let inner = block.start + 1..block.end - 1;
+    let mut tags = Vec::new();
+    let mut start = None;
+    for (i, c) in line[inner.clone()]
+        .char_indices()
+        .chain(std::iter::once((inner.len(), ' ')))
+    {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                tags.push((
+                    inner.start + s..inner.start + i,
+                    line[inner.start + s..inner.start + i].to_string(),
+                ));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    tags
+}
+
+/// The entries of a JSON object in order, keeping repeated keys
+struct Entries(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Entries {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct EntriesVisitor;
+
+        impl<'de> Visitor<'de> for EntriesVisitor {
+            type Value = Entries;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<Entries, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Entries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
+/// Gets the entries of a metadata block, or None if it isn't a valid JSON
+/// object, which the parser reports
+fn metadata(line: &str, block: &Range<usize>) -> Option<Vec<(String, Value)>> {
+    serde_json::from_str::<Entries>(&line[block.clone()])
+        .ok()
+        .map(|entries| entries.0)
+}
+
+/// The header line of each passage, sorted by passage name
+fn headers(story: &Story) -> Vec<(String, FullContext)> {
+    let mut headers: Vec<(String, FullContext)> =
+        utils::passage_headers(story).into_iter().collect();
+    headers.sort_by(|(a, _), (b, _)| a.cmp(b));
+    headers
+}
+
+/// Gets the context of a byte range within a header line
+fn subcontext(header: &FullContext, range: &Range<usize>) -> FullContext {
+    header.subcontext(Position::rel(1, range.start + 1)..=Position::rel(1, range.end))
+}
+
+/// Warns about any tag given more than once on the same passage
+pub struct DuplicateTag;
+
+impl Lint for DuplicateTag {
+    fn name(&self) -> &'static str {
+        DUPLICATE_TAG
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, _: &Config) -> Vec<LintWarning> {
+        let mut warnings = Vec::new();
+        for (name, header) in headers(story) {
+            let line = header.get_contents();
+            let block = match blocks(line).tags {
+                Some(block) => block,
+                None => continue,
+            };
+            let mut seen: Vec<String> = Vec::new();
+            for (range, tag) in tags(line, &block) {
+                if seen.contains(&tag) {
+                    warnings.push(
+                        LintWarning::new(
+                            DUPLICATE_TAG,
+                            format!("Tag {} is given more than once on passage {}", tag, name),
+                            Some(subcontext(&header, &range)),
+                        )
+                        .with_note("Run tweec lint --fix to remove repeated tags"),
+                    );
+                } else {
+                    seen.push(tag);
+                }
+            }
+        }
+        warnings
+    }
+}
+
+/// Warns about tags that differ from another tag in the story only by case
+///
+/// The spelling used most often is taken to be the intended one, and every
+/// other spelling is reported
+pub struct TagCaseMismatch;
+
+impl Lint for TagCaseMismatch {
+    fn name(&self) -> &'static str {
+        TAG_CASE
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, _: &Config) -> Vec<LintWarning> {
+        let mut occurrences = Vec::new();
+        let mut spellings: HashMap<String, Vec<(String, usize)>> = HashMap::new();
+        for (_, header) in headers(story) {
+            let line = header.get_contents();
+            if let Some(block) = blocks(line).tags {
+                for (range, tag) in tags(line, &block) {
+                    let counts = spellings.entry(tag.to_lowercase()).or_default();
+                    match counts.iter_mut().find(|(spelling, _)| *spelling == tag) {
+                        Some((_, count)) => *count += 1,
+                        None => counts.push((tag.clone(), 1)),
+                    }
+                    occurrences.push((subcontext(&header, &range), tag));
+                }
+            }
+        }
+
+        let mut warnings = Vec::new();
+        for (context, tag) in occurrences {
+            let counts = &spellings[&tag.to_lowercase()];
+            // The first spelling seen wins a tie
+            let (canonical, count) = counts.iter().rev().max_by_key(|(_, count)| *count).unwrap();
+            if *canonical != tag {
+                warnings.push(
+                    LintWarning::new(
+                        TAG_CASE,
+                        format!("Tag {} differs only in case from {}", tag, canonical),
+                        Some(context),
+                    )
+                    .with_note(format!(
+                        "{} is used {} time{}",
+                        canonical,
+                        count,
+                        if *count == 1 { "" } else { "s" }
+                    )),
+                );
+            }
+        }
+        warnings
+    }
+}
+
+/// Each tag given in a passage header, along with the number of passages
+/// each tag is given on
+fn tag_uses(story: &Story) -> (Vec<(FullContext, String)>, HashMap<String, usize>) {
+    let mut occurrences = Vec::new();
+    let mut passages: HashMap<String, usize> = HashMap::new();
+    for (_, header) in headers(story) {
+        let line = header.get_contents();
+        if let Some(block) = blocks(line).tags {
+            let mut seen: Vec<String> = Vec::new();
+            for (range, tag) in tags(line, &block) {
+                if !seen.contains(&tag) {
+                    *passages.entry(tag.clone()).or_default() += 1;
+                    seen.push(tag.clone());
+                }
+                occurrences.push((subcontext(&header, &range), tag));
+            }
+        }
+    }
+    (occurrences, passages)
+}
+
+/// Whether a tag has a meaning of its own, either to a story format or
+/// because the config names it
+fn is_known_tag(tag: &str, config: &Config) -> bool {
+    SPECIAL_TAGS.contains(&tag)
+        || config.ending_tags.iter().any(|t| t == tag)
+        || config.unlinked_tags.iter().any(|t| t == tag)
+        || config.recap_tags.iter().any(|t| t == tag)
+        || config.chapters.iter().any(|t| t == tag)
+        || config.tag_max_links.contains_key(tag)
+}
+
+/// Gets the tag that `tag` is most likely a misspelling of: a known tag or
+/// one used on more passages, that differs by more than case and by at most a
+/// couple of edits
+fn likely_tag<'a>(
+    tag: &str,
+    passages: &'a HashMap<String, usize>,
+    config: &'a Config,
+) -> Option<String> {
+    if is_known_tag(tag, config) {
+        return None;
+    }
+    let uses = passages[tag];
+    let mut candidates: Vec<&str> = passages
+        .iter()
+        .filter(|(other, count)| **count > uses || is_known_tag(other, config))
+        .map(|(other, _)| other.as_str())
+        .chain(SPECIAL_TAGS.iter().copied())
+        .chain(config.ending_tags.iter().map(String::as_str))
+        .chain(config.unlinked_tags.iter().map(String::as_str))
+        .filter(|other| other.to_lowercase() != tag.to_lowercase())
+        .collect();
+    // Sorted so that equally similar candidates are picked the same way every
+    // time
+    candidates.sort_unstable();
+    candidates.dedup();
+    // Short tags are similar to many others, so only a few edits are allowed
+    // on top of the similarity did_you_mean requires
+    issue::did_you_mean(tag, candidates)
+        .into_iter()
+        .rev()
+        .find(|other| {
+            let edits = strsim::damerau_levenshtein(tag, other);
+            edits <= 2 && edits * 3 <= tag.chars().count()
+        })
+}
+
+/// Warns about tags given on only one passage, which are often leftovers or
+/// misspellings
+///
+/// Many stories tag single passages on purpose, so the lint is only enabled
+/// when the config names it with a level, e.g. with `--warn SingleUseTag`.
+/// Likely misspellings are still reported by [`TagTypo`]. Tags that story formats or the config give a meaning to, tags reported by
+/// [`TagCaseMismatch`], and tags reported by [`TagTypo`] are skipped
+///
+/// [`TagCaseMismatch`]: struct.TagCaseMismatch.html
+/// [`TagTypo`]: struct.TagTypo.html
+pub struct SingleUseTag;
+
+impl Lint for SingleUseTag {
+    fn name(&self) -> &'static str {
+        SINGLE_USE_TAG
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        let (occurrences, passages) = tag_uses(story);
+        let mut warnings = Vec::new();
+        for (context, tag) in occurrences {
+            let case_variants = passages
+                .keys()
+                .filter(|other| other.to_lowercase() == tag.to_lowercase())
+                .count();
+            if passages[&tag] != 1
+                || case_variants > 1
+                || is_known_tag(&tag, config)
+                || likely_tag(&tag, &passages, config).is_some()
+            {
+                continue;
+            }
+            warnings.push(
+                LintWarning::new(
+                    SINGLE_USE_TAG,
+                    format!("Tag {} is used on only one passage", tag),
+                    Some(context),
+                )
+                .with_note(
+                    "Remove the tag if nothing uses it, or stop warning about SingleUseTag if \
+                     the story format or a script does",
+                ),
+            );
+        }
+        warnings
+    }
+
+    fn enabled(&self, config: &Config) -> bool {
+        config.levels.iter().any(|(name, _)| name == SINGLE_USE_TAG)
+    }
+}
+
+/// Warns about tags that are near-matches of a tag used on more passages, or
+/// of a tag with a special meaning, such as `widgit` for `widget`
+pub struct TagTypo;
+
+impl Lint for TagTypo {
+    fn name(&self) -> &'static str {
+        TAG_TYPO
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        let (occurrences, passages) = tag_uses(story);
+        let mut warnings = Vec::new();
+        for (context, tag) in occurrences {
+            if let Some(likely) = likely_tag(&tag, &passages, config) {
+                let uses = passages.get(&likely).copied().unwrap_or(0);
+                let note = if uses == 0 {
+                    format!(
+                        "{} has a special meaning to the story format or config",
+                        likely
+                    )
+                } else {
+                    format!(
+                        "{} is used on {} passage{}",
+                        likely,
+                        uses,
+                        if uses == 1 { "" } else { "s" }
+                    )
+                };
+                warnings.push(
+                    LintWarning::new(
+                        TAG_TYPO,
+                        format!("Tag {} may be a misspelling of {}", tag, likely),
+                        Some(context),
+                    )
+                    .with_note(note),
+                );
+            }
+        }
+        warnings
+    }
+}
+
+/// Warns about any metadata key given more than once in the same passage
+/// header
+pub struct DuplicateMetadataKey;
+
+impl Lint for DuplicateMetadataKey {
+    fn name(&self) -> &'static str {
+        DUPLICATE_METADATA_KEY
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, _: &Config) -> Vec<LintWarning> {
+        let mut warnings = Vec::new();
+        for (name, header) in headers(story) {
+            let line = header.get_contents();
+            let block = match blocks(line).metadata {
+                Some(block) => block,
+                None => continue,
+            };
+            let entries = metadata(line, &block).unwrap_or_default();
+            for (i, (key, value)) in entries.iter().enumerate() {
+                let earlier = entries[..i].iter().rev().find(|(k, _)| k == key);
+                let earlier = match earlier {
+                    Some((_, earlier)) => earlier,
+                    None => continue,
+                };
+                let message = if earlier == value {
+                    format!(
+                        "Metadata key {} is given more than once on passage {}",
+                        key, name
+                    )
+                } else {
+                    format!(
+                        "Metadata key {} is given conflicting values on passage {}",
+                        key, name
+                    )
+                };
+                warnings.push(
+                    LintWarning::new(
+                        DUPLICATE_METADATA_KEY,
+                        message,
+                        Some(subcontext(&header, &block)),
+                    )
+                    .with_note(format!("The last value is used: {}", value))
+                    .with_note("Run tweec lint --fix to keep only the last value"),
+                );
+            }
+        }
+        warnings
+    }
+}
+
+/// Removes repeated tags and metadata keys from a passage header line,
+/// returning the fixed line if anything changed
+///
+/// Of tags that differ only by case, the first is kept. Of repeated metadata
+/// keys, the last value is kept, matching the parser, at the position of the
+/// first
+pub fn fix_header(line: &str) -> Option<String> {
+    let blocks = blocks(line);
+    let mut fixed = line.to_string();
+    let mut changed = false;
+
+    // Fix the later block first so the earlier block's range stays valid
+    if let Some(block) = &blocks.metadata {
+        let entries = metadata(line, block).unwrap_or_default();
+        let mut deduped: Vec<(String, Value)> = Vec::new();
+        for (key, value) in entries.iter().cloned() {
+            match deduped.iter_mut().find(|(k, _)| *k == key) {
+                Some(entry) => entry.1 = value,
+                None => deduped.push((key, value)),
+            }
+        }
+        if deduped.len() < entries.len() {
+            let json: Vec<String> = deduped
+                .iter()
+                .map(|(key, value)| format!("{}:{}", Value::from(key.as_str()), value))
+                .collect();
+            fixed.replace_range(block.clone(), &format!("{{{}}}", json.join(",")));
+            changed = true;
+        }
+    }
+
+    if let Some(block) = &blocks.tags {
+        let all = tags(line, block);
+        let mut kept: Vec<String> = Vec::new();
+        for (_, tag) in &all {
+            if !kept.iter().any(|k| k.to_lowercase() == tag.to_lowercase()) {
+                kept.push(tag.clone());
+            }
+        }
+        if kept.len() < all.len() {
+            fixed.replace_range(block.clone(), &format!("[{}]", kept.join(" ")));
+            changed = true;
+        }
+    }
+
+    if changed {
+        Some(fixed)
+    } else {
+        None
+    }
+}
+
+/// Removes repeated tags and metadata keys from the passage headers in the
+/// given Twee source, as described in [`fix_header`]
+///
+/// Returns the fixed source along with the number of headers fixed
+///
+/// [`fix_header`]: fn.fix_header.html
+pub fn fix_source(source: &str) -> (String, usize) {
+    let mut count = 0;
+    let lines: Vec<String> = source
+        .split('\n')
+        .map(|line| match line.strip_prefix("::").and(fix_header(line)) {
+            Some(fixed) => {
+                count += 1;
+                fixed
+            }
+            None => line.to_string(),
+        })
+        .collect();
+    (lines.join("\n"), count)
+}
+
+/// Removes repeated tags and metadata keys from the passage headers in the
+/// Twee files given by the inputs, as described in [`fix_header`]
+///
+/// Returns each file that was changed along with the number of headers fixed
+/// in it
+///
+/// [`fix_header`]: fn.fix_header.html
+pub fn fix_files(inputs: &[String], extensions: &[String]) -> Result<Vec<(PathBuf, usize)>> {
+    let mut fixed_files = Vec::new();
+    for path in crate::inputs::twee_files(inputs, extensions) {
+        let source = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read input file {:?}", path))?;
+        let (fixed, count) = fix_source(&source);
+        if count > 0 {
+            utils::write_atomic(&path, fixed.as_bytes())
+                .wrap_err_with(|| format!("Failed to write input file {:?}", path))?;
+            fixed_files.push((path, count));
+        }
+    }
+    Ok(fixed_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_header_blocks() {
+        let line = r#":: A \[b\] [x y] {"k": "}"}"#;
+        let blocks = blocks(line);
+        assert_eq!(&line[blocks.tags.clone().unwrap()], "[x y]");
+        assert_eq!(&line[blocks.metadata.unwrap()], r#"{"k": "}"}"#);
+        let tags: Vec<String> = tags(line, &blocks.tags.unwrap())
+            .into_iter()
+            .map(|(_, tag)| tag)
+            .collect();
+        assert_eq!(tags, vec!["x", "y"]);
+    }
+
+    #[test]
+    fn finds_single_use_tags_and_typos() {
+        let input = ":: Start [intro]\nA\n\n:: Hall [intro draft]\nB\n\n\
+                     :: Cellar [intr]\nC\n\n:: Inventory [widgit]\nD\n\n\
+                     :: Cliff [ending]\nE\n";
+        // Passage headers are found in the story's files, so it is read from one
+        let path = std::env::temp_dir().join(format!("tweec-tags-{}.twee", std::process::id()));
+        std::fs::write(&path, input).unwrap();
+        let story = Story::from_path(&path).take().0.ok().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.ending_tags = vec!["ending".to_string()];
+        let graph = LinkGraph::new(&story, crate::linter::links::LinkSyntax::DEFAULT);
+        let messages = |lint: &dyn Lint| -> Vec<String> {
+            let mut messages: Vec<String> = lint
+                .check(&story, &graph, &config)
+                .into_iter()
+                .map(|warning| warning.message)
+                .collect();
+            messages.sort();
+            messages
+        };
+        assert_eq!(
+            messages(&SingleUseTag),
+            vec!["Tag draft is used on only one passage"]
+        );
+        assert!(!SingleUseTag.enabled(&config));
+        assert!(TagTypo.enabled(&config));
+        assert_eq!(
+            messages(&TagTypo),
+            vec![
+                "Tag intr may be a misspelling of intro",
+                "Tag widgit may be a misspelling of widget",
+            ]
+        );
+
+        // SingleUseTag is opt-in
+        let mut config = config.clone();
+        config
+            .levels
+            .push((SINGLE_USE_TAG.to_string(), issue::LintLevel::Warn));
+        assert!(SingleUseTag.enabled(&config));
+    }
+
+    #[test]
+    fn fixes_repeats() {
+        assert_eq!(
+            fix_header(r#":: A [hub x Hub x] {"size":"1","position":"2","size":"3"}"#).unwrap(),
+            r#":: A [hub x] {"size":"3","position":"2"}"#
+        );
+        assert_eq!(fix_header(":: A [hub x] {\"size\": \"1\"}"), None);
+    }
+}
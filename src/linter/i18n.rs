@@ -0,0 +1,220 @@
+//! Lints for segments of a story that a translation catalog does not
+//! translate, when building a translation with `tweec i18n build`
+//!
+//! See [`i18n`] for how the story is split into segments
+//!
+//! [`i18n`]: ../../i18n/index.html
+
+use crate::i18n::{self, Catalog, Lookup};
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::macros;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Command;
+use crate::Config;
+use crate::TranslateConfig;
+use tweep::Story;
+
+/// Name of the lint for segments with no translation
+pub const MISSING: &str = "MissingTranslation";
+
+/// Name of the lint for segments whose translation is marked fuzzy
+pub const FUZZY: &str = "FuzzyTranslation";
+
+/// Gets the translation being built, if any
+fn translation(config: &Config) -> Option<&TranslateConfig> {
+    match &config.command {
+        Command::Translate(translate) => Some(translate),
+        _ => None,
+    }
+}
+
+/// Shortens a segment for use in a message
+fn excerpt(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    let mut excerpt: String = line.chars().take(40).collect();
+    if excerpt.len() < text.len() {
+        excerpt.push('\u{2026}');
+    }
+    excerpt
+}
+
+/// Checks the given story for segments that the catalog for `lang` leaves
+/// untranslated, returning warnings for both [`MissingTranslation`] and
+/// [`FuzzyTranslation`]
+///
+/// [`MissingTranslation`]: struct.MissingTranslation.html
+/// [`FuzzyTranslation`]: struct.FuzzyTranslation.html
+pub fn check(story: &Story, catalog: &Catalog, lang: &str) -> Vec<LintWarning> {
+    let warning = |lookup: Lookup, context: &str, text: &str| {
+        let (name, message, note) = match lookup {
+            Lookup::Translated(_) => return None,
+            Lookup::Missing => (
+                MISSING,
+                format!("No {} translation of \"{}\"", lang, excerpt(text)),
+                format!(
+                    "Add an entry with msgctxt {:?} to the catalog; the text is left as written",
+                    context
+                ),
+            ),
+            Lookup::Fuzzy => (
+                FUZZY,
+                format!(
+                    "The {} translation of \"{}\" is marked fuzzy",
+                    lang,
+                    excerpt(text)
+                ),
+                "Review it and remove the fuzzy flag to use it; the text is left as written"
+                    .to_string(),
+            ),
+        };
+        Some((name, message, note))
+    };
+
+    let mut warnings = Vec::new();
+    if let Some(title) = &story.title {
+        let title = title.trim();
+        if let Some((name, message, note)) = warning(
+            catalog.lookup(i18n::TITLE_CONTEXT, title),
+            "StoryTitle",
+            title,
+        ) {
+            warnings.push(LintWarning::new(name, message, None).with_note(note));
+        }
+    }
+
+    let headers = utils::passage_headers(story);
+    let mut names: Vec<&String> = story.passages.keys().collect();
+    names.sort();
+    for name in names {
+        let passage = &story.passages[name];
+        if i18n::is_skipped(&passage.header.tags) {
+            continue;
+        }
+        let content = utils::get_content(passage);
+        for range in i18n::segments(content) {
+            let text = &content[range.clone()];
+            if let Some((lint, message, note)) = warning(catalog.lookup(name, text), name, text) {
+                let context = headers
+                    .get(name)
+                    .map(|header| macros::content_context(header, content, range));
+                warnings.push(LintWarning::new(lint, message, context).with_note(note));
+            }
+        }
+    }
+    warnings
+}
+
+/// Checks the story against the catalog of the translation being built,
+/// keeping the warnings of one lint
+///
+/// A catalog that can't be read gives no warnings here, as the build reports
+/// it as an error
+fn check_config(story: &Story, config: &Config, name: &str) -> Vec<LintWarning> {
+    let translate = match translation(config) {
+        Some(translate) => translate,
+        None => return Vec::new(),
+    };
+    let catalog = match Catalog::read(&translate.catalog) {
+        Ok(catalog) => catalog,
+        Err(_) => return Vec::new(),
+    };
+    check(story, &catalog, &translate.lang)
+        .into_iter()
+        .filter(|warning| warning.name == name)
+        .collect()
+}
+
+/// Warns about segments of the story that the catalog has no translation
+/// for
+///
+/// Only enabled when building a translation
+pub struct MissingTranslation;
+
+impl Lint for MissingTranslation {
+    fn name(&self) -> &'static str {
+        MISSING
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        check_config(story, config, MISSING)
+    }
+
+    fn enabled(&self, config: &Config) -> bool {
+        translation(config).is_some()
+    }
+}
+
+/// Warns about segments of the story whose translation in the catalog is
+/// marked fuzzy, and so is not used
+///
+/// Only enabled when building a translation
+pub struct FuzzyTranslation;
+
+impl Lint for FuzzyTranslation {
+    fn name(&self) -> &'static str {
+        FUZZY
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        check_config(story, config, FUZZY)
+    }
+
+    fn enabled(&self, config: &Config) -> bool {
+        translation(config).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn finds_missing_and_fuzzy_translations() {
+        let catalog = Catalog::parse(
+            r#"msgctxt "StoryTitle"
+msgid "The Cave"
+msgstr "La grotte"
+
+msgctxt "Start"
+msgid "You stand at the mouth of a cave."
+msgstr "Vous êtes à l'entrée d'une grotte."
+
+#, fuzzy
+msgctxt "Start"
+msgid "[[Go in->Inside]]"
+msgstr "[[Entrer->Inside]]"
+"#,
+        )
+        .unwrap();
+        let input = ":: StoryTitle\nThe Cave\n\n:: Start\nYou stand at the mouth of a cave.\n\n\
+                     [[Go in->Inside]]\n\n:: Inside\nDark.\n\n:: Style [stylesheet]\nbody {}\n";
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let warnings: Vec<(&str, String)> = check(&story, &catalog, "fr")
+            .into_iter()
+            .map(|warning| (warning.name, warning.message))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (MISSING, "No fr translation of \"Dark.\"".to_string()),
+                (
+                    FUZZY,
+                    "The fr translation of \"[[Go in->Inside]]\" is marked fuzzy".to_string()
+                ),
+            ]
+        );
+
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        assert!(!MissingTranslation.enabled(&config));
+        assert!(!FuzzyTranslation.enabled(&config));
+        config.command = Command::Translate(TranslateConfig {
+            lang: "fr".to_string(),
+            catalog: PathBuf::from("fr.po"),
+        });
+        assert!(MissingTranslation.enabled(&config));
+        assert!(FuzzyTranslation.enabled(&config));
+    }
+}
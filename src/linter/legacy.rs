@@ -0,0 +1,193 @@
+//! Lint for Twee 1 and Twee2 conventions that a story should migrate from
+//!
+//! These are translated when the story is read, as described in [`legacy`],
+//! so the story builds, but each is warned about so that it can be rewritten
+//! as Twee 3
+//!
+//! [`legacy`]: ../../legacy/index.html
+
+use crate::issue::LintWarning;
+use crate::legacy;
+use crate::linter::graph::LinkGraph;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use serde_json::json;
+use tweep::{PassageHeader, Position, Story};
+
+/// Name of the lint, used for allowing or denying it
+pub const NAME: &str = "LegacyTwee";
+
+/// The tag Twine 1 gives passages holding an image
+const IMAGE_TAG: &str = "Twine.image";
+
+/// Warns about `StorySettings` and `StoryIncludes` passages in stories without
+/// `StoryData`, Twee2 passage positions, and Twine 1 image passages
+pub struct LegacyTwee;
+
+impl Lint for LegacyTwee {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, _: &Config) -> Vec<LintWarning> {
+        check(story)
+    }
+}
+
+/// Gets the notes for a `StorySettings` passage with the given contents
+fn settings_notes(story: &Story, content: &str) -> Vec<String> {
+    let mut notes = vec![match &story.data {
+        Some(data) => {
+            let story_data = json!({ "ifid": data.ifid, "start": "Start" });
+            format!(
+                "Its IFID was used for the story. Replace it with a StoryData passage: {}",
+                story_data
+            )
+        }
+        None => "Replace it with a StoryData passage".to_string(),
+    }];
+    let ignored: Vec<String> = legacy::settings(content)
+        .into_iter()
+        .map(|(key, _)| key)
+        .filter(|key| key != "ifid")
+        .collect();
+    if !ignored.is_empty() {
+        notes.push(format!(
+            "These settings have no effect: {}",
+            ignored.join(", ")
+        ));
+    }
+    notes
+}
+
+/// Checks the given story for Twee 1 and Twee2 conventions. See
+/// [`LegacyTwee`]
+///
+/// [`LegacyTwee`]: struct.LegacyTwee.html
+pub fn check(story: &Story) -> Vec<LintWarning> {
+    // The special passages are left out of the story, so they are found in
+    // the source
+    let has_story_data = utils::contexts(&story.code_map)
+        .any(|context| utils::story_data_block(context.get_contents()).is_some());
+    let mut warnings = Vec::new();
+    for context in utils::contexts(&story.code_map) {
+        let lines: Vec<&str> = context.get_contents().split('\n').collect();
+        for (row, line) in lines.iter().enumerate() {
+            if !line.starts_with("::") {
+                continue;
+            }
+            let header =
+                context.subcontext(Position::abs(row + 1, 1)..=Position::abs(row + 1, line.len()));
+            let parsed = match PassageHeader::parse(header.clone()).take().0 {
+                Ok(parsed) => parsed,
+                Err(_) => continue,
+            };
+
+            if let Some((range, position)) = legacy::position(line) {
+                let metadata = json!({ "position": position });
+                warnings.push(
+                    LintWarning::new(
+                        NAME,
+                        "Passage position is written in Twee2 syntax",
+                        Some(header.subcontext(
+                            Position::rel(1, range.start + 1)..=Position::rel(1, range.end),
+                        )),
+                    )
+                    .with_note(format!("Write it as metadata instead: {}", metadata)),
+                );
+            }
+
+            if parsed.tags.iter().any(|tag| tag == IMAGE_TAG) {
+                warnings.push(
+                    LintWarning::new(
+                        NAME,
+                        format!("Passage {} is a Twine 1 image passage", parsed.name),
+                        Some(header.clone()),
+                    )
+                    .with_note(
+                        "Twine 2 story formats can't show it; save the image to a file and refer \
+                         to it by path",
+                    ),
+                );
+            }
+
+            if has_story_data {
+                continue;
+            }
+            let end = (row + 1..lines.len())
+                .find(|row| lines[*row].starts_with("::"))
+                .unwrap_or(lines.len());
+            let content = lines[row + 1..end].join("\n");
+            if parsed.name == legacy::SETTINGS {
+                let warning = LintWarning::new(
+                    NAME,
+                    "StorySettings is a Twine 1 passage",
+                    Some(header.clone()),
+                );
+                warnings.push(
+                    settings_notes(story, &content)
+                        .into_iter()
+                        .fold(warning, LintWarning::with_note),
+                );
+            } else if parsed.name == legacy::INCLUDES {
+                warnings.push(
+                    LintWarning::new(
+                        NAME,
+                        "StoryIncludes is a Twee2 passage",
+                        Some(header.clone()),
+                    )
+                    .with_note(
+                        "Its files were read as part of the story; give them as inputs instead",
+                    ),
+                );
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_twee2_positions() {
+        let dir = std::env::temp_dir().join(format!("tweec-legacy-lint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("story.tw");
+        std::fs::write(
+            &path,
+            ":: StoryIncludes\nmore.tw\n\n:: Start [intro] <100,200>\nHello\n\n:: Next\nBye\n",
+        )
+        .unwrap();
+        let story = legacy::translate(Story::from_path(&path))
+            .take()
+            .0
+            .ok()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let warnings = check(&story);
+        let messages: Vec<&str> = warnings
+            .iter()
+            .map(|warning| warning.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "StoryIncludes is a Twee2 passage",
+                "Passage position is written in Twee2 syntax",
+            ]
+        );
+        let position = &warnings[1];
+        assert_eq!(
+            position.context.as_ref().unwrap().get_contents(),
+            "<100,200>"
+        );
+        assert_eq!(
+            position.notes,
+            vec![r#"Write it as metadata instead: {"position":"100,200"}"#]
+        );
+    }
+}
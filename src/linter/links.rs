@@ -0,0 +1,345 @@
+//! The link syntaxes tweec recognizes when building the link graph and
+//! checking for dead links
+//!
+//! Which syntaxes count as real links differs between story formats and
+//! projects, so each can be turned on or off in the config file. The bracket
+//! syntaxes are found by `tweep` while parsing; tweec filters its links by
+//! syntax and scans passages for the format-specific macros itself.
+
+use crate::utils;
+use std::collections::HashMap;
+use std::ops::Range;
+use tweep::{FullContext, Position, Story, TwineLink, Warning, WarningKind};
+
+/// A way of writing a link to another passage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkSyntax {
+    /// `[[Passage]]`
+    Simple,
+
+    /// `[[Text|Passage]]`
+    Pipe,
+
+    /// `[[Text->Passage]]`
+    RightArrow,
+
+    /// `[[Passage<-Text]]`
+    LeftArrow,
+
+    /// SugarCube's `<<link "Text" "Passage">>`
+    LinkMacro,
+
+    /// Harlowe's `(link-goto: "Text", "Passage")`
+    LinkGoto,
+}
+
+impl LinkSyntax {
+    /// Every link syntax
+    pub const ALL: &'static [LinkSyntax] = &[
+        LinkSyntax::Simple,
+        LinkSyntax::Pipe,
+        LinkSyntax::RightArrow,
+        LinkSyntax::LeftArrow,
+        LinkSyntax::LinkMacro,
+        LinkSyntax::LinkGoto,
+    ];
+
+    /// The syntaxes recognized when not configured: the Twine 2 bracket links
+    pub const DEFAULT: &'static [LinkSyntax] = &[
+        LinkSyntax::Simple,
+        LinkSyntax::Pipe,
+        LinkSyntax::RightArrow,
+        LinkSyntax::LeftArrow,
+    ];
+
+    /// Gets the name of the syntax used in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkSyntax::Simple => "simple",
+            LinkSyntax::Pipe => "pipe",
+            LinkSyntax::RightArrow => "right-arrow",
+            LinkSyntax::LeftArrow => "left-arrow",
+            LinkSyntax::LinkMacro => "link-macro",
+            LinkSyntax::LinkGoto => "link-goto",
+        }
+    }
+
+    /// Finds the syntax with the given config file name
+    pub fn from_name(name: &str) -> Option<Self> {
+        LinkSyntax::ALL
+            .iter()
+            .copied()
+            .find(|syntax| syntax.name() == name)
+    }
+
+    /// Gets the syntax of a `[[...]]` link, deciding between them the same
+    /// way `tweep` does when finding the link's target
+    pub fn of_bracket_link(link: &str) -> Option<Self> {
+        let contents = link.strip_prefix("[[")?.strip_suffix("]]")?;
+        Some(if contents.contains('|') {
+            LinkSyntax::Pipe
+        } else if contents.contains("<-") {
+            LinkSyntax::LeftArrow
+        } else if contents.contains("->") {
+            LinkSyntax::RightArrow
+        } else {
+            LinkSyntax::Simple
+        })
+    }
+}
+
+/// Reads a quoted string at the start of `text`, returning its contents and
+/// the rest of the text
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let mut value = String::new();
+    let mut chars = text[1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+            c if c == quote => return Some((value, &text[i + 2..])),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Reads up to two quoted arguments, separated by whitespace or a comma,
+/// from the start of `text`
+fn quoted_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut rest = text.trim_start();
+    while args.len() < 2 {
+        match quoted(rest) {
+            Some((arg, after)) => {
+                args.push(arg);
+                rest = after.trim_start().trim_start_matches(',').trim_start();
+            }
+            None => break,
+        }
+    }
+    args
+}
+
+/// Gets the text a link shows, which for `[[Passage]]` is the passage name and
+/// for macro links is their first argument
+pub fn link_text(link: &TwineLink) -> String {
+    let written = link.context.get_contents();
+    if let Some(syntax) = LinkSyntax::of_bracket_link(written) {
+        let contents = &written[2..written.len() - 2];
+        let text = match syntax {
+            LinkSyntax::Pipe => contents.split('|').next(),
+            LinkSyntax::LeftArrow => contents.split_once("<-").map(|(_, text)| text),
+            LinkSyntax::RightArrow => contents.rsplit_once("->").map(|(text, _)| text),
+            _ => None,
+        };
+        return text.unwrap_or(contents).trim().to_string();
+    }
+    written
+        .find(['"', '\''])
+        .and_then(|start| quoted_args(&written[start..]).into_iter().next())
+        .unwrap_or_else(|| link.target.clone())
+}
+
+/// Finds the macro links in a line of passage text, returning the byte range
+/// of each macro along with its target
+fn macro_links(line: &str, syntaxes: &[LinkSyntax]) -> Vec<(Range<usize>, String)> {
+    let mut links = Vec::new();
+    if syntaxes.contains(&LinkSyntax::LinkMacro) {
+        for (start, _) in line.match_indices("<<link") {
+            let after = &line[start + "<<link".len()..];
+            if !after.starts_with(char::is_whitespace) {
+                continue;
+            }
+            let end = match after.find(">>") {
+                Some(end) => start + "<<link".len() + end + 2,
+                None => continue,
+            };
+            // <<link "Text" "Passage">>; a link with only text runs code
+            // instead of going to a passage
+            if let [_, target] = quoted_args(after).as_slice() {
+                links.push((start..end, target.clone()));
+            }
+        }
+    }
+    if syntaxes.contains(&LinkSyntax::LinkGoto) {
+        let lower = line.to_lowercase();
+        for prefix in &["(link-goto:", "(linkgoto:"] {
+            for (start, _) in lower.match_indices(prefix) {
+                let after = &line[start + prefix.len()..];
+                let end = match after.find(')') {
+                    Some(end) => start + prefix.len() + end + 1,
+                    None => continue,
+                };
+                // (link-goto: "Passage") or (link-goto: "Text", "Passage")
+                if let Some(target) = quoted_args(after).pop() {
+                    links.push((start..end, target));
+                }
+            }
+        }
+    }
+    links.sort_by_key(|(range, _)| range.start);
+    links
+}
+
+/// Gets the links out of each passage of the story, by passage name, using
+/// only the given syntaxes
+///
+/// Links appear in the order `tweep` found them, followed by any macro links
+/// in the order they appear
+pub fn story_links<'a>(
+    story: &'a Story,
+    syntaxes: &[LinkSyntax],
+) -> HashMap<&'a str, Vec<TwineLink>> {
+    let scan_macros = syntaxes
+        .iter()
+        .any(|syntax| matches!(syntax, LinkSyntax::LinkMacro | LinkSyntax::LinkGoto));
+    let headers = if scan_macros {
+        utils::passage_headers(story)
+    } else {
+        HashMap::new()
+    };
+
+    story
+        .passages
+        .iter()
+        .map(|(name, passage)| {
+            let mut links: Vec<TwineLink> = passage
+                .content
+                .get_links()
+                .iter()
+                .filter(|link| {
+                    LinkSyntax::of_bracket_link(link.context.get_contents())
+                        .is_none_or(|syntax| syntaxes.contains(&syntax))
+                })
+                // TwineLink isn't Clone, but its parts are
+                .map(|link| TwineLink::new(link.target.clone(), link.context.clone()))
+                .collect();
+            if let Some(header) = headers.get(name) {
+                links.extend(passage_macro_links(
+                    header,
+                    &passage.content.content,
+                    syntaxes,
+                ));
+            }
+            (name.as_str(), links)
+        })
+        .collect()
+}
+
+/// Finds the macro links in a passage's content, which starts on the line
+/// after its header
+fn passage_macro_links(
+    header: &FullContext,
+    content: &str,
+    syntaxes: &[LinkSyntax],
+) -> Vec<TwineLink> {
+    let header_line = header.get_start_position().line;
+    content
+        .split('\n')
+        .enumerate()
+        .flat_map(|(row, line)| {
+            let line_number = header_line + 1 + row;
+            macro_links(line, syntaxes)
+                .into_iter()
+                .map(move |(range, target)| {
+                    let context = header.subcontext(
+                        Position::abs(line_number, range.start + 1)
+                            ..=Position::abs(line_number, range.end),
+                    );
+                    TwineLink::new(target, context)
+                })
+        })
+        .collect()
+}
+
+/// Brings `tweep`'s dead link warnings in line with the configured syntaxes
+///
+/// Warnings for links written in a syntax that is turned off are removed, and
+/// warnings are added for macro links to passages that don't exist
+pub fn adjust_dead_links(story: &Story, warnings: &mut Vec<Warning>, syntaxes: &[LinkSyntax]) {
+    warnings.retain(|warning| {
+        let link = match (&warning.kind, &warning.context) {
+            (WarningKind::DeadLink(_), Some(context)) => context.get_contents(),
+            _ => return true,
+        };
+        match LinkSyntax::of_bracket_link(link) {
+            Some(syntax) => syntaxes.contains(&syntax),
+            None => true,
+        }
+    });
+
+    if !syntaxes
+        .iter()
+        .any(|syntax| matches!(syntax, LinkSyntax::LinkMacro | LinkSyntax::LinkGoto))
+    {
+        return;
+    }
+    let macro_syntaxes: Vec<LinkSyntax> = syntaxes
+        .iter()
+        .copied()
+        .filter(|syntax| matches!(syntax, LinkSyntax::LinkMacro | LinkSyntax::LinkGoto))
+        .collect();
+    let headers = utils::passage_headers(story);
+    let mut names: Vec<&String> = story.passages.keys().collect();
+    names.sort();
+    for name in names {
+        let header = match headers.get(name) {
+            Some(header) => header,
+            None => continue,
+        };
+        let content = &story.passages[name].content.content;
+        for link in passage_macro_links(header, content, &macro_syntaxes) {
+            if !story.passages.contains_key(link.target.trim()) {
+                warnings.push(Warning::new(
+                    WarningKind::DeadLink(link.target.clone()),
+                    Some(link.context),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_bracket_links() {
+        let syntax = LinkSyntax::of_bracket_link;
+        assert_eq!(syntax("[[A]]"), Some(LinkSyntax::Simple));
+        assert_eq!(syntax("[[a->b|c]]"), Some(LinkSyntax::Pipe));
+        assert_eq!(syntax("[[A<-a]]"), Some(LinkSyntax::LeftArrow));
+        assert_eq!(syntax("[[a->A]]"), Some(LinkSyntax::RightArrow));
+        assert_eq!(syntax("<<link>>"), None);
+    }
+
+    #[test]
+    fn finds_macro_links() {
+        let line = r#"<<link "Go" 'The \'End\''>><</link>> (link-goto: "Back", "Start") (Link-Goto: "Hub") <<link "Run code">>"#;
+        let targets = |syntaxes| -> Vec<String> {
+            macro_links(line, syntaxes)
+                .into_iter()
+                .map(|(_, target)| target)
+                .collect()
+        };
+        assert_eq!(targets(LinkSyntax::ALL), vec!["The 'End'", "Start", "Hub"]);
+        assert_eq!(targets(&[LinkSyntax::LinkGoto]), vec!["Start", "Hub"]);
+        assert!(targets(LinkSyntax::DEFAULT).is_empty());
+        assert_eq!(macro_links(line, &[LinkSyntax::LinkMacro])[0].0, 0..27);
+    }
+
+    #[test]
+    fn gets_link_text() {
+        let text = |written: &str| {
+            let context = FullContext::from(None, written.to_string());
+            link_text(&TwineLink::new("Target".to_string(), context))
+        };
+        assert_eq!(text("[[Target]]"), "Target");
+        assert_eq!(text("[[Go on|Target]]"), "Go on");
+        assert_eq!(text("[[Target<-Go on]]"), "Go on");
+        assert_eq!(text("[[Go -> on->Target]]"), "Go -> on");
+        assert_eq!(text(r#"<<link "Go on" "Target">>"#), "Go on");
+        assert_eq!(text(r#"(link-goto: "Target")"#), "Target");
+    }
+}
@@ -0,0 +1,78 @@
+//! Pieces shared by the story format macro lints
+//!
+//! Each story format with macro lints scans passage content its own way, but
+//! the lints share rule names, so allowing or denying a rule such as
+//! `UnknownMacro` works the same whichever story format is in use
+
+use std::ops::Range;
+use tweep::{FullContext, Position};
+
+/// Name of the lint for container macros that are not closed, or closing tags
+/// with nothing to close
+pub const UNCLOSED_MACRO: &str = "UnclosedMacro";
+
+/// Name of the lint for macros the story format does not provide
+pub const UNKNOWN_MACRO: &str = "UnknownMacro";
+
+/// Name of the lint for macros whose syntax is malformed, such as unbalanced
+/// brackets or unterminated strings
+pub const MACRO_SYNTAX: &str = "MacroSyntax";
+
+/// Gets the context of a byte range of a passage's content, which starts on
+/// the line after its header
+pub fn content_context(header: &FullContext, content: &str, range: Range<usize>) -> FullContext {
+    let header_line = header.get_start_position().line;
+    let position = |offset: usize| {
+        let before = &content[..offset];
+        let row = before.matches('\n').count();
+        let column = offset - before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        (header_line + 1 + row, column)
+    };
+    let (start_line, start_column) = position(range.start);
+    let (end_line, end_column) = position(range.end.max(range.start + 1) - 1);
+    header.subcontext(
+        Position::abs(start_line, start_column + 1)..=Position::abs(end_line, end_column + 1),
+    )
+}
+
+/// Gets the quoted strings at the start of `text`, which is either a single
+/// string or an array of strings, as in `Macro.add("name", ...)` or
+/// `Macro.add(["a", "b"], ...)`
+pub fn leading_strings(text: &str) -> Vec<String> {
+    let text = text.trim_start();
+    let (text, single) = match text.strip_prefix('[') {
+        Some(rest) => (rest, false),
+        None => (text, true),
+    };
+    let mut strings = Vec::new();
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        let quote = match rest.chars().next() {
+            Some(quote @ ('"' | '\'' | '`')) => quote,
+            _ => break,
+        };
+        let end = match rest[1..].find(quote) {
+            Some(end) => end + 1,
+            None => break,
+        };
+        strings.push(rest[1..end].to_string());
+        rest = &rest[end + 1..];
+        if single {
+            break;
+        }
+    }
+    strings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_leading_strings() {
+        assert_eq!(leading_strings(" \"a\", function"), vec!["a"]);
+        assert_eq!(leading_strings("['a', \"b\"], {"), vec!["a", "b"]);
+        assert!(leading_strings("name").is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+//! Lint for passages that offer too many choices
+
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use std::collections::HashSet;
+use tweep::Story;
+
+/// Name of the lint, used for allowing or denying it
+pub const NAME: &str = "TooManyLinks";
+
+/// Warns about any passage with links to more passages than the configured
+/// maximum
+///
+/// Links are found in the configured link syntaxes, as for the other link
+/// lints, and several links to the same passage count once
+///
+/// Passages tagged with a tag that has its own limit use that limit instead;
+/// if several such tags are present, the highest limit applies
+pub struct TooManyLinks;
+
+impl Lint for TooManyLinks {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn check(&self, story: &Story, graph: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        check(story, graph, config)
+    }
+
+    fn enabled(&self, config: &Config) -> bool {
+        config.max_links.is_some() || !config.tag_max_links.is_empty()
+    }
+}
+
+/// Checks the given story for passages with too many links. See
+/// [`TooManyLinks`]
+///
+/// [`TooManyLinks`]: struct.TooManyLinks.html
+pub fn check(story: &Story, graph: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    if config.max_links.is_none() && config.tag_max_links.is_empty() {
+        return warnings;
+    }
+
+    let headers = utils::passage_headers(story);
+    let mut names: Vec<&String> = story.passages.keys().collect();
+    names.sort();
+    for name in names {
+        let passage = &story.passages[name];
+        let tag_limit = passage
+            .header
+            .tags
+            .iter()
+            .filter_map(|tag| config.tag_max_links.get(tag).map(|max| (tag, *max)))
+            .max_by_key(|(_, max)| *max);
+        let (limit, reason) = match (tag_limit, config.max_links) {
+            (Some((tag, max)), _) => (max, format!(" for passages tagged {}", tag)),
+            (None, Some(max)) => (max, String::new()),
+            (None, None) => continue,
+        };
+
+        // Dead links are still choices, so they count too
+        let targets: HashSet<&str> = graph
+            .outgoing(name)
+            .iter()
+            .map(|edge| edge.target)
+            .chain(graph.dead_links(name).iter().map(|link| link.target.trim()))
+            .collect();
+        let count = targets.len();
+        if count > limit {
+            warnings.push(
+                LintWarning::new(
+                    NAME,
+                    format!(
+                        "Passage {} has {} link{}",
+                        name,
+                        count,
+                        if count == 1 { "" } else { "s" }
+                    ),
+                    headers.get(name).cloned(),
+                )
+                .with_note(format!("The maximum is {}{}", limit, reason)),
+            );
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::links::LinkSyntax;
+
+    fn messages(story: &Story, config: &Config) -> Vec<String> {
+        let graph = LinkGraph::new(story, &config.links);
+        check(story, &graph, config)
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+    }
+
+    #[test]
+    fn uses_highest_tag_limit() {
+        let input = r#":: Start
+[[A]] [[B]] [[C]]
+
+:: Hub [hub]
+[[A]] [[B]] [[C]]
+
+:: Menu [hub menu]
+[[A]] [[B]] [[C]] [[D]]
+
+:: A
+:: B
+:: C
+:: D
+"#;
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.max_links = Some(2);
+        config.tag_max_links.insert("hub".to_string(), 3);
+        config.tag_max_links.insert("menu".to_string(), 4);
+        // Menu's highest limit is 4, so its 4 links are allowed
+        assert_eq!(messages(&story, &config), vec!["Passage Start has 3 links"]);
+
+        config.tag_max_links.insert("hub".to_string(), 1);
+        config.tag_max_links.insert("menu".to_string(), 3);
+        assert_eq!(
+            messages(&story, &config),
+            vec![
+                "Passage Hub has 3 links",
+                "Passage Menu has 4 links",
+                "Passage Start has 3 links",
+            ]
+        );
+    }
+
+    #[test]
+    fn needs_a_limit() {
+        let input = ":: Start\n[[A]] [[B]] [[C]] [[D]] [[E]] [[F]] [[G]]\n\n:: Hub [hub]\n[[A]]\n";
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        assert!(!TooManyLinks.enabled(&config));
+        assert!(messages(&story, &config).is_empty());
+
+        // Only passages with a limited tag are checked
+        config.tag_max_links.insert("hub".to_string(), 0);
+        assert!(TooManyLinks.enabled(&config));
+        assert_eq!(messages(&story, &config), vec!["Passage Hub has 1 link"]);
+    }
+
+    #[test]
+    fn counts_distinct_targets_in_configured_syntaxes() {
+        let input = r#":: Start
+[[A]] [[Go to A->A]] <<link "B" "B">><</link>> [[Nowhere]]
+
+:: A
+:: B
+"#;
+        // Macro links are found from passage headers, so it is read from a file
+        let path = std::env::temp_dir().join(format!("tweec-links-{}.twee", std::process::id()));
+        std::fs::write(&path, input).unwrap();
+        let story = Story::from_path(&path).take().0.ok().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.max_links = Some(1);
+        // The repeated link to A counts once, and <<link>> isn't configured
+        assert_eq!(messages(&story, &config), vec!["Passage Start has 2 links"]);
+
+        config.links.push(LinkSyntax::LinkMacro);
+        assert_eq!(messages(&story, &config), vec!["Passage Start has 3 links"]);
+    }
+}
@@ -0,0 +1,404 @@
+//! Lint for misspelled words in the prose of passages
+//!
+//! Words are checked against the word lists given as `dictionaries` in the
+//! config, along with the story's own `dictionary` of names and invented
+//! words. Only the text a reader sees is checked: macros, HTML tags, comments,
+//! variables, and link targets are skipped, as are the vars sections and
+//! modifiers of Chapbook passages.
+
+use crate::issue::{Applicability, LintWarning};
+use crate::linter::graph::LinkGraph;
+use crate::linter::macros;
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use color_eyre::Result;
+use eyre::WrapErr;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+use tweep::Story;
+
+/// Name of the lint, used for allowing or denying it
+pub const NAME: &str = "Spelling";
+
+/// Text that is not prose, by its opening and closing delimiters
+const SKIPPED: &[(&str, &str)] = &[
+    ("/*", "*/"),
+    ("<!--", "-->"),
+    ("/%", "%/"),
+    ("<<", ">>"),
+    ("\"\"\"", "\"\"\""),
+    ("`", "`"),
+];
+
+/// Reads a word list, with one word per line
+///
+/// Hunspell `.dic` files are read as word lists, ignoring the word count on
+/// their first line and the affix flags after each word
+pub fn read_words(path: &Path) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read dictionary {:?}", path))?;
+    Ok(contents
+        .lines()
+        .map(|line| line.split('/').next().unwrap_or_default().trim())
+        .filter(|word| !word.is_empty() && !word.starts_with('#'))
+        .filter(|word| !word.chars().all(|c| c.is_ascii_digit()))
+        .map(|word| word.replace('’', "'"))
+        .collect())
+}
+
+/// Finds the end of a Harlowe macro call starting at `start`, skipping nested
+/// parentheses and strings
+fn call_end(content: &str, start: usize) -> usize {
+    let bytes = content.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'') if depth > 0 => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    content.len()
+}
+
+/// Finds the byte ranges of passage content that are not prose
+fn skipped(content: &str, chapbook: bool) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    if chapbook {
+        // The vars section, which ends at a line that is just --
+        let mut offset = 0;
+        for line in content.split('\n') {
+            offset += line.len() + 1;
+            if line.trim_end() == "--" {
+                ranges.push(0..offset.min(content.len()));
+                start = offset.min(content.len());
+                break;
+            }
+        }
+    }
+
+    let mut i = start;
+    let mut line_start = true;
+    while i < content.len() {
+        let rest = &content[i..];
+        let skip =
+            if let Some((open, close)) = SKIPPED.iter().find(|(open, _)| rest.starts_with(open)) {
+                rest[open.len()..]
+                    .find(close)
+                    .map(|end| open.len() + end + close.len())
+                    .unwrap_or(rest.len())
+            } else if let Some(inner) = rest.strip_prefix("[[") {
+                // Only the text of a link is prose
+                let end = inner.find("]]").unwrap_or(inner.len());
+                let link = &inner[..inner[..end].find("][").unwrap_or(end)];
+                let text = if let Some(bar) = link.rfind('|') {
+                    0..bar
+                } else if let Some(arrow) = link.rfind("->") {
+                    0..arrow
+                } else if let Some(arrow) = link.find("<-") {
+                    arrow + 2..link.len()
+                } else {
+                    0..0
+                };
+                let link_start = i + 2;
+                ranges.push(i..link_start + text.start);
+                ranges.push(link_start + text.end..(link_start + end + 2).min(content.len()));
+                i = (link_start + end + 2).min(content.len());
+                line_start = false;
+                continue;
+            } else if chapbook && line_start && rest.starts_with('[') {
+                // A modifier, which is a line that is just text in brackets
+                let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+                if line.trim_end().ends_with(']') {
+                    line.len()
+                } else {
+                    0
+                }
+            } else if chapbook && rest.starts_with('{') {
+                rest.find('}').map(|end| end + 1).unwrap_or(rest.len())
+            } else if rest.starts_with('<')
+                && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/')
+            {
+                // An HTML tag
+                rest.find('>').map(|end| end + 1).unwrap_or(rest.len())
+            } else if rest.starts_with('(')
+                && rest[1..]
+                    .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+                    .is_some_and(|end| end > 0 && rest[1 + end..].starts_with(':'))
+            {
+                // A Harlowe macro call
+                call_end(content, i) - i
+            } else if rest.starts_with(|c: char| !c.is_whitespace())
+                && content[..i]
+                    .chars()
+                    .next_back()
+                    .is_none_or(char::is_whitespace)
+            {
+                // A URL, which is skipped up to the next whitespace
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                if rest[..end].contains("://") || rest[..end].starts_with("www.") {
+                    end
+                } else {
+                    0
+                }
+            } else {
+                0
+            };
+        if skip > 0 {
+            ranges.push(i..i + skip);
+            i += skip;
+            line_start = content[..i].ends_with('\n');
+            continue;
+        }
+        let c = rest.chars().next().unwrap_or_default();
+        line_start = c == '\n';
+        i += c.len_utf8();
+    }
+    ranges
+}
+
+/// Finds the words in the prose of passage content, with their byte ranges
+pub fn words(content: &str, chapbook: bool) -> Vec<(String, Range<usize>)> {
+    let skipped = skipped(content, chapbook);
+    let is_skipped = |range: &Range<usize>| {
+        skipped
+            .iter()
+            .any(|skip| skip.start < range.end && range.start < skip.end)
+    };
+    let is_apostrophe = |c: char| c == '\'' || c == '’';
+
+    let mut words = Vec::new();
+    let mut chars = content.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !c.is_alphabetic() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = chars.peek() {
+            let continues = c.is_alphabetic()
+                || (is_apostrophe(c)
+                    && content[i + c.len_utf8()..].starts_with(char::is_alphabetic));
+            if !continues {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+
+        // Words stuck to digits or code, such as 3rd, $gold, _i, or ?hook,
+        // are not prose
+        let before = content[..start].chars().next_back();
+        let after = content[end..].chars().next();
+        let is_code = |c: Option<char>| {
+            c.is_some_and(|c| c.is_ascii_digit() || matches!(c, '_' | '$' | '?' | '&' | '#' | '@'))
+        };
+        if is_code(before) || is_code(after) || end - start < 2 || is_skipped(&(start..end)) {
+            continue;
+        }
+
+        let word = content[start..end].replace('’', "'");
+        let word = match word.strip_suffix("'s") {
+            Some(word) if !word.is_empty() => word.to_string(),
+            _ => word,
+        };
+        words.push((word, start..end));
+    }
+    words
+}
+
+/// The words a story is checked against
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Creates a dictionary of the given words
+    pub fn new<I: IntoIterator<Item = String>>(words: I) -> Self {
+        Dictionary {
+            words: words.into_iter().collect(),
+        }
+    }
+
+    /// Whether the word is spelled correctly. Words in the dictionary may be
+    /// capitalized or all in capitals in the text, but a word only in the
+    /// dictionary with a capital, such as a name, must have one in the text
+    pub fn contains(&self, word: &str) -> bool {
+        if self.words.contains(word) {
+            return true;
+        }
+        let lower = word.to_lowercase();
+        let mut chars = lower.chars();
+        let title: String = chars
+            .next()
+            .map(|c| c.to_uppercase().chain(chars).collect())
+            .unwrap_or_default();
+        self.words.contains(&lower) || (word == word.to_uppercase() && self.words.contains(&title))
+    }
+
+    /// Finds the closest word in the dictionary to a misspelled one, if any
+    /// is close enough to suggest
+    pub fn suggest(&self, word: &str) -> Option<&str> {
+        let lower = word.to_lowercase();
+        let length = lower.chars().count();
+        let first = lower.chars().next();
+        self.words
+            .iter()
+            .filter(|known| {
+                known.to_lowercase().chars().next() == first
+                    && known.chars().count().abs_diff(length) <= 1
+            })
+            .map(|known| {
+                (
+                    strsim::damerau_levenshtein(&lower, &known.to_lowercase()),
+                    known,
+                )
+            })
+            .filter(|(distance, _)| *distance <= 2)
+            .min()
+            .map(|(_, known)| known.as_str())
+    }
+}
+
+/// Checks the prose of every passage against the given dictionary
+pub fn check(story: &Story, config: &Config, dictionary: &Dictionary) -> Vec<LintWarning> {
+    let headers = utils::passage_headers(story);
+    let chapbook = config
+        .format
+        .as_deref()
+        .is_some_and(|format| format.starts_with("chapbook"));
+    let mut names: Vec<&String> = story.passages.keys().collect();
+    names.sort();
+
+    let mut suggestions: HashMap<String, Option<&str>> = HashMap::new();
+    let mut warnings = Vec::new();
+    for name in names {
+        let passage = &story.passages[name];
+        if passage
+            .header
+            .tags
+            .iter()
+            .any(|tag| tag == "script" || tag == "stylesheet")
+        {
+            continue;
+        }
+        let content = utils::get_content(passage);
+        let header = headers.get(name);
+        for (word, range) in words(content, chapbook) {
+            if dictionary.contains(&word) {
+                continue;
+            }
+            let context = header.map(|header| macros::content_context(header, content, range));
+            let mut warning = LintWarning::new(
+                NAME,
+                format!("Possible misspelling \"{}\" in passage {}", word, name),
+                context.clone(),
+            );
+            let suggestion = *suggestions
+                .entry(word.clone())
+                .or_insert_with(|| dictionary.suggest(&word));
+            if let Some(suggestion) = suggestion {
+                warning = warning.with_note(format!("Did you mean \"{}\"?", suggestion));
+                if let Some(context) = context {
+                    warning = warning.with_fix(
+                        context,
+                        suggestion,
+                        format!("Replace with \"{}\"", suggestion),
+                        Applicability::MaybeIncorrect,
+                    );
+                }
+            }
+            warnings.push(match &config.dictionary {
+                Some(path) => warning.with_note(format!(
+                    "If it is spelled correctly, add it to the story's dictionary at {:?}",
+                    path
+                )),
+                None => warning.with_note(
+                    "If it is spelled correctly, add it to a dictionary file given in the config",
+                ),
+            });
+        }
+    }
+    warnings
+}
+
+/// Warns about words in passage text that are not in any of the configured
+/// dictionaries
+///
+/// Only enabled when the config gives at least one word list in
+/// `dictionaries`. The story's own `dictionary` is checked too, if it exists
+pub struct Spelling;
+
+impl Lint for Spelling {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn check(&self, story: &Story, _: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        let mut words = Vec::new();
+        let story_dictionary = config.dictionary.iter().filter(|path| path.exists());
+        for path in config.dictionaries.iter().chain(story_dictionary) {
+            match read_words(path) {
+                Ok(read) => words.extend(read),
+                Err(err) => return vec![LintWarning::new(NAME, format!("{:#}", err), None)],
+            }
+        }
+        check(story, config, &Dictionary::new(words))
+    }
+
+    fn enabled(&self, config: &Config) -> bool {
+        !config.dictionaries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn misspelled(content: &str, chapbook: bool) -> Vec<String> {
+        let dictionary = Dictionary::new(
+            [
+                "the", "door", "opens", "Alice", "a", "you", "go", "through", "don't", "it",
+            ]
+            .iter()
+            .map(|word| word.to_string()),
+        );
+        words(content, chapbook)
+            .into_iter()
+            .filter(|(word, _)| !dictionary.contains(word))
+            .map(|(word, _)| word)
+            .collect()
+    }
+
+    #[test]
+    fn checks_prose_only() {
+        assert_eq!(
+            misspelled(
+                "The dorr OPENS. <<set $gold to 3>>Alice's $gold\n\
+                 [[Go through it|Secret Pasage]] /* commnt */ <span class=\"hiden\">alice</span>\n\
+                 (if: $x is 1)[You don't] https://exmaple.com 3rd",
+                false
+            ),
+            vec!["dorr", "alice"]
+        );
+        assert_eq!(
+            misspelled("mispeled: 1\n--\n[if mispeled]\nThe {mispeled} door", true),
+            Vec::<String>::new()
+        );
+    }
+}
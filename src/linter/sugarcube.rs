@@ -0,0 +1,737 @@
+//! Lints for the macros of stories using SugarCube 2
+//!
+//! Passage content is scanned for macros the way SugarCube finds them: a tag
+//! is `<<name arguments>>` or `<</name>>`, and its arguments end at the first
+//! `>>` outside of a string or `[[link]]`. Comments, verbatim text, and the
+//! bodies of `<<script>>` macros are skipped.
+//!
+//! Besides SugarCube's own macros, widgets defined in `widget` passages,
+//! macros added with `Macro.add` in the story's scripts, and macros listed in
+//! the config are known. A macro that isn't one of SugarCube's is treated as
+//! a container if it is closed anywhere in the story.
+//!
+//! [`Variables`] tells the dataflow lints how SugarCube passages use variables.
+//!
+//! [`Variables`]: struct.Variables.html
+
+use crate::issue::LintWarning;
+use crate::linter::dataflow::{Access, Variable, VariableSyntax};
+use crate::linter::graph::LinkGraph;
+use crate::linter::macros::{self, MACRO_SYNTAX, UNCLOSED_MACRO, UNKNOWN_MACRO};
+use crate::linter::Lint;
+use crate::utils;
+use crate::Config;
+use std::collections::HashSet;
+use std::ops::Range;
+use tweep::Story;
+
+/// The name of the story format these lints run for
+pub const FORMAT: &str = "sugarcube-2";
+
+/// SugarCube's built-in macros, including deprecated ones
+const MACROS: &[&str] = &[
+    "capture",
+    "set",
+    "unset",
+    "run",
+    "script",
+    "include",
+    "nobr",
+    "print",
+    "=",
+    "-",
+    "do",
+    "redo",
+    "silently",
+    "type",
+    "if",
+    "elseif",
+    "else",
+    "for",
+    "break",
+    "continue",
+    "switch",
+    "case",
+    "default",
+    "button",
+    "checkbox",
+    "cycle",
+    "option",
+    "optionsfrom",
+    "link",
+    "linkappend",
+    "linkprepend",
+    "linkreplace",
+    "listbox",
+    "numberbox",
+    "radiobutton",
+    "textarea",
+    "textbox",
+    "actions",
+    "back",
+    "choice",
+    "return",
+    "addclass",
+    "append",
+    "copy",
+    "prepend",
+    "remove",
+    "removeclass",
+    "replace",
+    "toggleclass",
+    "audio",
+    "cacheaudio",
+    "createaudiogroup",
+    "track",
+    "createplaylist",
+    "masteraudio",
+    "playlist",
+    "removeaudiogroup",
+    "removeplaylist",
+    "waitforaudio",
+    "done",
+    "goto",
+    "repeat",
+    "stop",
+    "timed",
+    "next",
+    "widget",
+    "click",
+    "display",
+    "forget",
+    "remember",
+    "setplaylist",
+    "stopallaudio",
+];
+
+/// SugarCube's built-in macros that must be closed
+const CONTAINERS: &[&str] = &[
+    "capture",
+    "script",
+    "nobr",
+    "do",
+    "silently",
+    "type",
+    "if",
+    "for",
+    "switch",
+    "button",
+    "cycle",
+    "link",
+    "linkappend",
+    "linkprepend",
+    "linkreplace",
+    "listbox",
+    "append",
+    "prepend",
+    "replace",
+    "createaudiogroup",
+    "createplaylist",
+    "done",
+    "repeat",
+    "timed",
+    "widget",
+    "click",
+];
+
+/// Text that is not scanned for macros, by its opening and closing delimiters
+const SKIPPED: &[(&str, &str)] = &[
+    ("/*", "*/"),
+    ("<!--", "-->"),
+    ("/%", "%/"),
+    ("\"\"\"", "\"\"\""),
+    ("<nowiki>", "</nowiki>"),
+];
+
+/// A macro tag in passage content
+#[derive(Debug)]
+struct Tag<'a> {
+    name: &'a str,
+
+    /// Whether this is a closing tag, such as `<</if>>`
+    closing: bool,
+
+    args: &'a str,
+
+    /// The byte range of the whole tag
+    range: Range<usize>,
+
+    /// Whether the brackets in the arguments are balanced
+    balanced: bool,
+}
+
+/// Gets the macro name at the start of `text`, if any
+fn macro_name(text: &str) -> Option<&str> {
+    if text.starts_with(['=', '-']) {
+        return Some(&text[..1]);
+    }
+    if !text.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(text.len());
+    Some(&text[..end])
+}
+
+/// Finds the end of a macro's arguments, returning the offset of the closing
+/// `>>` and whether the brackets in the arguments are balanced. Returns `None`
+/// if there is no closing `>>`, such as when a string is unterminated
+fn scan_args(text: &str) -> Option<(usize, bool)> {
+    let bytes = text.as_bytes();
+    let mut brackets = Vec::new();
+    let mut balanced = true;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'' | b'`') => {
+                i += 1;
+                while *bytes.get(i)? != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'[' if text[i..].starts_with("[[") => {
+                i += text[i..].find("]]")? + 1;
+            }
+            b'>' if text[i..].starts_with(">>") => {
+                return Some((i, balanced && brackets.is_empty()));
+            }
+            open @ (b'(' | b'[' | b'{') => brackets.push(open),
+            close @ (b')' | b']' | b'}') => {
+                let open = match close {
+                    b')' => b'(',
+                    b']' => b'[',
+                    _ => b'{',
+                };
+                if brackets.pop() != Some(open) {
+                    balanced = false;
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Finds the macro tags in passage content, along with the range of the start
+/// of a tag that is never ended, after which nothing more can be found
+fn tags(content: &str) -> (Vec<Tag<'_>>, Option<Range<usize>>) {
+    let mut tags = Vec::new();
+    let mut i = 0;
+    while i < content.len() {
+        let rest = &content[i..];
+        if let Some((open, close)) = SKIPPED.iter().find(|(open, _)| rest.starts_with(open)) {
+            i = match rest[open.len()..].find(close) {
+                Some(end) => i + open.len() + end + close.len(),
+                None => content.len(),
+            };
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("<<") {
+            let closing = after.starts_with('/');
+            let name_start = i + if closing { 3 } else { 2 };
+            if let Some(name) = macro_name(&content[name_start..]) {
+                let args_start = name_start + name.len();
+                let (end, balanced) = match scan_args(&content[args_start..]) {
+                    Some((end, balanced)) => (args_start + end, balanced),
+                    None => return (tags, Some(i..args_start)),
+                };
+                tags.push(Tag {
+                    name,
+                    closing,
+                    args: &content[args_start..end],
+                    range: i..end + 2,
+                    balanced,
+                });
+                i = end + 2;
+                if name == "script" && !closing {
+                    // The body is JavaScript
+                    i = content[i..]
+                        .find("<</script>>")
+                        .map(|end| i + end)
+                        .unwrap_or(content.len());
+                }
+                continue;
+            }
+        }
+        i += rest.chars().next().map(char::len_utf8).unwrap_or(1);
+    }
+    (tags, None)
+}
+
+/// The passages to check, by name, skipping scripts and stylesheets
+fn passages(story: &Story) -> Vec<(&str, &str)> {
+    let mut passages: Vec<(&str, &str)> = story
+        .passages
+        .iter()
+        .filter(|(_, passage)| {
+            !passage
+                .header
+                .tags
+                .iter()
+                .any(|tag| tag == "script" || tag == "stylesheet")
+        })
+        .map(|(name, passage)| (name.as_str(), utils::get_content(passage)))
+        .collect();
+    passages.sort_unstable();
+    passages
+}
+
+/// Gets the names of the macros defined by the story and config
+fn defined_macros(story: &Story, config: &Config) -> HashSet<String> {
+    let mut defined: HashSet<String> = config.macros.iter().cloned().collect();
+    for script in &story.scripts {
+        for (start, _) in script.match_indices("Macro.add(") {
+            defined.extend(macros::leading_strings(
+                &script[start + "Macro.add(".len()..],
+            ));
+        }
+    }
+    for passage in story.passages.values() {
+        if !passage.header.tags.iter().any(|tag| tag == "widget") {
+            continue;
+        }
+        let (tags, _) = tags(utils::get_content(passage));
+        for tag in tags {
+            if tag.name == "widget" && !tag.closing {
+                defined.extend(macros::leading_strings(tag.args).into_iter().take(1));
+            }
+        }
+    }
+    defined
+}
+
+/// Checks the macros of every passage, returning warnings for all of the
+/// SugarCube lints
+fn check(story: &Story, config: &Config) -> Vec<LintWarning> {
+    let headers = utils::passage_headers(story);
+    let passages = passages(story);
+    let scanned: Vec<_> = passages
+        .iter()
+        .map(|(name, content)| (*name, *content, tags(content)))
+        .collect();
+
+    let defined = defined_macros(story, config);
+    let custom_containers: HashSet<&str> = scanned
+        .iter()
+        .flat_map(|(_, _, (tags, _))| tags.iter())
+        .filter(|tag| tag.closing && !MACROS.contains(&tag.name))
+        .map(|tag| tag.name)
+        .collect();
+    let is_container = |name: &str| CONTAINERS.contains(&name) || custom_containers.contains(name);
+
+    let mut warnings = Vec::new();
+    for (name, content, (tags, unended)) in &scanned {
+        let header = headers.get(*name);
+        let context = |range: &Range<usize>| {
+            header.map(|header| macros::content_context(header, content, range.clone()))
+        };
+
+        let mut open: Vec<&Tag> = Vec::new();
+        for tag in tags {
+            // SugarCube 1 style closing tags, such as <<endif>>, still work
+            let (macro_name, closing) = match tag.name.strip_prefix("end") {
+                Some(opened) if !tag.closing && CONTAINERS.contains(&opened) => (opened, true),
+                _ => (tag.name, tag.closing),
+            };
+
+            if !closing && !MACROS.contains(&macro_name) && !defined.contains(macro_name) {
+                warnings.push(
+                    LintWarning::new(
+                        UNKNOWN_MACRO,
+                        format!("Unknown macro <<{}>> in passage {}", macro_name, name),
+                        context(&tag.range),
+                    )
+                    .with_note(
+                        "If it is defined outside the story, add it to macros in the config file",
+                    ),
+                );
+            }
+            if !tag.balanced {
+                warnings.push(LintWarning::new(
+                    MACRO_SYNTAX,
+                    format!(
+                        "The arguments of <<{}>> have unbalanced brackets",
+                        macro_name
+                    ),
+                    context(&tag.range),
+                ));
+            }
+
+            if closing {
+                match open.iter().rposition(|opened| opened.name == macro_name) {
+                    Some(i) => {
+                        for unclosed in open.drain(i..).skip(1) {
+                            warnings.push(unclosed_warning(unclosed, context(&unclosed.range)));
+                        }
+                    }
+                    None => warnings.push(LintWarning::new(
+                        UNCLOSED_MACRO,
+                        format!(
+                            "{} has no matching <<{}>> to close",
+                            &content[tag.range.clone()],
+                            macro_name
+                        ),
+                        context(&tag.range),
+                    )),
+                }
+            } else if is_container(macro_name) {
+                open.push(tag);
+            }
+        }
+        for unclosed in open {
+            warnings.push(unclosed_warning(unclosed, context(&unclosed.range)));
+        }
+
+        if let Some(range) = unended {
+            warnings.push(
+                LintWarning::new(
+                    MACRO_SYNTAX,
+                    format!(
+                        "{} is never ended with >>, so the rest of passage {} is part of it",
+                        &content[range.clone()],
+                        name
+                    ),
+                    context(range),
+                )
+                .with_note("Check for a missing >> or an unterminated string"),
+            );
+        }
+    }
+    warnings
+}
+
+fn unclosed_warning(tag: &Tag, context: Option<tweep::FullContext>) -> LintWarning {
+    LintWarning::new(
+        UNCLOSED_MACRO,
+        format!("<<{}>> is never closed", tag.name),
+        context,
+    )
+    .with_note(format!("Close it with <</{}>>", tag.name))
+}
+
+/// Warns about container macros such as `<<if>>` that are never closed, and
+/// closing tags with no macro to close
+pub struct UnclosedMacro;
+
+/// Warns about macros that are neither SugarCube's nor defined by the story
+pub struct UnknownMacro;
+
+/// Warns about macros with unbalanced brackets in their arguments, or that
+/// are never ended with `>>`
+pub struct MacroSyntax;
+
+macro_rules! sugarcube_lint {
+    ($lint:ident, $name:expr) => {
+        impl Lint for $lint {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn check(&self, story: &Story, _: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+                check(story, config)
+                    .into_iter()
+                    .filter(|warning| warning.name == $name)
+                    .collect()
+            }
+        }
+    };
+}
+
+sugarcube_lint!(UnclosedMacro, UNCLOSED_MACRO);
+sugarcube_lint!(UnknownMacro, UNKNOWN_MACRO);
+sugarcube_lint!(MacroSyntax, MACRO_SYNTAX);
+
+/// Macros whose first argument names the variable they set, such as
+/// `<<textbox "$name" "">>`
+const RECEIVERS: &[&str] = &[
+    "checkbox",
+    "cycle",
+    "listbox",
+    "numberbox",
+    "radiobutton",
+    "textarea",
+    "textbox",
+];
+
+/// Temporary variables SugarCube sets for widgets
+const WIDGET_VARIABLES: &[&str] = &["_args", "_contents"];
+
+/// Operators that set a variable from its own value, such as `$a += 1`
+const UPDATES: &[&str] = &[
+    "++", "--", "+=", "-=", "*=", "/=", "%=", "**=", "??=", "||=", "&&=",
+];
+
+/// Ways the story's scripts get at variables, and the sigil of the variables
+/// they get
+const SCRIPT_ACCESSORS: &[(&str, &str)] = &[
+    ("State.variables.", "$"),
+    ("variables().", "$"),
+    ("State.temporary.", "_"),
+    ("temporary().", "_"),
+];
+
+/// Whether `c` can be part of a JavaScript identifier
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Gets the variable name, including its sigil, at the start of `text`
+fn variable_name(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix(['$', '_'])?;
+    if !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let end = rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+    Some(&text[..1 + end])
+}
+
+/// Whether a variable can start at `i`, rather than being part of a longer
+/// name or a property
+fn at_boundary(text: &str, i: usize) -> bool {
+    text[..i]
+        .chars()
+        .next_back()
+        .is_none_or(|c| !is_ident(c) && c != '.')
+}
+
+/// Whether `text` ends with the word `word`
+fn ends_with_word(text: &str, word: &str) -> bool {
+    text.strip_suffix(word)
+        .is_some_and(|rest| !rest.ends_with(is_ident))
+}
+
+/// Works out how a variable in code is used, from the code before and after
+/// it
+fn access(before: &str, after: &str) -> Access {
+    let before = before.trim_end();
+    let after = after.trim_start();
+    if ["def", "ndef", "typeof"]
+        .iter()
+        .any(|word| ends_with_word(before, word))
+    {
+        Access::Test
+    } else if before.ends_with("++")
+        || before.ends_with("--")
+        || UPDATES.iter().any(|op| after.starts_with(op))
+    {
+        Access::Update
+    } else if after
+        .strip_prefix("to")
+        .is_some_and(|rest| !rest.starts_with(is_ident))
+        || (after.starts_with('=') && !after.starts_with("==") && !after.starts_with("=>"))
+    {
+        Access::Set
+    } else {
+        Access::Read
+    }
+}
+
+/// Finds the variables in code, such as a macro's arguments, which starts at
+/// `offset` in the passage content. Variables before `until` are set, as the
+/// ones before `range` in `<<for _i, _v range $list>>` are
+fn code_variables(code: &str, offset: usize, until: usize, variables: &mut Vec<Variable>) {
+    let bytes = code.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'' | b'`') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'[' if code[i..].starts_with("[[") => {
+                i += code[i..].find("]]").unwrap_or(code.len() - i);
+            }
+            b'$' | b'_' if at_boundary(code, i) => {
+                if let Some(name) = variable_name(&code[i..]) {
+                    let end = i + name.len();
+                    let access = if i < until {
+                        Access::Set
+                    } else {
+                        access(&code[..i], &code[end..])
+                    };
+                    variables.push(Variable::new(name, access, offset + i..offset + end));
+                    i = end;
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+}
+
+/// Finds the naked variables in passage text outside of macros, such as
+/// `$name` in `Hello, $name`, along with the variables set by links such as
+/// `[[Buy|Shop][$gold -= 5]]`
+fn text_variables(content: &str, range: Range<usize>, variables: &mut Vec<Variable>) {
+    let mut i = range.start;
+    while i < range.end {
+        let rest = &content[i..range.end];
+        if let Some((open, close)) = SKIPPED.iter().find(|(open, _)| rest.starts_with(open)) {
+            i = match rest[open.len()..].find(close) {
+                Some(end) => i + open.len() + end + close.len(),
+                None => range.end,
+            };
+            continue;
+        }
+        if rest.starts_with("$$") {
+            i += 2;
+            continue;
+        }
+        if rest.starts_with("[[") {
+            let end = rest.find("]]").unwrap_or(rest.len());
+            let (link, setter) = match rest[..end].find("][") {
+                Some(setter) => (&rest[..setter], Some(setter + 2)),
+                None => (&rest[..end], None),
+            };
+            text_variables(content, i + 2..i + link.len(), variables);
+            if let Some(setter) = setter {
+                code_variables(&rest[setter..end], i + setter, 0, variables);
+            }
+            i += (end + 2).min(rest.len());
+            continue;
+        }
+        if rest.starts_with(['$', '_']) && at_boundary(content, i) {
+            if let Some(name) = variable_name(rest) {
+                variables.push(Variable::new(name, Access::Read, i..i + name.len()));
+                i += name.len();
+                continue;
+            }
+        }
+        i += rest.chars().next().map(char::len_utf8).unwrap_or(1);
+    }
+}
+
+/// Finds where SugarCube passages set and read variables
+///
+/// Variables are set by `<<set>>` and other macros that assign to them, by
+/// macros such as `<<textbox>>` that are given a variable name to set, by
+/// `<<for>>`, and by link setters. Checks such as `def $a` are not treated as
+/// reads that need the variable to be set, and `<<unset>>` is ignored. Code in
+/// `<<script>>` macros and the story's scripts is not followed, but variables
+/// it uses through `State.variables` or `State.temporary` are not reported
+pub struct Variables;
+
+impl VariableSyntax for Variables {
+    fn variables(&self, content: &str) -> Vec<Variable> {
+        let mut variables = Vec::new();
+        let (tags, unended) = tags(content);
+        let mut text_start = 0;
+        let mut in_script = false;
+        for tag in &tags {
+            if !in_script {
+                text_variables(content, text_start..tag.range.start, &mut variables);
+            }
+            in_script = tag.name == "script" && !tag.closing;
+            text_start = tag.range.end;
+            if tag.closing || tag.name == "unset" {
+                continue;
+            }
+
+            let offset = tag.range.start + 2 + tag.name.len();
+            if RECEIVERS.contains(&tag.name) {
+                let receiver = macros::leading_strings(tag.args).into_iter().next();
+                if let Some(name) = receiver.as_deref().and_then(variable_name) {
+                    let start = offset + tag.args.find(name).unwrap_or(0);
+                    variables.push(Variable::new(name, Access::Set, start..start + name.len()));
+                }
+            }
+            let until = match tag.name {
+                "for" => tag.args.find(" range ").unwrap_or(0),
+                _ => 0,
+            };
+            code_variables(tag.args, offset, until, &mut variables);
+        }
+        let text_end = unended.map(|range| range.start).unwrap_or(content.len());
+        if !in_script && text_start < text_end {
+            text_variables(content, text_start..text_end, &mut variables);
+        }
+        variables.retain(|variable| !WIDGET_VARIABLES.contains(&variable.name.as_str()));
+        variables
+    }
+
+    fn script_variables(&self, story: &Story) -> HashSet<String> {
+        let mut used = HashSet::new();
+        let passages = story.passages.values().map(utils::get_content);
+        for code in story.scripts.iter().map(String::as_str).chain(passages) {
+            for (accessor, sigil) in SCRIPT_ACCESSORS {
+                for (start, _) in code.match_indices(accessor) {
+                    let rest = &code[start + accessor.len()..];
+                    let end = rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+                    used.insert(format!("{}{}", sigil, &rest[..end]));
+                }
+            }
+            for accessor in ["State.getVar(", "State.setVar("] {
+                for (start, _) in code.match_indices(accessor) {
+                    used.extend(macros::leading_strings(&code[start + accessor.len()..]));
+                }
+            }
+        }
+        used
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings(input: &str) -> Vec<(&'static str, String)> {
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let mut config = crate::linter::tests::config(Some(FORMAT), Vec::new());
+        config.macros = vec!["external".to_string()];
+        check(&story, &config)
+            .into_iter()
+            .map(|warning| (warning.name, warning.message))
+            .collect()
+    }
+
+    #[test]
+    fn finds_macro_problems() {
+        let input = r#":: Start
+<<if $a is "<<">>A<<elseif $b>>B<<else>>C<</if>>
+<<for _i to 0; _i lt 3; _i++>><<print _i>><</for>><<endif>>
+<<myWidget>><<external>><<mine>>x<</mine>><<bogus 1>>
+<<set $x to (1 + 2>>
+/* <<commented>> */
+<<link "Go" "Next">>
+<<if true>>
+
+:: Widgets [widget]
+<<widget "myWidget">><<print "hi">><</widget>>
+
+:: Script [script]
+Macro.add(["mine", "yours"], { tags: null, handler() {} });
+
+:: Next
+<<print "unterminated>>
+"#;
+        assert_eq!(
+            warnings(input),
+            vec![
+                (
+                    MACRO_SYNTAX,
+                    "<<print is never ended with >>, so the rest of passage Next is part of it"
+                        .to_string()
+                ),
+                (
+                    UNCLOSED_MACRO,
+                    "<<endif>> has no matching <<if>> to close".to_string()
+                ),
+                (
+                    UNKNOWN_MACRO,
+                    "Unknown macro <<bogus>> in passage Start".to_string()
+                ),
+                (
+                    MACRO_SYNTAX,
+                    "The arguments of <<set>> have unbalanced brackets".to_string()
+                ),
+                (UNCLOSED_MACRO, "<<link>> is never closed".to_string()),
+                (UNCLOSED_MACRO, "<<if>> is never closed".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Lint for passages that can't be reached from the start passage
+
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::Lint;
+use crate::partials;
+use crate::utils;
+use crate::Config;
+use std::collections::HashSet;
+use tweep::Story;
+
+/// Name of the lint, used for allowing or denying it
+pub const NAME: &str = "UnreachablePassage";
+
+/// Whether the named passage is meant to be used without being linked to,
+/// such as a widget or a partial
+pub fn is_unlinked(graph: &LinkGraph, name: &str, config: &Config) -> bool {
+    config
+        .unlinked_passages
+        .iter()
+        .any(|passage| passage == name)
+        || graph
+            .tags(name)
+            .iter()
+            .any(|tag| tag == partials::TAG || config.unlinked_tags.contains(tag))
+}
+
+/// Warns about any passage that can't be reached by following links from the
+/// start passage
+///
+/// Passages named in the configured unlinked passages or tagged with one of
+/// the configured unlinked tags are used by the story format without being
+/// linked to, so they are not reported. Links out of them are followed as if
+/// they were reachable. Partials are treated the same way
+pub struct UnreachablePassage;
+
+impl Lint for UnreachablePassage {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn check(&self, story: &Story, graph: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+        check(story, graph, config)
+    }
+}
+
+/// Checks the given link graph for passages that can't be reached from the
+/// start passage. See [`UnreachablePassage`]
+///
+/// [`UnreachablePassage`]: struct.UnreachablePassage.html
+pub fn check(story: &Story, graph: &LinkGraph, config: &Config) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    // A missing start passage is reported by tweep
+    let start = match story.get_start_passage_name() {
+        Some(start) if story.passages.contains_key(start) => start,
+        _ => return warnings,
+    };
+
+    let passages = graph.passages();
+    let mut reachable: HashSet<&str> = HashSet::new();
+    for root in std::iter::once(start).chain(
+        passages
+            .iter()
+            .copied()
+            .filter(|name| is_unlinked(graph, name, config)),
+    ) {
+        if !reachable.contains(root) {
+            reachable.extend(graph.reachable_from(root));
+        }
+    }
+
+    let headers = utils::passage_headers(story);
+    for name in passages {
+        if reachable.contains(name) {
+            continue;
+        }
+        let mut warning = LintWarning::new(
+            NAME,
+            format!("Passage {} can't be reached from {}", name, start),
+            headers.get(name).cloned(),
+        );
+        if let Some(from) = graph.incoming(name).first() {
+            warning = warning.with_note(format!(
+                "It is only linked from {}, which is also unreachable",
+                from
+            ));
+        }
+        let tags = if config.unlinked_tags.is_empty() {
+            partials::TAG.to_string()
+        } else {
+            config.unlinked_tags.join(", ")
+        };
+        warnings.push(warning.with_note(format!(
+            "Link to it, or if it is used without being linked to, tag it with one of: {}",
+            tags
+        )));
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::links::LinkSyntax;
+
+    #[test]
+    fn finds_unreachable_passages() {
+        let input = r#":: StoryData
+{"ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC", "start": "Start"}
+
+:: Start
+[[A]]
+
+:: A
+The end
+
+:: Menu
+[[Options]]
+
+:: Options
+Menu target
+
+:: Lost
+[[Also Lost]]
+
+:: Also Lost
+Nothing
+
+:: Button [widget]
+Widget
+"#;
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let graph = LinkGraph::new(&story, LinkSyntax::DEFAULT);
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.unlinked_tags = vec!["widget".to_string()];
+        config.unlinked_passages = vec!["Menu".to_string()];
+        let unreachable: Vec<String> = check(&story, &graph, &config)
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect();
+        assert_eq!(
+            unreachable,
+            vec![
+                "Passage Also Lost can't be reached from Start",
+                "Passage Lost can't be reached from Start",
+            ]
+        );
+    }
+}
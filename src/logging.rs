@@ -0,0 +1,105 @@
+//! Logs what tweec is doing to standard error, such as which config files it
+//! reads and which files it parses
+//!
+//! How much is logged is set with `-v` (info), `-vv` (debug), `-vvv` (trace),
+//! and `-q` (errors only); by default only warnings and errors are. The config
+//! file is read before the command line is parsed, so records are held until
+//! the level is known and then written if the level allows them
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::sync::Mutex;
+
+/// The level used unless `-v` or `-q` is given
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Warn;
+
+struct State {
+    /// The level to log at, once known
+    level: Option<LevelFilter>,
+
+    /// Records held until the level is known
+    held: Vec<(Level, String)>,
+}
+
+struct Logger {
+    state: Mutex<State>,
+}
+
+static LOGGER: Logger = Logger {
+    state: Mutex::new(State {
+        level: None,
+        held: Vec::new(),
+    }),
+};
+
+fn write(level: Level, message: &str) {
+    eprintln!("{}: {}", level.as_str().to_lowercase(), message);
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match self.state.lock() {
+            Ok(state) => state.level.is_none_or(|level| metadata.level() <= level),
+            Err(_) => false,
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        // Only log tweec's own records, not those of its dependencies
+        if record.target().split("::").next() != Some(env!("CARGO_CRATE_NAME")) {
+            return;
+        }
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        match state.level {
+            None => state.held.push((record.level(), record.args().to_string())),
+            Some(level) if record.level() <= level => {
+                write(record.level(), &record.args().to_string())
+            }
+            Some(_) => (),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, holding records until [`set_level`] is called
+///
+/// [`set_level`]: fn.set_level.html
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+}
+
+/// Sets the level to log at, writing any held records that it allows. Only
+/// the first call has any effect
+pub fn set_level(level: LevelFilter) {
+    let mut state = match LOGGER.state.lock() {
+        Ok(state) => state,
+        Err(_) => return,
+    };
+    if state.level.is_some() {
+        return;
+    }
+    state.level = Some(level);
+    for (record_level, message) in state.held.drain(..) {
+        if record_level <= level {
+            write(record_level, &message);
+        }
+    }
+    log::set_max_level(level);
+}
+
+/// Gets the level to log at for the number of times `-v` is given, or for
+/// `-q`
+pub fn level(verbose: u64, quiet: bool) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => DEFAULT_LEVEL,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    }
+}
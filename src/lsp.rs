@@ -0,0 +1,632 @@
+//! A language server that publishes tweec's warnings and errors as
+//! diagnostics, for use by editors
+//!
+//! The server lints the inputs given on the command line, or the workspace
+//! root if there are none. Since `tweep` reads its input from disk, unsaved
+//! changes to open documents are written to a scratch directory and linted
+//! from there in place of the original file.
+//!
+//! Passage names are symbols: the server can go from a link to the header of
+//! the passage it goes to, and find every link to a passage. See [`Index`].
+//! Passage names in links and tags in passage headers are completed as they
+//! are typed. See [`completion`]. Each passage is also a document symbol,
+//! with its tags as detail, and passages can be searched for by name across
+//! the workspace
+//!
+//! Issues with a suggested fix, such as a dead link to a passage with a
+//! similar name, offer it as a quick fix code action, which replaces the text
+//! the issue refers to
+//!
+//! [`Index`]: index/struct.Index.html
+//! [`completion`]: completion/index.html
+
+use crate::inputs;
+use crate::issue::{Fix, Issue};
+use crate::linter;
+use crate::Config;
+use crate::StoryFiles;
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use codespan_reporting::files::Files;
+use color_eyre::Result;
+use eyre::eyre;
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{
+    CodeActionRequest, Completion, DocumentSymbolRequest, GotoDefinition, References, Request as _,
+    WorkspaceSymbolRequest,
+};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DocumentSymbol,
+    DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse,
+    InitializeParams, Location, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    ReferenceParams, ServerCapabilities, SymbolInformation, SymbolKind, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TextDocumentSyncSaveOptions, TextEdit, Url,
+    WorkspaceEdit, WorkspaceSymbolParams, WorkspaceSymbolResponse,
+};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+pub mod completion;
+pub mod index;
+
+use index::{Index, Span};
+
+/// Runs the language server over stdio until the client shuts it down
+pub fn run(config: &Config) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
+        )),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(
+                completion::TRIGGER_CHARACTERS
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect(),
+            ),
+            ..Default::default()
+        }),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    let params = connection
+        .initialize(serde_json::to_value(capabilities)?)
+        .map_err(|e| eyre!("Failed to initialize language server: {}", e))?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+
+    let mut server = Server::new(config, &connection, &params);
+    server.publish()?;
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection
+                    .handle_shutdown(&request)
+                    .map_err(|e| eyre!("Language server protocol error: {}", e))?
+                {
+                    break;
+                }
+                server.handle_request(request)?;
+            }
+            Message::Notification(notification) => server.handle_notification(notification)?,
+            Message::Response(_) => (),
+        }
+    }
+
+    drop(server);
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// The state of the language server
+struct Server<'a> {
+    config: Config,
+    connection: &'a Connection,
+
+    /// Contents of open documents, by path
+    documents: HashMap<PathBuf, String>,
+
+    /// Files that diagnostics were last published for, so they can be cleared
+    published: HashSet<PathBuf>,
+
+    /// Directory unsaved documents are written to for parsing
+    scratch: PathBuf,
+
+    /// Where passages are defined and linked to, as of the last time the
+    /// story parsed
+    index: Index,
+
+    /// Quick fixes for the diagnostics last published, by the path of the
+    /// file the diagnostic is in
+    actions: HashMap<PathBuf, Vec<CodeAction>>,
+}
+
+impl<'a> Server<'a> {
+    #[allow(deprecated)]
+    fn new(config: &Config, connection: &'a Connection, params: &InitializeParams) -> Self {
+        let mut config = config.clone();
+        if config.inputs.is_empty() {
+            let root = params
+                .workspace_folders
+                .as_ref()
+                .and_then(|folders| folders.first())
+                .map(|folder| folder.uri.clone())
+                .or_else(|| params.root_uri.clone())
+                .and_then(|uri| uri.to_file_path().ok())
+                .unwrap_or_else(|| PathBuf::from("."));
+            config.inputs = vec![root.to_string_lossy().into_owned()];
+        }
+
+        Server {
+            config,
+            connection,
+            documents: HashMap::new(),
+            published: HashSet::new(),
+            scratch: std::env::temp_dir().join(format!("tweec-lsp-{}", std::process::id())),
+            index: Index::default(),
+            actions: HashMap::new(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn handle_request(&mut self, request: Request) -> Result<()> {
+        let response = match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position_params;
+                let definition = position
+                    .text_document
+                    .uri
+                    .to_file_path()
+                    .ok()
+                    .and_then(|path| self.index.passage_at(&canonical(path), position.position))
+                    .and_then(|name| self.index.definition(name))
+                    .and_then(location)
+                    .map(GotoDefinitionResponse::Scalar);
+                Response::new_ok(request.id, definition)
+            }
+            References::METHOD => {
+                let params: ReferenceParams = serde_json::from_value(request.params)?;
+                let (position, context) = (params.text_document_position, params.context);
+                let name = position
+                    .text_document
+                    .uri
+                    .to_file_path()
+                    .ok()
+                    .and_then(|path| self.index.passage_at(&canonical(path), position.position));
+                let references = name.map(|name| {
+                    let definition = self
+                        .index
+                        .definition(name)
+                        .filter(|_| context.include_declaration);
+                    definition
+                        .into_iter()
+                        .chain(self.index.references(name))
+                        .filter_map(location)
+                        .collect::<Vec<Location>>()
+                });
+                Response::new_ok(request.id, references)
+            }
+            Completion::METHOD => {
+                let params: CompletionParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position;
+                let items = position
+                    .text_document
+                    .uri
+                    .to_file_path()
+                    .ok()
+                    .and_then(|path| self.text(path))
+                    .map(|text| completion::complete(&self.index, &text, position.position))
+                    .unwrap_or_default();
+                Response::new_ok(request.id, CompletionResponse::Array(items))
+            }
+            CodeActionRequest::METHOD => {
+                let params: CodeActionParams = serde_json::from_value(request.params)?;
+                let range = params.range;
+                let actions: Vec<CodeActionOrCommand> = params
+                    .text_document
+                    .uri
+                    .to_file_path()
+                    .ok()
+                    .and_then(|path| self.actions.get(&canonical(path)))
+                    .into_iter()
+                    .flatten()
+                    .filter(|action| {
+                        action.diagnostics.iter().flatten().any(|diagnostic| {
+                            diagnostic.range.start <= range.end
+                                && range.start <= diagnostic.range.end
+                        })
+                    })
+                    .cloned()
+                    .map(CodeActionOrCommand::CodeAction)
+                    .collect();
+                Response::new_ok(request.id, actions)
+            }
+            DocumentSymbolRequest::METHOD => {
+                let params: DocumentSymbolParams = serde_json::from_value(request.params)?;
+                let symbols = params
+                    .text_document
+                    .uri
+                    .to_file_path()
+                    .map(|path| {
+                        self.index
+                            .passages_in(&canonical(path))
+                            .into_iter()
+                            .map(|(name, span)| DocumentSymbol {
+                                name: name.to_string(),
+                                detail: self.detail(name),
+                                kind: SymbolKind::MODULE,
+                                tags: None,
+                                deprecated: None,
+                                range: span.range,
+                                selection_range: span.range,
+                                children: None,
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Response::new_ok(request.id, DocumentSymbolResponse::Nested(symbols))
+            }
+            WorkspaceSymbolRequest::METHOD => {
+                let params: WorkspaceSymbolParams = serde_json::from_value(request.params)?;
+                let symbols = self
+                    .index
+                    .search(&params.query)
+                    .into_iter()
+                    .filter_map(|(name, span)| {
+                        Some(SymbolInformation {
+                            name: name.to_string(),
+                            kind: SymbolKind::MODULE,
+                            tags: None,
+                            deprecated: None,
+                            location: location(span)?,
+                            container_name: None,
+                        })
+                    })
+                    .collect();
+                Response::new_ok(request.id, WorkspaceSymbolResponse::Flat(symbols))
+            }
+            _ => Response::new_err(
+                request.id,
+                lsp_server::ErrorCode::MethodNotFound as i32,
+                format!("Unhandled method {}", request.method),
+            ),
+        };
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let Ok(path) = params.text_document.uri.to_file_path() {
+                    self.documents.insert(path, params.text_document.text);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let (Ok(path), Some(change)) = (
+                    params.text_document.uri.to_file_path(),
+                    params.content_changes.into_iter().last(),
+                ) {
+                    self.documents.insert(path, change.text);
+                }
+            }
+            DidSaveTextDocument::METHOD => (),
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let Ok(path) = params.text_document.uri.to_file_path() {
+                    self.documents.remove(&path);
+                }
+            }
+            _ => return Ok(()),
+        }
+        self.publish()
+    }
+
+    /// Gets the tags of the named passage as the detail of its symbol
+    fn detail(&self, name: &str) -> Option<String> {
+        let tags = self.index.tags_of(name);
+        if tags.is_empty() {
+            None
+        } else {
+            Some(tags.join(" "))
+        }
+    }
+
+    /// Gets the text of a document, as it is in the editor if it is open
+    fn text(&self, path: PathBuf) -> Option<String> {
+        match self.documents.get(&path) {
+            Some(text) => Some(text.clone()),
+            None => std::fs::read_to_string(path).ok(),
+        }
+    }
+
+    /// Gets the paths to parse, as `tweec lint` would find them, substituting
+    /// scratch copies of any open documents, along with a map from file name
+    /// back to the original path
+    ///
+    /// `tweep` names each file by its bare file name, so open documents and
+    /// files that share their name with another input are given a name in
+    /// the scratch directory that is unique to their path
+    fn paths(&self) -> Result<(Vec<PathBuf>, HashMap<String, PathBuf>)> {
+        let story_paths = inputs::story_paths(&self.config.inputs, &self.config.extensions);
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for path in &story_paths {
+            if let Some(name) = path.file_name() {
+                *counts
+                    .entry(name.to_string_lossy().into_owned())
+                    .or_default() += 1;
+            }
+        }
+
+        let mut paths = Vec::new();
+        let mut originals = HashMap::new();
+        for path in story_paths {
+            let file_name = match path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => {
+                    paths.push(path);
+                    continue;
+                }
+            };
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            let text = self
+                .documents
+                .get(&canonical)
+                .or_else(|| self.documents.get(&path));
+            if text.is_none() && counts[&file_name] == 1 {
+                paths.push(path);
+                originals.insert(file_name, canonical);
+                continue;
+            }
+
+            let mut hasher = DefaultHasher::new();
+            canonical.hash(&mut hasher);
+            let unique_name = format!("{:016x}-{}", hasher.finish(), file_name);
+            std::fs::create_dir_all(&self.scratch)?;
+            let scratch_path = self.scratch.join(&unique_name);
+            match text {
+                Some(text) => std::fs::write(&scratch_path, text)?,
+                None => {
+                    if std::fs::copy(&path, &scratch_path).is_err() {
+                        paths.push(path);
+                        originals.insert(file_name, canonical);
+                        continue;
+                    }
+                }
+            }
+            paths.push(scratch_path);
+            originals.insert(unique_name, canonical);
+        }
+        Ok((paths, originals))
+    }
+
+    /// Lints the inputs and publishes diagnostics for every file
+    fn publish(&mut self) -> Result<()> {
+        let (paths, originals) = self.paths()?;
+        let (story_result, issues, _) = linter::collect(inputs::read_paths(&paths), &self.config);
+        let story_files = StoryFiles::new(&story_result);
+        if let Ok(story) = &story_result {
+            self.index = Index::new(story, &story_files, &originals, &self.config.links);
+        }
+
+        let mut diagnostics: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+        let fallback = originals.values().min().cloned();
+        self.actions.clear();
+        for issue in &issues {
+            if let Some((path, diagnostic)) = convert(issue, &story_files, &originals) {
+                for fix in issue.fixes(&story_files) {
+                    if let Some(action) = quick_fix(&fix, &diagnostic, &story_files, &originals) {
+                        self.actions.entry(path.clone()).or_default().push(action);
+                    }
+                }
+                diagnostics.entry(path).or_default().push(diagnostic);
+            } else if let Some(path) = &fallback {
+                let diagnostic = Diagnostic {
+                    range: Range::default(),
+                    ..convert_unlocated(issue, &story_files)
+                };
+                diagnostics
+                    .entry(path.clone())
+                    .or_default()
+                    .push(diagnostic);
+            }
+        }
+
+        let stale: Vec<PathBuf> = self
+            .published
+            .iter()
+            .filter(|path| !diagnostics.contains_key(*path))
+            .cloned()
+            .collect();
+        for path in stale {
+            diagnostics.insert(path, Vec::new());
+        }
+
+        self.published.clear();
+        for (path, diagnostics) in diagnostics {
+            let uri = match Url::from_file_path(&path) {
+                Ok(uri) => uri,
+                Err(_) => continue,
+            };
+            if !diagnostics.is_empty() {
+                self.published.insert(path);
+            }
+            let params = PublishDiagnosticsParams {
+                uri,
+                diagnostics,
+                version: None,
+            };
+            let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+            self.connection.sender.send(notification.into())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for Server<'a> {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.scratch);
+    }
+}
+
+/// Gets the canonical form of a path from the client, as files are indexed by
+fn canonical(path: PathBuf) -> PathBuf {
+    path.canonicalize().unwrap_or(path)
+}
+
+/// Converts a span into an LSP location
+fn location(span: &Span) -> Option<Location> {
+    Some(Location::new(
+        Url::from_file_path(&span.path).ok()?,
+        span.range,
+    ))
+}
+
+/// Converts a byte offset in a file into an LSP position
+pub(crate) fn position(story_files: &StoryFiles, id: usize, byte: usize) -> Position {
+    let (line, character) = story_files.position(id, byte);
+    Position::new(line as u32, character as u32)
+}
+
+/// Converts a byte range in a file into an LSP range
+pub(crate) fn range(story_files: &StoryFiles, id: usize, bytes: std::ops::Range<usize>) -> Range {
+    Range::new(
+        position(story_files, id, bytes.start),
+        position(story_files, id, bytes.end),
+    )
+}
+
+/// Converts a fix for the given diagnostic into a quick fix code action
+fn quick_fix(
+    fix: &Fix,
+    diagnostic: &Diagnostic,
+    story_files: &StoryFiles,
+    originals: &HashMap<String, PathBuf>,
+) -> Option<CodeAction> {
+    let span = index::span(&fix.context, story_files, originals)?;
+    let edit = TextEdit::new(span.range, fix.replacement.clone());
+    let changes = std::iter::once((Url::from_file_path(&span.path).ok()?, vec![edit])).collect();
+    Some(CodeAction {
+        title: fix.title.clone(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit::new(changes)),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+/// Converts an issue into a diagnostic without a location
+fn convert_unlocated(issue: &Issue, story_files: &StoryFiles) -> Diagnostic {
+    let report = issue.report(story_files);
+    let severity = match report.severity {
+        Severity::Bug | Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Note => DiagnosticSeverity::INFORMATION,
+        Severity::Help => DiagnosticSeverity::HINT,
+    };
+    let mut message = report.message.clone();
+    for note in &report.notes {
+        message.push('\n');
+        message.push_str(note);
+    }
+    Diagnostic {
+        severity: Some(severity),
+        code: report.code.map(NumberOrString::String),
+        source: Some("tweec".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// Converts an issue into a diagnostic for the file containing its primary
+/// location, returning the original path of that file
+fn convert(
+    issue: &Issue,
+    story_files: &StoryFiles,
+    originals: &HashMap<String, PathBuf>,
+) -> Option<(PathBuf, Diagnostic)> {
+    let report = issue.report(story_files);
+    let path_of = |id: usize| -> Option<&PathBuf> {
+        story_files.name(id).and_then(|name| originals.get(name))
+    };
+
+    let primary = report
+        .labels
+        .iter()
+        .find(|label| label.style == LabelStyle::Primary)?;
+    let path = path_of(primary.file_id)?.clone();
+
+    let related: Vec<DiagnosticRelatedInformation> = report
+        .labels
+        .iter()
+        .filter(|label| label.style == LabelStyle::Secondary)
+        .filter_map(|label| {
+            let uri = Url::from_file_path(path_of(label.file_id)?).ok()?;
+            Some(DiagnosticRelatedInformation {
+                location: Location::new(
+                    uri,
+                    range(story_files, label.file_id, label.range.clone()),
+                ),
+                message: label.message.clone(),
+            })
+        })
+        .collect();
+
+    let diagnostic = Diagnostic {
+        range: range(story_files, primary.file_id, primary.range.clone()),
+        related_information: if related.is_empty() {
+            None
+        } else {
+            Some(related)
+        },
+        ..convert_unlocated(issue, story_files)
+    };
+    Some((path, diagnostic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_same_named_files_apart() {
+        let dir = std::env::temp_dir().join(format!("tweec-lsp-test-{}", std::process::id()));
+        let (first, second) = (dir.join("a/story.twee"), dir.join("b/story.twee"));
+        std::fs::create_dir_all(first.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(second.parent().unwrap()).unwrap();
+        std::fs::write(&first, ":: Start\n[[Cave]]\n").unwrap();
+        std::fs::write(&second, ":: Cave\nSaved\n").unwrap();
+        let (first, second) = (
+            first.canonicalize().unwrap(),
+            second.canonicalize().unwrap(),
+        );
+
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.inputs = vec![
+            dir.join("a").to_string_lossy().into_owned(),
+            dir.join("b").to_string_lossy().into_owned(),
+        ];
+        let (connection, _client) = Connection::memory();
+        let mut server = Server::new(&config, &connection, &InitializeParams::default());
+        server.scratch = dir.join("scratch");
+        server
+            .documents
+            .insert(second.clone(), ":: Cave\nUnsaved\n".to_string());
+
+        let (paths, originals) = server.paths().unwrap();
+        let story_result = inputs::read_paths(&paths).take().0;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().all(|path| path.starts_with(&server.scratch)));
+        let mut files: Vec<&PathBuf> = originals.values().collect();
+        files.sort();
+        assert_eq!(files, vec![&first, &second]);
+        let story = story_result.ok().unwrap();
+        assert_eq!(story.passages["Cave"].content.content, "Unsaved\n");
+    }
+}
@@ -0,0 +1,122 @@
+//! Completes passage names in links and tags in passage headers
+//!
+//! Completions are found from the text of the line before the cursor: after
+//! an unclosed `[[`, the passage name being typed is completed, following any
+//! `|` or `->` separating the link's text from its target. In the tag block of
+//! a passage header, the tag being typed is completed from the tags already
+//! given to passages
+
+use super::index::Index;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Position, Range, TextEdit,
+};
+
+/// Characters that start a completion when typed
+pub const TRIGGER_CHARACTERS: &[&str] = &["[", "|", ">"];
+
+/// What is being completed, and the byte offset in the line where the text
+/// being completed starts
+#[derive(Debug, PartialEq)]
+enum Context {
+    Passage(usize),
+    Tag(usize),
+}
+
+/// Finds what is being completed at the end of `before`, the text of a line
+/// before the cursor
+fn context(before: &str) -> Option<Context> {
+    if let Some(open) = before.rfind("[[") {
+        let inside = &before[open + 2..];
+        if !inside.contains("]]") {
+            let start = [
+                inside.rfind('|').map(|i| i + 1),
+                inside.rfind("->").map(|i| i + 2),
+            ]
+            .iter()
+            .flatten()
+            .max()
+            .copied()
+            .unwrap_or(0);
+            return Some(Context::Passage(open + 2 + start));
+        }
+    }
+    if before.starts_with("::") {
+        let open = before.find('[')?;
+        let tags = &before[open + 1..];
+        if tags.contains(']') || before[..open].contains('{') {
+            return None;
+        }
+        let start = tags.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        return Some(Context::Tag(open + 1 + start));
+    }
+    None
+}
+
+/// Gets the byte offset in `line` of an LSP character offset, which counts
+/// UTF-16 code units
+fn byte_offset(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character as usize {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+/// Gets the completions at the given position in a document
+pub fn complete(index: &Index, text: &str, position: Position) -> Vec<CompletionItem> {
+    let line = text.lines().nth(position.line as usize).unwrap_or_default();
+    let before = &line[..byte_offset(line, position.character)];
+    let (start, kind, candidates): (usize, CompletionItemKind, Vec<&str>) = match context(before) {
+        Some(Context::Passage(start)) => (
+            start,
+            CompletionItemKind::REFERENCE,
+            index.passages().collect(),
+        ),
+        Some(Context::Tag(start)) => (start, CompletionItemKind::KEYWORD, index.tags().collect()),
+        None => return Vec::new(),
+    };
+    let typed = &before[start..];
+    let range = Range::new(
+        Position::new(position.line, before[..start].encode_utf16().count() as u32),
+        position,
+    );
+    let mut items: Vec<CompletionItem> = candidates
+        .into_iter()
+        .filter(|candidate| {
+            candidate
+                .to_lowercase()
+                .starts_with(&typed.trim_start().to_lowercase())
+        })
+        .map(|candidate| CompletionItem {
+            label: candidate.to_string(),
+            kind: Some(kind),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                candidate.to_string(),
+            ))),
+            ..Default::default()
+        })
+        .collect();
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_completion_context() {
+        assert_eq!(context("Go [[Ca"), Some(Context::Passage(5)));
+        assert_eq!(context("[[the beach|Be"), Some(Context::Passage(12)));
+        assert_eq!(context("[[the beach->"), Some(Context::Passage(13)));
+        assert_eq!(context("[[Cave]] and"), None);
+        assert_eq!(context(":: Start [intro en"), Some(Context::Tag(16)));
+        assert_eq!(context(":: Start [intro]"), None);
+        assert_eq!(context("Just [text"), None);
+        assert_eq!(byte_offset("é[[x", 2), 3);
+    }
+}
@@ -0,0 +1,239 @@
+//! An index of where passages are defined and linked to, and the tags they
+//! are given, for navigating and completing a story from an editor
+
+use crate::linter::links::{self, LinkSyntax};
+use crate::utils;
+use crate::StoryFiles;
+use codespan_reporting::files::Files;
+use lsp_types::{Position, Range};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use tweep::{FullContext, Story};
+
+/// A span of a source file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    /// The original path of the file
+    pub path: PathBuf,
+
+    /// Where the span is in the file
+    pub range: Range,
+}
+
+impl Span {
+    /// Whether the span is in the given file and includes the given position
+    fn contains(&self, path: &Path, position: Position) -> bool {
+        self.path == path && self.range.start <= position && position <= self.range.end
+    }
+}
+
+/// A link to a passage
+#[derive(Clone, Debug)]
+pub struct Link {
+    /// The name of the passage linked to
+    pub target: String,
+
+    /// Where the link is written
+    pub span: Span,
+}
+
+/// Where each passage of a story is defined, and every link and tag in it
+#[derive(Debug, Default)]
+pub struct Index {
+    /// The header of each passage, by name
+    definitions: HashMap<String, Span>,
+
+    /// Every link written in one of the configured syntaxes, including links
+    /// to passages that don't exist, in no particular order
+    links: Vec<Link>,
+
+    /// Every tag given to a passage
+    tags: BTreeSet<String>,
+
+    /// The tags of each passage, by name
+    passage_tags: HashMap<String, Vec<String>>,
+}
+
+impl Index {
+    /// Indexes the passages and links of the story, using `originals` to find
+    /// the original path of each file by name
+    pub fn new(
+        story: &Story,
+        story_files: &StoryFiles,
+        originals: &HashMap<String, PathBuf>,
+        syntaxes: &[LinkSyntax],
+    ) -> Self {
+        let span = |context: &FullContext| span(context, story_files, originals);
+        let definitions = utils::passage_headers(story)
+            .into_iter()
+            .filter_map(|(name, context)| Some((name, span(&context)?)))
+            .collect();
+        let links = links::story_links(story, syntaxes)
+            .into_values()
+            .flatten()
+            .filter_map(|link| {
+                Some(Link {
+                    target: link.target.trim().to_string(),
+                    span: span(&link.context)?,
+                })
+            })
+            .collect();
+        let tags = story
+            .passages
+            .values()
+            .flat_map(|passage| passage.header.tags.iter().cloned())
+            .collect();
+        let passage_tags = story
+            .passages
+            .iter()
+            .map(|(name, passage)| (name.clone(), passage.header.tags.clone()))
+            .collect();
+        Index {
+            definitions,
+            links,
+            tags,
+            passage_tags,
+        }
+    }
+
+    /// Gets the name of every passage, in no particular order
+    pub fn passages(&self) -> impl Iterator<Item = &str> {
+        self.definitions.keys().map(String::as_str)
+    }
+
+    /// Gets every tag given to a passage, in order
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().map(String::as_str)
+    }
+
+    /// Gets the tags of the named passage
+    pub fn tags_of(&self, name: &str) -> &[String] {
+        self.passage_tags.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Gets the passages defined in the given file, in position order
+    pub fn passages_in(&self, path: &Path) -> Vec<(&str, &Span)> {
+        let mut passages: Vec<(&str, &Span)> = self
+            .definitions
+            .iter()
+            .filter(|(_, span)| span.path == path)
+            .map(|(name, span)| (name.as_str(), span))
+            .collect();
+        passages.sort_by_key(|(_, span)| span.range.start);
+        passages
+    }
+
+    /// Gets the passages whose names contain the characters of `query` in
+    /// order, ignoring case, sorted by name
+    pub fn search(&self, query: &str) -> Vec<(&str, &Span)> {
+        let query = query.to_lowercase();
+        let mut passages: Vec<(&str, &Span)> = self
+            .definitions
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.to_lowercase();
+                let mut chars = name.chars();
+                query.chars().all(|q| chars.any(|c| c == q))
+            })
+            .map(|(name, span)| (name.as_str(), span))
+            .collect();
+        passages.sort_by_key(|(name, _)| *name);
+        passages
+    }
+
+    /// Gets where the named passage is defined
+    pub fn definition(&self, name: &str) -> Option<&Span> {
+        self.definitions.get(name)
+    }
+
+    /// Gets the name of the passage at the given position: the passage a link
+    /// there goes to, or the passage whose header is there
+    pub fn passage_at(&self, path: &Path, position: Position) -> Option<&str> {
+        self.links
+            .iter()
+            .find(|link| link.span.contains(path, position))
+            .map(|link| link.target.as_str())
+            .or_else(|| {
+                self.definitions
+                    .iter()
+                    .find(|(_, span)| span.contains(path, position))
+                    .map(|(name, _)| name.as_str())
+            })
+    }
+
+    /// Gets every link to the named passage, in file and position order
+    pub fn references(&self, name: &str) -> Vec<&Span> {
+        let mut references: Vec<&Span> = self
+            .links
+            .iter()
+            .filter(|link| link.target == name)
+            .map(|link| &link.span)
+            .collect();
+        references.sort_by(|a, b| (&a.path, a.range.start).cmp(&(&b.path, b.range.start)));
+        references
+    }
+}
+
+/// Gets the span of a context in one of the story's files
+pub(super) fn span(
+    context: &FullContext,
+    story_files: &StoryFiles,
+    originals: &HashMap<String, PathBuf>,
+) -> Option<Span> {
+    let id = story_files
+        .code_map
+        .lookup_id(context.get_file_name().clone()?)?;
+    let path = originals.get(story_files.name(id)?)?.clone();
+    Some(Span {
+        path,
+        range: super::range(story_files, id, context.get_byte_range()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_definitions_and_references() {
+        let input = ":: Start\n[[Cave]] or [[the beach->Beach]]\n\n:: Cave\n[[Beach]]\n\n\
+                     :: Beach\nEnd\n";
+        let dir = std::env::temp_dir().join(format!("tweec-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("story.twee");
+        std::fs::write(&path, input).unwrap();
+        let story_result = Story::from_path(&path).take().0;
+        std::fs::remove_dir_all(&dir).unwrap();
+        let story = story_result.as_ref().ok().unwrap();
+        let story_files = StoryFiles::new(&story_result);
+        let originals = story_files
+            .name(0)
+            .map(|name| (name.to_string(), path.clone()))
+            .into_iter()
+            .collect();
+        let index = Index::new(story, &story_files, &originals, LinkSyntax::DEFAULT);
+
+        let beach = index.definition("Beach").unwrap();
+        assert_eq!(beach.range.start, Position::new(6, 0));
+        assert_eq!(index.passage_at(&path, Position::new(1, 15)), Some("Beach"));
+        assert_eq!(index.passage_at(&path, Position::new(3, 4)), Some("Cave"));
+        assert_eq!(index.passage_at(&path, Position::new(1, 9)), None);
+        let references = index.references("Beach");
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[0].range.start, Position::new(1, 12));
+        assert_eq!(references[1].range.start, Position::new(4, 0));
+
+        let names = |passages: Vec<(&str, &Span)>| -> Vec<String> {
+            passages
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect()
+        };
+        assert_eq!(
+            names(index.passages_in(&path)),
+            vec!["Start", "Cave", "Beach"]
+        );
+        assert_eq!(names(index.search("ae")), vec!["Cave"]);
+        assert_eq!(names(index.search("")).len(), 3);
+    }
+}
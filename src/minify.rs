@@ -0,0 +1,310 @@
+//! Shrinks the compiled story for `--minify`
+//!
+//! Only whitespace and comments are removed, and only where doing so can't
+//! change how the story looks or runs: the story format's template has the
+//! whitespace between its tags collapsed, leaving its scripts and styles
+//! alone, while the story's own stylesheet and script are minified. Passage
+//! text is never changed
+
+/// Elements whose contents are left as they are
+const RAW_ELEMENTS: &[&str] = &["script", "style", "pre", "textarea"];
+
+/// Collapses the whitespace in a run of HTML text
+///
+/// Whitespace that only separates two tags on different lines is removed, and
+/// any other run of whitespace becomes a single space, which a browser shows
+/// the same way
+fn collapse(text: &str, out: &mut String) {
+    if text.trim().is_empty() {
+        if !text.contains('\n') && !text.is_empty() {
+            out.push(' ');
+        }
+        return;
+    }
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            space = true;
+        } else {
+            if space {
+                out.push(' ');
+                space = false;
+            }
+            out.push(c);
+        }
+    }
+    if space {
+        out.push(' ');
+    }
+}
+
+/// Gets the length of the tag at the start of `html`, which starts with `<`,
+/// allowing for `>` in quoted attribute values
+fn tag_len(html: &str) -> usize {
+    if html.starts_with("<!--") {
+        return html.find("-->").map(|end| end + 3).unwrap_or(html.len());
+    }
+    let mut quote = None;
+    for (i, c) in html.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return i + 1,
+            _ => (),
+        }
+    }
+    html.len()
+}
+
+/// Gets the name of the raw element a tag opens, if it opens one
+fn raw_element(tag: &str) -> Option<&'static str> {
+    let name: String = tag[1..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    RAW_ELEMENTS.iter().find(|raw| **raw == name).copied()
+}
+
+/// Minifies an HTML document by collapsing whitespace between and around
+/// tags, outside of scripts, styles, and preformatted text
+pub fn html(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find('<') {
+        collapse(&rest[..start], &mut out);
+        rest = &rest[start..];
+        let len = tag_len(rest);
+        let tag = &rest[..len];
+        out.push_str(tag);
+        rest = &rest[len..];
+        if let Some(name) = raw_element(tag) {
+            let close = format!("</{}", name);
+            let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+    collapse(rest, &mut out);
+    out
+}
+
+/// Characters that whitespace after them in CSS can always be removed from
+const CSS_AFTER: &str = "{};,:>";
+
+/// Characters that whitespace before them in CSS can always be removed from.
+/// `:` is not among them, since `a :hover` and `a:hover` differ
+const CSS_BEFORE: &str = "{};,>";
+
+/// Minifies CSS by removing comments, needless whitespace, and semicolons
+/// before `}`
+pub fn css(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut space = false;
+    while let Some(c) = chars.next() {
+        if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            let mut last = ' ';
+            for c in chars.by_ref() {
+                if last == '*' && c == '/' {
+                    break;
+                }
+                last = c;
+            }
+            space = true;
+            continue;
+        }
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if space {
+            let after_punctuation = out
+                .chars()
+                .last()
+                .is_none_or(|last| CSS_AFTER.contains(last));
+            if !after_punctuation && !CSS_BEFORE.contains(c) {
+                out.push(' ');
+            }
+            space = false;
+        }
+        if c == '}' && out.ends_with(';') {
+            out.pop();
+        }
+        out.push(c);
+        if c == '"' || c == '\'' {
+            let mut escaped = false;
+            for s in chars.by_ref() {
+                out.push(s);
+                match s {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    _ if s == c => break,
+                    _ => (),
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Characters after which a `/` in JavaScript starts a regular expression
+/// rather than being a division
+const JS_REGEX_AFTER: &str = "(,=:[!&|?{};+-*%<>~^";
+
+/// Minifies JavaScript by removing comments, indentation, and blank lines
+///
+/// Line breaks are kept, since removing them can change what a script means
+/// where semicolons are left out. Strings, template literals, and regular
+/// expressions are copied as they are
+pub fn js(source: &str) -> String {
+    let chars: Vec<char> = source.chars().collect();
+    let mut out = String::with_capacity(source.len());
+    // The last character of code written, for telling regular expressions
+    // from division
+    let mut last_code = '\n';
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '/' if next == Some('/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if next == Some('*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                if !out.ends_with([' ', '\n']) && !out.is_empty() {
+                    out.push(' ');
+                }
+                continue;
+            }
+            '"' | '\'' | '`' => {
+                let end = literal_end(&chars, i, c);
+                out.extend(&chars[i..end]);
+                last_code = c;
+                i = end;
+                continue;
+            }
+            '/' if JS_REGEX_AFTER.contains(last_code) || last_code == '\n' => {
+                let end = regex_end(&chars, i);
+                out.extend(&chars[i..end]);
+                last_code = '/';
+                i = end;
+                continue;
+            }
+            '\n' | '\r' => {
+                while out.ends_with([' ', '\t']) {
+                    out.pop();
+                }
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+            ' ' | '\t' => {
+                if !out.is_empty() && !out.ends_with([' ', '\n']) {
+                    out.push(' ');
+                }
+            }
+            _ => {
+                out.push(c);
+                last_code = c;
+            }
+        }
+        i += 1;
+    }
+    while out.ends_with(char::is_whitespace) {
+        out.pop();
+    }
+    out
+}
+
+/// Gets the end of the string or template literal starting at `start`
+fn literal_end(chars: &[char], start: usize, quote: char) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            c if c == quote => return i + 1,
+            // Unterminated strings end at the line
+            '\n' if quote != '`' => return i,
+            _ => (),
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Gets the end of the regular expression starting at `start`, including its
+/// flags
+fn regex_end(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    let mut class = false;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => class = true,
+            ']' => class = false,
+            '/' if !class => {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                return i;
+            }
+            '\n' => return i,
+            _ => (),
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minifies_html() {
+        let source = "<!DOCTYPE html>\n<html>\n  <head>\n    <title>{{STORY_NAME}}</title>\n    \
+                      <style>\n  body { x: y }\n</style>\n  </head>\n  <body>\n    <p>Hello,\n      \
+                      <b>world</b> <i title=\"a > b\">!</i></p>\n    <pre>\n  keep\n</pre>\n  \
+                      </body>\n</html>\n";
+        assert_eq!(
+            html(source),
+            "<!DOCTYPE html><html><head><title>{{STORY_NAME}}</title><style>\n  body { x: y \
+             }\n</style></head><body><p>Hello, <b>world</b> <i title=\"a > b\">!</i></p><pre>\n  \
+             keep\n</pre></body></html>"
+        );
+    }
+
+    #[test]
+    fn minifies_css() {
+        let source = "/* Theme */\nbody  {\n  color: red;\n  font-family: \"A  B\", serif;\n}\n\
+                      a :hover , p > em { margin: 0 auto; }\n";
+        assert_eq!(
+            css(source),
+            "body{color:red;font-family:\"A  B\",serif}a :hover,p>em{margin:0 auto}"
+        );
+    }
+
+    #[test]
+    fn minifies_js() {
+        let source =
+            "// Setup\nvar a = \"//  not a comment\";\n\n    /* block */ var re = /\\/\\//g;\n\
+                      var t = `line\n    kept`;\nvar b = a / 2; // half\n";
+        assert_eq!(
+            js(source),
+            "var a = \"//  not a comment\";\nvar re = /\\/\\//g;\nvar t = `line\n    kept`;\n\
+             var b = a / 2;"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Opens the compiled story for `--open`
+//!
+//! The story is opened with the system's default program for HTML files,
+//! unless `--open-with` names a program, such as `firefox`, or the config
+//! file's `opener` sets a command, such as
+//! `[ "firefox", "--private-window", "{output}" ]`. `{output}` is replaced with
+//! the absolute path of the compiled story, which is added as the last
+//! argument if none of the command's arguments has it
+
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Gets the arguments to run the opener with to open `output`
+fn arguments(args: &[String], output: &str) -> Vec<String> {
+    let mut arguments: Vec<String> = args
+        .iter()
+        .map(|arg| arg.replace("{output}", output))
+        .collect();
+    if !args.iter().any(|arg| arg.contains("{output}")) {
+        arguments.push(output.to_string());
+    }
+    arguments
+}
+
+/// Opens the story compiled to `output` with `opener`, a program and its
+/// arguments, or with the system's default program if it isn't given
+///
+/// The opener isn't waited for, since a browser may keep running
+pub fn open(opener: Option<&[String]>, output: &Path) -> Result<()> {
+    let (program, args) = match opener {
+        Some(command) => command
+            .split_first()
+            .ok_or_else(|| eyre!("The opener command is empty"))?,
+        None => {
+            return opener::open(output)
+                .wrap_err_with(|| format!("Failed to open output file {}", output.display()))
+        }
+    };
+    let absolute = output
+        .canonicalize()
+        .wrap_err_with(|| format!("Failed to find output file {:?}", output))?;
+    Command::new(program)
+        .args(arguments(args, &absolute.to_string_lossy()))
+        .stdin(Stdio::null())
+        .spawn()
+        .wrap_err_with(|| {
+            format!(
+                "Failed to open output file {} with {}",
+                output.display(),
+                program
+            )
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_output_to_arguments() {
+        let args = |args: &[&str]| -> Vec<String> {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            arguments(&args, "/tmp/story.html")
+        };
+        assert_eq!(args(&[]), vec!["/tmp/story.html"]);
+        assert_eq!(
+            args(&["--new-window", "file://{output}"]),
+            vec!["--new-window", "file:///tmp/story.html"]
+        );
+    }
+}
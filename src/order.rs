@@ -0,0 +1,82 @@
+//! Numbers a story's passages in a stable order, so that building the same
+//! source always gives the same output
+//!
+//! `tweep` numbers passages in whatever order its hash maps give, which
+//! changes from run to run. Instead, passages are numbered in the order they
+//! appear in the source, with files in the order they are read, or by name
+//! with `--sort-passages name`. The compiled story lists its passages in
+//! order of pid. Passages that weren't read from a Twee file, such as those of
+//! JSON stories, come last, by name
+
+use crate::utils;
+use crate::SortPassages;
+use std::collections::HashSet;
+use tweep::Story;
+
+/// Gets the names of the story's passages in the given order
+pub fn names(story: &Story, order: SortPassages) -> Vec<String> {
+    let mut names = Vec::with_capacity(story.passages.len());
+    if order == SortPassages::File {
+        let mut seen = HashSet::new();
+        for (name, _) in utils::passage_header_list(story) {
+            if story.passages.contains_key(&name) && seen.insert(name.clone()) {
+                names.push(name);
+            }
+        }
+        let mut rest: Vec<&String> = story
+            .passages
+            .keys()
+            .filter(|name| !seen.contains(*name))
+            .collect();
+        rest.sort();
+        names.extend(rest.into_iter().cloned());
+    } else {
+        names.extend(story.passages.keys().cloned());
+        names.sort();
+    }
+    names
+}
+
+/// Renumbers the story's passages from 1 in the given order
+pub fn renumber(story: &mut Story, order: SortPassages) {
+    for (i, name) in names(story, order).into_iter().enumerate() {
+        if let Some(passage) = story.passages.get_mut(&name) {
+            passage.content.pid = i + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_passages_in_order() {
+        let input = ":: StoryTitle\nOrdered\n\n:: Start\n[[Cave]]\n\n:: Cave\n[[Beach]]\n\n\
+                     :: Beach\nEnd\n\n:: Setup [script]\nvar a = 1;\n";
+        let path = std::env::temp_dir().join(format!("tweec-order-{}.twee", std::process::id()));
+        std::fs::write(&path, input).unwrap();
+        let mut story = Story::from_path(&path).take().0.ok().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let pids = |story: &Story| {
+            let mut pids: Vec<(usize, String)> = story
+                .passages
+                .iter()
+                .map(|(name, passage)| (utils::get_pid(passage), name.clone()))
+                .collect();
+            pids.sort();
+            pids
+        };
+
+        renumber(&mut story, SortPassages::File);
+        assert_eq!(
+            pids(&story),
+            vec![(1, "Start".into()), (2, "Cave".into()), (3, "Beach".into())]
+        );
+        renumber(&mut story, SortPassages::Name);
+        assert_eq!(
+            pids(&story),
+            vec![(1, "Beach".into()), (2, "Cave".into()), (3, "Start".into())]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Packages a compiled story for upload to itch.io
+//!
+//! itch.io plays an HTML game from a zip file with an `index.html` at its
+//! root, so the compiled story is renamed to `index.html` and bundled with any
+//! assets it uses. Assets keep their file or directory name, so relative paths
+//! in the story (e.g., `images/map.png`) still work once uploaded. The package
+//! is checked against itch.io's limits on the number and size of files.
+//!
+//! `tweec package` writes a package, as does `tweec build --package zip`, which
+//! also packages the media copied by `--assets-dir`
+
+use crate::utils;
+use crate::PackageConfig;
+use crate::StoryFormat;
+use clap::crate_version;
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use tweep::Story;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// The most files itch.io accepts in an HTML game
+pub const MAX_FILES: usize = 1000;
+
+/// The most bytes itch.io accepts in an HTML game, once extracted
+pub const MAX_SIZE: u64 = 500 * 1024 * 1024;
+
+/// Image types itch.io accepts for a cover
+const COVER_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif"];
+
+/// A manifest telling the itch app how to launch the package, as described in
+/// [itch.io's docs](https://itch.io/docs/itch/integrating/manifest.html)
+const ITCH_TOML: &str = "[[actions]]\nname = \"play\"\npath = \"index.html\"\n";
+
+/// The contents of a file in the package
+enum Contents {
+    /// Generated contents
+    Bytes(Vec<u8>),
+
+    /// A file to copy
+    File(PathBuf),
+}
+
+impl Contents {
+    fn size(&self) -> Result<u64> {
+        match self {
+            Contents::Bytes(bytes) => Ok(bytes.len() as u64),
+            Contents::File(path) => Ok(std::fs::metadata(path)
+                .wrap_err_with(|| format!("Failed to read {:?}", path))?
+                .len()),
+        }
+    }
+
+    fn read(&self) -> Result<Vec<u8>> {
+        match self {
+            Contents::Bytes(bytes) => Ok(bytes.clone()),
+            Contents::File(path) => {
+                std::fs::read(path).wrap_err_with(|| format!("Failed to read {:?}", path))
+            }
+        }
+    }
+}
+
+/// The files of a package, by their `/`-separated path in the package
+type Files = BTreeMap<String, Contents>;
+
+fn add(files: &mut Files, name: String, contents: Contents) -> Result<()> {
+    if files.contains_key(&name) {
+        return Err(eyre!("More than one file would be packaged as {}", name));
+    }
+    files.insert(name, contents);
+    Ok(())
+}
+
+/// Adds the file or directory at `path` to the package as `name`
+fn add_asset(files: &mut Files, path: &Path, name: String) -> Result<()> {
+    if !path.is_dir() {
+        return add(files, name, Contents::File(path.to_path_buf()));
+    }
+    let entries =
+        std::fs::read_dir(path).wrap_err_with(|| format!("Failed to read directory {:?}", path))?;
+    for entry in entries {
+        let entry = entry.wrap_err_with(|| format!("Failed to read directory {:?}", path))?;
+        let child = format!("{}/{}", name, entry.file_name().to_string_lossy());
+        add_asset(files, &entry.path(), child)?;
+    }
+    Ok(())
+}
+
+/// Gathers the files of the package
+fn files(
+    story: &Story,
+    story_format: &StoryFormat,
+    html: &str,
+    config: &PackageConfig,
+) -> Result<Files> {
+    let mut files = Files::new();
+    add(
+        &mut files,
+        "index.html".to_string(),
+        Contents::Bytes(html.as_bytes().to_vec()),
+    )?;
+
+    if let Some(cover) = &config.cover {
+        let extension = cover
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .filter(|ext| COVER_EXTENSIONS.contains(&ext.as_str()))
+            .ok_or_else(|| eyre!("Cover image {:?} is not a PNG, JPEG, or GIF", cover))?;
+        if !cover.is_file() {
+            return Err(eyre!("Cover image {:?} does not exist", cover));
+        }
+        add(
+            &mut files,
+            format!("cover.{}", extension),
+            Contents::File(cover.clone()),
+        )?;
+    }
+
+    if config.metadata {
+        let data = story.data.as_ref();
+        let metadata = json!({
+            "title": story.title,
+            "ifid": data.map(|data| &data.ifid),
+            "format": story_format.name,
+            "format-version": story_format.version,
+            "compiler": format!("tweec {}", crate_version!()),
+        });
+        add(
+            &mut files,
+            "metadata.json".to_string(),
+            Contents::Bytes(serde_json::to_vec_pretty(&metadata)?),
+        )?;
+    }
+
+    if config.itch_toml {
+        add(
+            &mut files,
+            ".itch.toml".to_string(),
+            Contents::Bytes(ITCH_TOML.as_bytes().to_vec()),
+        )?;
+    }
+
+    if let Some((path, name)) = &config.copied_assets {
+        add_asset(&mut files, path, name.clone())?;
+    }
+    for asset in &config.assets {
+        let name = std::fs::canonicalize(asset)
+            .ok()
+            .and_then(|path| path.file_name().map(|name| name.to_owned()))
+            .ok_or_else(|| eyre!("Asset {:?} does not exist", asset))?;
+        add_asset(&mut files, asset, name.to_string_lossy().into_owned())?;
+    }
+
+    if files.len() > MAX_FILES {
+        return Err(eyre!(
+            "The package has {} files, but itch.io accepts at most {}",
+            files.len(),
+            MAX_FILES
+        ));
+    }
+    let mut size = 0;
+    for contents in files.values() {
+        size += contents.size()?;
+    }
+    if size > MAX_SIZE {
+        return Err(eyre!(
+            "The package is {} MB, but itch.io accepts at most {} MB",
+            size / (1024 * 1024),
+            MAX_SIZE / (1024 * 1024)
+        ));
+    }
+    Ok(files)
+}
+
+fn write_zip(files: &Files, path: &Path) -> Result<()> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, contents) in files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(&contents.read()?)?;
+    }
+    let bytes = zip.finish()?.into_inner();
+    if let Some(parent) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .wrap_err_with(|| format!("Failed to create directory {:?}", parent))?;
+    }
+    utils::write_atomic(path, &bytes)
+        .wrap_err_with(|| format!("Failed to write output file {:?}", path))
+}
+
+fn write_dir(files: &Files, path: &Path) -> Result<()> {
+    for (name, contents) in files {
+        let file_path = path.join(name);
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        utils::write_atomic(&file_path, &contents.read()?)
+            .wrap_err_with(|| format!("Failed to write output file {:?}", file_path))?;
+    }
+    Ok(())
+}
+
+/// Packages the compiled story as configured, returning the path written
+///
+/// `html` is the compiled story, which becomes the package's `index.html`
+pub fn package(
+    story: &Story,
+    story_format: &StoryFormat,
+    html: &str,
+    config: &PackageConfig,
+) -> Result<PathBuf> {
+    let files = files(story, story_format, html, config)?;
+    let title = utils::sanitize_file_name(story.title.as_deref().unwrap_or("Untitled Story"));
+    let path = match &config.output {
+        Some(path) => path.clone(),
+        None if config.zip => PathBuf::from(format!("{}.zip", title)),
+        None => PathBuf::from(title),
+    };
+    if config.zip {
+        write_zip(&files, &path)?;
+    } else {
+        write_dir(&files, &path)?;
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn packages_story_with_assets() {
+        let dir = std::env::temp_dir().join(format!("tweec-package-{}", std::process::id()));
+        let images = dir.join("images");
+        std::fs::create_dir_all(images.join("maps")).unwrap();
+        std::fs::write(images.join("maps/town.png"), b"town").unwrap();
+        std::fs::write(dir.join("cover.PNG"), b"cover").unwrap();
+
+        let story = Story::from_string(":: StoryTitle\nPackaged\n\n:: Start\nHi\n".to_string())
+            .take()
+            .0
+            .ok()
+            .unwrap();
+        let story_format = StoryFormat::from_source(
+            r#"window.storyFormat({"name":"Test","version":"1.0.0","source":"{{STORY_DATA}}"});"#,
+        )
+        .unwrap();
+        let config = PackageConfig {
+            zip: true,
+            output: Some(dir.join("out.zip")),
+            assets: vec![images],
+            cover: Some(dir.join("cover.PNG")),
+            metadata: true,
+            itch_toml: true,
+            copied_assets: None,
+        };
+        let path = package(&story, &story_format, "<html></html>", &config).unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<&str> = zip.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            vec![
+                ".itch.toml",
+                "cover.png",
+                "images/maps/town.png",
+                "index.html",
+                "metadata.json"
+            ]
+        );
+        let mut index = String::new();
+        zip.by_name("index.html")
+            .unwrap()
+            .read_to_string(&mut index)
+            .unwrap();
+        assert_eq!(index, "<html></html>");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,196 @@
+//! Compile-time partials: reusable passages whose text is copied into other
+//! passages
+//!
+//! A passage tagged `partial` is not compiled into the story itself. Instead,
+//! its text replaces every `{{> Name}}` directive naming it. A directive may
+//! give parameters, as in `{{> "Shop Header" title="Bakery" open=yes}}`, which
+//! replace the matching `{{title}}` and `{{open}}` placeholders in the
+//! partial's text. Placeholders without a matching parameter are left as they
+//! are. Partials may use other partials, up to [`MAX_DEPTH`] deep.
+//!
+//! Directives are expanded by tweec and are independent of any macros the
+//! story format provides at runtime.
+//!
+//! [`MAX_DEPTH`]: constant.MAX_DEPTH.html
+
+use color_eyre::Result;
+use eyre::eyre;
+use std::collections::HashMap;
+use tweep::Story;
+
+/// The tag that marks a passage as a partial
+pub const TAG: &str = "partial";
+
+/// How deeply partials may be nested inside each other
+pub const MAX_DEPTH: usize = 8;
+
+const OPEN: &str = "{{>";
+const CLOSE: &str = "}}";
+
+/// A `{{> Name ...}}` directive
+#[derive(Debug, PartialEq)]
+struct Directive {
+    /// The name of the partial
+    name: String,
+
+    /// The parameters given to the partial, in order
+    params: Vec<(String, String)>,
+}
+
+/// Reads a name or value starting at the beginning of `text`, which is either
+/// quoted or ends at whitespace, `=`, or the end of the directive. Returns it
+/// along with the rest of the text
+fn word(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|&c| c == '"' || c == '\'');
+    match quote {
+        Some(quote) => {
+            let end = text[1..].find(quote)? + 1;
+            Some((text[1..end].to_string(), &text[end + 1..]))
+        }
+        None => {
+            let end = text
+                .find(|c: char| c.is_whitespace() || c == '=')
+                .unwrap_or(text.len());
+            let end = text[..end].find(CLOSE).unwrap_or(end);
+            if end == 0 {
+                None
+            } else {
+                Some((text[..end].to_string(), &text[end..]))
+            }
+        }
+    }
+}
+
+/// Parses the directive at the start of `text`, which begins just after the
+/// opening `{{>`. Returns the directive and the text following it
+fn parse_directive(text: &str) -> Result<(Directive, &str)> {
+    let unclosed = || eyre!("Unclosed partial directive {}{}", OPEN, text);
+    let (name, mut rest) = word(text.trim_start()).ok_or_else(unclosed)?;
+    let mut params = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(CLOSE) {
+            return Ok((Directive { name, params }, after));
+        }
+        let (key, after) = word(rest).ok_or_else(unclosed)?;
+        let after = after
+            .trim_start()
+            .strip_prefix('=')
+            .ok_or_else(|| eyre!("Parameter {} of partial {} has no value", key, name))?;
+        let (value, after) = word(after.trim_start()).ok_or_else(unclosed)?;
+        params.push((key, value));
+        rest = after;
+    }
+}
+
+/// Expands the directives in `text`, where `stack` holds the names of the
+/// partials currently being expanded
+fn expand_text(
+    text: &str,
+    partials: &HashMap<&str, &str>,
+    stack: &mut Vec<String>,
+) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN) {
+        expanded.push_str(&rest[..start]);
+        let (directive, after) = parse_directive(&rest[start + OPEN.len()..])?;
+        rest = after;
+
+        let partial = partials
+            .get(directive.name.as_str())
+            .ok_or_else(|| eyre!("No passage tagged {} named {}", TAG, directive.name))?;
+        if stack.contains(&directive.name) {
+            return Err(eyre!(
+                "Partial {} includes itself ({} -> {})",
+                directive.name,
+                stack.join(" -> "),
+                directive.name
+            ));
+        }
+        if stack.len() >= MAX_DEPTH {
+            return Err(eyre!(
+                "Partials are nested more than {} deep ({} -> {})",
+                MAX_DEPTH,
+                stack.join(" -> "),
+                directive.name
+            ));
+        }
+
+        let mut body = partial.to_string();
+        for (key, value) in &directive.params {
+            body = body.replace(&format!("{{{{{}}}}}", key), value);
+        }
+        stack.push(directive.name);
+        expanded.push_str(&expand_text(&body, partials, stack)?);
+        stack.pop();
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expands the partial directives in every passage of the story
+///
+/// Returns the expanded text of each passage that is not itself a partial,
+/// mapped by passage name. It is an error to use a partial that does not
+/// exist, or to nest partials recursively or too deeply
+pub fn expand(story: &Story) -> Result<HashMap<String, String>> {
+    let is_partial = |tags: &[String]| tags.iter().any(|tag| tag == TAG);
+    let partials: HashMap<&str, &str> = story
+        .passages
+        .iter()
+        .filter(|(_, passage)| is_partial(&passage.header.tags))
+        .map(|(name, passage)| (name.as_str(), passage.content.content.as_str()))
+        .collect();
+
+    story
+        .passages
+        .iter()
+        .filter(|(_, passage)| !is_partial(&passage.header.tags))
+        .map(|(name, passage)| {
+            let content = passage.content.content.as_str();
+            let expanded = if content.contains(OPEN) {
+                expand_text(content, &partials, &mut Vec::new())
+                    .map_err(|e| e.wrap_err(format!("Failed to expand partials in {}", name)))?
+            } else {
+                content.to_string()
+            };
+            Ok((name.clone(), expanded))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_with(text: &str, partials: &[(&str, &str)]) -> Result<String> {
+        let partials = partials.iter().copied().collect();
+        expand_text(text, &partials, &mut Vec::new())
+    }
+
+    #[test]
+    fn substitutes_parameters() {
+        let partials = [
+            ("Shop Header", "== {{title}} =={{> Hours}}"),
+            ("Hours", " ({{open}})"),
+        ];
+        let expanded = expand_with(
+            "Hi {{> \"Shop Header\" title='Ye Olde Bakery' open=9-5}} there",
+            &partials,
+        );
+        assert_eq!(
+            expanded.unwrap(),
+            "Hi == Ye Olde Bakery == ({{open}}) there"
+        );
+    }
+
+    #[test]
+    fn rejects_recursion() {
+        let partials = [("A", "a {{> B}}"), ("B", "b {{> A}}")];
+        let error = expand_with("{{> A}}", &partials).unwrap_err();
+        assert_eq!(error.to_string(), "Partial A includes itself (A -> B -> A)");
+        assert!(expand_with("{{> Missing}}", &partials).is_err());
+        assert!(expand_with("{{> A", &partials).is_err());
+    }
+}
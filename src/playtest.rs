@@ -0,0 +1,243 @@
+//! Walks scripted playthroughs of a story along its links, checking the
+//! passages they reach
+//!
+//! `tweec test` reads the test scripts in `tests/`, or the files and
+//! directories given with `--tests`. A script is a JSON file, which may have
+//! comments like the config file, holding a list of tests:
+//!
+//! ```json
+//! [
+//!   {
+//!     "name": "Reach the treasure",
+//!     "steps": [
+//!       { "expect": ["mouth of a cave"] },
+//!       { "choose": "Go in", "passage": "Inside", "expect": ["Dark"] }
+//!     ]
+//!   }
+//! ]
+//! ```
+//!
+//! Each test starts at the story's start passage, or at its `start` if given.
+//! A step follows the link named by `choose`, matched by the text the link
+//! shows or else by the passage it goes to, then checks that the passage
+//! reached is `passage` and that its text contains everything in `expect`.
+//! Every field of a step may be left out.
+//!
+//! Links are those of the link graph, in the configured syntaxes. Macros are
+//! not run, so a step can follow any link written in the passage, whether or
+//! not the story would show it at that point
+
+use crate::issue::LintWarning;
+use crate::linter::graph::LinkGraph;
+use crate::linter::links;
+use crate::utils;
+use color_eyre::Result;
+use eyre::WrapErr;
+use json_comments::StripComments;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tweep::FullContext;
+
+/// Name of the failures reported, used for their diagnostics
+pub const NAME: &str = "PlaythroughFailed";
+
+/// The directory searched for test scripts when none are given
+pub const DEFAULT_DIR: &str = "tests";
+
+/// A step of a playthrough
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// The text or target of the link to follow
+    #[serde(default)]
+    pub choose: Option<String>,
+
+    /// The passage that should be reached
+    #[serde(default)]
+    pub passage: Option<String>,
+
+    /// Text the passage reached should contain
+    #[serde(default)]
+    pub expect: Vec<String>,
+}
+
+/// A scripted playthrough of a story
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Test {
+    /// The name of the test, used when reporting failures
+    pub name: String,
+
+    /// The passage to start at, instead of the story's start passage
+    #[serde(default)]
+    pub start: Option<String>,
+
+    /// The steps to take, in order
+    pub steps: Vec<Step>,
+}
+
+/// Reads the tests in one script
+fn read_script(path: &Path) -> Result<Vec<Test>> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read test script {:?}", path))?;
+    serde_json::from_reader(StripComments::new(contents.as_bytes()))
+        .wrap_err_with(|| format!("Failed to parse test script {:?}", path))
+}
+
+/// Reads the tests in the given scripts, reading every `.json` file in any
+/// directories given, in order of name
+pub fn read_scripts(paths: &[PathBuf]) -> Result<Vec<Test>> {
+    let mut tests = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            tests.extend(read_script(path)?);
+            continue;
+        }
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+            .wrap_err_with(|| format!("Failed to read test directory {:?}", path))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.is_file() && file.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+        for file in files {
+            tests.extend(read_script(&file)?);
+        }
+    }
+    Ok(tests)
+}
+
+/// Runs a test over the story's link graph, returning its first failure
+///
+/// `headers` gives the header of each passage, as from
+/// [`utils::passage_headers`], to point failures at
+///
+/// [`utils::passage_headers`]: ../utils/fn.passage_headers.html
+pub fn run(
+    test: &Test,
+    graph: &LinkGraph,
+    headers: &HashMap<String, FullContext>,
+) -> Option<LintWarning> {
+    let story = graph.story();
+    let fail = |step: usize, message: String, context: Option<FullContext>| {
+        let step = match step {
+            0 => String::new(),
+            step => format!(", step {}", step),
+        };
+        Some(LintWarning::new(
+            NAME,
+            format!("Test \"{}\"{}: {}", test.name, step, message),
+            context,
+        ))
+    };
+
+    let start = test
+        .start
+        .as_deref()
+        .or_else(|| story.get_start_passage_name());
+    let mut current = match start {
+        Some(start) if story.passages.contains_key(start) => start.to_string(),
+        Some(start) => return fail(0, format!("No start passage named {}", start), None),
+        None => return fail(0, "The story has no start passage".to_string(), None),
+    };
+
+    for (i, step) in test.steps.iter().enumerate() {
+        let mut chosen = None;
+        if let Some(choice) = &step.choose {
+            let edges = graph.outgoing(&current);
+            let edge = edges
+                .iter()
+                .find(|edge| links::link_text(&edge.link) == *choice)
+                .or_else(|| edges.iter().find(|edge| edge.target == choice));
+            let edge = match edge {
+                Some(edge) => edge,
+                None => {
+                    let shown: Vec<String> = edges
+                        .iter()
+                        .map(|edge| format!("\"{}\"", links::link_text(&edge.link)))
+                        .collect();
+                    let warning = fail(
+                        i + 1,
+                        format!("Passage {} has no link \"{}\"", current, choice),
+                        headers.get(&current).cloned(),
+                    )?;
+                    return Some(if shown.is_empty() {
+                        warning.with_note("It has no links")
+                    } else {
+                        warning.with_note(format!("Its links are {}", shown.join(", ")))
+                    });
+                }
+            };
+            chosen = Some(edge.link.context.clone());
+            current = edge.target.to_string();
+        }
+
+        if let Some(expected) = &step.passage {
+            if *expected != current {
+                return fail(
+                    i + 1,
+                    format!("Reached passage {} instead of {}", current, expected),
+                    chosen.or_else(|| headers.get(&current).cloned()),
+                );
+            }
+        }
+
+        let content = utils::get_content(&story.passages[&current]);
+        for text in &step.expect {
+            if !content.contains(text.as_str()) {
+                return fail(
+                    i + 1,
+                    format!("Passage {} does not contain \"{}\"", current, text),
+                    headers.get(&current).cloned(),
+                );
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::links::LinkSyntax;
+    use tweep::Story;
+
+    #[test]
+    fn walks_playthroughs() {
+        let input = ":: Start\nYou stand at the mouth of a cave.\n[[Go in->Inside]] [[Leave]]\n\n\
+                     :: Inside\nDark.\n\n:: Leave\nBye.\n"
+            .to_string();
+        let story = Story::from_string(input).take().0.ok().unwrap();
+        let graph = LinkGraph::new(&story, LinkSyntax::DEFAULT);
+        let headers = utils::passage_headers(&story);
+        let tests: Vec<Test> = serde_json::from_str(
+            r#"[
+                { "name": "in", "steps": [
+                    { "expect": ["mouth"] },
+                    { "choose": "Go in", "passage": "Inside", "expect": ["Dark"] }
+                ] },
+                { "name": "out", "steps": [{ "choose": "Leave", "expect": ["Bye"] }] },
+                { "name": "wrong", "steps": [{ "choose": "Inside", "passage": "Leave" }] },
+                { "name": "missing", "steps": [{ "choose": "Swim" }] },
+                { "name": "text", "start": "Inside", "steps": [{ "expect": ["Light"] }] }
+            ]"#,
+        )
+        .unwrap();
+        let messages: Vec<Option<String>> = tests
+            .iter()
+            .map(|test| run(test, &graph, &headers).map(|warning| warning.message))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                None,
+                None,
+                Some("Test \"wrong\", step 1: Reached passage Inside instead of Leave".to_string()),
+                Some("Test \"missing\", step 1: Passage Start has no link \"Swim\"".to_string()),
+                Some(
+                    "Test \"text\", step 1: Passage Inside does not contain \"Light\"".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! Reads a project's own config file, `tweec.toml`
+//!
+//! The file is found by walking up from the directory of the first input, or
+//! from the current directory if no inputs are given, so that it can sit in
+//! the project's root. Its settings apply on top of those in the config file,
+//! and options given on the command line apply on top of both. Paths in it are
+//! relative to the directory it is in. For example:
+//!
+//! ```toml
+//! # Build with just `tweec` in this directory
+//! format = "sugarcube-2"
+//! output = "dist/My Story.html"
+//! inputs = ["src/**/*.twee"]
+//! allow = ["DeadEnd"]
+//! deny = ["DeadLink", "UnreachablePassage"]
+//! forbid = ["WhitespaceInLink"]
+//!
+//! [placeholders]
+//! ANALYTICS = "<script src=\"stats.js\"></script>"
+//! ```
+//!
+//! `output_dir` sets a directory for the compiled story and the files written
+//! next to it, as `--output-dir` does, and `output` is then relative to it.
+//! A `[pwa]` table makes the compiled story installable as a web app, as
+//! `--pwa` does, with the given `title`, `icon`, and `theme_color`. See
+//! [`pwa`].
+//! `inputs` are only used when no inputs are given on the command line,
+//! `allow`, `warn`, `deny`, and `forbid` give warnings levels as in the config
+//! file, `baseline` sets a [baseline file] of known warnings, `max_warnings`
+//! fails linting when more warnings than it are reported, as `--max-warnings`
+//! does, and `extensions` sets the extensions of the Twee files read from
+//! input directories. Each
+//! key in `placeholders` names a placeholder like `{{ANALYTICS}}` in the story
+//! format's source, filled in with its value unless `--define` gives another.
+//! `title`, `start`, and `zoom` build the story with that title, start
+//! passage, and zoom level in place of those in `StoryTitle` and `StoryData`,
+//! as `--title`, `--start`, and `--zoom` do. `format` may also be the URL of a
+//! format.js, which is downloaded once and cached, as with `--format`.
+//!
+//! So that everyone on a team builds with the same format, `format` can pin an
+//! exact version and the SHA-256 hash of its format.js, which is checked
+//! before building:
+//!
+//! ```toml
+//! format = { name = "sugarcube-2", version = "2.36.1", sha256 = "4f0e..." }
+//! ```
+//!
+//! Only [part of TOML] is supported
+//!
+//! [baseline file]: ../baseline/index.html
+//! [`pwa`]: ../pwa/index.html
+//! [part of TOML]: ../toml/index.html
+
+use crate::formats;
+use crate::issue::{self, LintLevel};
+use crate::stdin;
+use crate::story_format;
+use crate::toml;
+use crate::PwaConfig;
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use log::info;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The name of a project's config file
+pub const FILE_NAME: &str = "tweec.toml";
+
+/// The story format, by name or pinned to a version and hash
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FormatSetting {
+    Name(String),
+    Pinned {
+        name: String,
+        #[serde(default)]
+        version: Option<String>,
+        #[serde(default)]
+        sha256: Option<String>,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProjectConfigInternal {
+    #[serde(default)]
+    format: Option<FormatSetting>,
+    #[serde(default)]
+    output: Option<String>,
+    #[serde(default)]
+    output_dir: Option<String>,
+    #[serde(default)]
+    inputs: Vec<String>,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    warn: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    #[serde(default)]
+    forbid: Vec<String>,
+    #[serde(default)]
+    extensions: Option<Vec<String>>,
+    #[serde(default)]
+    baseline: Option<String>,
+    #[serde(default)]
+    max_warnings: Option<usize>,
+    #[serde(default)]
+    placeholders: BTreeMap<String, String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    start: Option<String>,
+    #[serde(default)]
+    zoom: Option<f32>,
+    #[serde(default)]
+    pwa: Option<PwaConfig>,
+}
+
+/// Settings read from a project's `tweec.toml`, with paths made relative to
+/// the current directory
+#[derive(Debug, Default)]
+pub struct ProjectConfig {
+    /// The story format by name (e.g., sugarcube-2), if set, with the version
+    /// it is pinned to after an `@` (e.g., sugarcube-2@2.36.1)
+    pub format: Option<String>,
+
+    /// The SHA-256 hash the story format's format.js must have, in
+    /// lowercase hexadecimal, if pinned
+    pub format_sha256: Option<String>,
+
+    /// The html file to output, if set
+    pub output_file: Option<String>,
+
+    /// The directory to write the output to, if set
+    pub output_dir: Option<PathBuf>,
+
+    /// Inputs to use when none are given on the command line, which may be
+    /// glob patterns
+    pub inputs: Vec<String>,
+
+    /// Levels of warnings by name, from the allow, warn, deny, and forbid
+    /// lists
+    pub levels: Vec<(String, LintLevel)>,
+
+    /// Extensions of the Twee files read from input directories, if set
+    pub extensions: Option<Vec<String>>,
+
+    /// Baseline file of known warnings, if set
+    pub baseline: Option<PathBuf>,
+
+    /// Most warnings allowed before linting fails, if set
+    pub max_warnings: Option<usize>,
+
+    /// Values of custom placeholders in the story format's source, by name
+    pub placeholders: BTreeMap<String, String>,
+
+    /// Title to build the story with, if set
+    pub title: Option<String>,
+
+    /// Passage to start the story at, if set
+    pub start: Option<String>,
+
+    /// Zoom level to build the story with, if set
+    pub zoom: Option<f32>,
+
+    /// How to make the compiled story installable as a web app, if set
+    pub pwa: Option<PwaConfig>,
+}
+
+impl ProjectConfig {
+    /// Finds and loads the project config file for the given inputs, if
+    /// there is one
+    pub fn find(inputs: &[String]) -> Result<Option<Self>> {
+        let start = inputs
+            .iter()
+            .filter(|input| *input != stdin::INPUT)
+            .find_map(|input| Path::new(input).ancestors().find(|path| path.is_dir()))
+            .map(Path::to_path_buf);
+        let start = match start {
+            Some(start) => start,
+            None => std::env::current_dir().wrap_err_with(|| "Failed to get PWD")?,
+        };
+        let start = start.canonicalize().unwrap_or(start);
+        match start
+            .ancestors()
+            .map(|dir| dir.join(FILE_NAME))
+            .find(|path| path.is_file())
+        {
+            Some(path) => {
+                let project_config = ProjectConfig::load(&path)?;
+                info!("Read project config file {:?}", path);
+                Ok(Some(project_config))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Loads a project config file
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Error reading project config file {:?}", path))?;
+        let internal: ProjectConfigInternal = toml::parse(&source)
+            .and_then(|table| Ok(serde_json::from_value(Value::Object(table))?))
+            .wrap_err_with(|| format!("Error parsing project config file {:?}", path))?;
+        if let Some(zoom) = internal
+            .zoom
+            .filter(|zoom| *zoom <= 0. || !zoom.is_finite())
+        {
+            return Err(eyre!("zoom must be a positive number, not {}", zoom))
+                .wrap_err_with(|| format!("Error in project config file {:?}", path));
+        }
+        for name in internal.placeholders.keys() {
+            story_format::check_placeholder_name(name)
+                .map_err(|e| eyre!(e))
+                .wrap_err_with(|| format!("Error in project config file {:?}", path))?;
+        }
+
+        let (format, format_sha256) = match internal.format {
+            Some(FormatSetting::Name(name)) => (Some(name), None),
+            Some(FormatSetting::Pinned {
+                name,
+                version,
+                sha256,
+            }) => {
+                let format = match version {
+                    Some(_) if formats::is_url(&name) => {
+                        return Err(eyre!(
+                            "format can't pin a version of a format given by URL; pin its sha256 \
+                             instead"
+                        ))
+                        .wrap_err_with(|| format!("Error in project config file {:?}", path));
+                    }
+                    Some(version) => format!("{}@{}", name, version),
+                    None => name,
+                };
+                if let Some(sha256) = &sha256 {
+                    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(eyre!(
+                            "format's sha256 must be 64 hexadecimal digits, not {:?}",
+                            sha256
+                        ))
+                        .wrap_err_with(|| format!("Error in project config file {:?}", path));
+                    }
+                }
+                (Some(format), sha256.map(|sha256| sha256.to_lowercase()))
+            }
+            None => (None, None),
+        };
+
+        let root = path.parent().unwrap_or_else(|| Path::new(""));
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let resolve = |relative: &str| {
+            let path = root.join(relative);
+            match path.strip_prefix(&cwd) {
+                Ok(path) if path.as_os_str().is_empty() => ".".to_string(),
+                Ok(path) => path.to_string_lossy().into_owned(),
+                Err(_) => path.to_string_lossy().into_owned(),
+            }
+        };
+        let output_dir = internal
+            .output_dir
+            .as_deref()
+            .map(resolve)
+            .map(PathBuf::from);
+        Ok(ProjectConfig {
+            format,
+            format_sha256,
+            output_file: internal.output.as_deref().map(|output| match output {
+                // Standard output isn't a path, and the output is relative to
+                // the output directory if there is one
+                stdin::INPUT => output.to_string(),
+                _ if output_dir.is_some() => output.to_string(),
+                _ => resolve(output),
+            }),
+            output_dir,
+            inputs: internal.inputs.iter().map(|input| resolve(input)).collect(),
+            levels: issue::lint_levels(
+                &internal.allow,
+                &internal.warn,
+                &internal.deny,
+                &internal.forbid,
+            ),
+            extensions: internal.extensions,
+            baseline: internal.baseline.as_deref().map(resolve).map(PathBuf::from),
+            max_warnings: internal.max_warnings,
+            placeholders: internal.placeholders,
+            title: internal.title,
+            start: internal.start,
+            zoom: internal.zoom,
+            pwa: internal.pwa.map(|pwa| PwaConfig {
+                icon: pwa
+                    .icon
+                    .map(|icon| PathBuf::from(resolve(&icon.to_string_lossy()))),
+                ..pwa
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FormatConfig;
+    use crate::{CliConfig, Config, ConfigFile};
+    use serde_json::json;
+
+    #[test]
+    fn layers_between_config_file_and_command_line() {
+        let dir = std::env::temp_dir().join(format!("tweec-project-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILE_NAME);
+        std::fs::write(
+            &path,
+            r#"output = "dist/Story.html"
+extensions = ["tw"]
+title = "Project"
+zoom = 1.5
+deny = ["DeadEnd", "DeadLink"]
+
+[pwa]
+title = "App"
+icon = "icon.png"
+"#,
+        )
+        .unwrap();
+        let project_config = ProjectConfig::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            project_config.output_file.as_deref().map(PathBuf::from),
+            Some(dir.join("dist/Story.html"))
+        );
+        assert_eq!(
+            project_config.pwa.as_ref().unwrap().icon,
+            Some(dir.join("icon.png"))
+        );
+
+        let mut config_file = ConfigFile {
+            extensions: Some(vec!["twee".to_string()]),
+            ..ConfigFile::default()
+        };
+        let allowed = json!({ "allow": ["DeadEnd", "DeadLink", "UnreachablePassage"] });
+        config_file.format_configs.insert(
+            "default".to_string(),
+            serde_json::from_value::<FormatConfig>(allowed).unwrap(),
+        );
+        let config = Config::builder()
+            .config_file(config_file)
+            .project_config(project_config)
+            .cli_config(CliConfig {
+                inputs: vec!["src".to_string()],
+                format: Some("formats/test/format.js".to_string()),
+                title: Some("Command line".to_string()),
+                pwa: true,
+                ..CliConfig::default()
+            })
+            .allow(["DeadLink"])
+            .build()
+            .unwrap();
+        let level = |name| issue::lint_level(name, &config);
+
+        // The command line beats the project config
+        assert_eq!(config.title.as_deref(), Some("Command line"));
+        assert_eq!(level("DeadLink"), LintLevel::Allow);
+        // The project config beats the config file
+        assert_eq!(config.extensions, vec!["tw"]);
+        assert_eq!(level("DeadEnd"), LintLevel::Deny);
+        assert_eq!(level("UnreachablePassage"), LintLevel::Allow);
+        // The project config fills in what the command line doesn't give
+        assert_eq!(config.zoom, Some(1.5));
+        assert_eq!(
+            config.output_file.map(PathBuf::from),
+            Some(dir.join("dist/Story.html"))
+        );
+        // --pwa has no settings, so the project's are kept
+        assert_eq!(config.pwa.unwrap().title.as_deref(), Some("App"));
+    }
+}
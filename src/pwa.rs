@@ -0,0 +1,210 @@
+//! Makes a compiled story installable as a progressive web app
+//!
+//! With `--pwa`, or a `[pwa]` table in the project's `tweec.toml`, a web app
+//! manifest and a service worker are written next to the compiled story, and
+//! the story links to both. The service worker caches the story, the
+//! manifest, and the icon when the story is first opened, so it can be played
+//! offline afterward. For example:
+//!
+//! ```toml
+//! [pwa]
+//! title = "The Cave"
+//! icon = "images/icon.png"
+//! theme_color = "#203040"
+//! ```
+//!
+//! The title defaults to the story's title, and the icon is copied next to the
+//! compiled story
+
+use crate::story_html::escape;
+use crate::utils;
+use crate::PwaConfig;
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use serde_json::json;
+use std::path::Path;
+use tweep::Story;
+
+/// The name of the web app manifest written next to the compiled story
+pub const MANIFEST: &str = "manifest.webmanifest";
+
+/// The name of the service worker written next to the compiled story
+pub const SERVICE_WORKER: &str = "sw.js";
+
+/// Gets the media type of an icon from its extension
+fn icon_type(icon: &Path) -> Option<&'static str> {
+    let extension = icon.extension()?.to_string_lossy().to_lowercase();
+    Some(match extension.as_str() {
+        "png" => "image/png",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        _ => return None,
+    })
+}
+
+/// Gets the name an icon is copied to next to the compiled story
+fn icon_name(icon: &Path) -> Result<String> {
+    icon.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| eyre!("App icon {:?} is not a file", icon))
+}
+
+/// Links the compiled story to the manifest and registers the service worker,
+/// adding the theme color if one is given
+pub fn link(html: &str, pwa: &PwaConfig) -> String {
+    let mut head = format!("<link rel=\"manifest\" href=\"{}\">", MANIFEST);
+    if let Some(theme_color) = &pwa.theme_color {
+        head.push_str(&format!(
+            "<meta name=\"theme-color\" content=\"{}\">",
+            escape(theme_color)
+        ));
+    }
+    let script = format!(
+        "<script>if (\"serviceWorker\" in navigator) {{ \
+         navigator.serviceWorker.register(\"{}\"); }}</script>",
+        SERVICE_WORKER
+    );
+
+    let mut linked = html.to_string();
+    let lowercase = html.to_ascii_lowercase();
+    match lowercase.rfind("</body>") {
+        Some(i) => linked.insert_str(i, &script),
+        None => linked.push_str(&script),
+    }
+    match lowercase.find("</head>") {
+        Some(i) => linked.insert_str(i, &head),
+        None => linked.insert_str(0, &head),
+    }
+    linked
+}
+
+/// Gets the service worker, which caches `files` under a name that changes
+/// with `version`, removing the caches of earlier versions
+fn service_worker(files: &[String], version: &str) -> String {
+    format!(
+        r#"const CACHE = "tweec-{}";
+const FILES = {};
+
+self.addEventListener("install", (event) => {{
+  event.waitUntil(caches.open(CACHE).then((cache) => cache.addAll(FILES)));
+  self.skipWaiting();
+}});
+
+self.addEventListener("activate", (event) => {{
+  event.waitUntil(
+    caches.keys().then((keys) =>
+      Promise.all(
+        keys
+          .filter((key) => key.startsWith("tweec-") && key !== CACHE)
+          .map((key) => caches.delete(key))
+      )
+    )
+  );
+}});
+
+self.addEventListener("fetch", (event) => {{
+  event.respondWith(
+    caches.match(event.request).then((cached) => cached || fetch(event.request))
+  );
+}});
+"#,
+        version,
+        json!(files)
+    )
+}
+
+/// Writes the manifest and service worker next to the story compiled to
+/// `output_path`, and copies the icon there if one is given
+///
+/// `html` is the compiled story, as linked by [`link`], whose hash versions
+/// the service worker's cache so that a rebuilt story replaces the old one
+///
+/// [`link`]: fn.link.html
+pub fn write(story: &Story, html: &str, output_path: &Path, pwa: &PwaConfig) -> Result<()> {
+    let dir = match output_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let html_name = output_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let title = pwa
+        .title
+        .as_deref()
+        .or(story.title.as_deref())
+        .unwrap_or("Untitled Story");
+
+    let mut files = vec![format!("./{}", html_name), format!("./{}", MANIFEST)];
+    let mut manifest = json!({
+        "name": title,
+        "short_name": title,
+        "start_url": format!("./{}", html_name),
+        "display": "standalone",
+    });
+    if let Some(theme_color) = &pwa.theme_color {
+        manifest["theme_color"] = json!(theme_color);
+        manifest["background_color"] = json!(theme_color);
+    }
+    if let Some(icon) = &pwa.icon {
+        let media_type = icon_type(icon).ok_or_else(|| {
+            eyre!(
+                "App icon {:?} is not a PNG, SVG, WebP, JPEG, GIF, or ICO image",
+                icon
+            )
+        })?;
+        let name = icon_name(icon)?;
+        let destination = dir.join(&name);
+        let same = match (icon.canonicalize(), destination.canonicalize()) {
+            (Ok(icon), Ok(destination)) => icon == destination,
+            _ => false,
+        };
+        if !same {
+            std::fs::copy(icon, &destination).wrap_err_with(|| {
+                format!("Failed to copy app icon {:?} to {:?}", icon, destination)
+            })?;
+        }
+        manifest["icons"] = json!([{ "src": name, "sizes": "any", "type": media_type }]);
+        files.push(format!("./{}", name));
+    }
+
+    let manifest_path = dir.join(MANIFEST);
+    utils::write_atomic(
+        &manifest_path,
+        serde_json::to_string_pretty(&manifest)?.as_bytes(),
+    )
+    .wrap_err_with(|| format!("Failed to write web app manifest {:?}", manifest_path))?;
+
+    let digest = ring::digest::digest(&ring::digest::SHA256, html.as_bytes());
+    let version: String = digest.as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let worker_path = dir.join(SERVICE_WORKER);
+    utils::write_atomic(&worker_path, service_worker(&files, &version).as_bytes())
+        .wrap_err_with(|| format!("Failed to write service worker {:?}", worker_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_manifest_and_service_worker() {
+        let pwa = PwaConfig {
+            theme_color: Some("#203040".to_string()),
+            ..PwaConfig::default()
+        };
+        let html = link("<html><HEAD></HEAD><body>Hi</body></html>", &pwa);
+        assert!(html.starts_with(
+            "<html><HEAD><link rel=\"manifest\" href=\"manifest.webmanifest\">\
+             <meta name=\"theme-color\" content=\"#203040\"></HEAD>"
+        ));
+        assert!(html.ends_with("register(\"sw.js\"); }</script></body></html>"));
+        assert_eq!(icon_type(Path::new("icon.PNG")), Some("image/png"));
+        assert_eq!(icon_type(Path::new("icon.bmp")), None);
+    }
+}
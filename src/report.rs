@@ -0,0 +1,309 @@
+//! Exports outstanding issues as tickets for bulk import into an issue
+//! tracker
+//!
+//! Issues are grouped by file and rule, so that each group becomes one
+//! ticket listing every occurrence. The report can be written as Markdown, for
+//! pasting or reading, or as JSON with a `title`, `body`, and `labels` for each
+//! ticket, as used by GitHub and GitLab import tools.
+
+use crate::issue::Issue;
+use crate::rules;
+use crate::utils;
+use crate::ReportConfig;
+use crate::ReportFormat;
+use crate::StoryFiles;
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use codespan_reporting::files::Files;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// A single occurrence of an issue
+struct Occurrence {
+    /// Line and column of the issue, both 1-based, if it has a location
+    position: Option<(usize, usize)>,
+
+    /// The issue's message, followed by any notes
+    message: String,
+}
+
+/// A ticket: the occurrences of one rule in one file
+struct Ticket {
+    file: String,
+    rule: String,
+    severity: &'static str,
+    occurrences: Vec<Occurrence>,
+}
+
+impl Ticket {
+    fn title(&self) -> String {
+        format!(
+            "{}: {} in {}",
+            self.rule,
+            plural(self.occurrences.len(), "occurrence"),
+            self.file
+        )
+    }
+
+    fn body(&self) -> String {
+        let mut body = String::new();
+        if let Some(rule) = rules::find(&self.rule) {
+            body.push_str(&format!("{}.\n\n", rule.description));
+        }
+        body.push_str(&format!(
+            "tweec reports this as {} {}:\n\n",
+            article(self.severity),
+            self.severity
+        ));
+        for occurrence in &self.occurrences {
+            let location = match occurrence.position {
+                Some((line, column)) => format!("{}:{}:{}", self.file, line, column),
+                None => self.file.clone(),
+            };
+            let message = occurrence.message.replace('\n', "\n  ");
+            body.push_str(&format!("- [ ] `{}` {}\n", location, message));
+        }
+        body
+    }
+
+    fn labels(&self) -> Vec<String> {
+        vec![
+            "tweec".to_string(),
+            self.rule.clone(),
+            self.severity.to_string(),
+        ]
+    }
+}
+
+/// Formats a count of things, pluralizing the noun if needed
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+fn article(word: &str) -> &'static str {
+    match word.chars().next() {
+        Some('a') | Some('e') | Some('i') | Some('o') | Some('u') => "an",
+        _ => "a",
+    }
+}
+
+/// Groups the issues into tickets by file and rule, leaving out issues that
+/// are not errors if `errors_only` is true
+fn tickets(
+    issues: &[Issue],
+    story_files: &StoryFiles,
+    inputs: &[String],
+    extensions: &[String],
+    errors_only: bool,
+) -> Vec<Ticket> {
+    let paths = utils::file_paths(inputs, extensions);
+    let mut tickets: BTreeMap<(String, String), Ticket> = BTreeMap::new();
+    for issue in issues {
+        let diagnostic = issue.report(story_files);
+        let rule = diagnostic.code.clone().unwrap_or_default();
+        let severity = match diagnostic.severity {
+            Severity::Bug | Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note | Severity::Help => "note",
+        };
+        if errors_only && severity != "error" {
+            continue;
+        }
+        let primary = diagnostic
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary);
+        let (file, position) = match primary {
+            Some(label) => {
+                let name = story_files.name(label.file_id).unwrap_or_default();
+                let file = paths
+                    .get(name)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| name.to_string());
+                let (line, column) = story_files.position(label.file_id, label.range.start);
+                (file, Some((line + 1, column + 1)))
+            }
+            None => ("the story".to_string(), None),
+        };
+
+        let mut message = diagnostic.message.clone();
+        for note in &diagnostic.notes {
+            message.push('\n');
+            message.push_str(note);
+        }
+
+        let ticket = tickets
+            .entry((file.clone(), rule.clone()))
+            .or_insert_with(|| Ticket {
+                file,
+                rule,
+                severity,
+                occurrences: Vec::new(),
+            });
+        // A group is as severe as its worst issue
+        if severity == "error" {
+            ticket.severity = severity;
+        }
+        ticket.occurrences.push(Occurrence { position, message });
+    }
+    tickets.into_values().collect()
+}
+
+/// Creates a report of the given issues as configured
+///
+/// `inputs` are the inputs the story was parsed from, and `extensions` the
+/// extensions of the Twee files found in them, used to report the path of
+/// each file
+pub fn report(
+    issues: &[Issue],
+    story_files: &StoryFiles,
+    inputs: &[String],
+    extensions: &[String],
+    config: &ReportConfig,
+) -> String {
+    let tickets = tickets(issues, story_files, inputs, extensions, config.errors_only);
+    match config.format {
+        ReportFormat::Json => {
+            let tickets: Vec<Value> = tickets
+                .iter()
+                .map(|ticket| {
+                    json!({
+                        "title": ticket.title(),
+                        "body": ticket.body(),
+                        "labels": ticket.labels(),
+                        "rule": ticket.rule,
+                        "file": ticket.file,
+                        "severity": ticket.severity,
+                        "count": ticket.occurrences.len(),
+                    })
+                })
+                .collect();
+            format!("{}\n", serde_json::to_string_pretty(&tickets).unwrap())
+        }
+        ReportFormat::Markdown => {
+            let mut output = String::from("# Lint report\n\n");
+            if tickets.is_empty() {
+                output.push_str("No outstanding issues.\n");
+                return output;
+            }
+            let count: usize = tickets.iter().map(|t| t.occurrences.len()).sum();
+            output.push_str(&format!(
+                "{} in {}.\n",
+                plural(count, "issue"),
+                plural(tickets.len(), "group")
+            ));
+            for ticket in &tickets {
+                output.push_str(&format!("\n## {}\n\n{}", ticket.title(), ticket.body()));
+            }
+            output
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issue::LintWarning;
+    use crate::utils;
+    use tweep::Story;
+
+    /// Reports two dead ends and a denied dead link, in a story read from a
+    /// directory
+    fn report_story(format: ReportFormat, errors_only: bool) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "tweec-report-{:?}-{}-{}",
+            format,
+            errors_only,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("story.twee");
+        let input = ":: Start\n[[Nowhere]]\n\n:: Lonely\nHi\n\n:: Alone\nHello\n";
+        std::fs::write(&path, input).unwrap();
+        let story_result = Story::from_path(&path).take().0;
+        let headers = utils::passage_headers(story_result.as_ref().ok().unwrap());
+        let dead_end = |name: &str| Issue::Lint {
+            lint: LintWarning::new(
+                "DeadEnd",
+                format!("Passage {} has no links out", name),
+                headers.get(name).cloned(),
+            ),
+            denied: false,
+        };
+        let issues = vec![
+            Issue::Lint {
+                lint: LintWarning::new("DeadLink", "Dead link", headers.get("Start").cloned())
+                    .with_note("No passage is named Nowhere"),
+                denied: true,
+            },
+            dead_end("Lonely"),
+            dead_end("Alone"),
+        ];
+        let inputs = vec![dir.to_string_lossy().into_owned()];
+        let config = ReportConfig {
+            format,
+            output: None,
+            errors_only,
+        };
+        let report = report(
+            &issues,
+            &StoryFiles::new(&story_result),
+            &inputs,
+            &crate::inputs::default_extensions(),
+            &config,
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        report.replace(&path.to_string_lossy().into_owned(), "story.twee")
+    }
+
+    #[test]
+    fn reports_markdown() {
+        assert_eq!(
+            report_story(ReportFormat::Markdown, false),
+            "# Lint report
+
+3 issues in 2 groups.
+
+## DeadEnd: 2 occurrences in story.twee
+
+Passage has no links out and is not tagged as an ending.
+
+tweec reports this as a warning:
+
+- [ ] `story.twee:4:1` Passage Lonely has no links out
+- [ ] `story.twee:7:1` Passage Alone has no links out
+
+## DeadLink: 1 occurrence in story.twee
+
+Link to a passage that does not exist.
+
+tweec reports this as an error:
+
+- [ ] `story.twee:1:1` Dead link
+  No passage is named Nowhere
+"
+        );
+    }
+
+    #[test]
+    fn reports_json() {
+        let report: Value = serde_json::from_str(&report_story(ReportFormat::Json, true)).unwrap();
+        assert_eq!(
+            report,
+            json!([{
+                "title": "DeadLink: 1 occurrence in story.twee",
+                "body": "Link to a passage that does not exist.\n\n\
+                         tweec reports this as an error:\n\n\
+                         - [ ] `story.twee:1:1` Dead link\n  No passage is named Nowhere\n",
+                "labels": ["tweec", "DeadLink", "error"],
+                "rule": "DeadLink",
+                "file": "story.twee",
+                "severity": "error",
+                "count": 1,
+            }])
+        );
+    }
+}
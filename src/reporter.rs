@@ -0,0 +1,398 @@
+//! Reports the issues found by the linter, one at a time, so that they can be
+//! shown in a terminal or handed to other tools
+//!
+//! [`linter::lint`] reports through the [`Reporter`] chosen by the config's
+//! message format. Embedders can implement `Reporter` to receive issues as
+//! values instead of reading them from standard output
+//!
+//! [`linter::lint`]: ../linter/fn.lint.html
+//! [`Reporter`]: trait.Reporter.html
+
+use crate::issue::{self, Issue};
+use crate::junit;
+use crate::rules;
+use crate::sarif;
+use crate::MessageFormat;
+use crate::StoryFiles;
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use codespan_reporting::files::Files;
+use codespan_reporting::term;
+use color_eyre::Result;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use termcolor::{StandardStream, WriteColor};
+
+/// Counts of the issues reported
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Issues reported as errors
+    pub errors: usize,
+
+    /// Issues reported as warnings
+    pub warnings: usize,
+
+    /// Issues left out as repeats of others
+    pub folded: usize,
+}
+
+impl Summary {
+    /// Counts the given issues, of which `folded` were left out
+    pub fn new(issues: &[Issue], folded: usize) -> Self {
+        let errors = issues.iter().filter(|issue| issue.is_error()).count();
+        Summary {
+            errors,
+            warnings: issues.len() - errors,
+            folded,
+        }
+    }
+}
+
+/// Receives issues as they are reported
+pub trait Reporter {
+    /// Reports an issue, whose spans are in `story_files`
+    fn report(&mut self, issue: &Issue, story_files: &StoryFiles) -> Result<()>;
+
+    /// Notes that `hidden` issues like the last one reported were left out
+    fn fold(&mut self, _hidden: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Ends the report, once every issue has been reported
+    fn finish(&mut self, summary: &Summary) -> Result<()>;
+
+    /// Whether repeated issues should be left out, as `fold` describes
+    fn folds(&self) -> bool {
+        true
+    }
+}
+
+/// Shows each issue with the source it refers to, using `codespan_reporting`
+///
+/// When finished, suggests `tweec explain` for the rules that were reported
+pub struct TerminalReporter<'a> {
+    stdout: &'a mut StandardStream,
+    config: term::Config,
+    codes: Vec<String>,
+}
+
+impl<'a> TerminalReporter<'a> {
+    /// Creates a reporter that writes to the given stream
+    pub fn new(stdout: &'a mut StandardStream) -> Self {
+        TerminalReporter {
+            stdout,
+            config: term::Config::default(),
+            codes: Vec::new(),
+        }
+    }
+}
+
+impl Reporter for TerminalReporter<'_> {
+    fn report(&mut self, issue: &Issue, story_files: &StoryFiles) -> Result<()> {
+        let diagnostic = issue.report(story_files);
+        if let Some(code) = diagnostic
+            .code
+            .as_deref()
+            .filter(|c| rules::find(c).is_some())
+        {
+            if !self.codes.iter().any(|seen| seen == code) {
+                self.codes.push(code.to_string());
+            }
+        }
+        term::emit(
+            &mut self.stdout.lock(),
+            &self.config,
+            story_files,
+            &diagnostic,
+        )?;
+        Ok(())
+    }
+
+    fn fold(&mut self, hidden: usize) -> Result<()> {
+        writeln!(
+            self.stdout,
+            "\u{2026}and {} more like this (run with --no-fold to see all)",
+            hidden
+        )?;
+        writeln!(self.stdout)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &Summary) -> Result<()> {
+        match self.codes.as_slice() {
+            [] => {}
+            [code] => writeln!(
+                self.stdout,
+                "For more information about {}, run `tweec explain {}`",
+                code, code
+            )?,
+            codes => writeln!(
+                self.stdout,
+                "Some warnings or errors have explanations: {}\n\
+                 For more information about one, run `tweec explain {}`",
+                codes.join(", "),
+                codes[0]
+            )?,
+        }
+        self.stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Shows each issue on one line
+pub struct CompactReporter<'a> {
+    stdout: &'a mut StandardStream,
+}
+
+impl<'a> CompactReporter<'a> {
+    /// Creates a reporter that writes to the given stream
+    pub fn new(stdout: &'a mut StandardStream) -> Self {
+        CompactReporter { stdout }
+    }
+}
+
+impl Reporter for CompactReporter<'_> {
+    fn report(&mut self, issue: &Issue, _: &StoryFiles) -> Result<()> {
+        issue::print_issue(issue, self.stdout)
+    }
+
+    fn fold(&mut self, hidden: usize) -> Result<()> {
+        writeln!(
+            self.stdout,
+            "\u{2026}and {} more like this (run with --no-fold to see all)",
+            hidden
+        )?;
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &Summary) -> Result<()> {
+        self.stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Gets an issue as JSON, with its code, severity, message, and notes, and
+/// the file and positions of its primary label if it has one
+///
+/// `paths` maps the file names used by `story_files` to the paths to report
+/// for them. Lines and columns count from 0
+pub fn issue_json(
+    issue: &Issue,
+    story_files: &StoryFiles,
+    paths: &HashMap<String, PathBuf>,
+) -> Value {
+    let diagnostic = issue.report(story_files);
+    let mut value = json!({
+        "code": diagnostic.code,
+        "severity": match diagnostic.severity {
+            Severity::Bug | Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note | Severity::Help => "note",
+        },
+        "message": diagnostic.message,
+        "notes": diagnostic.notes,
+    });
+    let primary = diagnostic
+        .labels
+        .iter()
+        .find(|label| label.style == LabelStyle::Primary);
+    if let Some(label) = primary {
+        let name = story_files.name(label.file_id).unwrap_or_default();
+        let (start_line, start_column) = story_files.position(label.file_id, label.range.start);
+        let (end_line, end_column) = story_files.position(label.file_id, label.range.end);
+        value["file"] = json!(paths
+            .get(name)
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_else(|| name.to_string()));
+        value["start"] = json!({ "line": start_line, "column": start_column });
+        value["end"] = json!({ "line": end_line, "column": end_column });
+    }
+    value
+}
+
+/// Writes every issue as a JSON object when finished, holding `issues`, as
+/// given by [`issue_json`], and the `errors` and `warnings` counted
+///
+/// [`issue_json`]: fn.issue_json.html
+pub struct JsonReporter<W> {
+    out: W,
+    paths: HashMap<String, PathBuf>,
+    issues: Vec<Value>,
+}
+
+impl<W: Write> JsonReporter<W> {
+    /// Creates a reporter that writes to `out`, using `paths` to find the
+    /// path of each file by name
+    pub fn new(out: W, paths: HashMap<String, PathBuf>) -> Self {
+        JsonReporter {
+            out,
+            paths,
+            issues: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Reporter for JsonReporter<W> {
+    fn report(&mut self, issue: &Issue, story_files: &StoryFiles) -> Result<()> {
+        self.issues
+            .push(issue_json(issue, story_files, &self.paths));
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<()> {
+        let report = json!({
+            "issues": self.issues,
+            "errors": summary.errors,
+            "warnings": summary.warnings,
+        });
+        serde_json::to_writer_pretty(&mut self.out, &report)?;
+        writeln!(self.out)?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn folds(&self) -> bool {
+        false
+    }
+}
+
+/// Writes every issue as a SARIF log when finished. See [`sarif`]
+///
+/// [`sarif`]: ../sarif/index.html
+pub struct SarifReporter<W> {
+    out: W,
+    paths: HashMap<String, PathBuf>,
+    log: sarif::Log,
+}
+
+impl<W: Write> SarifReporter<W> {
+    /// Creates a reporter that writes to `out`, using `paths` to find the
+    /// path of each file by name
+    pub fn new(out: W, paths: HashMap<String, PathBuf>) -> Self {
+        SarifReporter {
+            out,
+            paths,
+            log: sarif::Log::default(),
+        }
+    }
+}
+
+impl<W: Write> Reporter for SarifReporter<W> {
+    fn report(&mut self, issue: &Issue, story_files: &StoryFiles) -> Result<()> {
+        self.log.add(issue, story_files, &self.paths);
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &Summary) -> Result<()> {
+        serde_json::to_writer_pretty(&mut self.out, &self.log.to_json())?;
+        writeln!(self.out)?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn folds(&self) -> bool {
+        false
+    }
+}
+
+/// Writes every issue as JUnit XML when finished. See [`junit`]
+///
+/// [`junit`]: ../junit/index.html
+pub struct JunitReporter<W> {
+    out: W,
+    paths: HashMap<String, PathBuf>,
+    report: junit::Report,
+}
+
+impl<W: Write> JunitReporter<W> {
+    /// Creates a reporter that writes to `out`, using `paths` to find the
+    /// path of each file by name
+    pub fn new(out: W, paths: HashMap<String, PathBuf>) -> Self {
+        JunitReporter {
+            out,
+            paths,
+            report: junit::Report::default(),
+        }
+    }
+}
+
+impl<W: Write> Reporter for JunitReporter<W> {
+    fn report(&mut self, issue: &Issue, story_files: &StoryFiles) -> Result<()> {
+        self.report.add(issue, story_files, &self.paths);
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &Summary) -> Result<()> {
+        write!(self.out, "{}", self.report.to_xml())?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn folds(&self) -> bool {
+        false
+    }
+}
+
+/// Creates the reporter for the given message format, writing to `stdout`
+///
+/// `paths` maps file names to the paths to report for them, for the formats
+/// that give paths
+pub fn new<'a>(
+    message_format: MessageFormat,
+    stdout: &'a mut StandardStream,
+    paths: HashMap<String, PathBuf>,
+) -> Result<Box<dyn Reporter + 'a>> {
+    Ok(match message_format {
+        MessageFormat::Human => Box::new(TerminalReporter::new(stdout)),
+        MessageFormat::Compact => Box::new(CompactReporter::new(stdout)),
+        MessageFormat::Json => {
+            stdout.reset()?;
+            Box::new(JsonReporter::new(stdout, paths))
+        }
+        MessageFormat::Sarif => {
+            stdout.reset()?;
+            Box::new(SarifReporter::new(stdout, paths))
+        }
+        MessageFormat::Junit => {
+            stdout.reset()?;
+            Box::new(JunitReporter::new(stdout, paths))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issue::LintWarning;
+    use tweep::Story;
+
+    #[test]
+    fn reports_issues_as_json() {
+        let story_result = Story::from_string(":: Start\nHello\n".to_string()).take().0;
+        let story_files = StoryFiles::new(&story_result);
+        let issues = vec![
+            Issue::Lint {
+                lint: LintWarning::new("DeadEnd", "Passage Start has no links out", None),
+                denied: false,
+            },
+            Issue::Lint {
+                lint: LintWarning::new("DeadLink", "Dead link", None),
+                denied: true,
+            },
+        ];
+        let mut out = Vec::new();
+        let mut reporter = JsonReporter::new(&mut out, HashMap::new());
+        for issue in &issues {
+            reporter.report(issue, &story_files).unwrap();
+        }
+        reporter.finish(&Summary::new(&issues, 0)).unwrap();
+
+        let report: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(report["errors"], 1);
+        assert_eq!(report["warnings"], 1);
+        assert_eq!(report["issues"][0]["code"], "DeadEnd");
+        assert_eq!(report["issues"][0]["severity"], "warning");
+        assert_eq!(report["issues"][1]["severity"], "error");
+    }
+}
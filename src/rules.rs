@@ -0,0 +1,251 @@
+//! Metadata about the warnings and errors tweec can report
+
+use codespan_reporting::diagnostic::Severity;
+use color_eyre::Result;
+use eyre::eyre;
+use std::io::Write;
+use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
+
+mod explanations;
+
+/// A named warning or error that tweec can report
+#[derive(Debug)]
+pub struct Rule {
+    /// The name of the rule, used for allowing or denying it
+    pub name: &'static str,
+
+    /// A short description of what the rule checks for
+    pub description: &'static str,
+
+    /// The severity of the rule when it is neither allowed nor denied
+    pub severity: Severity,
+}
+
+impl Rule {
+    /// A longer explanation of what the rule checks for, with an example of
+    /// how to fix it
+    pub fn explanation(&self) -> &'static str {
+        explanations::get(self.name).unwrap_or(self.description)
+    }
+}
+
+const fn error(name: &'static str, description: &'static str) -> Rule {
+    Rule {
+        name,
+        description,
+        severity: Severity::Error,
+    }
+}
+
+const fn warning(name: &'static str, description: &'static str) -> Rule {
+    Rule {
+        name,
+        description,
+        severity: Severity::Warning,
+    }
+}
+
+/// Every rule known to tweec, including those reported by `tweep`
+pub const RULES: &[Rule] = &[
+    error("EmptyName", "Passage header has an empty name"),
+    error("LeadingWhitespace", "Passage header starts with whitespace"),
+    error(
+        "MetadataBeforeTags",
+        "Passage header has metadata before tags",
+    ),
+    error("MissingSigil", "Passage header is missing the :: sigil"),
+    error("UnescapedOpenSquare", "Unescaped [ in a passage name"),
+    error("UnescapedOpenCurly", "Unescaped { in a passage name"),
+    error("UnescapedCloseSquare", "Unescaped ] in a passage name"),
+    error("UnescapedCloseCurly", "Unescaped } in a passage name"),
+    error("UnclosedTagBlock", "Unclosed tag block in a passage header"),
+    error("BadInputPath", "Input file or directory could not be read"),
+    warning("EscapedOpenSquare", "Escaped [ in a passage name"),
+    warning("EscapedCloseSquare", "Escaped ] in a passage name"),
+    warning("EscapedOpenCurly", "Escaped { in a passage name"),
+    warning("EscapedCloseCurly", "Escaped } in a passage name"),
+    warning("JsonError", "Invalid JSON in passage metadata or StoryData"),
+    warning("DuplicateStoryData", "Multiple StoryData passages"),
+    warning("DuplicateStoryTitle", "Multiple StoryTitle passages"),
+    warning("MissingStoryData", "No StoryData passage"),
+    warning("MissingStoryTitle", "No StoryTitle passage"),
+    warning("UnclosedLink", "Passage link is missing its closing ]]"),
+    warning(
+        "WhitespaceInLink",
+        "Passage link target has surrounding whitespace",
+    ),
+    warning("DeadLink", "Link to a passage that does not exist"),
+    warning("MissingStartPassage", "No start passage"),
+    warning("DeadStartPassage", "Start passage does not exist"),
+    warning("DuplicatePassage", "Multiple passages with the same name"),
+    warning(
+        crate::linter::max_links::NAME,
+        "Passage has more links than the configured maximum",
+    ),
+    warning(
+        crate::linter::chapters::NAME,
+        "Link from a chapter back to an earlier chapter",
+    ),
+    warning(
+        crate::linter::unreachable::NAME,
+        "Passage can't be reached from the start passage",
+    ),
+    warning(
+        crate::linter::dead_ends::NAME,
+        "Passage has no links out and is not tagged as an ending",
+    ),
+    warning(
+        crate::linter::collisions::NAME,
+        "Passage name differs from another only in case, whitespace, or look-alike characters",
+    ),
+    warning(
+        crate::linter::headers::DUPLICATE_TAG,
+        "Tag given more than once on a passage",
+    ),
+    warning(
+        crate::linter::headers::TAG_CASE,
+        "Tag differs from another tag only by case",
+    ),
+    warning(
+        crate::linter::headers::SINGLE_USE_TAG,
+        "Tag used on only one passage (off unless given a level)",
+    ),
+    warning(
+        crate::linter::headers::TAG_TYPO,
+        "Tag is a near-match of a more common or special tag",
+    ),
+    warning(
+        crate::linter::headers::DUPLICATE_METADATA_KEY,
+        "Metadata key given more than once in a passage header",
+    ),
+    warning(
+        crate::linter::macros::UNCLOSED_MACRO,
+        "Container macro is never closed, or a closing tag has nothing to close",
+    ),
+    warning(
+        crate::linter::macros::UNKNOWN_MACRO,
+        "Macro is not provided by the story format or defined by the story",
+    ),
+    warning(
+        crate::linter::macros::MACRO_SYNTAX,
+        "Macro has unbalanced brackets or is never ended",
+    ),
+    warning(
+        crate::linter::harlowe::HOOK_SYNTAX,
+        "Hook is never closed, or a hook name tag has no hook",
+    ),
+    warning(
+        crate::linter::chapbook::INVALID_VARIABLE_NAME,
+        "Vars section line has an invalid variable name or no name",
+    ),
+    warning(
+        crate::linter::chapbook::DUPLICATE_VARIABLE,
+        "Variable set more than once in a vars section",
+    ),
+    warning(
+        crate::linter::chapbook::MALFORMED_MODIFIER,
+        "Modifier is unknown or has a missing or unexpected condition",
+    ),
+    warning(
+        crate::linter::dataflow::READ_BEFORE_SET,
+        "Variable read before any path from the start passage sets it",
+    ),
+    warning(
+        crate::linter::dataflow::UNUSED_VARIABLE,
+        "Variable set but never read anywhere in the story",
+    ),
+    warning(
+        crate::linter::spelling::NAME,
+        "Word in passage text is not in any configured dictionary",
+    ),
+    warning(
+        crate::linter::assets::NAME,
+        "Media file referred to by a passage or stylesheet does not exist",
+    ),
+    warning(
+        crate::linter::legacy::NAME,
+        "Twee 1 or Twee2 convention that was translated when reading the story",
+    ),
+    warning(
+        crate::linter::i18n::MISSING,
+        "Text has no translation in the catalog being built",
+    ),
+    warning(
+        crate::linter::i18n::FUZZY,
+        "Translation in the catalog being built is marked fuzzy",
+    ),
+    error(
+        crate::playtest::NAME,
+        "Playthrough test did not reach the passage or text it expected",
+    ),
+];
+
+/// Finds the rule with the given name
+pub fn find(name: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.name == name)
+}
+
+/// Prints the explanation of the rule with the given name, which is matched
+/// ignoring case
+pub fn explain(name: &str, stdout: &mut StandardStream) -> Result<()> {
+    let rule = RULES
+        .iter()
+        .find(|rule| rule.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            eyre!(
+                "Unknown rule {}. Run `tweec lint --list-rules` to see every rule",
+                name
+            )
+        })?;
+    let (level, color) = match rule.severity {
+        Severity::Error | Severity::Bug => ("error", Color::Red),
+        _ => ("warning", Color::Yellow),
+    };
+    stdout.reset()?;
+    stdout.set_color(ColorSpec::new().set_bold(true))?;
+    write!(stdout, "{}", rule.name)?;
+    stdout.reset()?;
+    write!(stdout, " (")?;
+    stdout.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+    write!(stdout, "{}", level)?;
+    stdout.reset()?;
+    writeln!(stdout, " by default): {}", rule.description)?;
+    writeln!(stdout)?;
+    writeln!(stdout, "{}", rule.explanation())?;
+    if rule.severity == Severity::Warning {
+        writeln!(stdout)?;
+        writeln!(
+            stdout,
+            "To change how it is reported, pass --allow {0}, --warn {0}, or --deny {0},\n\
+             or list it under allow, warn, or deny in the config.",
+            rule.name
+        )?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linter::Linter;
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_lint_is_registered() {
+        let mut names = HashSet::new();
+        for rule in RULES {
+            assert!(names.insert(rule.name), "{} is listed twice", rule.name);
+        }
+        for (name, _) in Linter::new().rule_scopes() {
+            assert!(find(name).is_some(), "{} is not in RULES", name);
+        }
+        for rule in RULES {
+            assert!(
+                explanations::get(rule.name).is_some(),
+                "{} has no explanation",
+                rule.name
+            );
+        }
+    }
+}
@@ -0,0 +1,628 @@
+//! The extended explanation of each rule, printed by `tweec explain`
+//!
+//! Each explanation says why the rule is reported and, where there is one,
+//! gives an example along with a fix
+
+/// Gets the extended explanation of the rule with the given name
+pub fn get(name: &str) -> Option<&'static str> {
+    let explanation = match name {
+        "EmptyName" => {
+            r#"A passage header must give the passage a name after the :: sigil.
+
+For example, this header has no name, so the passage can't be linked to:
+
+    ::
+    You are in a dark room.
+
+Give the passage a name:
+
+    :: Dark Room
+    You are in a dark room."#
+        }
+        "LeadingWhitespace" => {
+            r#"A passage header must start with the :: sigil at the very beginning of a
+line. With whitespace before it, the line is not read as a header.
+
+For example:
+
+      :: Start
+    Hello!
+
+Remove the whitespace before the sigil:
+
+    :: Start
+    Hello!"#
+        }
+        "MetadataBeforeTags" => {
+            r#"In a passage header, the tag block in square brackets must come before the
+metadata block in curly braces.
+
+For example:
+
+    :: Start {"position": "100,100"} [intro]
+
+Put the tags first:
+
+    :: Start [intro] {"position": "100,100"}"#
+        }
+        "MissingSigil" => {
+            r#"Every passage starts with a header line beginning with the :: sigil. Text
+before the first header belongs to no passage.
+
+For example, a file that starts without a header:
+
+    Start
+    Hello!
+
+Begin the passage with a header:
+
+    :: Start
+    Hello!"#
+        }
+        "UnescapedOpenSquare" | "UnescapedCloseSquare" => {
+            r#"Square brackets in a passage header start and end its tag block, so a
+passage name can only contain them when they are escaped with a backslash.
+
+For example:
+
+    :: Room [2]
+
+If the brackets are part of the name, escape them:
+
+    :: Room \[2\]
+
+Otherwise, put a space between the name and its tags."#
+        }
+        "UnescapedOpenCurly" | "UnescapedCloseCurly" => {
+            r#"Curly braces in a passage header start and end its metadata block, so a
+passage name can only contain them when they are escaped with a backslash.
+
+For example:
+
+    :: Set {a}
+
+If the braces are part of the name, escape them:
+
+    :: Set \{a\}"#
+        }
+        "UnclosedTagBlock" => {
+            r#"The tag block of a passage header is missing its closing square bracket, so
+the tags can't be read.
+
+For example:
+
+    :: Start [intro beginning
+
+Close the tag block:
+
+    :: Start [intro beginning]"#
+        }
+        "BadInputPath" => {
+            r#"An input file or directory given on the command line or in the config could
+not be read, for example because it doesn't exist or tweec doesn't have
+permission to read it. The message gives the reason.
+
+Check the path for typos, or that the file is where tweec is run from, which
+relative paths are resolved against."#
+        }
+        "EscapedOpenSquare" | "EscapedCloseSquare" | "EscapedOpenCurly" | "EscapedCloseCurly" => {
+            r#"A passage name contains an escaped bracket or brace. This is allowed, but
+links to the passage must use the name without the backslash, and some story
+formats and tools handle such names poorly.
+
+For example:
+
+    :: Room \[2\]
+
+Consider a name without brackets:
+
+    :: Room 2
+
+If the name is intended, allow this rule."#
+        }
+        "JsonError" => {
+            r#"The metadata block of a passage header, or the contents of the StoryData
+passage, must be valid JSON. Invalid JSON is ignored.
+
+For example, keys must be quoted with double quotes:
+
+    :: StoryData
+    {ifid: 'D674C58C-DEFA-4F70-B7A2-27742230C0FC'}
+
+Write it as JSON:
+
+    :: StoryData
+    {"ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC"}"#
+        }
+        "DuplicateStoryData" | "DuplicateStoryTitle" => {
+            r#"A story has only one StoryData and one StoryTitle passage. When there are
+several, only the first is used and the others are discarded.
+
+This often happens when a story is split across files, or when a decompiled
+story is added to an existing one. Keep one of the passages and remove the
+rest."#
+        }
+        "MissingStoryData" => {
+            r#"Twee 3 stories give their IFID, story format, and start passage in a
+StoryData passage. Without it, the story can't be compiled.
+
+For example:
+
+    :: StoryData
+    {
+      "ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC",
+      "format": "SugarCube",
+      "format-version": "2.36.1",
+      "start": "Start"
+    }
+
+`tweec` can add a StoryData passage with a new IFID to the first input file
+when building."#
+        }
+        "MissingStoryTitle" => {
+            r#"The story's title is given by a StoryTitle passage. Without it, the story is
+titled "Untitled Story".
+
+Add a StoryTitle passage:
+
+    :: StoryTitle
+    The Dark Room
+
+or give a title on the command line with --title."#
+        }
+        "UnclosedLink" => {
+            r#"A link was started with [[ but never closed with ]], so it is not a link
+and will be shown as text.
+
+For example:
+
+    Go [[north
+
+Close the link:
+
+    Go [[north]]"#
+        }
+        "WhitespaceInLink" => {
+            r#"The target of a link has whitespace around it, which becomes part of the
+passage name it links to. Most story formats don't trim it, so the link goes
+nowhere.
+
+For example:
+
+    [[Go north | North Room]]
+
+Remove the whitespace around the separator:
+
+    [[Go north|North Room]]"#
+        }
+        "DeadLink" => {
+            r#"A link goes to a passage that doesn't exist. Readers who follow it will see
+an error instead of the story.
+
+For example, this link's target is misspelled:
+
+    :: Start
+    [[Go north->Nroth Room]]
+
+    :: North Room
+    It is cold here.
+
+Link to the passage by its exact name, which is case sensitive:
+
+    :: Start
+    [[Go north->North Room]]
+
+Which link syntaxes are followed can be set with `links` in the config."#
+        }
+        "MissingStartPassage" => {
+            r#"The story has no start passage: StoryData doesn't name one, and there is no
+passage named Start.
+
+Either name the first passage Start, give it in StoryData:
+
+    :: StoryData
+    {"ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC", "start": "Prologue"}
+
+or give one on the command line with --start."#
+        }
+        "DeadStartPassage" => {
+            r#"StoryData names a start passage that doesn't exist.
+
+For example:
+
+    :: StoryData
+    {"ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC", "start": "Prolog"}
+
+    :: Prologue
+    Once upon a time...
+
+Name the start passage exactly as it is written in its header."#
+        }
+        "DuplicatePassage" => {
+            r#"Two passages have the same name. Only the first is kept, and the other is
+discarded, so links can only reach one of them.
+
+Passages are read in order: the inputs in the order they are given, the files
+in a directory in order of their paths, and the passages in a file from top
+to bottom. When the same name appears in two files, the definition in the
+file read first is kept, even if the other file was changed more recently.
+
+Rename one of the passages, or remove it if it is a copy. The diagnostic
+shows both headers and which one is kept."#
+        }
+        "TooManyLinks" => {
+            r#"A passage offers more choices than the configured maximum, which can be too
+many for a reader to take in. This is only checked when `max_links` or
+`tag_max_links` is set in the config.
+
+For example, with a maximum of 3:
+
+    :: Market
+    [[Baker]] [[Butcher]] [[Smith]] [[Tailor]] [[Leave]]
+
+Group some of the choices into another passage:
+
+    :: Market
+    [[Food stalls]] [[Craft stalls]] [[Leave]]
+
+Passages with a tag listed in `tag_max_links` use that tag's limit instead.
+Links in any of the configured `links` syntaxes count, and several links to
+the same passage count as one choice."#
+        }
+        "BackwardsChapterLink" => {
+            r#"A passage in one chapter links to a passage in an earlier chapter. Chapters
+are given by the list of chapter tags in the config's `chapters`, in story
+order, and are only checked when it is set.
+
+For example, with chapters ["ch1", "ch2"]:
+
+    :: Crossroads [ch2]
+    [[Go back->Village]]
+
+    :: Village [ch1]
+    Home again.
+
+Link forward instead, or tag the passage with one of the `recap_tags` if it
+is meant to look back."#
+        }
+        "UnreachablePassage" => {
+            r#"No path of links from the start passage reaches this passage, so readers
+will never see it.
+
+For example:
+
+    :: Start
+    [[Forest]]
+
+    :: Forest
+    Trees everywhere.
+
+    :: Cave
+    It is dark.
+
+Link to the passage from one that can be reached, or remove it. Passages the
+story format uses without links, such as widgets, can be named in
+`unlinked_passages` or tagged with one of the `unlinked_tags` in the config."#
+        }
+        "DeadEnd" => {
+            r#"A passage has no links out, so the reader is stuck there. Endings are
+expected to have none, and are marked with one of the config's
+`ending_tags`.
+
+For example:
+
+    :: Cliff
+    You fall.
+
+If the passage is an ending, tag it:
+
+    :: Cliff [ending]
+    You fall.
+
+Otherwise, give the reader somewhere to go."#
+        }
+        "PassageNameCollision" => {
+            r#"Two passage names differ only in case, surrounding whitespace, or
+characters that look alike, such as a Cyrillic а for a Latin a, or a
+zero-width space. Story formats compare passage names differently, so a link
+meant for one passage may go to the other, or to neither, and readers can't
+tell the names apart.
+
+For example:
+
+    :: Start
+    [[Enter the cave|Cave]]
+
+    :: Cave
+    It is dark.
+
+    :: cave
+    It is very dark.
+
+The name with the most links to it is suggested. If the passages are meant to
+be the same, merge them under that name. Otherwise, give one a clearly
+different name."#
+        }
+        "DuplicateTag" => {
+            r#"A passage header gives the same tag more than once. The repeat has no
+effect, but can confuse tools that count tags.
+
+For example:
+
+    :: Start [intro intro]
+
+Give each tag once:
+
+    :: Start [intro]
+
+`tweec lint --fix` removes repeats within a header."#
+        }
+        "TagCaseMismatch" => {
+            r#"Two tags differ only by case. Tags are case sensitive, so they are treated
+as different tags, which is rarely intended.
+
+For example:
+
+    :: Start [Intro]
+
+    :: Hall [intro]
+
+Use one spelling of the tag everywhere."#
+        }
+        "SingleUseTag" => {
+            r#"A tag is given on only one passage. Such tags are often left over from
+an earlier draft, or are misspellings that the story format silently ignores.
+
+For example:
+
+    :: Start [intro]
+
+    :: Hall [draft]
+
+Remove the tag if nothing uses it. Tags the story format gives a meaning to,
+such as `widget` or `nobr`, and tags named in the config, such as its
+`ending_tags`, are not reported.
+
+Since many stories tag single passages on purpose, this rule is off unless it
+is given a level, e.g. with `--warn SingleUseTag` or in the config file. Tags
+that are likely misspellings of another tag are reported by TagTypo either
+way."#
+        }
+        "TagTypo" => {
+            r#"A tag is a near-match of a tag used on more passages, or of a tag the
+story format or config gives a meaning to. A misspelled tag is treated as a
+different tag, so the passage silently loses the behavior it was meant to
+have.
+
+For example:
+
+    :: Inventory [widgit]
+    <<widget "inventory">>...<</widget>>
+
+Spell the tag like the one it was meant to be:
+
+    :: Inventory [widget]"#
+        }
+        "DuplicateMetadataKey" => {
+            r#"A passage header's metadata gives the same key more than once. Only the last
+value is kept.
+
+For example:
+
+    :: Start {"position": "100,100", "position": "200,100"}
+
+Give each key once:
+
+    :: Start {"position": "200,100"}
+
+`tweec lint --fix` keeps the last value of a repeated key."#
+        }
+        "UnclosedMacro" => {
+            r#"A container macro, such as <<if>> or <<link>>, is never closed, or a
+closing tag has no macro to close. The story format will report an error when
+the passage is shown.
+
+For example:
+
+    <<if $gold > 10>>You are rich.
+
+Close the macro:
+
+    <<if $gold > 10>>You are rich.<</if>>"#
+        }
+        "UnknownMacro" => {
+            r#"A macro is not one the story format provides, and the story doesn't define
+it. This is often a typo, or a macro from another story format.
+
+For example, in SugarCube:
+
+    <<prnt $gold>>
+
+Use the macro's name:
+
+    <<print $gold>>
+
+Macros defined outside the story, such as by a library loaded at runtime, can
+be listed in `macros` in the config."#
+        }
+        "MacroSyntax" => {
+            r#"A macro has unbalanced brackets or quotes, or is never ended, so the story
+format can't read it.
+
+For example, in SugarCube:
+
+    <<set $name to "Ann>>
+
+or in Harlowe:
+
+    (set: $name to "Ann"
+
+Close the string and the macro:
+
+    <<set $name to "Ann">>
+    (set: $name to "Ann")"#
+        }
+        "HookSyntax" => {
+            r#"In Harlowe, a hook in square brackets is never closed, or a hook name tag
+such as |name> isn't followed by a hook.
+
+For example:
+
+    (if: $gold > 10)[You are rich.
+
+Close the hook:
+
+    (if: $gold > 10)[You are rich.]"#
+        }
+        "InvalidVariableName" => {
+            r#"A line in a Chapbook vars section doesn't set a variable with a valid name.
+Each line must be `name: value`, and names may only contain letters, digits,
+`_`, `$`, and `.`, and can't start with a digit.
+
+For example:
+
+    1st place: "Ann"
+    --
+
+Use a valid name:
+
+    firstPlace: "Ann"
+    --"#
+        }
+        "DuplicateVariable" => {
+            r#"A Chapbook vars section sets the same variable more than once without a
+condition, so only the last value is kept.
+
+For example:
+
+    gold: 10
+    gold: 20
+    --
+
+Set the variable once, or give the later line a condition:
+
+    gold: 10
+    gold (rich): 20
+    --"#
+        }
+        "MalformedModifier" => {
+            r#"A Chapbook modifier is not one Chapbook knows, has a missing or unexpected
+condition, or is missing its closing ].
+
+For example, [if] needs a condition:
+
+    [if]
+    You are rich.
+
+Give it one:
+
+    [if gold > 10]
+    You are rich.
+
+Modifiers like [continue] take no condition."#
+        }
+        "ReadBeforeSet" => {
+            r#"A story variable is read in a passage that can be reached from the start
+passage without it being set on the way. Readers who take that path will see
+it as undefined.
+
+For example:
+
+    :: Start
+    [[Shop]] [[Mine]]
+
+    :: Mine
+    <<set $gold to 10>>[[Shop]]
+
+    :: Shop
+    You have $gold gold.
+
+Set the variable before any path can reach where it is read, such as in
+StoryInit:
+
+    :: StoryInit
+    <<set $gold to 0>>"#
+        }
+        "UnusedVariable" => {
+            r#"A story variable is set but never read anywhere in the story. It may be left
+over from an earlier draft, or read under a misspelled name.
+
+For example:
+
+    <<set $gould to 10>>
+    You have $gold gold.
+
+Remove the variable, or fix the name it is set or read under."#
+        }
+        "Spelling" => {
+            r#"A word in the text a reader sees is not in any of the word lists given as
+`dictionaries` in the config, or in the story's own `dictionary`. Macros,
+HTML, variables, and link targets are not checked.
+
+For example:
+
+    You enter the tavren.
+
+Fix the spelling, or add names and invented words to the story's dictionary
+file, one per line."#
+        }
+        "MissingAsset" => {
+            r#"A passage or stylesheet refers to a local media file that doesn't exist.
+This is only checked when images are embedded or media are copied to an
+assets directory.
+
+For example:
+
+    <img src="images/dragn.png">
+
+Fix the path, which is resolved relative to the file the story is in, or add
+the missing file."#
+        }
+        "LegacyTwee" => {
+            r#"The story uses a Twee 1 or Twee2 convention, such as a StorySettings or
+StoryIncludes passage, a Twee2 position in the header, or a Twine 1 image
+passage. tweec translates these when reading the story, so it still builds,
+but they should be rewritten as Twee 3.
+
+For example, a Twee2 position:
+
+    :: Start <100,200>
+
+Give the position as metadata:
+
+    :: Start {"position": "100,200"}
+
+The diagnostic's notes say how to rewrite each one."#
+        }
+        "MissingTranslation" => {
+            r#"When building a translation with `tweec i18n build`, a piece of text has no
+translation in the catalog, so it is left in the original language.
+
+Each paragraph of a passage is an entry in the catalog, with the name of the
+passage as its msgctxt and the paragraph as its msgid. Add an entry for it
+with a msgstr, or update an entry whose msgid no longer matches the text."#
+        }
+        "FuzzyTranslation" => {
+            r#"When building a translation with `tweec i18n build`, a piece of text's
+translation in the catalog is marked fuzzy. Fuzzy translations were made for
+an earlier version of the text and may be out of date, so they are left out
+and the text is left in the original language.
+
+Check the translation against the current text, then remove the `#, fuzzy`
+mark."#
+        }
+        "PlaythroughFailed" => {
+            r#"A scripted playthrough run by `tweec test` didn't go as it expected: a link
+it chose doesn't exist, or the passage reached wasn't the one named, or
+didn't contain the expected text. The message gives the test and step.
+
+For example, this step fails if Start has no link to Inside:
+
+    { "choose": "Go in", "passage": "Inside" }
+
+Fix the story, or update the test if the story changed on purpose."#
+        }
+        _ => return None,
+    };
+    Some(explanation)
+}
@@ -0,0 +1,221 @@
+//! Serializes issues into the SARIF 2.1 format used by code scanning tools
+
+use crate::issue::Issue;
+use crate::rules;
+use crate::StoryFiles;
+use codespan_reporting::diagnostic::{Label, LabelStyle, Severity};
+use codespan_reporting::files::Files;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug | Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note | Severity::Help => "note",
+    }
+}
+
+/// Gets the SARIF location of the given label, using `paths` to find the path
+/// of each file by name
+fn location(
+    label: &Label<usize>,
+    story_files: &StoryFiles,
+    paths: &HashMap<String, PathBuf>,
+) -> Value {
+    let name = story_files.name(label.file_id).unwrap_or_default();
+    let uri = paths
+        .get(name)
+        .map(|path| {
+            let uri = path.to_string_lossy().replace('\\', "/");
+            uri.trim_start_matches("./").to_string()
+        })
+        .unwrap_or_else(|| name.to_string());
+    let (start_line, start_column) = story_files.position(label.file_id, label.range.start);
+    let (end_line, end_column) = story_files.position(label.file_id, label.range.end);
+    let mut location = json!({
+        "physicalLocation": {
+            "artifactLocation": { "uri": uri },
+            "region": {
+                "startLine": start_line + 1,
+                "startColumn": start_column + 1,
+                "endLine": end_line + 1,
+                "endColumn": end_column + 1,
+            },
+        },
+    });
+    if !label.message.is_empty() {
+        location["message"] = json!({ "text": label.message });
+    }
+    location
+}
+
+/// A SARIF log that issues are added to one at a time
+///
+/// Each rule that fired is described once in the tool's rule list, and
+/// results refer back to it by index
+#[derive(Default)]
+pub struct Log {
+    rules: Vec<Value>,
+    rule_indices: HashMap<String, usize>,
+    results: Vec<Value>,
+}
+
+impl Log {
+    /// Adds an issue to the log. `paths` maps the file names used by
+    /// `story_files` to the paths to report for them
+    pub fn add(
+        &mut self,
+        issue: &Issue,
+        story_files: &StoryFiles,
+        paths: &HashMap<String, PathBuf>,
+    ) {
+        let diagnostic = issue.report(story_files);
+        let name = diagnostic.code.clone().unwrap_or_default();
+        let rules = &mut self.rules;
+        let rule_index = *self.rule_indices.entry(name.clone()).or_insert_with(|| {
+            let rule = rules::find(&name);
+            rules.push(json!({
+                "id": name,
+                "name": name,
+                "shortDescription": {
+                    "text": rule.map(|r| r.description).unwrap_or(&name),
+                },
+                "defaultConfiguration": {
+                    "level": level(rule.map(|r| r.severity).unwrap_or(Severity::Warning)),
+                },
+            }));
+            rules.len() - 1
+        });
+
+        let mut text = diagnostic.message.clone();
+        for note in &diagnostic.notes {
+            text.push('\n');
+            text.push_str(note);
+        }
+
+        let mut result = json!({
+            "ruleId": name,
+            "ruleIndex": rule_index,
+            "level": level(diagnostic.severity),
+            "message": { "text": text },
+        });
+        let (primary, secondary): (Vec<_>, Vec<_>) = diagnostic
+            .labels
+            .iter()
+            .partition(|label| label.style == LabelStyle::Primary);
+        if !primary.is_empty() {
+            result["locations"] = primary
+                .iter()
+                .map(|label| location(label, story_files, paths))
+                .collect();
+        }
+        if !secondary.is_empty() {
+            result["relatedLocations"] = secondary
+                .iter()
+                .enumerate()
+                .map(|(id, label)| {
+                    let mut location = location(label, story_files, paths);
+                    location["id"] = json!(id);
+                    location
+                })
+                .collect();
+        }
+        self.results.push(result);
+    }
+
+    /// Gets the log as JSON
+    pub fn to_json(&self) -> Value {
+        json!({
+            "$schema": SCHEMA,
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                        "rules": self.rules,
+                    },
+                },
+                "results": self.results,
+            }],
+        })
+    }
+}
+
+/// Creates a SARIF log containing the given issues. See [`Log`]
+///
+/// [`Log`]: struct.Log.html
+pub fn log(issues: &[Issue], story_files: &StoryFiles, paths: &HashMap<String, PathBuf>) -> Value {
+    let mut log = Log::default();
+    for issue in issues {
+        log.add(issue, story_files, paths);
+    }
+    log.to_json()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issue::LintWarning;
+    use crate::utils;
+    use tweep::Story;
+
+    #[test]
+    fn logs_rules_levels_and_regions() {
+        let input = ":: Start\n[[Nowhere]]\n\n:: Lonely\nHi\n";
+        let path = std::env::temp_dir().join(format!("tweec-sarif-{}.twee", std::process::id()));
+        std::fs::write(&path, input).unwrap();
+        let story_result = Story::from_path(&path).take().0;
+        std::fs::remove_file(&path).unwrap();
+        let headers = utils::passage_headers(story_result.as_ref().ok().unwrap());
+        let issues = vec![
+            Issue::Lint {
+                lint: LintWarning::new(
+                    "DeadEnd",
+                    "Passage Lonely has no links out",
+                    headers.get("Lonely").cloned(),
+                ),
+                denied: false,
+            },
+            Issue::Lint {
+                lint: LintWarning::new("DeadLink", "Dead link", headers.get("Start").cloned()),
+                denied: true,
+            },
+        ];
+        let story_files = StoryFiles::new(&story_result);
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let paths = vec![(file_name, PathBuf::from("./story/story.twee"))]
+            .into_iter()
+            .collect();
+        let log = log(&issues, &story_files, &paths);
+
+        let run = &log["runs"][0];
+        let rules = &run["tool"]["driver"]["rules"];
+        assert_eq!(rules[0]["id"], "DeadEnd");
+        assert_eq!(rules[1]["id"], "DeadLink");
+        assert_eq!(rules[1]["defaultConfiguration"]["level"], "warning");
+
+        let warning = &run["results"][0];
+        assert_eq!(warning["ruleId"], "DeadEnd");
+        assert_eq!(warning["ruleIndex"], 0);
+        assert_eq!(warning["level"], "warning");
+        let location = &warning["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "story/story.twee");
+        assert_eq!(location["region"]["startLine"], 4);
+        assert_eq!(location["region"]["startColumn"], 1);
+
+        // A denied warning is reported as an error
+        let error = &run["results"][1];
+        assert_eq!(error["ruleId"], "DeadLink");
+        assert_eq!(error["ruleIndex"], 1);
+        assert_eq!(error["level"], "error");
+        let region = &error["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 1);
+        assert_eq!(region["startColumn"], 1);
+    }
+}
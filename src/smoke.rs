@@ -0,0 +1,135 @@
+//! Runs a story format's smoke test on a compiled story
+//!
+//! A format can be paired in the config with a command, such as a headless
+//! browser dumping the rendered page, and text that its output should contain
+//! once the story format's engine has booted and shown the start passage.
+//! This catches builds that compile without errors but show a blank page.
+
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use serde::Deserialize;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tweep::Story;
+
+/// A command to run on each compiled story, and what its output must contain
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SmokeTest {
+    /// The program and its arguments. `{output}` is replaced with the absolute
+    /// path of the compiled story
+    pub command: Vec<String>,
+
+    /// Text the command's standard output must contain. `{start}` is replaced
+    /// with the name of the start passage, escaped as in HTML
+    #[serde(default)]
+    pub expect: Vec<String>,
+
+    /// Seconds to wait for the command before failing
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Runs the smoke test on the story compiled to `output`, returning an error
+/// if the command fails, times out, or is missing any expected output
+pub fn run(test: &SmokeTest, story: &Story, output: &Path) -> Result<()> {
+    let (program, args) = test
+        .command
+        .split_first()
+        .ok_or_else(|| eyre!("The smoke test command is empty"))?;
+    let output = output
+        .canonicalize()
+        .wrap_err_with(|| format!("Failed to find output file {:?}", output))?;
+    let output = output.to_string_lossy();
+    let mut child = Command::new(program)
+        .args(args.iter().map(|arg| arg.replace("{output}", &output)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .wrap_err_with(|| format!("Failed to run smoke test command {}", program))?;
+
+    // Read on another thread so a full pipe can't stall the command
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut text = String::new();
+        stdout.read_to_string(&mut text).map(|_| text)
+    });
+    let deadline = Instant::now() + Duration::from_secs(test.timeout);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(eyre!("Smoke test timed out after {} seconds", test.timeout));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let text = reader
+        .join()
+        .map_err(|_| eyre!("Failed to read smoke test output"))?
+        .wrap_err("Failed to read smoke test output")?;
+    if !status.success() {
+        return Err(eyre!("Smoke test command {} failed ({})", program, status));
+    }
+
+    let start = escape_html(story.get_start_passage_name().unwrap_or_default());
+    for expected in &test.expect {
+        let expected = expected.replace("{start}", &start);
+        if !text.contains(&expected) {
+            return Err(eyre!(
+                "Smoke test output does not contain {:?}; the story format may have failed to \
+                 start or show the start passage",
+                expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_command_output() {
+        let input = r#":: StoryData
+{"ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC", "start": "A & B"}
+
+:: A & B
+Hi
+"#;
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let dir = std::env::temp_dir().join(format!("tweec-smoke-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("story.html");
+        std::fs::write(&output, "<div data-passage=\"A &amp; B\">Hi</div>").unwrap();
+
+        let mut test = SmokeTest {
+            command: vec!["cat".to_string(), "{output}".to_string()],
+            expect: vec!["data-passage=\"{start}\"".to_string()],
+            timeout: 10,
+        };
+        assert!(run(&test, &story, &output).is_ok());
+        test.expect.push("tw-passage".to_string());
+        assert!(run(&test, &story, &output).is_err());
+        test.command = vec!["false".to_string()];
+        assert!(run(&test, &story, &output).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Writes a source map relating the passages in the compiled HTML back to the
+//! Twee files they came from
+//!
+//! With `--source-map`, building `My Story.html` also writes
+//! `My Story.html.map`, a JSON file with an entry for each `tw-passagedata`
+//! element giving its name and pid along with the file and lines it was
+//! defined on, so that tools such as error reporters can point at the source:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "file": "My Story.html",
+//!   "passages": [
+//!     { "name": "Start", "pid": 1, "source": "src/start.twee", "start_line": 3, "end_line": 7 }
+//!   ]
+//! }
+//! ```
+//!
+//! Lines start at 1 and include the passage header. Source paths are relative
+//! to the directory of the map, where possible
+
+use crate::partials;
+use crate::utils;
+use color_eyre::Result;
+use eyre::WrapErr;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tweep::Story;
+
+/// Version of the source map format
+const VERSION: u32 = 1;
+
+/// Gets the path of the source map for an output file
+fn map_file(output_file: &str) -> String {
+    format!("{}.map", output_file)
+}
+
+/// Gets the first and last lines of each passage in the story's source,
+/// by passage name, leaving out blank lines at the end of a passage
+fn line_ranges(story: &Story) -> HashMap<String, (String, usize, usize)> {
+    let sources: HashMap<&str, Vec<&str>> = utils::contexts(&story.code_map)
+        .filter_map(|context| {
+            let name = context.get_file_name().as_deref()?;
+            Some((name, context.get_contents().lines().collect()))
+        })
+        .collect();
+    let mut headers: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+    for (name, header) in utils::passage_headers(story) {
+        if let Some(file) = header.get_file_name().clone() {
+            let line = header.get_start_position().line;
+            headers.entry(file).or_default().push((line, name));
+        }
+    }
+
+    let mut ranges = HashMap::new();
+    for (file, mut headers) in headers {
+        headers.sort();
+        let lines = sources
+            .get(file.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for (i, (start, name)) in headers.iter().enumerate() {
+            let mut end = headers
+                .get(i + 1)
+                .map(|(next, _)| next - 1)
+                .unwrap_or(lines.len());
+            while end > *start && lines.get(end - 1).is_some_and(|l| l.trim().is_empty()) {
+                end -= 1;
+            }
+            ranges.insert(name.clone(), (file.clone(), *start, end));
+        }
+    }
+    ranges
+}
+
+/// Makes a path relative to a directory if it is inside it, or else absolute
+fn relative_to(path: &Path, dir: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    path.strip_prefix(&dir)
+        .map(Path::to_path_buf)
+        .unwrap_or(path)
+}
+
+/// Creates the source map for a story compiled to `output_file`
+pub fn source_map(
+    story: &Story,
+    inputs: &[String],
+    extensions: &[String],
+    output_file: &str,
+) -> Value {
+    let paths = utils::file_paths(inputs, extensions);
+    let output = Path::new(output_file);
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let ranges = line_ranges(story);
+
+    let mut passages: Vec<_> = story
+        .passages
+        .iter()
+        .filter(|(_, passage)| !passage.header.tags.iter().any(|tag| tag == partials::TAG))
+        .collect();
+    passages.sort_by_key(|(_, passage)| utils::get_pid(passage));
+    let passages: Vec<Value> = passages
+        .into_iter()
+        .map(|(name, passage)| {
+            let mut entry = json!({
+                "name": name,
+                "pid": utils::get_pid(passage),
+            });
+            if let Some((file, start, end)) = ranges.get(name) {
+                let source = match paths.get(file) {
+                    Some(path) => relative_to(path, dir).to_string_lossy().into_owned(),
+                    None => file.clone(),
+                };
+                entry["source"] = json!(source);
+                entry["start_line"] = json!(start);
+                entry["end_line"] = json!(end);
+            }
+            entry
+        })
+        .collect();
+
+    json!({
+        "version": VERSION,
+        "file": output.file_name().map(|name| name.to_string_lossy()),
+        "passages": passages,
+    })
+}
+
+/// Writes the source map for a story compiled to `output_file` next to it
+pub fn write(
+    story: &Story,
+    inputs: &[String],
+    extensions: &[String],
+    output_file: &str,
+) -> Result<()> {
+    let map = source_map(story, inputs, extensions, output_file);
+    let file_name = map_file(output_file);
+    let mut contents = serde_json::to_string_pretty(&map)?;
+    contents.push('\n');
+    utils::write_atomic(&file_name, contents.as_bytes())
+        .wrap_err_with(|| format!("Failed to write source map {}", file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_passage_lines() {
+        let dir = std::env::temp_dir().join(format!("tweec-source-map-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("story.twee");
+        std::fs::write(
+            &path,
+            ":: Start\nHello [[Next]]\n\n\n:: Next\nOne\nTwo\n\n:: Partial [partial]\nHi\n",
+        )
+        .unwrap();
+        let story = Story::from_paths(&[&path]).take().0.ok().unwrap();
+        let input = path.to_string_lossy().into_owned();
+        let output = dir.join("out.html").to_string_lossy().into_owned();
+        let map = source_map(&story, &[input], &["twee".to_string()], &output);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(map["file"], "out.html");
+        let passages = map["passages"].as_array().unwrap();
+        assert_eq!(passages.len(), 2);
+        let next = passages.iter().find(|p| p["name"] == "Next").unwrap();
+        assert_eq!(next["source"], "story.twee");
+        assert_eq!(next["start_line"], 5);
+        assert_eq!(next["end_line"], 7);
+        let start = passages.iter().find(|p| p["name"] == "Start").unwrap();
+        assert_eq!(start["end_line"], 2);
+    }
+}
@@ -0,0 +1,453 @@
+//! Prints statistics about a story, optionally with who last changed each
+//! passage according to git
+//!
+//! The structure of the story is measured on its link graph, so dead links are
+//! not counted anywhere: link counts are of links to passages that exist, the
+//! branching factor is the average number of distinct passages each passage
+//! links to, and path lengths are the fewest links needed to get from the
+//! start passage to another passage. The shortest path is to the nearest ending, which is a
+//! passage with an ending tag or no links out, and the longest is to the
+//! passage farthest from the start.
+//!
+//! Authorship comes from running `git blame` on each source file and finding
+//! the most recent change to the lines of each passage, from its header up to
+//! the next passage's header. This needs `git` on the `PATH` and the source
+//! files to be in a git repository.
+
+use crate::inputs;
+use crate::linter::graph::LinkGraph;
+use crate::utils;
+use crate::Config;
+use crate::StatsConfig;
+use crate::StatsFormat;
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use tweep::Story;
+
+/// The commit that last changed a line
+#[derive(Clone, Debug, PartialEq)]
+struct LineBlame {
+    author: String,
+
+    /// Seconds since the Unix epoch
+    time: i64,
+}
+
+/// Parses the output of `git blame --line-porcelain` into the commit that
+/// last changed each line, in order
+fn parse_blame(output: &str) -> Vec<LineBlame> {
+    let mut lines = Vec::new();
+    let mut author = String::new();
+    let mut time = 0;
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            lines.push(LineBlame {
+                author: author.clone(),
+                time,
+            });
+        } else if let Some(name) = line.strip_prefix("author ") {
+            author = name.to_string();
+        } else if let Some(seconds) = line.strip_prefix("author-time ") {
+            time = seconds.parse().unwrap_or(0);
+        }
+    }
+    lines
+}
+
+/// Runs `git blame` on the file at `path`
+fn blame(path: &Path) -> Result<Vec<LineBlame>> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| eyre!("{:?} is not a file", path))?;
+    let output = Command::new("git")
+        .args(["blame", "--line-porcelain", "--"])
+        .arg(file_name)
+        .current_dir(dir)
+        .output()
+        .wrap_err("Failed to run git")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "git blame failed for {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_blame(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Formats seconds since the Unix epoch as a UTC date (YYYY-MM-DD)
+fn date(timestamp: i64) -> String {
+    // Converts days since the epoch to a civil date, after Howard Hinnant's
+    // days_from_civil algorithm
+    let days = timestamp.div_euclid(86400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The fewest links from the start passage to another passage
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct StoryPath<'a> {
+    to: &'a str,
+    links: usize,
+}
+
+/// Counts and measurements of a story
+#[derive(Debug)]
+struct Summary<'a> {
+    files: usize,
+    words: usize,
+    links: usize,
+    branching: f64,
+    shortest: Option<StoryPath<'a>>,
+    longest: Option<StoryPath<'a>>,
+
+    /// Each passage with its number of words and links, by name
+    passages: Vec<(&'a str, usize, usize)>,
+}
+
+fn summarize<'a>(story: &'a Story, config: &Config) -> Summary<'a> {
+    let graph = LinkGraph::new(story, &config.links);
+    let mut names: Vec<&str> = story.passages.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    let passages: Vec<(&str, usize, usize)> = names
+        .iter()
+        .map(|name| {
+            let words = story.passages[*name]
+                .content
+                .content
+                .split_whitespace()
+                .count();
+            let links = graph.outgoing(name).len();
+            (*name, words, links)
+        })
+        .collect();
+
+    let targets: usize = names
+        .iter()
+        .map(|name| {
+            let targets: HashSet<&str> = graph.outgoing(name).iter().map(|e| e.target).collect();
+            targets.len()
+        })
+        .sum();
+    let branching = if names.is_empty() {
+        0.
+    } else {
+        targets as f64 / names.len() as f64
+    };
+
+    let distances = story
+        .get_start_passage_name()
+        .map(|start| graph.distances_from(start))
+        .unwrap_or_default();
+    let mut distances: Vec<StoryPath> = distances
+        .into_iter()
+        .map(|(to, links)| StoryPath { to, links })
+        .collect();
+    // Ties go to the first passage by name
+    distances.sort_unstable_by(|a, b| a.links.cmp(&b.links).then(a.to.cmp(b.to)));
+    let is_ending = |name: &str| {
+        graph.outgoing(name).is_empty()
+            || graph
+                .tags(name)
+                .iter()
+                .any(|tag| config.ending_tags.contains(tag))
+    };
+    let shortest = distances.iter().find(|path| is_ending(path.to)).copied();
+    let farthest = distances.last().map(|path| path.links);
+    let longest = distances
+        .iter()
+        .find(|path| Some(path.links) == farthest)
+        .copied();
+
+    Summary {
+        files: inputs::twee_files(&config.inputs, &config.extensions).len(),
+        words: passages.iter().map(|(_, words, _)| words).sum(),
+        links: passages.iter().map(|(_, _, links)| links).sum(),
+        branching,
+        shortest,
+        longest,
+        passages,
+    }
+}
+
+fn path_json(path: &Option<StoryPath>) -> Value {
+    match path {
+        Some(path) => json!({ "to": path.to, "links": path.links }),
+        None => Value::Null,
+    }
+}
+
+fn path_text(path: &Option<StoryPath>) -> String {
+    match path {
+        Some(path) => format!("{} (to {})", path.links, path.to),
+        None => "-".to_string(),
+    }
+}
+
+/// Writes the counts and measurements of the story, and the words and links
+/// in each passage, to `out`
+fn print_summary(
+    story: &Story,
+    config: &Config,
+    format: StatsFormat,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let summary = summarize(story, config);
+    if format == StatsFormat::Json {
+        let passages: Vec<Value> = summary
+            .passages
+            .iter()
+            .map(|(name, words, links)| json!({ "name": name, "words": words, "links": links }))
+            .collect();
+        let value = json!({
+            "files": summary.files,
+            "passages": summary.passages.len(),
+            "words": summary.words,
+            "links": summary.links,
+            "branching_factor": summary.branching,
+            "shortest_path": path_json(&summary.shortest),
+            "longest_path": path_json(&summary.longest),
+            "passage_stats": passages,
+        });
+        writeln!(out, "{}", serde_json::to_string_pretty(&value)?)?;
+        return Ok(());
+    }
+
+    let average = if summary.passages.is_empty() {
+        0.
+    } else {
+        summary.words as f64 / summary.passages.len() as f64
+    };
+    let rows = vec![
+        vec!["Files".to_string(), summary.files.to_string()],
+        vec!["Passages".to_string(), summary.passages.len().to_string()],
+        vec!["Words".to_string(), summary.words.to_string()],
+        vec!["Words per passage".to_string(), format!("{:.1}", average)],
+        vec!["Links".to_string(), summary.links.to_string()],
+        vec![
+            "Branching factor".to_string(),
+            format!("{:.2}", summary.branching),
+        ],
+        vec!["Shortest path".to_string(), path_text(&summary.shortest)],
+        vec!["Longest path".to_string(), path_text(&summary.longest)],
+    ];
+    write!(out, "{}", utils::format_table(&["STORY", "COUNT"], &rows))?;
+    writeln!(out)?;
+    let rows: Vec<Vec<String>> = summary
+        .passages
+        .iter()
+        .map(|(name, words, links)| vec![name.to_string(), words.to_string(), links.to_string()])
+        .collect();
+    write!(
+        out,
+        "{}",
+        utils::format_table(&["PASSAGE", "WORDS", "LINKS"], &rows)
+    )?;
+    Ok(())
+}
+
+/// Writes when and by whom each passage was last changed, oldest first, to
+/// `out`
+fn print_authors(
+    story: &Story,
+    config: &Config,
+    format: StatsFormat,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let paths = utils::file_paths(&config.inputs, &config.extensions);
+    let sources: HashMap<String, Vec<&str>> = utils::contexts(&story.code_map)
+        .filter_map(|context| {
+            let lines = context.get_contents().lines().collect();
+            Some((context.get_file_name().clone()?, lines))
+        })
+        .collect();
+
+    // The header line of each passage, grouped by file
+    let mut files: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+    for (name, header) in utils::passage_headers(story) {
+        if let Some(file) = header.get_file_name().clone() {
+            let line = header.get_start_position().line;
+            files.entry(file).or_default().push((line, name));
+        }
+    }
+
+    let mut passages = Vec::new();
+    for (file, mut headers) in files {
+        let path = paths
+            .get(&file)
+            .ok_or_else(|| eyre!("Failed to find the path of {}", file))?;
+        let blame = blame(path)?;
+        headers.sort();
+        let source = sources.get(&file).map(Vec::as_slice).unwrap_or_default();
+        for (i, (line, name)) in headers.iter().enumerate() {
+            let mut end = headers
+                .get(i + 1)
+                .map(|(next, _)| next - 1)
+                .unwrap_or(source.len());
+            // Blank lines between passages belong to neither
+            while end > *line && source.get(end - 1).is_some_and(|l| l.trim().is_empty()) {
+                end -= 1;
+            }
+            let latest = blame
+                .get(line - 1..end.min(blame.len()))
+                .unwrap_or_default()
+                .iter()
+                .max_by_key(|blame| blame.time);
+            if let Some(latest) = latest {
+                passages.push((
+                    latest.time,
+                    name.clone(),
+                    format!("{}:{}", path.to_string_lossy(), line),
+                    (end + 1 - line).to_string(),
+                    latest.author.clone(),
+                ));
+            }
+        }
+    }
+
+    passages.sort();
+    if format == StatsFormat::Json {
+        let passages: Vec<Value> = passages
+            .into_iter()
+            .map(|(time, name, location, lines, author)| {
+                json!({
+                    "name": name,
+                    "location": location,
+                    "lines": lines.parse::<usize>().unwrap_or_default(),
+                    "modified": date(time),
+                    "author": author,
+                })
+            })
+            .collect();
+        writeln!(out, "{}", serde_json::to_string_pretty(&passages)?)?;
+        return Ok(());
+    }
+    let rows: Vec<Vec<String>> = passages
+        .into_iter()
+        .map(|(time, name, location, lines, author)| {
+            vec![name, location, lines, date(time), author]
+        })
+        .collect();
+    write!(
+        out,
+        "{}",
+        utils::format_table(&["PASSAGE", "FILE", "LINES", "MODIFIED", "AUTHOR"], &rows)
+    )?;
+    Ok(())
+}
+
+/// Writes statistics about the story as configured to `out`
+///
+/// The story must have been parsed from the inputs in `config`
+pub fn stats(
+    story: &Story,
+    config: &Config,
+    stats: &StatsConfig,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if stats.authors {
+        print_authors(story, config, stats.format, out)
+    } else {
+        print_summary(story, config, stats.format, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_blame_and_dates() {
+        let output = "\
+1234 1 1 2
+author Ann
+author-time 1700000000
+summary Start
+\t:: Start
+1234 2 2
+author Ann
+author-time 1700000000
+\tHello
+0000 3 3 1
+author Not Committed Yet
+author-time 1800000000
+\t[[End]]
+";
+        let lines = parse_blame(output);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].author, "Ann");
+        assert_eq!(lines[2].author, "Not Committed Yet");
+        assert_eq!(date(lines[0].time), "2023-11-14");
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+    }
+
+    #[test]
+    fn summarizes_structure() {
+        let input = r#":: StoryData
+{"ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC", "start": "Start"}
+
+:: Start
+You wake up. [[Left]] [[Right]] [[Left]]
+
+:: Left [ending]
+The end. [[Start]]
+
+:: Right
+[[Deep]] [[Nowhere]]
+
+:: Deep
+It is dark
+"#;
+        let story = Story::from_string(input.to_string()).take().0.ok().unwrap();
+        let mut config = crate::linter::tests::config(None, Vec::new());
+        config.ending_tags = vec!["ending".to_string()];
+        let summary = summarize(&story, &config);
+        // The dead link from Right is counted nowhere
+        assert_eq!(summary.passages.len(), 4);
+        assert_eq!(summary.links, 5);
+        assert_eq!(summary.branching, 1.);
+        assert_eq!(summary.passages[3], ("Start", 6, 3));
+        assert_eq!(
+            summary.shortest,
+            Some(StoryPath {
+                to: "Left",
+                links: 1
+            })
+        );
+        assert_eq!(
+            summary.longest,
+            Some(StoryPath {
+                to: "Deep",
+                links: 2
+            })
+        );
+
+        let mut out = Vec::new();
+        print_summary(&story, &config, StatsFormat::Json, &mut out).unwrap();
+        let value: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value["links"], 5);
+        assert_eq!(value["longest_path"], json!({ "to": "Deep", "links": 2 }));
+    }
+}
@@ -0,0 +1,111 @@
+//! Reads a story from standard input, given as the input `-`
+//!
+//! `tweep` only knows the names of stories read from files, so standard input
+//! is copied to a file in a temporary directory that stands in for `-` in the
+//! inputs. The file is named `<stdin>`, which diagnostics then use
+
+use color_eyre::Result;
+use eyre::{eyre, WrapErr};
+use std::io::Read;
+use std::path::PathBuf;
+
+/// The input that reads the story from standard input
+pub const INPUT: &str = "-";
+
+/// The file name diagnostics give for a story read from standard input
+pub const NAME: &str = "<stdin>";
+
+/// The name of the file standard input is copied to. Windows does not allow
+/// `<` or `>` in file names, so a plain name is used there and renamed by
+/// [`display_name`]
+///
+/// [`display_name`]: fn.display_name.html
+const FILE_NAME: &str = if cfg!(windows) { "stdin.twee" } else { NAME };
+
+/// The temporary directory this process copies standard input to
+fn dir() -> PathBuf {
+    std::env::temp_dir().join(format!("tweec-stdin-{}", std::process::id()))
+}
+
+/// Whether the inputs read from standard input
+pub fn is_read(inputs: &[String]) -> bool {
+    inputs.iter().any(|input| input == INPUT)
+}
+
+/// Copies standard input to a temporary file and replaces `-` in the inputs
+/// with its path. Does nothing if `-` is not one of the inputs
+pub fn replace_input(inputs: &mut [String]) -> Result<()> {
+    read_input(inputs, std::io::stdin())
+}
+
+/// Copies `reader` to a temporary file in place of standard input. See
+/// [`replace_input`]
+///
+/// [`replace_input`]: fn.replace_input.html
+fn read_input<R: Read>(inputs: &mut [String], mut reader: R) -> Result<()> {
+    match inputs.iter().filter(|input| *input == INPUT).count() {
+        0 => return Ok(()),
+        1 => (),
+        _ => {
+            return Err(eyre!(
+                "Standard input (-) can only be given once as an input"
+            ))
+        }
+    }
+
+    let mut source = String::new();
+    reader
+        .read_to_string(&mut source)
+        .wrap_err_with(|| "Failed to read the story from standard input")?;
+    let dir = dir();
+    std::fs::create_dir_all(&dir)
+        .wrap_err_with(|| format!("Failed to create temporary directory {:?}", dir))?;
+    let path = dir.join(FILE_NAME);
+    std::fs::write(&path, source)
+        .wrap_err_with(|| format!("Failed to write standard input to {:?}", path))?;
+
+    let path = path.to_string_lossy().into_owned();
+    for input in inputs.iter_mut().filter(|input| *input == INPUT) {
+        *input = path.clone();
+    }
+    Ok(())
+}
+
+/// Gets the name to show for a file read by `tweep`, which is `<stdin>` for
+/// the copy of standard input
+pub fn display_name(name: &str) -> &str {
+    if name == FILE_NAME {
+        NAME
+    } else {
+        name
+    }
+}
+
+/// Removes the copy of standard input, if any
+pub fn cleanup() {
+    let _ = std::fs::remove_dir_all(dir());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StoryFiles;
+    use codespan_reporting::files::Files;
+
+    #[test]
+    fn reads_story_from_stdin() {
+        let mut inputs = vec![INPUT.to_string(), INPUT.to_string()];
+        assert!(read_input(&mut inputs, &b""[..]).is_err());
+
+        let mut inputs = vec![INPUT.to_string()];
+        assert!(is_read(&inputs));
+        read_input(&mut inputs, &b":: Start\nHello\n"[..]).unwrap();
+        assert!(!is_read(&inputs));
+        let story_result = crate::inputs::read_story(&inputs, &[]).take().0;
+        cleanup();
+
+        let story = story_result.as_ref().ok().unwrap();
+        assert_eq!(story.passages["Start"].content.content, "Hello\n");
+        assert_eq!(StoryFiles::new(&story_result).name(0), Some(NAME));
+    }
+}
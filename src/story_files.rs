@@ -1,3 +1,4 @@
+use crate::stdin;
 use crate::StoryResult;
 use codespan_reporting::files::Files;
 use std::ops::Range;
@@ -20,9 +21,7 @@ impl<'a> StoryFiles<'a> {
                 let names = story.passages.keys().cloned().collect();
                 (&story.code_map, Some(names))
             }
-            Err(e) => {
-                (&e.code_map, None)
-            }
+            Err(e) => (&e.code_map, None),
         };
 
         StoryFiles {
@@ -30,6 +29,20 @@ impl<'a> StoryFiles<'a> {
             passage_names,
         }
     }
+
+    /// Gets the zero-based line and column of the given byte offset in a
+    /// file, with the column counted in UTF-16 code units as editors and code
+    /// scanning tools expect
+    pub fn position(&self, id: usize, byte_index: usize) -> (usize, usize) {
+        let line = self.line_index(id, byte_index).unwrap_or(0);
+        let start = self.line_range(id, line).map(|r| r.start).unwrap_or(0);
+        let column = self
+            .source(id)
+            .and_then(|source| source.get(start..byte_index))
+            .map(|prefix| prefix.encode_utf16().count())
+            .unwrap_or(0);
+        (line, column)
+    }
 }
 
 impl<'a> Files<'a> for StoryFiles<'a> {
@@ -38,7 +51,7 @@ impl<'a> Files<'a> for StoryFiles<'a> {
     type Source = &'a str;
 
     fn name(&'a self, id: Self::FileId) -> Option<Self::Name> {
-        self.code_map.lookup_name(id)
+        self.code_map.lookup_name(id).map(stdin::display_name)
     }
 
     fn source(&'a self, id: Self::FileId) -> Option<Self::Source> {
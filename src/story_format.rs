@@ -3,8 +3,25 @@ use serde::{Deserialize, Serialize};
 use color_eyre::Result;
 use eyre::{eyre, WrapErr};
 
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Checks that a custom placeholder name is made of letters, digits, and
+/// underscores, and isn't one of the names the story is written into
+pub fn check_placeholder_name(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!(
+            "Placeholder name {:?} must be letters, digits, and underscores",
+            name
+        ));
+    }
+    if name == "STORY_NAME" || name == "STORY_DATA" {
+        return Err(format!("Placeholder {} is filled in by tweec", name));
+    }
+    Ok(())
+}
 
 fn default_name() -> String {
     "Untitled Story Format".to_string()
@@ -64,24 +81,322 @@ impl StoryFormat {
 
         let mut contents = String::new();
         format_file.read_to_string(&mut contents)?;
+        StoryFormat::from_source(&contents)
+    }
+
+    /// Attempts to parse the contents of a `format.js` file as a `StoryFormat`
+    ///
+    /// Allows Harlowe's malformed JSON blob, but rejects any other malformed
+    /// JSON
+    pub fn from_source(contents: &str) -> Result<StoryFormat> {
+        let f = serde_json::from_str(&json_blob(contents)?)
+            .wrap_err_with(|| "Failed to parse story format JSON")?;
+        Ok(f)
+    }
+
+    /// Checks the contents of a `format.js` file against the Twine 2 spec,
+    /// returning every problem found, or nothing if it is a valid format
+    ///
+    /// Unlike the spec, the `{{STORY_NAME}}` and `{{STORY_DATA}}` placeholders
+    /// are required, since a format without them can't show a story
+    pub fn check_source(contents: &str) -> Vec<String> {
+        let blob = match json_blob(contents) {
+            Ok(blob) => blob,
+            Err(e) => return vec![e.to_string()],
+        };
+        let value: serde_json::Value = match serde_json::from_str(&blob) {
+            Ok(value) => value,
+            Err(e) => return vec![format!("Invalid JSON: {}", e)],
+        };
+        let fields = match value.as_object() {
+            Some(fields) => fields,
+            None => return vec!["The JSON blob is not an object".to_string()],
+        };
+
+        let mut problems = Vec::new();
+        let mut string = |field: &str, required: bool| match fields.get(field) {
+            Some(serde_json::Value::String(value)) => Some(value.as_str()),
+            Some(_) => {
+                problems.push(format!("Field \"{}\" must be a string", field));
+                None
+            }
+            None => {
+                if required {
+                    problems.push(format!("Missing required field \"{}\"", field));
+                }
+                None
+            }
+        };
+        for field in &["name", "author", "description", "image", "url", "license"] {
+            string(field, false);
+        }
+        let version = string("version", true);
+        let source = string("source", true);
+        if let Some(version) = version {
+            if !is_semver(version) {
+                problems.push(format!(
+                    "Version \"{}\" is not a semantic version (x.y.z)",
+                    version
+                ));
+            }
+        }
+        if let Some(source) = source {
+            for placeholder in &["{{STORY_NAME}}", "{{STORY_DATA}}"] {
+                if !source.contains(placeholder) {
+                    problems.push(format!("Source is missing the {} placeholder", placeholder));
+                }
+            }
+        }
+        if fields
+            .get("proofing")
+            .is_some_and(|proofing| !proofing.is_boolean())
+        {
+            problems.push("Field \"proofing\" must be true or false".to_string());
+        }
+        problems
+    }
 
-        let start = contents
-            .find('{')
-            .ok_or_else(|| eyre!("Could not find Twine2 JSON blob"))?;
-        let end = if contents.contains("harlowe") {
-            contents.rfind(",\"setup\":")
+    /// Gets the placeholders that are missing from this format's source
+    pub fn missing_placeholders(&self) -> Vec<&'static str> {
+        ["{{STORY_NAME}}", "{{STORY_DATA}}"]
+            .iter()
+            .copied()
+            .filter(|placeholder| !self.source.contains(placeholder))
+            .collect()
+    }
+
+    /// Replaces each `{{NAME}}` placeholder in this format's source with its
+    /// value from `placeholders`, returning the names of any that the source
+    /// doesn't contain
+    pub fn fill_placeholders(&mut self, placeholders: &BTreeMap<String, String>) -> Vec<String> {
+        let mut missing = Vec::new();
+        for (name, value) in placeholders {
+            let placeholder = format!("{{{{{}}}}}", name);
+            if self.source.contains(&placeholder) {
+                self.source = self.source.replace(&placeholder, value);
+            } else {
+                missing.push(name.clone());
+            }
+        }
+        missing
+    }
+
+    /// Gets the key used for this format in the config file and when
+    /// installing it: its lowercase name with dashes for spaces, followed by
+    /// its major version (e.g., sugarcube-2)
+    pub fn family(&self) -> String {
+        let name = self
+            .name
+            .to_lowercase()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("-");
+        let major = self.version.split('.').next().unwrap_or_default();
+        format!("{}-{}", name, major)
+    }
+
+    /// Parses every installed format that can be parsed, sorted by key
+    fn installed(formats: &HashMap<String, PathBuf>) -> Vec<(&str, StoryFormat)> {
+        let mut keys: Vec<&String> = formats.keys().collect();
+        keys.sort();
+        keys.into_iter()
+            .filter_map(|key| Some((key.as_str(), StoryFormat::parse(&formats[key]).ok()?)))
+            .collect()
+    }
+
+    /// Picks the format to use from the installed versions of a format
+    ///
+    /// If `exact` is true, only the given version matches. Otherwise, the
+    /// latest version with the same major version that is at least the given
+    /// one is compatible, following semantic versioning. With no version
+    /// given, the latest version is used
+    fn pick<'a>(
+        candidates: &[(&'a str, StoryFormat)],
+        version: Option<&str>,
+        exact: bool,
+    ) -> Option<&'a str> {
+        let latest = |matching: &dyn Fn(&StoryFormat) -> bool| {
+            candidates
+                .iter()
+                .filter(|(_, format)| matching(format))
+                .max_by_key(|(_, format)| parse_version(&format.version))
+                .map(|(key, _)| *key)
+        };
+        let version = match version {
+            Some(version) => version,
+            None => return latest(&|_| true),
+        };
+        if let Some((key, _)) = candidates.iter().find(|(_, f)| f.version == version) {
+            return Some(key);
+        }
+        let requested = parse_version(version).filter(|_| !exact)?;
+        latest(&|format| match parse_version(&format.version) {
+            Some(installed) => installed.0 == requested.0 && installed >= requested,
+            None => false,
+        })
+    }
+
+    /// Builds the error for a format that could not be resolved
+    fn not_installed(requested: &str, candidates: &[(&str, StoryFormat)]) -> color_eyre::Report {
+        if candidates.is_empty() {
+            eyre!(
+                "Story format {} is not installed. Use --format to select a format",
+                requested
+            )
         } else {
-            contents.rfind('}')
+            let installed: Vec<&str> = candidates
+                .iter()
+                .map(|(_, format)| format.version.as_str())
+                .collect();
+            eyre!(
+                "Story format {} is not installed (installed versions: {}). Use --format to \
+                 select a format",
+                requested,
+                installed.join(", ")
+            )
         }
-        .ok_or_else(|| eyre!("Could not find Twine2 JSON blob"))?;
+    }
 
-        let mut json_blob_contents = contents[start..end].to_owned();
-        json_blob_contents.push('}');
+    /// Finds the installed format with the given name that is compatible
+    /// with the given version
+    ///
+    /// `formats` maps format keys (the names of the directories containing
+    /// each format, as discovered by [`ConfigFile::load`]) to their paths.
+    /// Names are compared case-insensitively against the `name` field of each
+    /// format. An exact version match is preferred; otherwise the latest
+    /// installed version with the same major version that is no older than
+    /// the requested one is used. With no version, the latest installed
+    /// version is used. Returns the key of the matching format
+    ///
+    /// [`ConfigFile::load`]: struct.ConfigFile.html#method.load
+    pub fn resolve<'a>(
+        formats: &'a HashMap<String, PathBuf>,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<&'a str> {
+        let candidates: Vec<_> = StoryFormat::installed(formats)
+            .into_iter()
+            .filter(|(_, format)| format.name.eq_ignore_ascii_case(name))
+            .collect();
+        StoryFormat::pick(&candidates, version, false).ok_or_else(|| {
+            let requested = match version {
+                Some(version) => format!("{} {}", name, version),
+                None => name.to_string(),
+            };
+            StoryFormat::not_installed(&requested, &candidates)
+        })
+    }
 
-        let f = serde_json::from_str(&json_blob_contents)
-            .wrap_err_with(|| "Failed to parse story format JSON")?;
-        Ok(f)
+    /// Finds the first installed proofing format, by key, returning its key
+    pub fn find_proofing(formats: &HashMap<String, PathBuf>) -> Option<&str> {
+        StoryFormat::installed(formats)
+            .into_iter()
+            .find(|(_, format)| format.proofing)
+            .map(|(key, _)| key)
     }
+
+    /// Finds the installed format requested with `--format`
+    ///
+    /// The request may be a format key, or a format's name or family (e.g.,
+    /// SugarCube or sugarcube-2) optionally pinned to a version with `@`, as
+    /// in `sugarcube-2@2.36.1`. Without a pin, the latest installed version is
+    /// used. Returns `None` if no installed format matches the name, in which
+    /// case the request may be a path to a format file
+    pub fn resolve_request<'a>(
+        formats: &'a HashMap<String, PathBuf>,
+        request: &str,
+    ) -> Result<Option<&'a str>> {
+        if let Some((key, _)) = formats.get_key_value(request) {
+            return Ok(Some(key));
+        }
+        let (name, version) = match request.rfind('@') {
+            Some(at) => (&request[..at], Some(&request[at + 1..])),
+            None => (request, None),
+        };
+        let candidates: Vec<_> = StoryFormat::installed(formats)
+            .into_iter()
+            .filter(|(key, format)| {
+                format.name.eq_ignore_ascii_case(name)
+                    || format.family().eq_ignore_ascii_case(name)
+                    || (version.is_some() && key.eq_ignore_ascii_case(name))
+            })
+            .collect();
+        if candidates.is_empty() && version.is_none() {
+            return Ok(None);
+        }
+        StoryFormat::pick(&candidates, version, true)
+            .map(Some)
+            .ok_or_else(|| StoryFormat::not_installed(request, &candidates))
+    }
+}
+
+/// Checks that the file at `path` has the given SHA-256 hash, in hexadecimal
+pub fn check_sha256(path: &Path, expected: &str) -> Result<()> {
+    let contents =
+        std::fs::read(path).wrap_err_with(|| format!("Failed to read story format {:?}", path))?;
+    let actual = sha256(&contents);
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "Story format {:?} has SHA-256 hash {}, but the project pins {}. Install the pinned \
+             version of the format, or update the hash in {}",
+            path,
+            actual,
+            expected,
+            crate::project::FILE_NAME
+        ))
+    }
+}
+
+/// Gets the SHA-256 hash of some bytes, in lowercase hexadecimal
+pub(crate) fn sha256(bytes: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Gets the JSON blob from the contents of a `format.js` file, allowing
+/// Harlowe's, which is followed by code rather than ending the file
+fn json_blob(contents: &str) -> Result<String> {
+    let start = contents
+        .find('{')
+        .ok_or_else(|| eyre!("Could not find Twine2 JSON blob"))?;
+    let end = if contents.contains("harlowe") {
+        contents.rfind(",\"setup\":")
+    } else {
+        contents.rfind('}')
+    }
+    .filter(|&end| end >= start)
+    .ok_or_else(|| eyre!("Could not find Twine2 JSON blob"))?;
+
+    let mut json_blob_contents = contents[start..end].to_owned();
+    json_blob_contents.push('}');
+    Ok(json_blob_contents)
+}
+
+/// Whether a version is written as a semantic version: three numbers
+/// separated by dots, with an optional pre-release or build suffix
+fn is_semver(version: &str) -> bool {
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Parses a semantic version into its major, minor, and patch numbers,
+/// ignoring any pre-release or build suffix
+pub fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    Some((major, minor, patch))
 }
 
 #[cfg(test)]
@@ -101,7 +416,140 @@ mod tests {
         assert_eq!(story_format.image, None);
         assert_eq!(story_format.url, None);
         assert_eq!(story_format.license, None);
-        assert_eq!(story_format.proofing, false);
+        assert!(!story_format.proofing);
         assert_eq!(story_format.source, "blah");
+        assert_eq!(
+            story_format.missing_placeholders(),
+            vec!["{{STORY_NAME}}", "{{STORY_DATA}}"]
+        );
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        let input =
+            r#"{ "version": "1.0.0", "source": "{{BUILD_ID}} {{STORY_DATA}} {{BUILD_ID}}" }"#;
+        let mut story_format: StoryFormat = serde_json::from_str(input).unwrap();
+        let mut placeholders = BTreeMap::new();
+        placeholders.insert("BUILD_ID".to_string(), "42".to_string());
+        placeholders.insert("ANALYTICS".to_string(), "<script></script>".to_string());
+        assert_eq!(
+            story_format.fill_placeholders(&placeholders),
+            vec!["ANALYTICS"]
+        );
+        assert_eq!(story_format.source, "42 {{STORY_DATA}} 42");
+
+        assert!(check_placeholder_name("BUILD_ID").is_ok());
+        assert!(check_placeholder_name("BUILD ID").is_err());
+        assert!(check_placeholder_name("").is_err());
+        assert!(check_placeholder_name("STORY_DATA").is_err());
+    }
+
+    #[test]
+    fn resolve_by_name_and_version() {
+        let dir = std::env::temp_dir().join(format!("tweec-formats-{}", uuid::Uuid::new_v4()));
+        let mut formats = HashMap::new();
+        for (key, version) in &[("fmt-1", "1.0.0"), ("fmt-2", "2.1.0")] {
+            let path = dir.join(key).join("format.js");
+            std::fs::create_dir_all(path.parent().unwrap())
+                .ok()
+                .unwrap();
+            let source = format!(
+                r#"window.storyFormat({{"name":"Fmt","version":"{}","source":""}});"#,
+                version
+            );
+            std::fs::write(&path, source).ok().unwrap();
+            formats.insert(key.to_string(), path);
+        }
+
+        assert_eq!(
+            StoryFormat::resolve(&formats, "fmt", Some("2.1.0")).ok(),
+            Some("fmt-2")
+        );
+        assert_eq!(
+            StoryFormat::resolve(&formats, "Fmt", None).ok(),
+            Some("fmt-2")
+        );
+        assert!(StoryFormat::resolve(&formats, "Fmt", Some("3.0.0")).is_err());
+        assert!(StoryFormat::resolve(&formats, "Other", None).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn resolve_latest_compatible_version() {
+        let dir = std::env::temp_dir().join(format!("tweec-formats-{}", uuid::Uuid::new_v4()));
+        let mut formats = HashMap::new();
+        for version in &["2.34.1", "2.36.1", "2.9.0", "3.0.0"] {
+            let key = format!("fmt-{}", version);
+            let path = dir.join(&key).join("format.js");
+            std::fs::create_dir_all(path.parent().unwrap())
+                .ok()
+                .unwrap();
+            let source = format!(
+                r#"window.storyFormat({{"name":"Fmt","version":"{}","source":""}});"#,
+                version
+            );
+            std::fs::write(&path, source).ok().unwrap();
+            formats.insert(key, path);
+        }
+
+        let resolve = |name, version| StoryFormat::resolve(&formats, name, version).ok();
+        assert_eq!(resolve("Fmt", Some("2.10.0")), Some("fmt-2.36.1"));
+        assert_eq!(resolve("Fmt", Some("2.34.1")), Some("fmt-2.34.1"));
+        assert_eq!(resolve("Fmt", Some("2.40.0")), None);
+
+        let request = |request| StoryFormat::resolve_request(&formats, request).ok();
+        assert_eq!(request("fmt-2"), Some(Some("fmt-2.36.1")));
+        assert_eq!(request("Fmt"), Some(Some("fmt-3.0.0")));
+        assert_eq!(request("fmt-2@2.9.0"), Some(Some("fmt-2.9.0")));
+        assert_eq!(request("fmt-2@2.10.0"), None);
+        assert_eq!(request("other.js"), Some(None));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn checks_pinned_hashes() {
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let path = std::env::temp_dir().join(format!("tweec-sha256-{}.js", std::process::id()));
+        std::fs::write(&path, "abc").unwrap();
+        assert!(check_sha256(
+            &path,
+            "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"
+        )
+        .is_ok());
+        assert!(check_sha256(&path, &"0".repeat(64)).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let valid =
+            r#"window.storyFormat({"version":"1.2.3","source":"{{STORY_NAME}}{{STORY_DATA}}"});"#;
+        assert!(StoryFormat::check_source(valid).is_empty());
+        assert_eq!(
+            StoryFormat::check_source(
+                r#"window.storyFormat({"version":"2.1","source":"","proofing":1});"#
+            ),
+            vec![
+                "Version \"2.1\" is not a semantic version (x.y.z)",
+                "Source is missing the {{STORY_NAME}} placeholder",
+                "Source is missing the {{STORY_DATA}} placeholder",
+                "Field \"proofing\" must be true or false",
+            ]
+        );
+        assert_eq!(
+            StoryFormat::check_source(r#"window.storyFormat({"name":3});"#),
+            vec![
+                "Field \"name\" must be a string",
+                "Missing required field \"version\"",
+                "Missing required field \"source\"",
+            ]
+        );
+        assert!(
+            StoryFormat::check_source("window.storyFormat({version: 1});")[0]
+                .starts_with("Invalid JSON")
+        );
     }
 }
@@ -0,0 +1,212 @@
+//! Serializes a parsed story as the `tw-storydata` element of a compiled
+//! Twine 2 story
+//!
+//! Text is escaped as Twine 2 escapes it when publishing: attribute values
+//! and passage text have `&`, `<`, `>`, `"`, and `'` replaced with character
+//! references. The user script and stylesheet are written as they are, as
+//! browsers don't read character references in `script` and `style`
+//! elements. [`decompile`] reads the output back into the same story
+//!
+//! [`decompile`]: ../decompile/index.html
+
+use crate::minify;
+use crate::partials;
+use crate::utils;
+use crate::OutputStyle;
+use clap::{crate_name, crate_version};
+use color_eyre::Result;
+use eyre::eyre;
+use tweep::{Story, TwinePassage};
+
+/// Replaces the characters that are special in HTML text and attribute
+/// values with character references
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes an element with the given attributes, whose values are escaped,
+/// around contents that are written as given
+///
+/// An attribute with no value is written without one, as in `hidden`
+fn element(name: &str, attributes: &[(&str, Option<&str>)], contents: &str) -> String {
+    let mut element = format!("<{}", name);
+    for (attribute, value) in attributes {
+        match value {
+            Some(value) => element.push_str(&format!(" {}=\"{}\"", attribute, escape(value))),
+            None => element.push_str(&format!(" {}", attribute)),
+        }
+    }
+    element.push_str(&format!(">{}</{}>", contents, name));
+    element
+}
+
+/// Gets a piece of a passage's metadata that is written as an attribute
+fn metadata<'a>(passage: &'a TwinePassage, key: &str) -> &'a str {
+    passage
+        .header
+        .metadata
+        .get(key)
+        .and_then(|value| value.as_str())
+        .unwrap_or_default()
+}
+
+/// Renders the `tw-storydata` element holding the story's stylesheet, script,
+/// and passages, for the story format with the given name and version
+///
+/// It is an error if the story has no `StoryData` or start passage. As in
+/// tweego, passages tagged `script` or `stylesheet` are not passages of their
+/// own, but are joined in source order into the user script and stylesheet.
+/// The colors of tags in `StoryData` are written as `tw-tag` elements, by tag
+/// name, as the Twine editor does, and the other passages are written in
+/// order of pid
+pub fn story_data(
+    story: &Story,
+    format: &str,
+    format_version: &str,
+    style: OutputStyle,
+) -> Result<String> {
+    let contents = partials::expand(story)?;
+    let stylesheet = story.stylesheets.join("\n");
+    let script = story.scripts.join("\n");
+    let (stylesheet, script) = match style {
+        OutputStyle::Minified => (minify::css(&stylesheet), minify::js(&script)),
+        OutputStyle::Normal | OutputStyle::Pretty => (stylesheet, script),
+    };
+
+    let mut children = vec![
+        element(
+            "style",
+            &[
+                ("id", Some("twine-user-stylesheet")),
+                ("type", Some("text/twine-css")),
+                ("role", Some("stylesheet")),
+            ],
+            &stylesheet,
+        ),
+        element(
+            "script",
+            &[
+                ("id", Some("twine-user-script")),
+                ("type", Some("text/twine-javascript")),
+                ("role", Some("script")),
+            ],
+            &script,
+        ),
+    ];
+    let data = story
+        .data
+        .as_ref()
+        .ok_or_else(|| eyre!("The story has no StoryData"))?;
+    if let Some(tag_colors) = &data.tag_colors {
+        let mut tags: Vec<(&String, &String)> = tag_colors.iter().collect();
+        tags.sort();
+        for (tag, color) in tags {
+            children.push(element(
+                "tw-tag",
+                &[("name", Some(tag)), ("color", Some(color))],
+                "",
+            ));
+        }
+    }
+    let mut passages: Vec<(&String, &TwinePassage)> = story.passages.iter().collect();
+    passages.sort_by_key(|(_, passage)| utils::get_pid(passage));
+    for (name, passage) in passages {
+        let content = match contents.get(name) {
+            Some(content) => content,
+            None => continue,
+        };
+        children.push(element(
+            "tw-passagedata",
+            &[
+                ("pid", Some(&utils::get_pid(passage).to_string())),
+                ("name", Some(name)),
+                ("tags", Some(&passage.header.tags.join(" "))),
+                ("position", Some(metadata(passage, "position"))),
+                ("size", Some(metadata(passage, "size"))),
+            ],
+            &escape(content),
+        ));
+    }
+    let inner = match style {
+        OutputStyle::Pretty => format!("\n  {}\n", children.join("\n  ")),
+        OutputStyle::Normal | OutputStyle::Minified => children.concat(),
+    };
+
+    let startnode = story
+        .get_start_passage_name()
+        .and_then(|start| story.passages.get(start))
+        .map(utils::get_pid)
+        .ok_or_else(|| eyre!("The story has no start passage"))?
+        .to_string();
+    let zoom = data.zoom.unwrap_or(1.).to_string();
+    Ok(element(
+        "tw-storydata",
+        &[
+            (
+                "name",
+                Some(story.title.as_deref().unwrap_or("Untitled Story")),
+            ),
+            ("startnode", Some(&startnode)),
+            ("creator", Some(crate_name!())),
+            ("creator-version", Some(crate_version!())),
+            ("ifid", Some(&data.ifid)),
+            ("zoom", Some(&zoom)),
+            ("format", Some(format)),
+            ("format-version", Some(format_version)),
+            ("options", Some("")),
+            ("hidden", None),
+        ],
+        &inner,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompile;
+
+    #[test]
+    fn round_trips_through_decompiler() {
+        let input = ":: StoryTitle\n\"Fish\" & <Chips>\n\n:: StoryData\n{\"ifid\": \
+                     \"D674C58C-DEFA-4F70-B7A2-27742230C0FC\", \"start\": \"Tom's <Start>\", \
+                     \"tag-colors\": {\"q\": \"red\", \"a&b\": \"green\"}}\n\n\
+                     :: Tom's <Start> [a&b \"q\"]\n<<if $x < 1 && $y > 2>>'Hi' & \"bye\"<</if>>\n\
+                     &amp; stays &amp;\n\n\
+                     :: Setup [script]\nif (a < b && c) { s = '</b>'; }\n\n\
+                     :: Theme [stylesheet]\na > b { content: \"&\"; }\n"
+            .to_string();
+        let story = Story::from_string(input).take().0.ok().unwrap();
+        let html = story_data(&story, "Test", "1.0.0", OutputStyle::Normal).unwrap();
+        assert!(html.contains("name=\"&quot;Fish&quot; &amp; &lt;Chips&gt;\""));
+        assert!(html.contains("tags=\"a&amp;b &quot;q&quot;\""));
+        assert!(html.contains(">if (a < b && c) { s = '</b>'; }</script>"));
+
+        let decompiled = decompile::parse(&html).unwrap();
+        assert_eq!(decompiled.title, "\"Fish\" & <Chips>");
+        assert_eq!(decompiled.data["start"], "Tom's <Start>");
+        assert_eq!(
+            decompiled.data["tag-colors"],
+            serde_json::json!({ "q": "red", "a&b": "green" })
+        );
+        assert_eq!(decompiled.script, "if (a < b && c) { s = '</b>'; }");
+        assert_eq!(decompiled.stylesheet, "a > b { content: \"&\"; }");
+        let passage = &decompiled.passages[0];
+        assert_eq!(passage.name, "Tom's <Start>");
+        assert_eq!(passage.tags, vec!["a&b", "\"q\""]);
+        assert_eq!(
+            passage.content,
+            story.passages["Tom's <Start>"].content.content
+        );
+    }
+}
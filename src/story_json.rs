@@ -0,0 +1,316 @@
+//! Serializes a parsed story to JSON, for tools that need its passages without
+//! parsing Twee themselves, and reads such JSON back as a story
+//!
+//! The JSON is an object with these fields:
+//!
+//! * `version`: the version of this schema, currently `1`
+//! * `title`: the story's title, or `null` if it has none
+//! * `data`: the contents of `StoryData`, with the same fields (`ifid`,
+//!   `format`, `format-version`, `start`, `tag-colors`, and `zoom`), or
+//!   `null` if the story has none
+//! * `scripts`: the content of each `script` passage, in the order read
+//! * `stylesheets`: the content of each `stylesheet` passage, in the order
+//!   read
+//! * `passages`: the other passages, ordered by pid, each an object with:
+//!   * `pid`: the passage's number, as written into the compiled story
+//!   * `name`: the passage's name
+//!   * `tags`: an array of its tags
+//!   * `metadata`: an object holding its metadata, such as `position`
+//!   * `content`: its text
+//!
+//! `StoryTitle` and `StoryData` are given by `title` and `data` rather than as
+//! passages
+//!
+//! Inputs ending in `.json` are read as stories in this format, and can be
+//! given alongside Twee files. Only `version` is required; the other fields
+//! may be left out, as may the `pid`, `tags`, `metadata`, and `content` of a
+//! passage. Passages are numbered as if read from Twee, so their `pid`s are
+//! not kept
+
+use crate::twee;
+use crate::StoryResult;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use tweep::{Error, ErrorKind, FullContext, Output, Story, StoryData, StoryPassages, WarningKind};
+
+/// The version of the schema written
+pub const VERSION: u32 = 1;
+
+/// A passage of an exported story
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PassageJson {
+    /// The passage's pid
+    #[serde(default)]
+    pub pid: usize,
+
+    /// The name of the passage
+    pub name: String,
+
+    /// The passage's tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// The passage's metadata
+    #[serde(default)]
+    pub metadata: Map<String, Value>,
+
+    /// The content of the passage
+    #[serde(default)]
+    pub content: String,
+}
+
+/// An exported story
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoryJson {
+    /// The version of the schema
+    pub version: u32,
+
+    /// The story's title
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// The contents of the `StoryData` passage
+    #[serde(default)]
+    pub data: Option<StoryData>,
+
+    /// The content of the story's script passages
+    #[serde(default)]
+    pub scripts: Vec<String>,
+
+    /// The content of the story's stylesheet passages
+    #[serde(default)]
+    pub stylesheets: Vec<String>,
+
+    /// The story's passages, ordered by pid
+    #[serde(default)]
+    pub passages: Vec<PassageJson>,
+}
+
+impl StoryJson {
+    /// Copies the parts of a parsed story into the exported form
+    pub fn new(story: &Story) -> Self {
+        let mut passages: Vec<PassageJson> = story
+            .passages
+            .values()
+            .map(|passage| PassageJson {
+                pid: passage.content.pid,
+                name: passage.header.name.clone(),
+                tags: passage.header.tags.clone(),
+                metadata: passage.header.metadata.clone(),
+                content: passage.content.content.clone(),
+            })
+            .collect();
+        passages.sort_by_key(|passage| passage.pid);
+        let data = story.data.as_ref().map(|data| StoryData {
+            ifid: data.ifid.clone(),
+            format: data.format.clone(),
+            format_version: data.format_version.clone(),
+            start: data.start.clone(),
+            tag_colors: data.tag_colors.clone(),
+            zoom: data.zoom,
+        });
+        StoryJson {
+            version: VERSION,
+            title: story.title.clone(),
+            data,
+            scripts: story.scripts.clone(),
+            stylesheets: story.stylesheets.clone(),
+            passages,
+        }
+    }
+}
+
+/// Writes a parsed story as pretty printed JSON
+pub fn to_json(story: &Story) -> String {
+    let mut json = serde_json::to_string_pretty(&StoryJson::new(story)).unwrap();
+    json.push('\n');
+    json
+}
+
+/// Whether an input is read as a JSON story rather than as Twee
+pub fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// Writes an exported story as Twee source
+///
+/// Script and stylesheet passages are named `Script 1`, `Stylesheet 1`, and
+/// so on, and passages are written in order of their pids
+pub fn to_twee(story: &StoryJson) -> String {
+    let none = Map::new();
+    let mut source = String::new();
+    if let Some(title) = &story.title {
+        source.push_str(&twee::passage("StoryTitle", &[], &none, title));
+    }
+    if let Some(data) = &story.data {
+        let mut data = serde_json::to_value(data).unwrap();
+        if let Value::Object(fields) = &mut data {
+            fields.retain(|_, value| !value.is_null());
+        }
+        let data = serde_json::to_string_pretty(&data).unwrap();
+        source.push_str(&twee::passage("StoryData", &[], &none, &data));
+    }
+    let special = [
+        ("Script", &story.scripts),
+        ("Stylesheet", &story.stylesheets),
+    ];
+    for (kind, contents) in special.iter() {
+        let tags = vec![kind.to_lowercase()];
+        for (i, content) in contents.iter().enumerate() {
+            let name = format!("{} {}", kind, i + 1);
+            let content = twee::escape_content(content);
+            source.push_str(&twee::passage(&name, &tags, &none, &content));
+        }
+    }
+    let mut passages: Vec<&PassageJson> = story.passages.iter().collect();
+    passages.sort_by_key(|passage| passage.pid);
+    for passage in passages {
+        source.push_str(&twee::passage(
+            &passage.name,
+            &passage.tags,
+            &passage.metadata,
+            &twee::escape_content(&passage.content),
+        ));
+    }
+    source
+}
+
+/// Reads a JSON story file and parses it as Twee
+fn read(path: &Path) -> Output<Result<StoryPassages, tweep::ContextErrorList>> {
+    let bad = |message: String| {
+        let kind = ErrorKind::BadInputPath(path.to_string_lossy().into_owned(), message);
+        Output::new(Err(Error::new::<FullContext>(kind, None).into()))
+    };
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return bad(e.to_string()),
+    };
+    let story: StoryJson = match serde_json::from_str(&source) {
+        Ok(story) => story,
+        Err(e) => return bad(format!("Not a JSON story: {}", e)),
+    };
+    if story.version > VERSION {
+        return bad(format!(
+            "JSON story version {} is newer than the supported version {}",
+            story.version, VERSION
+        ));
+    }
+    StoryPassages::from_string(to_twee(&story))
+}
+
+/// Parses a story from Twee files and JSON stories
+///
+/// The Twee files are read first. The warnings about the story as a whole,
+/// such as dead links, are only checked once every input has been read
+pub fn read_story(twee_paths: &[PathBuf], json_paths: &[PathBuf]) -> Output<StoryResult> {
+    let output = if twee_paths.is_empty() {
+        Output::new(Ok(StoryPassages::default()))
+    } else {
+        StoryPassages::from_paths(twee_paths)
+    };
+    let (result, mut warnings) = output.take();
+    let mut passages = match result {
+        Ok(passages) => passages,
+        Err(errors) => return Output::new(Err(errors)).with_warnings(warnings),
+    };
+    warnings.retain(|warning| {
+        !matches!(
+            warning.kind,
+            WarningKind::MissingStoryTitle
+                | WarningKind::MissingStoryData
+                | WarningKind::MissingStartPassage
+                | WarningKind::DeadStartPassage(_)
+                | WarningKind::DeadLink(_)
+        )
+    });
+    for path in json_paths {
+        let (result, mut json_warnings) = read(path).take();
+        warnings.append(&mut json_warnings);
+        match result {
+            Ok(other) => warnings.append(&mut passages.merge_from(other)),
+            Err(errors) => return Output::new(Err(errors)).with_warnings(warnings),
+        }
+    }
+    warnings.append(&mut passages.check());
+    Output::new(Ok(Story::from(passages))).with_warnings(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_story() {
+        let input = ":: StoryTitle\nExported\n\n:: StoryData\n{\"ifid\": \
+                     \"D674C58C-DEFA-4F70-B7A2-27742230C0FC\", \"start\": \"Start\"}\n\n\
+                     :: Start [intro] {\"position\": \"10,20\"}\nHello\n\n\
+                     :: Setup [script]\nvar a = 1;\n"
+            .to_string();
+        let story = Story::from_string(input).take().0.ok().unwrap();
+        let json: Value = serde_json::from_str(&to_json(&story)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "version": 1,
+                "title": "Exported",
+                "data": {
+                    "ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC",
+                    "format": null,
+                    "format-version": null,
+                    "start": "Start",
+                    "tag-colors": null,
+                    "zoom": null,
+                },
+                "scripts": ["var a = 1;"],
+                "stylesheets": [],
+                "passages": [
+                    {
+                        "pid": 1,
+                        "name": "Start",
+                        "tags": ["intro"],
+                        "metadata": { "position": "10,20", "size": "100,100" },
+                        "content": "Hello\n",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn imports_story() {
+        let dir = std::env::temp_dir().join(format!("tweec-json-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("story.json"),
+            r#"{
+                "version": 1,
+                "title": "Imported",
+                "data": { "ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC", "start": "Begin" },
+                "scripts": ["var a = 1;"],
+                "passages": [
+                    { "name": "Begin", "tags": ["intro"], "content": "Go [[More]]\n:: Not a header" },
+                    { "name": "Odd", "metadata": { "position": "5,5" } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("more.twee"), ":: More\nBack to [[Begin]]\n").unwrap();
+
+        let (result, warnings) =
+            read_story(&[dir.join("more.twee")], &[dir.join("story.json")]).take();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let story = result.ok().unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(story.title.as_deref(), Some("Imported"));
+        assert_eq!(story.get_start_passage_name(), Some("Begin"));
+        assert_eq!(story.scripts.len(), 1);
+        let begin = &story.passages["Begin"];
+        assert_eq!(begin.header.tags, vec!["intro"]);
+        // Lines that would start a passage are escaped, as in decompiled Twee
+        assert_eq!(begin.content.content, "Go [[More]]\n\\:: Not a header\n");
+        assert_eq!(story.passages["Odd"].header.metadata["position"], "5,5");
+        assert_eq!(story.passages.len(), 3);
+    }
+}
@@ -0,0 +1,174 @@
+//! Finds comments in Twee source that allow warnings where they occur, such as
+//! `/* tweec-allow: WhitespaceInLink */`
+//!
+//! A comment on a line of its own at the start of a passage, before any other
+//! content, allows the named warnings anywhere in the passage, including its
+//! header. Elsewhere, a comment on a line of its own allows them on the next
+//! line, and a comment after other content allows them on its line. Several
+//! warnings can be named, separated by commas, and HTML comments
+//! (`<!-- ... -->`) work the same for story formats without `/* */` comments.
+//! Forbidden warnings can't be allowed this way
+
+use crate::utils;
+use std::collections::HashMap;
+use tweep::FullContext;
+use tweep::Story;
+
+const DIRECTIVE: &str = "tweec-allow:";
+
+/// Warnings allowed by comments in a story's source
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    /// Warnings allowed on a single line, by file name and line
+    lines: HashMap<(String, usize), Vec<String>>,
+
+    /// Warnings allowed in a passage, by file name and header line
+    passages: HashMap<(String, usize), Vec<String>>,
+
+    /// The header line of every passage, by file name
+    headers: HashMap<String, Vec<usize>>,
+}
+
+impl Suppressions {
+    /// Finds the comments in every source file of a story
+    pub fn new(story: &Story) -> Self {
+        let mut suppressions = Suppressions::default();
+        for context in utils::contexts(&story.code_map) {
+            if let Some(file) = context.get_file_name() {
+                suppressions.scan(file, context.get_contents());
+            }
+        }
+        suppressions
+    }
+
+    fn scan(&mut self, file: &str, source: &str) {
+        let headers = self.headers.entry(file.to_string()).or_default();
+        // Whether the current passage has had any content other than comments
+        let mut seen = true;
+        for (row, line) in source.lines().enumerate() {
+            let line_number = row + 1;
+            if line.starts_with("::") {
+                headers.push(line_number);
+                seen = false;
+                continue;
+            }
+            let own_line = is_only_comments(line);
+            let names = directive_names(line);
+            if !names.is_empty() {
+                let key = match (own_line, seen) {
+                    (true, false) => {
+                        let header = *headers.last().expect("passages start with a header");
+                        self.passages
+                            .entry((file.to_string(), header))
+                            .or_default()
+                            .extend(names);
+                        continue;
+                    }
+                    // A comment on its own line is about the line after it
+                    (true, true) => (file.to_string(), line_number + 1),
+                    (false, _) => (file.to_string(), line_number),
+                };
+                self.lines.entry(key).or_default().extend(names);
+            }
+            seen |= !own_line;
+        }
+    }
+
+    /// Whether the named warning is allowed at the given location
+    pub fn allows(&self, name: &str, context: &Option<FullContext>) -> bool {
+        let (file, line) = match context {
+            Some(context) => match context.get_file_name() {
+                Some(file) => (file, context.get_start_position().line),
+                None => return false,
+            },
+            None => return false,
+        };
+        let listed = |names: &Vec<String>| names.iter().any(|n| n == name || n == "all");
+        let header = self
+            .headers
+            .get(file)
+            .and_then(|headers| headers.iter().rev().find(|header| **header <= line));
+        self.lines.get(&(file.clone(), line)).is_some_and(listed)
+            || header
+                .and_then(|header| self.passages.get(&(file.clone(), *header)))
+                .is_some_and(listed)
+    }
+}
+
+/// Gets the warnings named by the directives in the comments on a line
+fn directive_names(line: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for (open, close) in &[("/*", "*/"), ("<!--", "-->")] {
+        let mut rest = line;
+        while let Some(start) = rest.find(open) {
+            let after = &rest[start + open.len()..];
+            let end = after.find(close).unwrap_or(after.len());
+            if let Some(list) = after[..end].trim().strip_prefix(DIRECTIVE) {
+                names.extend(
+                    list.split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(String::from),
+                );
+            }
+            rest = &after[end..];
+        }
+    }
+    names
+}
+
+/// Whether a line holds nothing but comments
+fn is_only_comments(line: &str) -> bool {
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let (open, close) = if rest.starts_with("/*") {
+            ("/*", "*/")
+        } else if rest.starts_with("<!--") {
+            ("<!--", "-->")
+        } else {
+            return false;
+        };
+        let after = &rest[open.len()..];
+        rest = match after.find(close) {
+            Some(end) => after[end + close.len()..].trim_start(),
+            None => "",
+        };
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_scoped_suppressions() {
+        let source = "\
+:: Start
+/* tweec-allow: DeadEnd, DeadLink */
+
+[[ Next ]] /* tweec-allow: WhitespaceInLink */
+
+:: Next
+Text
+<!-- tweec-allow: DeadLink -->
+[[Missing]]
+";
+        let mut suppressions = Suppressions::default();
+        suppressions.scan("story.twee", source);
+        let allows = |name: &str, line: usize| {
+            let context = FullContext::from(Some("story.twee".to_string()), source.to_string());
+            let context =
+                context.subcontext(tweep::Position::abs(line, 1)..=tweep::Position::abs(line, 1));
+            suppressions.allows(name, &Some(context))
+        };
+
+        assert!(allows("DeadEnd", 1));
+        assert!(allows("DeadLink", 4));
+        assert!(allows("WhitespaceInLink", 4));
+        assert!(!allows("WhitespaceInLink", 5));
+        assert!(!allows("DeadLink", 6));
+        assert!(allows("DeadLink", 9));
+        assert!(!allows("DeadLink", 7));
+    }
+}
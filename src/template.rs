@@ -0,0 +1,96 @@
+//! Wraps the compiled story in a user-supplied HTML shell
+//!
+//! With `--template index.html`, the template takes the place of the story
+//! format's source. Besides `{{STORY_NAME}}`, `{{STORY_DATA}}`, and any
+//! custom placeholders, it may contain:
+//!
+//! * `{{FORMAT_HEAD}}`: the contents of the format's `<head>` element, such as
+//!   its styles and scripts
+//! * `{{FORMAT_BODY}}`: the contents of the format's `<body>` element, which
+//!   usually holds `{{STORY_DATA}}` and the format's engine
+//!
+//! A template for a branded landing page could be:
+//!
+//! ```html
+//! <!DOCTYPE html>
+//! <html>
+//! <head>
+//!   <title>{{STORY_NAME}}</title>
+//!   <link rel="stylesheet" href="brand.css">
+//!   {{FORMAT_HEAD}}
+//! </head>
+//! <body>
+//!   <header class="brand">My Studio</header>
+//!   {{FORMAT_BODY}}
+//! </body>
+//! </html>
+//! ```
+//!
+//! A format with no `<body>` element gives its whole source as
+//! `{{FORMAT_BODY}}`, and one with no `<head>` element gives nothing as
+//! `{{FORMAT_HEAD}}`
+
+use color_eyre::Result;
+use eyre::WrapErr;
+use std::path::Path;
+
+/// Gets the contents of the first element with the given name in `html`
+///
+/// `lower` must be `html` converted to ASCII lowercase, so byte offsets match
+fn element_contents<'a>(html: &'a str, lower: &str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}", name);
+    let start = lower.match_indices(&open).map(|(i, _)| i).find(|&i| {
+        lower[i + open.len()..]
+            .chars()
+            .next()
+            .is_some_and(|c| c == '>' || c == '/' || c.is_ascii_whitespace())
+    })?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = lower[start..]
+        .find(&format!("</{}", name))
+        .map(|end| start + end)
+        .unwrap_or(html.len());
+    Some(&html[start..end])
+}
+
+/// Fills in a template with the parts of a story format's source
+pub fn apply(template: &str, format_source: &str) -> String {
+    let lower = format_source.to_ascii_lowercase();
+    let head = element_contents(format_source, &lower, "head").unwrap_or_default();
+    let body = element_contents(format_source, &lower, "body").unwrap_or(format_source);
+    template
+        .replace("{{FORMAT_HEAD}}", head)
+        .replace("{{FORMAT_BODY}}", body)
+}
+
+/// Reads a template and fills it in with the parts of a story format's source
+pub fn read(path: &Path, format_source: &str) -> Result<String> {
+    let template = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read template {:?}", path))?;
+    Ok(apply(&template, format_source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_format_parts() {
+        let source = "<html><HEAD><script>engine()</script></HEAD>\
+                      <body class=\"x\"><tw-story></tw-story>{{STORY_DATA}}</body></html>";
+        let template = "<head><title>{{STORY_NAME}}</title>{{FORMAT_HEAD}}</head>\
+                        <body><header>Brand</header>{{FORMAT_BODY}}</body>";
+        assert_eq!(
+            apply(template, source),
+            "<head><title>{{STORY_NAME}}</title><script>engine()</script></head>\
+             <body><header>Brand</header><tw-story></tw-story>{{STORY_DATA}}</body>"
+        );
+        assert_eq!(
+            apply(
+                "<main>{{FORMAT_HEAD}}{{FORMAT_BODY}}</main>",
+                "{{STORY_DATA}}"
+            ),
+            "<main>{{STORY_DATA}}</main>"
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! Parses the subset of TOML used by tweec's config files
+//!
+//! Supported are comments, `key = value` pairs (with bare, quoted, or dotted
+//! keys), `[table]` headers, strings on one line, arrays, inline tables,
+//! integers, floats, and booleans. Arrays of tables, multi-line strings, and
+//! dates are not. The result is a JSON value, so config files can be read
+//! with the same `Deserialize` types whichever format they are in
+
+use color_eyre::Result;
+use eyre::eyre;
+use serde_json::{Map, Value};
+
+/// Parses TOML into a table
+pub fn parse(source: &str) -> Result<Map<String, Value>> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Map::new();
+    let mut table: Vec<String> = Vec::new();
+    let mut defined: Vec<Vec<String>> = Vec::new();
+    loop {
+        parser.skip_space(true);
+        match parser.peek() {
+            None => return Ok(root),
+            Some('[') => {
+                parser.next();
+                if parser.peek() == Some('[') {
+                    return Err(parser.error("Arrays of tables are not supported"));
+                }
+                parser.skip_space(false);
+                table = parser.keys()?;
+                parser.skip_space(false);
+                parser.expect(']')?;
+                if defined.contains(&table) {
+                    return Err(parser.error(&format!(
+                        "Table {} is defined more than once",
+                        table.join(".")
+                    )));
+                }
+                defined.push(table.clone());
+                table_at(&mut root, &table).map_err(|e| parser.error(&e))?;
+            }
+            Some(_) => {
+                let keys = parser.keys()?;
+                parser.skip_space(false);
+                parser.expect('=')?;
+                parser.skip_space(false);
+                let value = parser.value()?;
+                let path: Vec<String> = table.iter().chain(&keys).cloned().collect();
+                insert(&mut root, &path, value).map_err(|e| parser.error(&e))?;
+            }
+        }
+        parser.skip_space(false);
+        if !matches!(parser.peek(), None | Some('\n')) {
+            return Err(parser.error("Expected a new line"));
+        }
+    }
+}
+
+/// Gets the table at a path of keys, creating any tables that don't exist
+fn table_at<'a>(
+    root: &'a mut Map<String, Value>,
+    path: &[String],
+) -> std::result::Result<&'a mut Map<String, Value>, String> {
+    let mut table = root;
+    for (i, key) in path.iter().enumerate() {
+        table = match table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            Value::Object(next) => next,
+            _ => return Err(format!("{} is not a table", path[..=i].join("."))),
+        };
+    }
+    Ok(table)
+}
+
+/// Sets the value at a path of keys, which must not already be set
+fn insert(
+    root: &mut Map<String, Value>,
+    path: &[String],
+    value: Value,
+) -> std::result::Result<(), String> {
+    let (key, tables) = path.split_last().expect("keys are never empty");
+    let table = table_at(root, tables)?;
+    if table.contains_key(key) {
+        return Err(format!("Key {} is set more than once", path.join(".")));
+    }
+    table.insert(key.clone(), value);
+    Ok(())
+}
+
+/// Reads TOML one character at a time
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> color_eyre::Report {
+        eyre!("{} on line {}", message, self.line)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("Expected {}", expected))),
+        }
+    }
+
+    /// Skips spaces and comments, and new lines too if `newlines` is true
+    fn skip_space(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => (),
+                '\n' if newlines => (),
+                '#' => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.next();
+                    }
+                    continue;
+                }
+                _ => return,
+            }
+            self.next();
+        }
+    }
+
+    /// Reads a key, which may be dotted, such as `format_configs.default`
+    fn keys(&mut self) -> Result<Vec<String>> {
+        let mut keys = vec![self.key()?];
+        loop {
+            self.skip_space(false);
+            if self.peek() != Some('.') {
+                return Ok(keys);
+            }
+            self.next();
+            self.skip_space(false);
+            keys.push(self.key()?);
+        }
+    }
+
+    fn key(&mut self) -> Result<String> {
+        if matches!(self.peek(), Some('"' | '\'')) {
+            return self.string();
+        }
+        let mut key = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        {
+            key.push(c);
+            self.next();
+        }
+        if key.is_empty() {
+            return Err(self.error("Expected a key"));
+        }
+        Ok(key)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('"' | '\'') => self.string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(c) if c.is_ascii_alphanumeric() || c == '+' || c == '-' => {
+                let mut word = String::new();
+                while let Some(c) = self
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '_' | '.'))
+                {
+                    word.push(c);
+                    self.next();
+                }
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => {
+                        let number = word.replace('_', "");
+                        number
+                            .parse::<i64>()
+                            .map(Value::from)
+                            .ok()
+                            .or_else(|| {
+                                number
+                                    .parse::<f64>()
+                                    .ok()
+                                    .filter(|float| float.is_finite())
+                                    .and_then(serde_json::Number::from_f64)
+                                    .map(Value::Number)
+                            })
+                            .ok_or_else(|| self.error(&format!("Unsupported value {}", word)))
+                    }
+                }
+            }
+            _ => Err(self.error("Expected a value")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_space(true);
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_space(true);
+            match self.next() {
+                Some(',') => (),
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(self.error("Expected , or ] in array")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut table = Map::new();
+        self.skip_space(false);
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::Object(table));
+        }
+        loop {
+            self.skip_space(false);
+            let keys = self.keys()?;
+            self.skip_space(false);
+            self.expect('=')?;
+            self.skip_space(false);
+            let value = self.value()?;
+            insert(&mut table, &keys, value).map_err(|e| self.error(&e))?;
+            self.skip_space(false);
+            match self.next() {
+                Some(',') => (),
+                Some('}') => return Ok(Value::Object(table)),
+                _ => return Err(self.error("Expected , or } in inline table")),
+            }
+        }
+    }
+
+    /// Reads a basic (`"..."`) or literal (`'...'`) string on one line
+    fn string(&mut self) -> Result<String> {
+        let quote = self.next();
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err(self.error("Unterminated string")),
+                Some(c) if Some(c) == quote => return Ok(string),
+                Some('\\') if quote == Some('"') => {
+                    let escaped = match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        _ => return Err(self.error("Unsupported escape in string")),
+                    };
+                    string.push(escaped);
+                }
+                Some(c) => string.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_toml() {
+        let source = r#"
+# Project settings
+format = "sugarcube-2"
+output = 'dist\My Story.html'  # Literal string
+inputs = [
+    "src/**/*.twee",  # Chapters
+    "start.twee",
+]
+"max-links" = 1_000
+zoom = 0.6
+
+[format_configs.default]
+tag_max_links = { hub = 20 }
+build.args = []
+
+[format_configs."sugarcube-2"]
+smoke_test = { command = ["chromium", "--headless"], timeout = 30 }
+"#;
+        let table = parse(source).unwrap();
+        assert_eq!(
+            Value::Object(table),
+            serde_json::json!({
+                "format": "sugarcube-2",
+                "output": "dist\\My Story.html",
+                "inputs": ["src/**/*.twee", "start.twee"],
+                "max-links": 1000,
+                "zoom": 0.6,
+                "format_configs": {
+                    "default": {
+                        "tag_max_links": { "hub": 20 },
+                        "build": { "args": [] },
+                    },
+                    "sugarcube-2": {
+                        "smoke_test": { "command": ["chromium", "--headless"], "timeout": 30 },
+                    },
+                },
+            })
+        );
+
+        assert_eq!(
+            parse("[[formats]]\n").unwrap_err().to_string(),
+            "Arrays of tables are not supported on line 1"
+        );
+        assert_eq!(
+            parse("deny = [\"DeadLink\"\nformat = \"a\"")
+                .unwrap_err()
+                .to_string(),
+            "Expected , or ] in array on line 2"
+        );
+        assert_eq!(
+            parse("a = 1\n[a]\n").unwrap_err().to_string(),
+            "a is not a table on line 2"
+        );
+        assert_eq!(
+            parse("[a]\nb = 1\n[a]\n").unwrap_err().to_string(),
+            "Table a is defined more than once on line 3"
+        );
+    }
+}
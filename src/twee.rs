@@ -0,0 +1,78 @@
+//! Utilities for writing Twee v3 source code
+use serde_json::{Map, Value};
+
+/// Escapes the characters in a passage name or tag that have special meaning
+/// in a Twee v3 passage header
+pub fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if let '\\' | '[' | ']' | '{' | '}' = c {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes any lines of passage content that would otherwise be read as the
+/// start of a new passage
+pub fn escape_content(content: &str) -> String {
+    content
+        .split('\n')
+        .map(|line| {
+            if line.starts_with("::") {
+                format!("\\{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Creates the header line of a passage from its name, tags, and metadata
+///
+/// The tag block is omitted when there are no tags, and the metadata block is
+/// omitted when there is no metadata
+pub fn header(name: &str, tags: &[String], metadata: &Map<String, Value>) -> String {
+    let mut header = format!(":: {}", escape(name));
+    if !tags.is_empty() {
+        let tags: Vec<String> = tags.iter().map(|t| escape(t)).collect();
+        header.push_str(&format!(" [{}]", tags.join(" ")));
+    }
+    if !metadata.is_empty() {
+        header.push_str(&format!(" {}", Value::Object(metadata.clone())));
+    }
+    header
+}
+
+/// Creates the full source of a passage, followed by a blank line
+pub fn passage(
+    name: &str,
+    tags: &[String],
+    metadata: &Map<String, Value>,
+    content: &str,
+) -> String {
+    format!(
+        "{}\n{}\n\n",
+        header(name, tags, metadata),
+        content.trim_end_matches('\n')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers() {
+        let tags = vec!["foo".to_string(), "bar".to_string()];
+        let mut metadata = Map::new();
+        metadata.insert("position".to_string(), "10,10".into());
+        assert_eq!(header("Start", &[], &Map::new()), ":: Start");
+        assert_eq!(
+            header("A [weird] name", &tags, &metadata),
+            r#":: A \[weird\] name [foo bar] {"position":"10,10"}"#
+        );
+    }
+}
@@ -1,105 +1,659 @@
 //! Handles the actual running of the compiler
 
+use crate::assets;
+use crate::assets::EmbedCache;
+use crate::compile::{self, OutputOptions};
+use crate::daemon;
+use crate::decompile;
+use crate::exit::{self, StoryError};
+use crate::export;
+use crate::extract;
+use crate::fix;
+use crate::formats;
+use crate::i18n;
+use crate::ifid;
+use crate::inputs;
+use crate::issue::Issue;
 use crate::linter;
+use crate::linter::graph::LinkGraph;
+use crate::logging;
+use crate::lsp;
+use crate::open;
+use crate::package;
+use crate::playtest;
+use crate::pwa;
+use crate::report;
+use crate::reporter::{CompactReporter, Reporter, TerminalReporter};
+use crate::rules;
+use crate::smoke;
+use crate::source_map;
+use crate::stats;
+use crate::stdin;
+use crate::story_format;
+use crate::story_html;
+use crate::story_json;
+use crate::template;
 use crate::utils;
+use crate::validate;
+use crate::watch;
+use crate::ArchiveConfig;
+use crate::Command;
 use crate::Config;
+use crate::ConfigFile;
+use crate::ExportConfig;
+use crate::ExportFormat;
+use crate::FormatCommand;
+use crate::GraphConfig;
+use crate::GraphFormat;
+use crate::MessageFormat;
+use crate::ReportConfig;
+use crate::StatsConfig;
+use crate::StoryFiles;
 use crate::StoryFormat;
+use crate::TestConfig;
 
-use tweep::Story;
-
-use clap::{crate_name, crate_version};
+use clap::crate_name;
 
 use color_eyre::Result;
-use eyre::WrapErr;
+use eyre::{eyre, WrapErr};
+
+use log::info;
 
-use horrorshow::html;
+use serde_json::{Map, Value};
 
-use std::fs::File;
 use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
 
 use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
 
 /// Runs the compiler
-pub fn run() -> Result<()> {
-    let config = Config::build()?;
+pub fn run() -> ExitCode {
+    logging::init();
+    let result = Config::build()
+        .map_err(|e| (exit::USAGE, e))
+        .and_then(|config| {
+            logging::set_level(config.log_level);
+            run_command(&config).map_err(|e| (exit::FAILURE, e))
+        });
+    // Write anything logged before the config failed to build
+    logging::set_level(logging::DEFAULT_LEVEL);
+    stdin::cleanup();
+    match result {
+        Ok(()) => ExitCode::from(exit::SUCCESS),
+        Err((_, e)) if e.downcast_ref::<clap::Error>().is_some() => {
+            let e = e.downcast_ref::<clap::Error>().unwrap();
+            if e.use_stderr() {
+                eprintln!("{}", e.message);
+                return ExitCode::from(exit::USAGE);
+            }
+            // Help and version information, which may be piped into a pager
+            // that closes early
+            let _ = writeln!(std::io::stdout().lock(), "{}", e.message);
+            ExitCode::from(exit::SUCCESS)
+        }
+        Err((default, e)) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit::code(&e, default))
+        }
+    }
+}
 
-    let mut stdout = StandardStream::stdout(config.use_color);
+/// Runs the command given by the config
+fn run_command(config: &Config) -> Result<()> {
+    if !config.warnings.is_empty() && config.log_level >= log::LevelFilter::Warn {
+        let mut stderr = StandardStream::stderr(config.use_color);
+        for message in &config.warnings {
+            warn(&mut stderr, message)?;
+        }
+    }
+
+    match &config.command {
+        Command::Lsp => return lsp::run(config),
+        Command::Decompile(decompile) => return decompile::decompile_file(decompile),
+        Command::Format(FormatCommand::List) => return formats::list(&ConfigFile::load()?),
+        Command::Format(FormatCommand::Install { source, dir }) => {
+            return formats::install(&ConfigFile::load()?, source, dir.as_deref()).map(|_| ())
+        }
+        Command::Format(FormatCommand::Remove(format)) => {
+            return formats::remove(&ConfigFile::load()?, format)
+        }
+        Command::Format(FormatCommand::Check(path)) => return formats::check(path),
+        Command::Daemon(port) => return daemon::run(config, *port),
+        Command::Report(report) => return write_report(config, report),
+        Command::Archive(archive) => return write_archive(config, archive),
+        Command::Stats(stats) => return print_stats(config, stats),
+        Command::Graph(graph) => return write_graph(config, graph),
+        Command::Export(export) => return write_export(config, export),
+        Command::ShowConfig { json } => return show_config(config, *json),
+        Command::Fix(fix) => {
+            return fix::fix(config, fix, &mut StandardStream::stdout(config.use_color))
+        }
+        _ => (),
+    }
+
+    let mut stdout = if config.writes_to_stdout() {
+        StandardStream::stderr(config.use_color)
+    } else {
+        StandardStream::stdout(config.use_color)
+    };
     stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
 
-    let story = linter::lint(Story::from_paths(&config.inputs), &config, &mut stdout)?;
+    if config.command == Command::Rules {
+        return linter::print_rules(config, &mut stdout);
+    }
+
+    if config.command == Command::ListRules {
+        return linter::list_rules(&mut stdout);
+    }
+
+    if let Command::Explain(name) = &config.command {
+        return rules::explain(name, &mut stdout);
+    }
+
+    if let Command::Test(test) = &config.command {
+        return run_tests(config, test, &mut stdout);
+    }
+
+    if config.watch {
+        return watch::watch(config, &mut stdout);
+    }
+
+    if config.command == Command::Lint && config.fix {
+        fix_headers(config, &mut stdout)?;
+    }
+
+    build(config, &mut EmbedCache::default(), &mut stdout)
+}
+
+/// Removes repeated tags and metadata keys from the passage headers of the
+/// inputs, for `tweec lint --fix`
+fn fix_headers(config: &Config, stdout: &mut StandardStream) -> Result<()> {
+    for (path, count) in linter::headers::fix_files(&config.inputs, &config.extensions)? {
+        writeln!(
+            stdout,
+            "Fixed {} passage header{} in {:?}",
+            count,
+            if count == 1 { "" } else { "s" },
+            path
+        )?;
+    }
+    Ok(())
+}
+
+/// Performs a single lint or build of the story as given by the config
+///
+/// Warnings and errors are written to `stdout`, which is standard error when
+/// the compiled story is written to standard output. Images embedded by
+/// `--embed-images` are kept in `cache` for later builds
+pub fn build(config: &Config, cache: &mut EmbedCache, stdout: &mut StandardStream) -> Result<()> {
+    let mut story = linter::lint(
+        inputs::read_story(&config.inputs, &config.extensions),
+        config,
+        stdout,
+    )?;
+
+    if config.command == Command::Lint {
+        return Ok(());
+    }
+
+    if let Some(ifid) = ifid::ensure(&mut story) {
+        let message = if config.write_ifid {
+            let path = ifid::write_back(&config.inputs, &config.extensions, &ifid)?;
+            format!(
+                "No IFID found; generated {} and wrote it to {:?}",
+                ifid, path
+            )
+        } else {
+            format!(
+                "No IFID found; generated {} for this build (use --write-ifid to keep it)",
+                ifid
+            )
+        };
+        warn(stdout, &message)?;
+    }
+
+    let options = OutputOptions::from(config);
+    compile::prepare(&mut story, &options)?;
+
+    if let Command::Extract(extract) = &config.command {
+        let source = extract::extract(&story, &extract.passage, extract.depth)?;
+        std::fs::create_dir_all(&extract.output_dir).wrap_err_with(|| {
+            format!(
+                "Failed to create output directory {:?}",
+                &extract.output_dir
+            )
+        })?;
+        let file_name = extract.output_dir.join("story.twee");
+        utils::write_atomic(&file_name, source.as_bytes())
+            .wrap_err_with(|| format!("Failed to write output file {:?}", &file_name))?;
+        return Ok(());
+    }
+
+    if let Command::Translate(translate) = &config.command {
+        let catalog = i18n::Catalog::read(&translate.catalog)?;
+        i18n::translate(&mut story, &catalog);
+    }
 
-    if config.linting {
-        std::process::exit(0);
+    if config.embed_images || config.assets_dir.is_some() {
+        let dirs = assets::search_dirs(config);
+        if config.embed_images {
+            assets::embed_images(&mut story, &dirs, cache)?;
+        }
+        if let Some(assets_dir) = &config.assets_dir {
+            let output_dir = assets::output_dir(config);
+            let count = assets::copy_assets(&mut story, &dirs, &output_dir, assets_dir)?;
+            info!(
+                "Copied {} media files to {:?}",
+                count,
+                output_dir.join(assets_dir)
+            );
+        }
+    }
+
+    let (story_format, format_warnings) = load_format(config)?;
+    for message in &format_warnings {
+        warn(stdout, message)?;
+    }
+
+    let output = compile::render(&story, &story_format, options.style)?;
+    if config.validate_html {
+        for message in validate::validate(&story, &output)? {
+            warn(stdout, &message)?;
+        }
+    }
+    if let Command::Package(package) = &config.command {
+        let path = package::package(&story, &story_format, &output, package)?;
+        writeln!(stdout, "Packaged {:?}", path)?;
+        return Ok(());
+    }
+    if let Some(package) = &config.package {
+        let mut package = package.clone();
+        package.output = Some(config.output_path(&story).with_extension("zip"));
+        package.copied_assets = config.assets_dir.as_ref().map(|assets_dir| {
+            let name = assets_dir
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            (assets::output_dir(config).join(assets_dir), name)
+        });
+        if config.check {
+            writeln!(
+                stdout,
+                "Check passed; {:?} was not written",
+                package.output.unwrap()
+            )?;
+            return Ok(());
+        }
+        let path = package::package(&story, &story_format, &output, &package)?;
+        writeln!(stdout, "Packaged {:?}", path)?;
+        return Ok(());
+    }
+
+    if config.check {
+        // Nothing is written, but the output must be writable for the
+        // check to pass
+        if config.writes_to_stdout() {
+            writeln!(stdout, "Check passed; nothing was written")?;
+        } else {
+            let file_name = config.output_path(&story);
+            // Directories missing from the output path would be created, so
+            // the nearest one that exists must be writable instead
+            let existing = file_name
+                .ancestors()
+                .skip(1)
+                .find(|dir| dir.as_os_str().is_empty() || dir.exists());
+            let checked = match (existing, file_name.file_name()) {
+                (Some(dir), Some(name)) if Some(dir) != file_name.parent() => dir.join(name),
+                _ => file_name.clone(),
+            };
+            utils::check_writable(&checked)
+                .wrap_err_with(|| format!("Can't write output file {}", file_name.display()))?;
+            check_overwrite(&file_name, config.force)?;
+            writeln!(
+                stdout,
+                "Check passed; {} was not written",
+                file_name.display()
+            )?;
+        }
+        return Ok(());
     }
 
-    let story_format = StoryFormat::parse(&config.format_file).wrap_err_with(|| {
+    if config.writes_to_stdout() {
+        std::io::stdout()
+            .lock()
+            .write_all(output.as_bytes())
+            .wrap_err_with(|| "Failed to write output to standard output")?;
+        if let Some(smoke_test) = &config.smoke_test {
+            // The smoke test needs a file to run on
+            let file_name =
+                std::env::temp_dir().join(format!("tweec-smoke-{}.html", std::process::id()));
+            utils::write_atomic(&file_name, output.as_bytes())
+                .wrap_err_with(|| format!("Failed to write smoke test file {:?}", &file_name))?;
+            let result = smoke::run(smoke_test, &story, &file_name)
+                .wrap_err_with(|| "Smoke test failed for the compiled story");
+            let _ = std::fs::remove_file(&file_name);
+            result?;
+        }
+        return Ok(());
+    }
+
+    let file_name = config.output_path(&story);
+    let output = match &config.pwa {
+        Some(pwa_config) => pwa::link(&output, pwa_config),
+        None => output,
+    };
+    write_output(&file_name, &output, config.force)?;
+    if let Some(pwa_config) = &config.pwa {
+        pwa::write(&story, &output, &file_name, pwa_config)?;
+    }
+    if config.source_map {
+        source_map::write(
+            &story,
+            &config.inputs,
+            &config.extensions,
+            &file_name.to_string_lossy(),
+        )?;
+    }
+
+    if let Some(smoke_test) = &config.smoke_test {
+        smoke::run(smoke_test, &story, &file_name)
+            .wrap_err_with(|| format!("Smoke test failed for {}", file_name.display()))?;
+    }
+
+    if config.should_open {
+        open::open(config.opener.as_deref(), &file_name)?;
+    }
+
+    Ok(())
+}
+
+/// Parses the story format given by the config, wraps it in the template if
+/// one is given, and fills in its custom placeholders, returning it along with
+/// warnings about any placeholders missing from its source. A format given by
+/// URL is downloaded first, unless it is cached
+///
+/// It is an error if the format has no `{{STORY_DATA}}` placeholder, since the
+/// output would not contain the story, or if the project pins a SHA-256 hash
+/// that the format file doesn't have
+pub fn load_format(config: &Config) -> Result<(StoryFormat, Vec<String>)> {
+    if let Some(url) = &config.format_url {
+        formats::fetch_cached(url, config.refresh_formats, config.format_sha256.as_deref())?;
+    }
+    if let Some(sha256) = &config.format_sha256 {
+        story_format::check_sha256(&config.format_file, sha256)?;
+    }
+    let mut story_format = StoryFormat::parse(&config.format_file).wrap_err_with(|| {
         format!(
             "Failed to parse story format file: {:?}",
             &config.format_file
         )
     })?;
-    let story_title = story.title.as_deref().unwrap_or("Untitled Story");
-    let story_data = format!(
-        "{}",
-        html! {
-            tw-storydata(name = story_title,
-                         startnode = utils::get_start_passage_pid(&story).unwrap(),
-                         creator = crate_name!(),
-                         creator-version = crate_version!(),
-                         ifid = story.data.as_ref().unwrap().ifid.as_str(),
-                         zoom = story.data.as_ref().unwrap().zoom.unwrap_or(1.),
-                         format = story_format.name.as_str(),
-                         format-version = story_format.version.as_str(),
-                         options = "",
-                         hidden = "") {
-                style(id = "twine-user-stylesheet",
-                      type = "text_twine-css",
-                      role = "stylesheet") {
-                    : story.stylesheets.join("\n")
-                }
-
-                script(id = "twine-user-script",
-                       type = "text/twine-javascript",
-                       role = "script") {
-                    : story.scripts.join("\n")
-                }
-
-                @ for (name,passage) in story.passages.iter() {
-                    tw-passagedata(name = name,
-                                   pid = utils::get_pid(passage),
-                                   tags = passage.header.tags.join(" "),
-                                   position = passage
-                                     .header
-                                     .metadata["position"]
-                                     .as_str()
-                                     .unwrap(),
-                                   size = passage
-                                     .header
-                                     .metadata["size"]
-                                     .as_str()
-                                     .unwrap()) {
-                        : utils::get_content(passage)
-                    }
-                }
-            }
+    if let Some(template) = &config.template {
+        story_format.source = template::read(template, &story_format.source)?;
+    }
+    let missing = story_format.missing_placeholders();
+    if let (Some(template), true) = (&config.template, missing.contains(&"{{STORY_DATA}}")) {
+        return Err(eyre!(
+            "Template {:?} has no {{{{STORY_DATA}}}} or {{{{FORMAT_BODY}}}} placeholder, so the \
+             output would not contain the story",
+            template
+        ));
+    }
+    if missing.contains(&"{{STORY_DATA}}") {
+        return Err(eyre!(
+            "Story format {} has no {{{{STORY_DATA}}}} placeholder, so the output would not \
+             contain the story",
+            &story_format.name
+        ));
+    }
+    let mut warnings: Vec<String> = missing
+        .iter()
+        .map(|placeholder| {
+            format!(
+                "Story format {} has no {} placeholder",
+                &story_format.name, placeholder
+            )
+        })
+        .collect();
+    for name in story_format.fill_placeholders(&config.placeholders) {
+        warnings.push(format!(
+            "Story format {} has no {{{{{}}}}} placeholder to fill in",
+            &story_format.name, name
+        ));
+    }
+    if config.proof && !story_format.proofing {
+        warnings.push(format!(
+            "Story format {} is not a proofing format",
+            &story_format.name
+        ));
+    }
+    Ok((story_format, warnings))
+}
+
+/// Checks that the compiled story may be written to `path`: that nothing is
+/// there, that tweec wrote what is there, or that `force` is set
+///
+/// Compiled stories are recognized by the `creator` attribute of their story
+/// data
+pub fn check_overwrite(path: &Path, force: bool) -> Result<()> {
+    if force || !path.is_file() {
+        return Ok(());
+    }
+    let contents = std::fs::read(path)
+        .wrap_err_with(|| format!("Failed to read existing output file {}", path.display()))?;
+    let creator = format!("creator=\"{}\"", crate_name!());
+    if String::from_utf8_lossy(&contents).contains(&creator) {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "{} already exists and wasn't written by tweec; use --force to overwrite it",
+            path.display()
+        ))
+    }
+}
+
+/// Writes the compiled story to `path`, from [`Config::output_path`],
+/// creating the output directory if it doesn't exist
+///
+/// Fails without writing if [`check_overwrite`] does
+///
+/// [`Config::output_path`]: ../config/struct.Config.html#method.output_path
+/// [`check_overwrite`]: fn.check_overwrite.html
+pub fn write_output(path: &Path, output: &str, force: bool) -> Result<()> {
+    check_overwrite(path, force)?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("Failed to create output directory {:?}", dir))?;
+    }
+    utils::write_atomic(path, output.as_bytes())
+        .wrap_err_with(|| format!("Failed to write output file {}", path.display()))
+}
+
+/// Lints and compiles each input as a separate story, writing their story
+/// data together as a Twine 2 archive
+///
+/// An archive holds only `tw-storydata` elements, so no story format is
+/// needed; each story names the format in its `StoryData`
+fn write_archive(config: &Config, archive: &ArchiveConfig) -> Result<()> {
+    let mut stdout = match archive.output {
+        Some(_) => StandardStream::stdout(config.use_color),
+        None => StandardStream::stderr(config.use_color),
+    };
+    let mut stories = Vec::new();
+    for input in &config.inputs {
+        let mut story = linter::lint(
+            inputs::read_story(&[input], &config.extensions),
+            config,
+            &mut stdout,
+        )?;
+        if let Some(ifid) = ifid::ensure(&mut story) {
+            let message = format!("No IFID found in {}; generated {}", input, ifid);
+            warn(&mut stdout, &message)?;
         }
+        let data = story.data.as_ref().unwrap();
+        stories.push(story_html::story_data(
+            &story,
+            data.format.as_deref().unwrap_or_default(),
+            data.format_version.as_deref().unwrap_or_default(),
+            config.output_style,
+        )?);
+    }
+    let output = format!("{}\n", stories.join("\n"));
+    match &archive.output {
+        Some(path) => utils::write_atomic(path, output.as_bytes())
+            .wrap_err_with(|| format!("Failed to write archive {:?}", path))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+/// Lints the story and writes a report of its issues
+fn write_report(config: &Config, report: &ReportConfig) -> Result<()> {
+    let (story_result, issues, _) = linter::collect(
+        inputs::read_story(&config.inputs, &config.extensions),
+        config,
     );
+    let story_files = StoryFiles::new(&story_result);
+    let output = report::report(
+        &issues,
+        &story_files,
+        &config.inputs,
+        &config.extensions,
+        report,
+    );
+    match &report.output {
+        Some(path) => utils::write_atomic(path, output.as_bytes())
+            .wrap_err_with(|| format!("Failed to write report {:?}", path))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
 
-    let output = story_format
-        .source
-        .replace("{{STORY_NAME}}", story_title)
-        .replace("{{STORY_DATA}}", &story_data);
-    let file_name = config
-        .output_file
-        .unwrap_or(format!("{}.html", story_title));
-    let mut file = File::create(&file_name).ok().unwrap();
-    writeln!(file, "{}", output)
-        .wrap_err_with(|| format!("Failed to write output file {}", &file_name))?;
+/// Prints the resolved configuration, as a table or as JSON
+fn show_config(config: &Config, json: bool) -> Result<()> {
+    let settings = config.settings();
+    if json {
+        let settings: Map<String, Value> = settings
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&settings)?);
+        return Ok(());
+    }
 
-    if config.should_open {
-        opener::open(&file_name)
-            .wrap_err_with(|| format!("Failed to open output file {}", &file_name))?;
+    fn display(value: &Value) -> String {
+        match value {
+            Value::Null => "(not set)".to_string(),
+            Value::String(s) => s.clone(),
+            Value::Array(values) if values.is_empty() => "(none)".to_string(),
+            Value::Array(values) => values.iter().map(display).collect::<Vec<_>>().join(", "),
+            Value::Object(map) if map.is_empty() => "(none)".to_string(),
+            Value::Object(map) => map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key, display(value)))
+                .collect::<Vec<_>>()
+                .join(", "),
+            value => value.to_string(),
+        }
     }
+    let rows: Vec<Vec<String>> = settings
+        .iter()
+        .map(|(name, value)| vec![name.to_string(), display(value)])
+        .collect();
+    print!("{}", utils::format_table(&["SETTING", "VALUE"], &rows));
+    Ok(())
+}
+
+fn print_stats(config: &Config, stats: &StatsConfig) -> Result<()> {
+    let (story_result, _) = inputs::read_story(&config.inputs, &config.extensions).take();
+    let story = story_result
+        .map_err(|_| StoryError("Failed to parse the story; run tweec lint for details"))?;
+    // Output piped into e.g. `head` may be cut off, which isn't an error
+    utils::ignore_broken_pipe(stats::stats(
+        &story,
+        config,
+        stats,
+        &mut std::io::stdout().lock(),
+    ))
+}
+
+fn write_graph(config: &Config, graph_config: &GraphConfig) -> Result<()> {
+    let (story_result, _) = inputs::read_story(&config.inputs, &config.extensions).take();
+    let story = story_result
+        .map_err(|_| StoryError("Failed to parse the story; run tweec lint for details"))?;
+    let graph = LinkGraph::new(&story, &config.links);
+    let output = match graph_config.format {
+        GraphFormat::Dot => export::to_dot(&graph),
+        GraphFormat::Mermaid => export::to_mermaid(&graph),
+        GraphFormat::Json => export::to_json(&graph),
+    };
+    match &graph_config.output {
+        Some(path) => utils::write_atomic(path, output.as_bytes())
+            .wrap_err_with(|| format!("Failed to write graph {:?}", path))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+fn write_export(config: &Config, export_config: &ExportConfig) -> Result<()> {
+    let (story_result, _) = inputs::read_story(&config.inputs, &config.extensions).take();
+    let story = story_result
+        .map_err(|_| StoryError("Failed to parse the story; run tweec lint for details"))?;
+    let output = match export_config.format {
+        ExportFormat::Json => story_json::to_json(&story),
+    };
+    match &export_config.output {
+        Some(path) => utils::write_atomic(path, output.as_bytes())
+            .wrap_err_with(|| format!("Failed to write story {:?}", path))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+/// Runs the playthrough tests, reporting each failure like a denied lint
+fn run_tests(config: &Config, test_config: &TestConfig, stdout: &mut StandardStream) -> Result<()> {
+    let tests = playtest::read_scripts(&test_config.scripts)?;
+    let (story_result, _) = inputs::read_story(&config.inputs, &config.extensions).take();
+    let story = story_result
+        .as_ref()
+        .map_err(|_| StoryError("Failed to parse the story; run tweec lint for details"))?;
+    let graph = LinkGraph::new(story, &config.links);
+    let headers = utils::passage_headers(story);
+    let failures: Vec<Issue> = tests
+        .iter()
+        .filter_map(|test| playtest::run(test, &graph, &headers))
+        .map(|lint| Issue::Lint { lint, denied: true })
+        .collect();
+
+    let story_files = StoryFiles::new(&story_result);
+    let mut reporter: Box<dyn Reporter> = match config.message_format {
+        MessageFormat::Compact => Box::new(CompactReporter::new(stdout)),
+        _ => Box::new(TerminalReporter::new(stdout)),
+    };
+    linter::report(&mut *reporter, &failures, &story_files, false)?;
+    drop(reporter);
+    stdout.reset()?;
+    writeln!(
+        stdout,
+        "{} passed, {} failed",
+        tests.len() - failures.len(),
+        failures.len()
+    )?;
+    stdout.flush()?;
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(StoryError("Playthrough tests failed").into())
+    }
+}
 
-    std::process::exit(0);
+/// Writes a warning that is not associated with any source location
+fn warn(stdout: &mut StandardStream, message: &str) -> Result<()> {
+    stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+    write!(stdout, "Warning: ")?;
+    stdout.reset()?;
+    writeln!(stdout, "{}", message)?;
+    Ok(())
 }
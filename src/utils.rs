@@ -1,14 +1,16 @@
 //! Utility functions for dealing with tweep types
+use crate::inputs;
+use crate::story_json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tweep::CodeMap;
+use tweep::FullContext;
+use tweep::PassageHeader;
+use tweep::Position;
 use tweep::Story;
 use tweep::TwinePassage;
 
-/// Gets the pid of the start passage of a story, if possible
-pub fn get_start_passage_pid(story: &Story) -> Option<usize> {
-    let start_name = story.get_start_passage_name().expect("No start passage");
-    let passage = &story.passages.get(start_name);
-    passage.and_then(|twine| Some(twine.content.pid))
-}
-
 /// Gets the pid of a `TwinePassage`
 pub fn get_pid(twine: &TwinePassage) -> usize {
     twine.content.pid
@@ -18,3 +20,358 @@ pub fn get_pid(twine: &TwinePassage) -> usize {
 pub fn get_content(twine: &TwinePassage) -> &str {
     twine.content.content.as_str()
 }
+
+/// Iterates over the contexts of every file in a `CodeMap`
+pub fn contexts(code_map: &CodeMap) -> impl Iterator<Item = &FullContext> {
+    (0..).map_while(move |id| code_map.get_context(id))
+}
+
+/// Finds the header line of every passage in a story's source files, in the
+/// order they appear, files in the order they were read
+///
+/// A passage name defined more than once appears once for each definition
+pub fn passage_header_list(story: &Story) -> Vec<(String, FullContext)> {
+    let mut headers = Vec::new();
+    for context in contexts(&story.code_map) {
+        for (row, line) in context.get_contents().split('\n').enumerate() {
+            if !line.starts_with("::") {
+                continue;
+            }
+            let header_context =
+                context.subcontext(Position::abs(row + 1, 1)..=Position::abs(row + 1, line.len()));
+            let (res, _) = PassageHeader::parse(header_context.clone()).take();
+            if let Ok(header) = res {
+                headers.push((header.name, header_context));
+            }
+        }
+    }
+    headers
+}
+
+/// Finds the header line of every passage in a story's source files, mapped
+/// by passage name
+///
+/// `Story` does not retain the location of each passage, so this rescans the
+/// source for passage headers. If a passage name is defined more than once,
+/// the first definition is used, matching the passage `tweep` keeps
+pub fn passage_headers(story: &Story) -> HashMap<String, FullContext> {
+    let mut headers = HashMap::new();
+    for (name, context) in passage_header_list(story) {
+        headers.entry(name).or_insert(context);
+    }
+    headers
+}
+
+/// Gets the contents of the `StoryData` passage in the given Twee source, if
+/// there is one
+pub fn story_data_block(source: &str) -> Option<&str> {
+    let start = source.match_indices("::").map(|(i, _)| i).find(|&i| {
+        (i == 0 || source[..i].ends_with('\n'))
+            && source[i + 2..].lines().next().unwrap_or_default().trim() == "StoryData"
+    })?;
+    let start = start + source[start..].find('\n')? + 1;
+    let end = source[start..]
+        .find("\n::")
+        .map(|i| start + i)
+        .unwrap_or_else(|| source.len());
+    Some(&source[start..end])
+}
+
+/// Reads the JSON blob of the first `StoryData` passage found in the given
+/// inputs without parsing the rest of the story, or the `data` of a JSON
+/// story
+pub fn read_story_data<P: AsRef<Path>>(
+    inputs: &[P],
+    extensions: &[String],
+) -> Option<serde_json::Value> {
+    inputs::twee_files(inputs, extensions)
+        .iter()
+        .find_map(|path| {
+            let source = std::fs::read_to_string(path).ok()?;
+            if story_json::is_json(path) {
+                let story: serde_json::Value = serde_json::from_str(&source).ok()?;
+                return story.get("data").filter(|data| data.is_object()).cloned();
+            }
+            story_data_block(&source).and_then(|block| serde_json::from_str(block).ok())
+        })
+}
+
+/// Maps the file names `tweep` uses for the Twee files given by a list of
+/// inputs back to their paths
+pub fn file_paths<P: AsRef<Path>>(inputs: &[P], extensions: &[String]) -> HashMap<String, PathBuf> {
+    inputs::twee_files(inputs, extensions)
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            Some((name, path))
+        })
+        .collect()
+}
+
+/// Lays out rows of text as a table with aligned columns, below a header
+pub fn format_table<S: AsRef<str>>(header: &[&str], rows: &[Vec<S>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.as_ref().chars().count());
+        }
+    }
+    let header: Vec<&str> = header.to_vec();
+    let rows = rows
+        .iter()
+        .map(|row| row.iter().map(AsRef::as_ref).collect::<Vec<&str>>());
+    let mut table = String::new();
+    for row in std::iter::once(header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Writes `contents` to `path` atomically
+///
+/// The contents are written to a temporary file in the same directory, which
+/// is then renamed over the destination. A failed or interrupted write leaves
+/// any existing file untouched rather than truncated
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_path = dir.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = std::fs::File::create(&temp_path).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    let result = result.and_then(|_| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Makes a name safe to use as a file name on any platform, replacing the
+/// characters that aren't allowed and renaming reserved device names
+///
+/// Leading and trailing whitespace and dots are removed, and a name left
+/// empty becomes `Untitled Story`
+pub fn sanitize_file_name(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim_matches(|c: char| c.is_whitespace() || c == '.');
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    if name.is_empty() {
+        "Untitled Story".to_string()
+    } else if RESERVED
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        format!("_{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Checks that [`write_atomic`] could write to `path`, without writing
+/// anything
+///
+/// The directory it would be written in must exist and be writable, and the
+/// path must not be a directory or a read-only file
+///
+/// [`write_atomic`]: fn.write_atomic.html
+pub fn check_writable<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind};
+
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let read_only = |what: &str, path: &Path| {
+        Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} {:?} is read-only", what, path),
+        )
+    };
+    let metadata = std::fs::metadata(dir)?;
+    if !metadata.is_dir() {
+        return Err(Error::other(format!("{:?} is not a directory", dir)));
+    }
+    if metadata.permissions().readonly() {
+        return Err(read_only("Directory", dir));
+    }
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            Err(Error::other(format!("{:?} is a directory", path)))
+        }
+        Ok(metadata) if metadata.permissions().readonly() => Err(read_only("File", path)),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// How long a lock file may exist before it is assumed to have been left
+/// behind by a process that died while holding it
+const STALE_LOCK: Duration = Duration::from_secs(30);
+
+/// An exclusive lock on a path, held for as long as this value lives
+///
+/// The lock is a separate `.lock` file next to the locked path, created
+/// exclusively so that only one process can hold it at a time. It is removed
+/// when the lock is dropped
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Acquires the lock for `path`, waiting for any other process holding it
+    /// to release it
+    ///
+    /// Lock files older than 30 seconds are assumed to be stale and are
+    /// broken rather than waited on
+    pub fn acquire<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+            {
+                Ok(_) => return Ok(FileLock { path: lock_path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let stale = std::fs::metadata(&lock_path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .map(|age| age > STALE_LOCK)
+                        .unwrap_or(false);
+                    if stale {
+                        let _ = std::fs::remove_file(&lock_path);
+                    } else {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Treats a write that failed because its reader went away, such as output
+/// piped into `head`, as having succeeded
+pub fn ignore_broken_pipe(result: color_eyre::Result<()>) -> color_eyre::Result<()> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = std::env::temp_dir().join(format!("tweec-lock-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("locked");
+        let holders = Arc::new(AtomicUsize::new(0));
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let (path, holders) = (path.clone(), holders.clone());
+                std::thread::spawn(move || {
+                    let _lock = FileLock::acquire(&path).unwrap();
+                    assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0);
+                    std::thread::sleep(Duration::from_millis(20));
+                    holders.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(!dir.join("locked.lock").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ignores_broken_pipes() {
+        use std::io::{Error, ErrorKind};
+
+        let broken = Error::new(ErrorKind::BrokenPipe, "closed");
+        assert!(ignore_broken_pipe(Err(broken.into())).is_ok());
+        let denied = Error::new(ErrorKind::PermissionDenied, "denied");
+        assert!(ignore_broken_pipe(Err(denied.into())).is_err());
+    }
+
+    #[test]
+    fn checks_writable() {
+        let dir = std::env::temp_dir().join(format!("tweec-writable-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("story.html");
+        assert!(check_writable(&file).is_ok());
+        assert!(check_writable(dir.join("missing").join("story.html")).is_err());
+        assert!(check_writable(&dir).is_err());
+
+        std::fs::write(&file, "").unwrap();
+        let mut permissions = std::fs::metadata(&file).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&file, permissions).unwrap();
+        assert!(check_writable(&file).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sanitizes_file_names() {
+        assert_eq!(sanitize_file_name("Two: Return?"), "Two_ Return_");
+        assert_eq!(sanitize_file_name("a/b\\c.html"), "a_b_c.html");
+        assert_eq!(sanitize_file_name(" ..My Story. "), "My Story");
+        assert_eq!(sanitize_file_name("con.html"), "_con.html");
+        assert_eq!(sanitize_file_name("Console.html"), "Console.html");
+        assert_eq!(sanitize_file_name("..."), "Untitled Story");
+    }
+}
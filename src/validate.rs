@@ -0,0 +1,210 @@
+//! Checks compiled HTML by parsing it the way a browser would
+//!
+//! Passage content is escaped when compiled, but a story format's template or
+//! an unusual story can still produce HTML that a browser reads differently
+//! than intended, such as story data that ends up inside another element or
+//! passage text that changes when parsed. Each problem found in a passage is
+//! traced back to the passage's header in the source.
+
+use crate::partials;
+use crate::utils;
+use color_eyre::Result;
+use html5ever::tendril::TendrilSink;
+use html5ever::tree_builder::TreeBuilderOpts;
+use html5ever::{local_name, namespace_url, ns, ParseOpts, QualName};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::collections::HashMap;
+use tweep::Story;
+
+/// A `tw-passagedata` element found in the parsed HTML
+struct ParsedPassage {
+    /// The name of the element containing it
+    parent: String,
+
+    /// Its text, as the story format will see it
+    text: String,
+}
+
+fn parse_opts() -> ParseOpts {
+    ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            exact_errors: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn attribute(node: &Handle, name: &str) -> Option<String> {
+    match &node.data {
+        NodeData::Element { attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|attr| &*attr.name.local == name)
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
+
+fn element_name(node: &Handle) -> Option<String> {
+    match &node.data {
+        NodeData::Element { name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+fn text(node: &Handle, text_content: &mut String) {
+    match &node.data {
+        NodeData::Text { contents } => text_content.push_str(&contents.borrow()),
+        _ => {
+            for child in node.children.borrow().iter() {
+                text(child, text_content);
+            }
+        }
+    }
+}
+
+/// Finds the `tw-storydata` and `tw-passagedata` elements under `node`
+fn find(
+    node: &Handle,
+    parent: &str,
+    story_data: &mut usize,
+    passages: &mut Vec<(Option<String>, ParsedPassage)>,
+) {
+    let name = element_name(node);
+    match name.as_deref() {
+        Some("tw-storydata") => *story_data += 1,
+        Some("tw-passagedata") => {
+            let mut text_content = String::new();
+            text(node, &mut text_content);
+            passages.push((
+                attribute(node, "name"),
+                ParsedPassage {
+                    parent: parent.to_string(),
+                    text: text_content,
+                },
+            ));
+        }
+        _ => (),
+    }
+    let parent = name.as_deref().unwrap_or(parent);
+    for child in node.children.borrow().iter() {
+        find(child, parent, story_data, passages);
+    }
+}
+
+/// Checks the compiled HTML of the story, returning a warning for each
+/// problem found
+pub fn validate(story: &Story, html: &str) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    let dom = html5ever::parse_document(RcDom::default(), parse_opts()).one(html);
+
+    let mut story_data = 0;
+    let mut parsed = Vec::new();
+    find(&dom.document, "", &mut story_data, &mut parsed);
+    if story_data != 1 {
+        warnings.push(format!(
+            "The output has {} tw-storydata elements instead of 1; check the story format's \
+             template",
+            story_data
+        ));
+    }
+
+    // Parse errors within the story data are caused by the story rather than
+    // the story format
+    if let (Some(start), Some(end)) = (html.find("<tw-storydata"), html.rfind("</tw-storydata>")) {
+        let context = QualName::new(None, ns!(html), local_name!("body"));
+        let fragment = html5ever::parse_fragment(RcDom::default(), parse_opts(), context, vec![])
+            .one(&html[start..end + "</tw-storydata>".len()]);
+        for error in &fragment.errors {
+            warnings.push(format!("The story data has invalid HTML: {}", error));
+        }
+    }
+
+    let headers = utils::passage_headers(story);
+    let location = |name: &str| match headers.get(name) {
+        Some(header) => format!(
+            " (defined at {}:{})",
+            header.get_file_name().as_deref().unwrap_or("<unknown>"),
+            header.get_start_position().line
+        ),
+        None => String::new(),
+    };
+    let mut parsed: HashMap<Option<String>, ParsedPassage> = parsed.into_iter().collect();
+    let contents = partials::expand(story)?;
+    let mut names: Vec<&String> = contents.keys().collect();
+    names.sort();
+    for name in names {
+        let passage = match parsed.remove(&Some(name.clone())) {
+            Some(passage) => passage,
+            None => {
+                warnings.push(format!(
+                    "Passage {}{} is missing from the output",
+                    name,
+                    location(name)
+                ));
+                continue;
+            }
+        };
+        if passage.parent != "tw-storydata" {
+            warnings.push(format!(
+                "Passage {}{} ends up inside {} instead of tw-storydata",
+                name,
+                location(name),
+                passage.parent
+            ));
+        }
+        if passage.text != contents[name] {
+            warnings.push(format!(
+                "The text of passage {}{} changes when the output is parsed",
+                name,
+                location(name)
+            ));
+        }
+    }
+    let mut extra: Vec<String> = parsed
+        .into_keys()
+        .map(|name| name.unwrap_or_else(|| "(unnamed)".to_string()))
+        .collect();
+    extra.sort();
+    for name in extra {
+        warnings.push(format!(
+            "The output has a passage {} that is not in the story",
+            name
+        ));
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn story() -> Story {
+        let input = ":: Start\n<b>Bold</b> & [[End]]\n\n:: End\nDone\n";
+        Story::from_string(input.to_string()).take().0.ok().unwrap()
+    }
+
+    #[test]
+    fn accepts_valid_output() {
+        let html = r#"<!DOCTYPE html><html><head><title>T</title></head><body>
+<tw-storydata name="T"><tw-passagedata name="End" pid="2">Done
+</tw-passagedata><tw-passagedata name="Start" pid="1">&lt;b&gt;Bold&lt;/b&gt; &amp; [[End]]
+</tw-passagedata></tw-storydata>
+</body></html>"#;
+        assert_eq!(validate(&story(), html).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn finds_broken_output() {
+        let html = r#"<!DOCTYPE html><html><head><title>T</title></head><body>
+<tw-storydata name="T"><tw-passagedata name="Start" pid="1"><b>Bold</b> & [[End]]</tw-passagedata><p><tw-passagedata name="Other">x</tw-passagedata></p></tw-storydata>
+</body></html>"#;
+        let warnings = validate(&story(), html).unwrap();
+        assert!(warnings.contains(&"Passage End is missing from the output".to_string()));
+        assert!(warnings
+            .contains(&"The text of passage Start changes when the output is parsed".to_string()));
+        assert!(warnings
+            .contains(&"The output has a passage Other that is not in the story".to_string()));
+    }
+}
@@ -0,0 +1,194 @@
+//! Watches the input files and rebuilds the story whenever they change
+
+use crate::assets::EmbedCache;
+use crate::inputs;
+use crate::tweec;
+use crate::Config;
+use color_eyre::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
+
+/// How often to check the inputs for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long the inputs must go unchanged before rebuilding, so that a burst of
+/// saves only causes a single rebuild
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The last seen state of a single input file
+struct FileState {
+    modified: Option<SystemTime>,
+    hash: u64,
+}
+
+/// Tracks the state of the Twee files given by a list of inputs
+///
+/// Files are only read when their modification time changes, and a file only
+/// counts as changed if its contents hash differently than before
+pub struct Watcher {
+    inputs: Vec<String>,
+    extensions: Vec<String>,
+    files: HashMap<PathBuf, FileState>,
+}
+
+impl Watcher {
+    /// Creates a watcher for the Twee files with the given extensions in the
+    /// given inputs, recording their current state
+    pub fn new(inputs: &[String], extensions: &[String]) -> Self {
+        let mut watcher = Watcher {
+            inputs: inputs.to_vec(),
+            extensions: extensions.to_vec(),
+            files: HashMap::new(),
+        };
+        watcher.poll();
+        watcher
+    }
+
+    /// Checks the inputs for changes, returning the paths of any files that
+    /// were added, removed, or modified since the last poll
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        let files = inputs::twee_files(&self.inputs, &self.extensions);
+
+        let removed: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| !files.contains(path))
+            .cloned()
+            .collect();
+        for path in removed {
+            self.files.remove(&path);
+            changed.push(path);
+        }
+
+        for path in files {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            if let Some(state) = self.files.get(&path) {
+                if state.modified == modified {
+                    continue;
+                }
+            }
+
+            let contents = match std::fs::read(&path) {
+                Ok(contents) => contents,
+                Err(_) => continue,
+            };
+            let mut hasher = DefaultHasher::new();
+            contents.hash(&mut hasher);
+            let hash = hasher.finish();
+
+            let previous = self
+                .files
+                .insert(path.clone(), FileState { modified, hash });
+            if previous.map(|p| p.hash != hash).unwrap_or(true) {
+                changed.push(path);
+            }
+        }
+
+        changed
+    }
+
+    /// Blocks until the inputs change and then stay unchanged for the debounce
+    /// period, returning the paths of all files that changed
+    pub fn wait(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let mut new_changes = self.poll();
+            if new_changes.is_empty() {
+                if !changed.is_empty() {
+                    return changed;
+                }
+                continue;
+            }
+            changed.append(&mut new_changes);
+            thread::sleep(DEBOUNCE);
+        }
+    }
+}
+
+/// Builds the story, then rebuilds it every time the inputs change
+///
+/// Errors from a build are reported without stopping the watcher. The output
+/// is only opened after the first build, and embedded images are only encoded
+/// again when they change
+pub fn watch(config: &Config, stdout: &mut StandardStream) -> Result<()> {
+    let mut watcher = Watcher::new(&config.inputs, &config.extensions);
+    let mut config = config.clone();
+    let mut changed = Vec::new();
+    let mut cache = EmbedCache::default();
+    loop {
+        header(stdout, &changed)?;
+        match tweec::build(&config, &mut cache, stdout) {
+            Ok(()) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+                writeln!(stdout, "Build succeeded")?;
+            }
+            Err(e) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+                write!(stdout, "Build failed: ")?;
+                stdout.reset()?;
+                writeln!(stdout, "{:#}", e)?;
+            }
+        }
+        stdout.reset()?;
+        writeln!(stdout, "Watching for changes...")?;
+        stdout.flush()?;
+
+        config.should_open = false;
+        changed = watcher.wait();
+    }
+}
+
+/// Writes a header separating the output of each build
+fn header(stdout: &mut StandardStream, changed: &[PathBuf]) -> Result<()> {
+    stdout.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)).set_bold(true))?;
+    writeln!(stdout, "\n{}", "=".repeat(80))?;
+    if changed.is_empty() {
+        writeln!(stdout, "Building")?;
+    } else {
+        let names: Vec<String> = changed
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        writeln!(stdout, "Rebuilding after changes to {}", names.join(", "))?;
+    }
+    stdout.reset()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_content_changes_count() {
+        let dir = std::env::temp_dir().join(format!("tweec-watch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).ok().unwrap();
+        let path = dir.join("story.twee");
+        std::fs::write(&path, ":: Start\nHello\n").ok().unwrap();
+
+        let mut watcher = Watcher::new(
+            &[dir.to_string_lossy().into_owned()],
+            &inputs::default_extensions(),
+        );
+        assert!(watcher.poll().is_empty());
+
+        std::fs::write(&path, ":: Start\nHello\n").ok().unwrap();
+        assert!(watcher.poll().is_empty());
+
+        std::fs::write(&path, ":: Start\nGoodbye\n").ok().unwrap();
+        watcher.files.get_mut(&path).unwrap().modified = None;
+        assert_eq!(watcher.poll(), vec![path.clone()]);
+
+        std::fs::remove_file(&path).ok().unwrap();
+        assert_eq!(watcher.poll(), vec![path]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}